@@ -0,0 +1,31 @@
+//! Draw a few values from a server
+//!
+//! ```bash
+//! cargo run --example demo -- http://localhost:8080/api/v1
+//! ```
+
+use quantum_entropy_client::{BytesOptions, Client, Error, Source};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let base_url = std::env::args().nth(1).unwrap_or_else(|| "http://localhost:8080/api/v1".to_string());
+    let client = Client::builder(&base_url).env_credentials().build()?;
+
+    let version = client.version().await?;
+    println!("Server {} ({})", version.version, version.git_commit);
+
+    let bytes = client.random_bytes(32).await?;
+    println!("Random bytes: {}", hex::encode(&bytes.bytes));
+
+    let dice = client.random_integers(1, 6, 2).await?;
+    println!("Dice roll: {:?} (total: {})", dice.integers, dice.integers.iter().sum::<i64>());
+
+    let options = BytesOptions {
+        source: Some(Source::Raw),
+        ..Default::default()
+    };
+    let raw = client.random_bytes_with(16, &options).await?;
+    println!("Raw device output: {}", hex::encode(&raw.bytes));
+
+    Ok(())
+}
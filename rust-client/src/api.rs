@@ -0,0 +1,96 @@
+//! The client's requests as a trait, for code that should also run against
+//! [`MockClient`](crate::MockClient)
+
+use std::future::Future;
+use uuid::Uuid;
+
+use crate::{BytesOptions, Client, Error, Health, RandomBytes, RandomIntegers, Version};
+
+/// Draws and status requests of a server, implemented by [`Client`] and
+/// [`MockClient`](crate::MockClient)
+///
+/// Take `&impl EntropyApi` instead of `&Client` to unit-test against the
+/// mock. Floats and UUIDs derive from `random_bytes` either way.
+pub trait EntropyApi: Send + Sync {
+    /// `count` random bytes with the server's default processing
+    fn random_bytes(&self, count: usize) -> impl Future<Output = Result<RandomBytes, Error>> + Send;
+
+    /// `count` random bytes produced as `options` ask
+    fn random_bytes_with(
+        &self,
+        count: usize,
+        options: &BytesOptions,
+    ) -> impl Future<Output = Result<RandomBytes, Error>> + Send;
+
+    /// `count` random integers between `min` and `max` inclusive
+    fn random_integers(
+        &self,
+        min: i64,
+        max: i64,
+        count: usize,
+    ) -> impl Future<Output = Result<RandomIntegers, Error>> + Send;
+
+    /// Server health, also when it reports itself unhealthy or starting
+    fn health(&self) -> impl Future<Output = Result<Health, Error>> + Send;
+
+    /// Version and build of the server
+    fn version(&self) -> impl Future<Output = Result<Version, Error>> + Send;
+
+    /// `count` uniform floats in `[0, 1)`, from 53 random bits each
+    fn random_floats(&self, count: usize) -> impl Future<Output = Result<Vec<f64>, Error>> + Send {
+        async move { Ok(floats(&self.random_bytes(count * 8).await?.bytes)) }
+    }
+
+    /// Version 4 UUID from 16 random bytes
+    fn random_uuid(&self) -> impl Future<Output = Result<Uuid, Error>> + Send {
+        async move { uuid(self.random_bytes(16).await?.bytes) }
+    }
+}
+
+impl EntropyApi for Client {
+    fn random_bytes(&self, count: usize) -> impl Future<Output = Result<RandomBytes, Error>> + Send {
+        Client::random_bytes(self, count)
+    }
+
+    fn random_bytes_with(
+        &self,
+        count: usize,
+        options: &BytesOptions,
+    ) -> impl Future<Output = Result<RandomBytes, Error>> + Send {
+        Client::random_bytes_with(self, count, options)
+    }
+
+    fn random_integers(
+        &self,
+        min: i64,
+        max: i64,
+        count: usize,
+    ) -> impl Future<Output = Result<RandomIntegers, Error>> + Send {
+        Client::random_integers(self, min, max, count)
+    }
+
+    fn health(&self) -> impl Future<Output = Result<Health, Error>> + Send {
+        Client::health(self)
+    }
+
+    fn version(&self) -> impl Future<Output = Result<Version, Error>> + Send {
+        Client::version(self)
+    }
+}
+
+/// Uniform floats in `[0, 1)` from each 8 bytes
+pub(crate) fn floats(bytes: &[u8]) -> Vec<f64> {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| {
+            let bits = u64::from_le_bytes(chunk.try_into().expect("8-byte chunk"));
+            (bits >> 11) as f64 / (1u64 << 53) as f64
+        })
+        .collect()
+}
+
+/// Version 4 UUID from 16 random bytes
+pub(crate) fn uuid(bytes: Vec<u8>) -> Result<Uuid, Error> {
+    let bytes = bytes.try_into().map_err(|_| Error::Decode("UUID needs 16 bytes".to_string()))?;
+    Ok(uuid::Builder::from_random_bytes(bytes).into_uuid())
+}
@@ -0,0 +1,212 @@
+//! `quantum`: draw entropy from a Quantis QRNG server in the terminal
+//!
+//! ```bash
+//! export QUANTUM_API_URL=https://entropy.example.com/api/v1 QUANTUM_API_KEY=k-123
+//! quantum bytes 32
+//! quantum password --length 24
+//! quantum stream --count 1048576 sample.bin
+//! ```
+
+use anyhow::{ensure, Context, Result};
+use base64::Engine;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use quantum_entropy_client::{Client, EntropyApi};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+#[command(name = "quantum", version, about)]
+struct Cli {
+    /// API root of the server
+    #[arg(long, env = "QUANTUM_API_URL", default_value = "http://localhost:8080/api/v1")]
+    url: String,
+
+    /// API key, if the server requires one
+    #[arg(long, env = "QUANTUM_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// JWT bearer token, if the server requires one
+    #[arg(long, env = "QUANTUM_API_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print random bytes
+    Bytes {
+        #[arg(default_value_t = 32)]
+        count: usize,
+        #[arg(long, value_enum, default_value = "hex")]
+        format: Format,
+    },
+    /// Print random integers between MIN and MAX inclusive, one per line
+    Int {
+        min: i64,
+        max: i64,
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+    },
+    /// Print random version 4 UUIDs, one per line
+    Uuid {
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+    },
+    /// Print a random password
+    Password {
+        #[arg(short, long, default_value_t = 20)]
+        length: usize,
+        #[arg(long, value_enum, default_value = "symbols")]
+        charset: Charset,
+    },
+    /// Print a random symmetric key
+    Key {
+        /// Key size, a multiple of 8
+        #[arg(long, default_value_t = 256)]
+        bits: usize,
+        #[arg(long, value_enum, default_value = "hex")]
+        format: Format,
+    },
+    /// Write random bytes to a file, or to stdout with `-`
+    Stream {
+        /// Bytes to write
+        #[arg(short = 'n', long)]
+        count: usize,
+        #[arg(default_value = "-")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Hex,
+    Base64,
+    /// Unencoded bytes
+    Raw,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Charset {
+    /// Digits only
+    Digits,
+    /// Letters and digits
+    Alnum,
+    /// Letters, digits and punctuation
+    Symbols,
+}
+
+impl Charset {
+    fn alphabet(self) -> &'static [u8] {
+        match self {
+            Self::Digits => b"0123456789",
+            Self::Alnum => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            Self::Symbols => concat!(
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "!#$%&()*+,-./:;<=>?@[]^_{|}~"
+            )
+            .as_bytes(),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut builder = Client::builder(&cli.url).env_credentials();
+    if let Some(key) = &cli.api_key {
+        builder = builder.api_key(key);
+    }
+    if let Some(token) = &cli.token {
+        builder = builder.bearer_token(token);
+    }
+    let client = builder.build()?;
+
+    match cli.command {
+        Command::Bytes { count, format } => print_bytes(&client.random_bytes(count).await?.bytes, format),
+        Command::Int { min, max, count } => {
+            for integer in client.random_integers(min, max, count).await?.integers {
+                println!("{}", integer);
+            }
+            Ok(())
+        }
+        Command::Uuid { count } => {
+            for _ in 0..count {
+                println!("{}", client.random_uuid().await?);
+            }
+            Ok(())
+        }
+        Command::Password { length, charset } => {
+            println!("{}", password(&client, length, charset.alphabet()).await?);
+            Ok(())
+        }
+        Command::Key { bits, format } => {
+            ensure!(bits > 0 && bits % 8 == 0, "--bits must be a positive multiple of 8");
+            print_bytes(&client.random_bytes(bits / 8).await?.bytes, format)
+        }
+        Command::Stream { count, output } => stream(&client, count, &output).await,
+    }
+}
+
+fn print_bytes(bytes: &[u8], format: Format) -> Result<()> {
+    match format {
+        Format::Hex => println!("{}", hex::encode(bytes)),
+        Format::Base64 => println!("{}", base64::engine::general_purpose::STANDARD.encode(bytes)),
+        Format::Raw => io::stdout().write_all(bytes)?,
+    }
+    Ok(())
+}
+
+/// `length` characters of `alphabet`, each equally likely
+async fn password(api: &impl EntropyApi, length: usize, alphabet: &[u8]) -> Result<String> {
+    ensure!(length > 0, "--length must be at least 1");
+    // Bytes at or above the last whole multiple of the alphabet are skipped
+    let limit = 256 - 256 % alphabet.len();
+    let mut password = String::with_capacity(length);
+    while password.len() < length {
+        for byte in api.random_bytes(length * 2).await?.bytes {
+            if (byte as usize) < limit && password.len() < length {
+                password.push(alphabet[byte as usize % alphabet.len()] as char);
+            }
+        }
+    }
+    Ok(password)
+}
+
+async fn stream(client: &Client, count: usize, output: &Path) -> Result<()> {
+    let mut writer: Box<dyn Write> = match output.to_str() {
+        Some("-") => Box::new(io::stdout().lock()),
+        _ => {
+            let path = output.display();
+            Box::new(File::create(output).with_context(|| format!("Failed to create {}", path))?)
+        }
+    };
+    let mut chunks = client.stream_bytes(count).await?;
+    while let Some(chunk) = chunks.next().await {
+        writer.write_all(&chunk?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quantum_entropy_client::MockClient;
+
+    #[tokio::test]
+    async fn passwords_use_only_the_charset() {
+        let mock = MockClient::new(1);
+        let digits = password(&mock, 64, Charset::Digits.alphabet()).await.unwrap();
+        assert_eq!(digits.len(), 64);
+        assert!(digits.bytes().all(|c| c.is_ascii_digit()));
+        let symbols = password(&mock, 40, Charset::Symbols.alphabet()).await.unwrap();
+        assert!(symbols.bytes().all(|c| Charset::Symbols.alphabet().contains(&c)));
+        assert!(password(&mock, 0, b"ab").await.is_err());
+    }
+}
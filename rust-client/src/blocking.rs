@@ -0,0 +1,186 @@
+//! Synchronous client, for code without an async runtime
+//!
+//! Each [`Client`] runs the async client on a runtime of its own, so it
+//! must not be used from within an async context. Prefetching and streams
+//! need a running runtime and are only offered by the async client.
+
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+use crate::{
+    ActiveDevice, AuditFilter, AuditRecord, BeaconInfo, BeaconRound, BytesOptions, Commitment, Device,
+    EntropyEstimate, Error, Health, PublicKeys, RandomBytes, RandomIntegers, TestReport, Version, VrfProof,
+    VrfVerification,
+};
+
+/// Blocking connection to one server
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Client for the API at `base_url`, e.g. `http://localhost:8080/api/v1`
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        Self::from_async(crate::Client::new(base_url)?)
+    }
+
+    /// Blocking wrapper around an async client, see also
+    /// [`ClientBuilder::build_blocking`](crate::ClientBuilder::build_blocking)
+    pub fn from_async(inner: crate::Client) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// `count` random bytes with the server's default processing
+    pub fn random_bytes(&self, count: usize) -> Result<RandomBytes, Error> {
+        self.runtime.block_on(self.inner.random_bytes_with(count, &BytesOptions::default()))
+    }
+
+    /// `count` random bytes produced as `options` ask
+    pub fn random_bytes_with(&self, count: usize, options: &BytesOptions) -> Result<RandomBytes, Error> {
+        self.runtime.block_on(self.inner.random_bytes_with(count, options))
+    }
+
+    /// `count` random integers between `min` and `max` inclusive
+    pub fn random_integers(&self, min: i64, max: i64, count: usize) -> Result<RandomIntegers, Error> {
+        self.runtime.block_on(self.inner.random_integers(min, max, count))
+    }
+
+    /// `count` random integers between `min` and `max` inclusive, drawn
+    /// as `options` ask
+    pub fn random_integers_with(
+        &self,
+        min: i64,
+        max: i64,
+        count: usize,
+        options: &BytesOptions,
+    ) -> Result<RandomIntegers, Error> {
+        self.runtime.block_on(self.inner.random_integers_with(min, max, count, options))
+    }
+
+    /// `count` uniform floats in `[0, 1)`
+    pub fn random_floats(&self, count: usize) -> Result<Vec<f64>, Error> {
+        self.runtime.block_on(self.inner.random_floats(count))
+    }
+
+    /// Version 4 UUID from 16 random bytes
+    pub fn random_uuid(&self) -> Result<Uuid, Error> {
+        self.runtime.block_on(self.inner.random_uuid())
+    }
+
+    /// The active device and buffer fill
+    pub fn device_info(&self) -> Result<ActiveDevice, Error> {
+        self.runtime.block_on(self.inner.device_info())
+    }
+
+    /// Every device the server knows about
+    pub fn devices(&self) -> Result<Vec<Device>, Error> {
+        self.runtime.block_on(self.inner.devices())
+    }
+
+    /// Buffer, health monitor and pool statistics
+    pub fn stats(&self) -> Result<serde_json::Value, Error> {
+        self.runtime.block_on(self.inner.stats())
+    }
+
+    /// Prometheus metrics in the text exposition format
+    pub fn metrics(&self) -> Result<String, Error> {
+        self.runtime.block_on(self.inner.metrics())
+    }
+
+    /// Run the SP 800-22 suite over `megabytes` of fresh raw output
+    pub fn sp800_22(&self, megabytes: usize, device: Option<&str>) -> Result<TestReport, Error> {
+        self.runtime.block_on(self.inner.sp800_22(megabytes, device))
+    }
+
+    /// Estimate the min-entropy of `samples` fresh raw bytes
+    pub fn estimate_entropy(&self, samples: usize, device: Option<&str>) -> Result<EntropyEstimate, Error> {
+        self.runtime.block_on(self.inner.estimate_entropy(samples, device))
+    }
+
+    /// Health test results matching `filter`, oldest first
+    pub fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, Error> {
+        self.runtime.block_on(self.inner.audit_trail(filter))
+    }
+
+    /// Draw `count` bytes and publish only their commitment
+    pub fn commit(&self, count: usize) -> Result<Commitment, Error> {
+        self.runtime.block_on(self.inner.commit(count))
+    }
+
+    /// Reveal the value and nonce of commitment `id`
+    pub fn reveal(&self, id: Uuid) -> Result<Commitment, Error> {
+        self.runtime.block_on(self.inner.reveal(id))
+    }
+
+    /// Commitment `id`, with its value once revealed
+    pub fn commitment(&self, id: Uuid) -> Result<Commitment, Error> {
+        self.runtime.block_on(self.inner.commitment(id))
+    }
+
+    /// Prove `alpha` with the server's VRF key
+    pub fn vrf_prove(&self, alpha: &[u8]) -> Result<VrfProof, Error> {
+        self.runtime.block_on(self.inner.vrf_prove(alpha))
+    }
+
+    /// Check a hex `proof` of `alpha` under the hex `public_key`
+    pub fn vrf_verify(&self, public_key: &str, alpha: &[u8], proof: &str) -> Result<VrfVerification, Error> {
+        self.runtime.block_on(self.inner.vrf_verify(public_key, alpha, proof))
+    }
+
+    /// VRF public key and the keys signing attested outputs
+    pub fn public_keys(&self) -> Result<PublicKeys, Error> {
+        self.runtime.block_on(self.inner.public_keys())
+    }
+
+    /// Parameters of the server's drand-compatible beacon chain
+    pub fn beacon_info(&self) -> Result<BeaconInfo, Error> {
+        self.runtime.block_on(self.inner.beacon_info())
+    }
+
+    /// Most recent beacon round
+    pub fn beacon_latest(&self) -> Result<BeaconRound, Error> {
+        self.runtime.block_on(self.inner.beacon_latest())
+    }
+
+    /// Beacon round `round`
+    pub fn beacon_round(&self, round: u64) -> Result<BeaconRound, Error> {
+        self.runtime.block_on(self.inner.beacon_round(round))
+    }
+
+    /// Server health, also when it reports itself unhealthy or starting
+    pub fn health(&self) -> Result<Health, Error> {
+        self.runtime.block_on(self.inner.health())
+    }
+
+    /// Version and build of the server
+    pub fn version(&self) -> Result<Version, Error> {
+        self.runtime.block_on(self.inner.version())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::serve;
+
+    #[test]
+    fn draws_without_a_runtime() {
+        let server = Runtime::new().unwrap();
+        let body = r#"{"success":true,"data":{"bytes":"2a","count":1,"format":"hex","correction":"none",
+            "source":"conditioned","sha256":"abc"},"error":null}"#;
+        let base_url = server.block_on(serve(&[("200 OK", "", body)]));
+
+        let client = crate::Client::builder(&base_url).api_key("k-1").build_blocking().unwrap();
+        assert_eq!(client.random_bytes(1).unwrap().bytes, [42]);
+    }
+}
@@ -0,0 +1,304 @@
+//! Configuring a [`Client`]
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION},
+    Proxy, Url,
+};
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{prefetch::Prefetcher, verify::Verifier, Client, Error, RetryPolicy};
+use crate::{API_KEY_ENV, API_KEY_HEADER, TOKEN_ENV};
+
+/// Options for a [`Client`], from [`Client::builder`]
+///
+/// Unset HTTP options keep reqwest's defaults: no request timeout, system
+/// proxies and an unbounded idle pool.
+#[derive(Clone)]
+#[must_use]
+pub struct ClientBuilder {
+    base_url: String,
+    http: Option<reqwest::Client>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    proxy: Option<String>,
+    no_proxy: bool,
+    retry: RetryPolicy,
+    prefetch: Option<usize>,
+    api_key: Option<String>,
+    bearer_token: Option<String>,
+    verify_signatures: bool,
+    os_fallback: bool,
+}
+
+impl ClientBuilder {
+    pub(crate) fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            http: None,
+            connect_timeout: None,
+            timeout: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            proxy: None,
+            no_proxy: false,
+            retry: RetryPolicy::default(),
+            prefetch: None,
+            api_key: None,
+            bearer_token: None,
+            verify_signatures: false,
+            os_fallback: false,
+        }
+    }
+
+    /// Give up connecting after `timeout`
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on each attempt at a request after `timeout`, including
+    /// reading the body; long streams need a generous one
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections to the server
+    pub fn pool_max_idle(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Close idle connections after `timeout`
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Send all requests through the proxy at `url`, e.g.
+    /// `http://proxy:3128` or `socks5://proxy:1080`
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Ignore proxies set in the environment, such as `HTTPS_PROXY`
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
+    }
+
+    /// Send requests through `http`, e.g. one with client certificates,
+    /// instead of the timeout, pool and proxy options above
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Retry failed requests as `policy` says, instead of up to 3 times
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Keep up to `size` bytes fetched ahead to serve small `random_bytes`
+    /// draws from, shared by clones of the client; ignored when verifying
+    /// signatures
+    pub fn prefetch(mut self, size: usize) -> Self {
+        self.prefetch = Some(size);
+        self
+    }
+
+    /// Authenticate with an API key
+    pub fn api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    /// Authenticate with a JWT bearer token
+    pub fn bearer_token(mut self, token: &str) -> Self {
+        self.bearer_token = Some(token.to_string());
+        self
+    }
+
+    /// Have the server sign `random_bytes` output and check each signature
+    /// against the keys at `/pubkey`, failing draws that do not verify
+    ///
+    /// Signing needs the crypto role. Prefetched bytes carry no signature,
+    /// so prefetching is off.
+    pub fn verify_signatures(mut self) -> Self {
+        self.verify_signatures = true;
+        self
+    }
+
+    /// Serve `random_bytes` draws from the operating system's generator
+    /// when the server cannot be reached, marked with
+    /// [`Source::Os`](crate::Source::Os)
+    ///
+    /// For applications preferring availability over quantum sourcing.
+    /// Ignored when verifying signatures.
+    pub fn os_fallback(mut self) -> Self {
+        self.os_fallback = true;
+        self
+    }
+
+    /// Authenticate with the API key in `QUANTIS_API_KEY` and the bearer
+    /// token in `QUANTIS_TOKEN`, each where set and not empty
+    pub fn env_credentials(self) -> Self {
+        self.credentials_from(|name| std::env::var(name).ok())
+    }
+
+    fn credentials_from(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let set = |name| var(name).filter(|value| !value.is_empty());
+        if let Some(key) = set(API_KEY_ENV) {
+            self.api_key = Some(key);
+        }
+        if let Some(token) = set(TOKEN_ENV) {
+            self.bearer_token = Some(token);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let base_url = parse_base_url(&self.base_url)?;
+        let mut headers = HeaderMap::new();
+        if let Some(key) = &self.api_key {
+            headers.insert(API_KEY_HEADER, sensitive(API_KEY_HEADER, key)?);
+        }
+        if let Some(token) = &self.bearer_token {
+            headers.insert(AUTHORIZATION, sensitive(AUTHORIZATION.as_str(), &format!("Bearer {}", token))?);
+        }
+        let http = match &self.http {
+            Some(http) => http.clone(),
+            None => self.http_client_from_options()?,
+        };
+        Ok(Client {
+            http,
+            base_url,
+            headers,
+            retry: self.retry,
+            prefetch: match self.verify_signatures {
+                true => None,
+                false => self.prefetch.map(|size| Arc::new(Prefetcher::new(size))),
+            },
+            verifier: self.verify_signatures.then(|| Arc::new(Verifier::default())),
+            os_fallback: self.os_fallback && !self.verify_signatures,
+        })
+    }
+
+    /// Blocking client with these options; prefetching does not apply
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::Client, Error> {
+        crate::blocking::Client::from_async(self.build()?)
+    }
+
+    fn http_client_from_options(&self) -> Result<reqwest::Client, Error> {
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            http = http.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            http = http.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            http = http.pool_idle_timeout(timeout);
+        }
+        if self.no_proxy {
+            http = http.no_proxy();
+        }
+        if let Some(url) = &self.proxy {
+            let proxy = Proxy::all(url).map_err(|e| Error::InvalidUrl(format!("{}: {}", url, e)))?;
+            http = http.proxy(proxy);
+        }
+        Ok(http.build()?)
+    }
+}
+
+// Credentials stay out of debug output
+impl fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientBuilder")
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .field("proxy", &self.proxy)
+            .field("retry", &self.retry)
+            .field("prefetch", &self.prefetch)
+            .field("verify_signatures", &self.verify_signatures)
+            .field("os_fallback", &self.os_fallback)
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_base_url(base_url: &str) -> Result<Url, Error> {
+    let mut url = Url::parse(base_url).map_err(|e| Error::InvalidUrl(format!("{}: {}", base_url, e)))?;
+    if url.cannot_be_a_base() {
+        return Err(Error::InvalidUrl(url.to_string()));
+    }
+    // Paths are joined below the base, not beside its last segment
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
+}
+
+fn sensitive(name: &'static str, value: &str) -> Result<HeaderValue, Error> {
+    let mut value = value.parse::<HeaderValue>().map_err(|_| Error::InvalidHeader(name))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const BASE_URL: &str = "http://localhost:8080/api/v1";
+
+    #[test]
+    fn picks_up_credentials_from_the_environment() {
+        let env = |name: &str| match name {
+            API_KEY_ENV => Some("k-1".to_string()),
+            _ => Some(String::new()),
+        };
+        let keyed = Client::builder(BASE_URL).credentials_from(env).build().unwrap();
+        assert_eq!(keyed.headers[API_KEY_HEADER], "k-1");
+        assert!(keyed.headers[API_KEY_HEADER].is_sensitive());
+        assert!(!keyed.headers.contains_key(AUTHORIZATION));
+
+        let token = |name: &str| (name == TOKEN_ENV).then(|| "t".to_string());
+        let builder = Client::builder(BASE_URL).api_key("k-2").credentials_from(token);
+        assert!(!format!("{:?}", builder).contains("k-2"));
+        let both = builder.build().unwrap();
+        assert_eq!(both.headers[AUTHORIZATION], "Bearer t");
+        assert_eq!(both.headers[API_KEY_HEADER], "k-2");
+
+        let invalid = Client::builder(BASE_URL).api_key("k\n").build();
+        assert!(matches!(invalid, Err(Error::InvalidHeader(API_KEY_HEADER))));
+        let invalid = Client::builder(BASE_URL).proxy("not a proxy").build();
+        assert!(matches!(invalid, Err(Error::InvalidUrl(_))));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_request_timeout() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+        let client = Client::builder(&base_url)
+            .timeout(Duration::from_millis(100))
+            .connect_timeout(Duration::from_secs(1))
+            .pool_max_idle(1)
+            .no_proxy()
+            .retry(RetryPolicy::never())
+            .build()
+            .unwrap();
+        match client.version().await {
+            Err(Error::Transport(e)) => assert!(e.is_timeout()),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+}
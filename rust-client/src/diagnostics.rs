@@ -0,0 +1,74 @@
+//! Device, statistics and health test endpoints
+
+use crate::{ActiveDevice, AuditData, AuditRecord, Client, Device, EntropyEstimate, Error, Reply, TestReport};
+
+/// Which `/entropy/audit` records to return, all of the newest 100 when
+/// unset
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Earliest Unix timestamp, inclusive
+    pub from: Option<u64>,
+    /// Latest Unix timestamp, inclusive
+    pub to: Option<u64>,
+    /// `startup`, `continuous`, `self_test` or `alarm`
+    pub category: Option<String>,
+    /// Exact test name, e.g. `repetition_count`
+    pub test: Option<String>,
+    /// Newest matching records to return, up to 1000
+    pub limit: Option<usize>,
+}
+
+impl Client {
+    /// The active device and buffer fill
+    pub async fn device_info(&self) -> Result<ActiveDevice, Error> {
+        self.get("device/info", &[], Reply::data).await
+    }
+
+    /// Every device the server knows about
+    pub async fn devices(&self) -> Result<Vec<Device>, Error> {
+        self.get("devices", &[], Reply::data).await
+    }
+
+    /// Buffer, health monitor and pool statistics, as the server reports
+    /// them
+    pub async fn stats(&self) -> Result<serde_json::Value, Error> {
+        self.get("stats", &[], Reply::data).await
+    }
+
+    /// Prometheus metrics in the text exposition format
+    pub async fn metrics(&self) -> Result<String, Error> {
+        self.get("metrics", &[], Reply::text).await
+    }
+
+    /// Run the SP 800-22 suite over `megabytes` of fresh raw output from
+    /// `device`, or the active device
+    pub async fn sp800_22(&self, megabytes: usize, device: Option<&str>) -> Result<TestReport, Error> {
+        let mut query = vec![("megabytes", megabytes.to_string())];
+        query.extend(device.map(|device| ("device", device.to_string())));
+        self.get("tests/sp800-22", &query, Reply::data).await
+    }
+
+    /// Estimate the min-entropy of `samples` fresh raw bytes from `device`,
+    /// or the active device
+    pub async fn estimate_entropy(
+        &self,
+        samples: usize,
+        device: Option<&str>,
+    ) -> Result<EntropyEstimate, Error> {
+        let mut query = vec![("samples", samples.to_string())];
+        query.extend(device.map(|device| ("device", device.to_string())));
+        self.get("entropy/estimate", &query, Reply::data).await
+    }
+
+    /// Health test results matching `filter`, oldest first
+    pub async fn audit_trail(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, Error> {
+        let mut query = Vec::new();
+        query.extend(filter.from.map(|from| ("from", from.to_string())));
+        query.extend(filter.to.map(|to| ("to", to.to_string())));
+        query.extend(filter.category.clone().map(|category| ("category", category)));
+        query.extend(filter.test.clone().map(|test| ("test", test)));
+        query.extend(filter.limit.map(|limit| ("limit", limit.to_string())));
+        let data: AuditData = self.get("entropy/audit", &query, Reply::data).await?;
+        Ok(data.records)
+    }
+}
@@ -0,0 +1,70 @@
+//! Errors returned by the client
+
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The base URL cannot be parsed
+    #[error("Invalid base URL: {0}")]
+    InvalidUrl(String),
+
+    /// An API key or token cannot be sent as a header
+    #[error("Invalid value for the {0} header")]
+    InvalidHeader(&'static str),
+
+    /// The request did not complete, e.g. connection refused or timed out
+    #[error("Transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    /// The server answered with an error
+    #[error("API error ({status}): {message}")]
+    Api {
+        /// HTTP status code, 200 for requests the server refused with
+        /// `success: false`
+        status: u16,
+        message: String,
+        /// How long the server asked the client to wait before retrying
+        retry_after: Option<Duration>,
+    },
+
+    /// The response is not what the API returns
+    #[error("Failed to decode response: {0}")]
+    Decode(String),
+
+    /// A signature the client was told to check is missing or wrong
+    #[error("Signature check failed: {0}")]
+    Signature(String),
+
+    /// The blocking client could not start its runtime
+    #[error("Failed to start the runtime: {0}")]
+    Runtime(std::io::Error),
+}
+
+impl Error {
+    /// HTTP status of an API error
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Api { status, .. } => Some(*status),
+            Self::Transport(e) => e.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// How long the server asked the client to wait before retrying
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Api { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether the request may succeed if sent again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Api { status, .. } => matches!(status, 429 | 503),
+            Self::Transport(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Drawing from the operating system when the server cannot be reached
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, RandomBytes, Source};
+
+/// Whether a failed draw may fall back: the server could not be reached,
+/// directly or by a gateway in front of it
+pub(crate) fn unreachable(error: &Error) -> bool {
+    match error {
+        Error::Transport(_) => true,
+        Error::Api { status, .. } => matches!(status, 502 | 504),
+        _ => false,
+    }
+}
+
+/// `count` bytes from the operating system, marked with [`Source::Os`]
+pub(crate) fn os_bytes(count: usize) -> Result<RandomBytes, getrandom::Error> {
+    let mut bytes = vec![0; count];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(RandomBytes {
+        sha256: hex::encode(Sha256::digest(&bytes)),
+        bytes,
+        correction: "none".to_string(),
+        source: Source::Os,
+        device: None,
+        verified: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::serve, Client, RetryPolicy};
+
+    #[tokio::test]
+    async fn falls_back_only_when_the_server_is_unreachable() {
+        let refused = Client::builder("http://127.0.0.1:1/api/v1").retry(RetryPolicy::never());
+        let bytes = refused.clone().os_fallback().build().unwrap().random_bytes(16).await.unwrap();
+        assert_eq!((bytes.bytes.len(), bytes.source), (16, Source::Os));
+        assert!(refused.build().unwrap().random_bytes(16).await.is_err());
+
+        let invalid = r#"{"success":false,"data":null,"error":"Count must be between 1 and 65536"}"#;
+        let base_url = serve(&[("400 Bad Request", "", invalid)]).await;
+        let client = Client::builder(&base_url).os_fallback().build().unwrap();
+        assert!(matches!(client.random_bytes(0).await, Err(Error::Api { status: 400, .. })));
+    }
+}
@@ -0,0 +1,597 @@
+//! Client for the Quantis QRNG entropy API
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), quantum_entropy_client::Error> {
+//! let client = quantum_entropy_client::Client::new("http://localhost:8080/api/v1")?;
+//! let key = client.random_bytes(32).await?;
+//! println!("{}", hex::encode(&key.bytes));
+//! # Ok(())
+//! # }
+//! ```
+
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Method, StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+use std::{future::Future, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod builder;
+mod diagnostics;
+mod error;
+mod fallback;
+mod mock;
+mod prefetch;
+mod retry;
+mod rng;
+mod stream;
+mod types;
+mod verifiable;
+mod verify;
+
+pub use api::EntropyApi;
+pub use builder::ClientBuilder;
+pub use diagnostics::AuditFilter;
+pub use error::Error;
+pub use mock::MockClient;
+pub use prefetch::MAX_PREFETCHED_DRAW;
+use prefetch::Prefetcher;
+pub use retry::RetryPolicy;
+pub use rng::{QuantumRng, DEFAULT_CHUNK_SIZE};
+pub use stream::ByteStream;
+pub use types::{
+    ActiveDevice, AuditRecord, BeaconInfo, BeaconRound, Commitment, Device, DeviceInfo, EntropyEstimate,
+    Health, PublicKeys, RandomBytes, RandomIntegers, SigningKey, Source, TestReport, TestResult, Version,
+    VrfProof, VrfVerification,
+};
+use types::{ApiResponse, AuditData, BytesData};
+pub use verify::Verified;
+use verify::Verifier;
+
+/// Header carrying an API key
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Environment variable holding an API key, see [`ClientBuilder::env_credentials`]
+pub const API_KEY_ENV: &str = "QUANTIS_API_KEY";
+/// Environment variable holding a JWT bearer token
+pub const TOKEN_ENV: &str = "QUANTIS_TOKEN";
+
+/// How `/random` output is produced, server defaults when unset
+#[derive(Debug, Clone, Default)]
+pub struct BytesOptions {
+    /// Post-processing pipeline, e.g. `von_neumann|sha3`
+    pub correction: Option<String>,
+    pub source: Option<Source>,
+    /// Serial of the device to read from
+    pub device: Option<String>,
+}
+
+impl BytesOptions {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        query.extend(self.correction.clone().map(|correction| ("correction", correction)));
+        query.extend(self.source.map(|source| ("source", source.as_str().to_string())));
+        query.extend(self.device.clone().map(|device| ("device", device)));
+        query
+    }
+}
+
+/// Connection to one server
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    headers: HeaderMap,
+    retry: RetryPolicy,
+    prefetch: Option<Arc<Prefetcher>>,
+    verifier: Option<Arc<Verifier>>,
+    /// Draw bytes from the operating system when the server is unreachable
+    os_fallback: bool,
+}
+
+impl Client {
+    /// Client for the API at `base_url`, e.g. `http://localhost:8080/api/v1`,
+    /// with default options
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        Self::builder(base_url).build()
+    }
+
+    /// Configure a client for the API at `base_url`
+    pub fn builder(base_url: &str) -> ClientBuilder {
+        ClientBuilder::new(base_url)
+    }
+
+    /// Client for refilling the prefetch buffer, which takes only server
+    /// bytes
+    fn without_prefetch(&self) -> Self {
+        Self {
+            prefetch: None,
+            os_fallback: false,
+            ..self.clone()
+        }
+    }
+
+    /// `count` random bytes with the server's default processing, from
+    /// prefetched bytes when enabled
+    pub async fn random_bytes(&self, count: usize) -> Result<RandomBytes, Error> {
+        if let Some(bytes) = self.prefetch.as_ref().and_then(|prefetch| prefetch.take(self, count)) {
+            return Ok(bytes);
+        }
+        self.random_bytes_with(count, &BytesOptions::default()).await
+    }
+
+    /// `count` random bytes produced as `options` ask, or from the
+    /// operating system when falling back
+    pub async fn random_bytes_with(
+        &self,
+        count: usize,
+        options: &BytesOptions,
+    ) -> Result<RandomBytes, Error> {
+        match self.draw_bytes(count, options).await {
+            // The server's error says more than a failing OS generator's
+            Err(e) if self.os_fallback && fallback::unreachable(&e) => {
+                fallback::os_bytes(count).map_err(|_| e)
+            }
+            result => result,
+        }
+    }
+
+    async fn draw_bytes(&self, count: usize, options: &BytesOptions) -> Result<RandomBytes, Error> {
+        let mut query = vec![("count", count.to_string()), ("format", "hex".to_string())];
+        query.extend(options.query());
+        if self.verifier.is_some() {
+            query.push(("sign", "true".to_string()));
+        }
+
+        let data: BytesData = self.get("random/bytes", &query, Reply::data).await?;
+        let bytes = hex::decode(&data.bytes).map_err(|e| Error::Decode(format!("bytes: {}", e)))?;
+        let verified = match &self.verifier {
+            Some(verifier) => Some(verifier.verify(self, &bytes, data.signature).await?),
+            None => None,
+        };
+        Ok(RandomBytes {
+            bytes,
+            correction: data.correction,
+            source: data.source,
+            sha256: data.sha256,
+            device: data.device,
+            verified,
+        })
+    }
+
+    /// `count` random integers between `min` and `max` inclusive
+    pub async fn random_integers(&self, min: i64, max: i64, count: usize) -> Result<RandomIntegers, Error> {
+        self.random_integers_with(min, max, count, &BytesOptions::default()).await
+    }
+
+    /// `count` random integers between `min` and `max` inclusive, drawn
+    /// as `options` ask
+    pub async fn random_integers_with(
+        &self,
+        min: i64,
+        max: i64,
+        count: usize,
+        options: &BytesOptions,
+    ) -> Result<RandomIntegers, Error> {
+        let mut query = vec![
+            ("min", min.to_string()),
+            ("max", max.to_string()),
+            ("count", count.to_string()),
+        ];
+        query.extend(options.query());
+        self.get("random/int", &query, Reply::data).await
+    }
+
+    /// `count` uniform floats in `[0, 1)`, from 53 random bits each
+    ///
+    /// The server has no float endpoint; they are derived from
+    /// `random_bytes` here.
+    pub async fn random_floats(&self, count: usize) -> Result<Vec<f64>, Error> {
+        Ok(api::floats(&self.random_bytes(count * 8).await?.bytes))
+    }
+
+    /// Version 4 UUID from 16 random bytes, derived here like
+    /// [`random_floats`](Self::random_floats)
+    pub async fn random_uuid(&self) -> Result<Uuid, Error> {
+        api::uuid(self.random_bytes(16).await?.bytes)
+    }
+
+    /// Server health, also when it reports itself unhealthy or starting
+    pub async fn health(&self) -> Result<Health, Error> {
+        self.get("health", &[], |reply| match reply.status {
+            StatusCode::OK | StatusCode::SERVICE_UNAVAILABLE => reply.json(),
+            _ => Err(reply.error()),
+        })
+        .await
+    }
+
+    /// Version and build of the server
+    pub async fn version(&self) -> Result<Version, Error> {
+        self.get("version", &[], |reply| match reply.status.is_success() {
+            true => reply.json(),
+            false => Err(reply.error()),
+        })
+        .await
+    }
+
+    /// Send a GET to `path` and `parse` the reply, retrying as the retry
+    /// policy allows
+    async fn get<T>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        parse: impl Fn(&Reply) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let url = self.base_url.join(path).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        self.retrying(|| async {
+            parse(&Reply::read(self.send(Method::GET, url.clone(), query).await?).await?)
+        })
+        .await
+    }
+
+    /// Send a POST to `path` and `parse` the reply, without retrying: the
+    /// server may have acted on a request whose reply was lost
+    async fn post<T>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        parse: impl Fn(&Reply) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let url = self.base_url.join(path).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        parse(&Reply::read(self.send(Method::POST, url, query).await?).await?)
+    }
+
+    /// Send a GET to `path` and return a successful response unread,
+    /// retrying as the retry policy allows
+    async fn send_streaming(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response, Error> {
+        let url = self.base_url.join(path).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        self.retrying(|| async {
+            let response = self.send(Method::GET, url.clone(), query).await?;
+            if !response.status().is_success() {
+                return Err(Reply::read(response).await?.error());
+            }
+            Ok(response)
+        })
+        .await
+    }
+
+    async fn retrying<T, F>(&self, attempt: impl Fn() -> F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(e) => match self.retry.delay(retries, &e) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(e),
+                },
+                ok => return ok,
+            }
+            retries += 1;
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        query: &[(&str, String)],
+    ) -> Result<reqwest::Response, Error> {
+        Ok(self.http.request(method, url).headers(self.headers.clone()).query(query).send().await?)
+    }
+}
+
+/// A response as received
+struct Reply {
+    status: StatusCode,
+    /// `Retry-After` in seconds; the server never sends an HTTP date
+    retry_after: Option<Duration>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    async fn read(response: reqwest::Response) -> Result<Self, Error> {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        let body = response.bytes().await?.to_vec();
+        Ok(Self {
+            status,
+            retry_after,
+            body,
+        })
+    }
+
+    fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_slice(&self.body).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    /// Body of a successful response that is not JSON
+    fn text(&self) -> Result<String, Error> {
+        if !self.status.is_success() {
+            return Err(self.error());
+        }
+        String::from_utf8(self.body.clone()).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    /// Body of a successful response that is JSON without the envelope
+    fn plain<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        if !self.status.is_success() {
+            return Err(self.error());
+        }
+        self.json()
+    }
+
+    /// Data of an enveloped response
+    fn data<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        if !self.status.is_success() {
+            return Err(self.error());
+        }
+        let response: ApiResponse<T> = self.json()?;
+        match response.data {
+            Some(data) if response.success => Ok(data),
+            _ => Err(Error::Api {
+                status: self.status.as_u16(),
+                message: response.error.unwrap_or_else(|| "Request failed".to_string()),
+                retry_after: self.retry_after,
+            }),
+        }
+    }
+
+    /// Error for a non-success status, with the server's message when it
+    /// sent one
+    fn error(&self) -> Error {
+        let message = serde_json::from_slice::<ApiResponse<serde_json::Value>>(&self.body)
+            .ok()
+            .and_then(|response| response.error)
+            .unwrap_or_else(|| self.status.canonical_reason().unwrap_or("Request failed").to_string());
+        Error::Api {
+            status: self.status.as_u16(),
+            message,
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sha2::Digest;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Base URL of a server sending `replies` as `(status, headers, body)`,
+    /// one per connection
+    pub(crate) async fn serve(replies: &[(&str, &str, &str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let replies: Vec<_> = replies
+            .iter()
+            .map(|(status, headers, body)| {
+                format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{}\
+                     connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                )
+            })
+            .collect();
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await.unwrap();
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/api/v1", address)
+    }
+
+    const STARTING: &str = r#"{"success":false,"data":null,"error":"Starting up"}"#;
+
+    #[tokio::test]
+    async fn decodes_random_bytes() {
+        let body = r#"{"success":true,"data":{"bytes":"00ff","count":2,"format":"hex","correction":"none",
+            "source":"conditioned","sha256":"abc"},"error":null}"#;
+        let client = Client::new(&serve(&[("200 OK", "", body)]).await).unwrap();
+        let bytes = client.random_bytes(2).await.unwrap();
+        assert_eq!(bytes.bytes, [0x00, 0xff]);
+        assert_eq!(bytes.source, Source::Conditioned);
+        assert_eq!(bytes.device, None);
+    }
+
+    #[tokio::test]
+    async fn reports_api_errors_with_their_status() {
+        let base_url = serve(&[("503 Service Unavailable", "retry-after: 1\r\n", STARTING)]).await;
+        let client = Client::builder(&base_url).retry(RetryPolicy::never()).build().unwrap();
+        let error = client.random_integers(1, 6, 1).await.unwrap_err();
+        assert!(matches!(&error, Error::Api { status: 503, message, .. } if message == "Starting up"));
+        assert_eq!(error.status(), Some(503));
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(1)));
+
+        let body = r#"{"success":false,"data":null,"error":"Count must be between 1 and 65536"}"#;
+        let client = Client::new(&serve(&[("200 OK", "", body)]).await).unwrap();
+        let error = client.random_bytes(0).await.unwrap_err();
+        assert!(matches!(error, Error::Api { status: 200, .. }));
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_server_answers() {
+        let integers = r#"{"success":true,"data":{"integers":[4],"min":1,"max":6,"count":1,
+            "source":"conditioned"},"error":null}"#;
+        let base_url = serve(&[
+            ("503 Service Unavailable", "retry-after: 0\r\n", STARTING),
+            ("429 Too Many Requests", "retry-after: 0\r\n", "{}"),
+            ("200 OK", "", integers),
+        ])
+        .await;
+        let client = Client::new(&base_url).unwrap();
+        assert_eq!(client.random_integers(1, 6, 1).await.unwrap().integers, [4]);
+    }
+
+    /// Server routes and the methods binding them, without the index
+    const BINDINGS: &[(&str, &str)] = &[
+        ("/health", "health"),
+        ("/version", "version"),
+        ("/random/bytes", "random_bytes, stream_bytes"),
+        ("/random/int", "random_integers"),
+        ("/device/info", "device_info"),
+        ("/devices", "devices"),
+        ("/tests/sp800-22", "sp800_22"),
+        ("/entropy/estimate", "estimate_entropy"),
+        ("/entropy/audit", "audit_trail"),
+        ("/stats", "stats"),
+        ("/metrics", "metrics"),
+        ("/commitments", "commit"),
+        ("/commitments/:id", "commitment"),
+        ("/commitments/:id/reveal", "reveal"),
+        ("/vrf", "vrf_prove"),
+        ("/vrf/verify", "vrf_verify"),
+        ("/pubkey", "public_keys"),
+        ("/beacon/info", "beacon_info"),
+        ("/beacon/public/latest", "beacon_latest"),
+        ("/beacon/public/:round", "beacon_round"),
+    ];
+
+    /// Router modules whose routes are not served under `/api/v1`
+    const UNBOUND_MODULES: &[&str] = &["admin"];
+
+    /// Routes of the server's router module `module` below `prefix`,
+    /// following the modules it nests or merges, with every module visited
+    /// added to `seen`
+    fn server_routes(
+        api: &std::path::Path,
+        module: &str,
+        prefix: &str,
+        routes: &mut std::collections::BTreeSet<String>,
+        seen: &mut std::collections::BTreeSet<String>,
+    ) {
+        seen.insert(module.to_string());
+        let source = std::fs::read_to_string(api.join(format!("{}.rs", module))).unwrap();
+        let source = source.split("#[cfg(test)]").next().unwrap();
+        for route in source.split(".route(\"").skip(1) {
+            let path = format!("{}{}", prefix, &route[..route.find('"').unwrap()]);
+            routes.insert(path.trim_end_matches('/').to_string());
+        }
+        // Only routers of other modules are followed: nesting one of the
+        // module's own routers, as the beacon does by chain hash, adds
+        // aliases of routes already listed
+        for (call, nests) in [(".nest(\"", true), (".merge(", false)] {
+            for rest in source.split(call).skip(1) {
+                let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                let Some(end) = line.find("::routes()") else {
+                    continue;
+                };
+                let start = line[..end].rfind(|c: char| !c.is_alphanumeric() && c != '_').map_or(0, |i| i + 1);
+                let prefix = match nests {
+                    true => format!("{}{}", prefix, &line[..line.find('"').unwrap()]),
+                    false => prefix.to_string(),
+                };
+                server_routes(api, &line[start..end], &prefix, routes, seen);
+            }
+        }
+    }
+
+    #[test]
+    fn every_server_route_has_a_binding() {
+        let api = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../rust-server/src/api");
+        // Only checkable next to the server sources
+        if !api.exists() {
+            return;
+        }
+        let mut routes = std::collections::BTreeSet::new();
+        let mut seen = std::collections::BTreeSet::new();
+        server_routes(&api, "mod", "", &mut routes, &mut seen);
+        routes.remove("");
+
+        // A module with routes the walk did not reach is mounted some
+        // other way, which the walk would need to learn
+        for entry in std::fs::read_dir(&api).unwrap() {
+            let path = entry.unwrap().path();
+            let module = path.file_stem().unwrap().to_str().unwrap().to_string();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let has_routes = source.split("#[cfg(test)]").next().unwrap().contains(".route(\"");
+            assert!(
+                !has_routes || seen.contains(&module) || UNBOUND_MODULES.contains(&module.as_str()),
+                "routes of api/{}.rs are not reached from the API router",
+                module
+            );
+        }
+
+        let bound = BINDINGS.iter().map(|(path, _)| path.to_string()).collect();
+        assert_eq!(routes, bound, "server routes and client bindings differ");
+    }
+
+    #[tokio::test]
+    async fn binds_commitments_and_metrics() {
+        let digest = hex::encode(sha2::Sha256::digest([0x00, 0x2a]));
+        let commitment = format!(
+            r#"{{"success":true,"data":{{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","commitment":"{}",
+            "count":1,"committed_at":1,"revealed_at":2,"value":"2a","nonce":"00"}},"error":null}}"#,
+            digest
+        );
+        let base_url = serve(&[("200 OK", "", &commitment), ("200 OK", "", "quantis_up 1\n")]).await;
+        let client = Client::new(&base_url).unwrap();
+        let revealed = client.reveal(Uuid::nil()).await.unwrap();
+        assert!(revealed.verify());
+        assert!(!Commitment { value: Some("2b".to_string()), ..revealed }.verify());
+        assert_eq!(client.metrics().await.unwrap(), "quantis_up 1\n");
+    }
+
+    #[tokio::test]
+    async fn decodes_unenveloped_beacon_rounds() {
+        let round = r#"{"round":3,"randomness":"ab","signature":"cd"}"#;
+        let too_early = r#"{"success":false,"data":null,"error":"Round 9 is not yet published"}"#;
+        let base_url = serve(&[("200 OK", "", round), ("425 Too Early", "retry-after: 4\r\n", too_early)]).await;
+        let client = Client::builder(&base_url).retry(RetryPolicy::never()).build().unwrap();
+        assert_eq!(client.beacon_latest().await.unwrap().round, 3);
+        let error = client.beacon_round(9).await.unwrap_err();
+        assert_eq!(error.status(), Some(425));
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(4)));
+    }
+
+    #[tokio::test]
+    async fn derives_floats_and_uuids_from_bytes() {
+        let bytes = |hex: &str| {
+            format!(
+                r#"{{"success":true,"data":{{"bytes":"{}","correction":"none","source":"conditioned",
+                "sha256":""}},"error":null}}"#,
+                hex
+            )
+        };
+        let (floats, uuid) = (bytes(&"ff".repeat(16)), bytes(&"00".repeat(16)));
+        let client = Client::new(&serve(&[("200 OK", "", &floats), ("200 OK", "", &uuid)]).await).unwrap();
+        let floats = client.random_floats(2).await.unwrap();
+        assert_eq!(floats, [1.0 - f64::EPSILON / 2.0; 2]);
+        let uuid = client.random_uuid().await.unwrap();
+        assert_eq!(uuid.get_version_num(), 4);
+    }
+
+    #[tokio::test]
+    async fn reports_undecodable_responses() {
+        let client = Client::new(&serve(&[("200 OK", "", "<html></html>")]).await).unwrap();
+        assert!(matches!(client.version().await, Err(Error::Decode(_))));
+
+        assert!(matches!(Client::new("localhost:8080"), Err(Error::InvalidUrl(_))));
+        let refused = Client::builder("http://127.0.0.1:1/api/v1")
+            .retry(RetryPolicy::never())
+            .build()
+            .unwrap();
+        let error = refused.health().await.unwrap_err();
+        assert!(matches!(error, Error::Transport(_)));
+        assert!(error.is_retryable());
+    }
+}
@@ -0,0 +1,218 @@
+//! In-memory stand-in for a server, for testing code built on the client
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::VecDeque,
+    future::{ready, Future},
+    sync::Mutex,
+};
+
+use crate::{BytesOptions, EntropyApi, Error, Health, RandomBytes, RandomIntegers, Source, Version};
+
+/// [`EntropyApi`] answering from memory, with the same bytes for the
+/// same seed
+///
+/// Bytes are SHA-256 blocks over the seed and a counter; they are
+/// predictable and only fit for tests. Errors queued with
+/// [`fail_next`](Self::fail_next) are returned by the next requests.
+#[derive(Debug)]
+pub struct MockClient {
+    seed: u64,
+    state: Mutex<MockState>,
+    health: Health,
+    version: Version,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// Blocks generated so far
+    counter: u64,
+    /// Generated bytes not yet served
+    pending: VecDeque<u8>,
+    failures: VecDeque<Error>,
+    requests: usize,
+}
+
+impl MockClient {
+    /// Mock serving the byte stream of `seed`, healthy
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: Mutex::default(),
+            health: Health {
+                status: "healthy".to_string(),
+                device: "connected".to_string(),
+                buffer_available: 1024 * 1024,
+                reason: None,
+            },
+            version: Version {
+                version: "mock".to_string(),
+                git_commit: "unknown".to_string(),
+                build_timestamp: None,
+                features: Vec::new(),
+                buffer_backend: "builtin".to_string(),
+                device_backend: Some("mock".to_string()),
+            },
+        }
+    }
+
+    /// Answer `health` with `health`
+    pub fn with_health(mut self, health: Health) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Answer `version` with `version`
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Fail the next request with `error`, after any queued before it
+    pub fn fail_next(&self, error: Error) {
+        self.state.lock().unwrap().failures.push_back(error);
+    }
+
+    /// Requests answered so far, including failed ones
+    pub fn requests(&self) -> usize {
+        self.state.lock().unwrap().requests
+    }
+
+    /// Count a request, failing it when a failure is queued
+    fn request(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        state.failures.pop_front().map_or(Ok(()), Err)
+    }
+
+    fn next_bytes(&self, count: usize) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        while state.pending.len() < count {
+            let block = Sha256::new()
+                .chain_update(self.seed.to_le_bytes())
+                .chain_update(state.counter.to_le_bytes())
+                .finalize();
+            state.counter += 1;
+            state.pending.extend(block);
+        }
+        state.pending.drain(..count).collect()
+    }
+
+    fn bytes(&self, count: usize, options: &BytesOptions) -> Result<RandomBytes, Error> {
+        self.request()?;
+        let bytes = self.next_bytes(count);
+        Ok(RandomBytes {
+            sha256: hex::encode(Sha256::digest(&bytes)),
+            bytes,
+            correction: options.correction.clone().unwrap_or_else(|| "none".to_string()),
+            source: options.source.unwrap_or_default(),
+            device: options.device.clone(),
+            verified: None,
+        })
+    }
+
+    fn integers(&self, min: i64, max: i64, count: usize) -> Result<RandomIntegers, Error> {
+        self.request()?;
+        if min >= max || count == 0 {
+            let message = match count {
+                0 => "count must be at least 1",
+                _ => "min must be less than max",
+            };
+            return Err(Error::Api {
+                status: 200,
+                message: message.to_string(),
+                retry_after: None,
+            });
+        }
+        // Rejection sampling keeps every value equally likely
+        let range = max.abs_diff(min) as u128 + 1;
+        let limit = (1u128 << 64) - (1u128 << 64) % range;
+        let mut integers = Vec::with_capacity(count);
+        while integers.len() < count {
+            let value = u64::from_le_bytes(self.next_bytes(8).try_into().expect("8 bytes")) as u128;
+            if value < limit {
+                integers.push(min.wrapping_add((value % range) as i64));
+            }
+        }
+        Ok(RandomIntegers {
+            integers,
+            min,
+            max,
+            source: Source::default(),
+            device: None,
+        })
+    }
+}
+
+impl EntropyApi for MockClient {
+    fn random_bytes(&self, count: usize) -> impl Future<Output = Result<RandomBytes, Error>> + Send {
+        ready(self.bytes(count, &BytesOptions::default()))
+    }
+
+    fn random_bytes_with(
+        &self,
+        count: usize,
+        options: &BytesOptions,
+    ) -> impl Future<Output = Result<RandomBytes, Error>> + Send {
+        ready(self.bytes(count, options))
+    }
+
+    fn random_integers(
+        &self,
+        min: i64,
+        max: i64,
+        count: usize,
+    ) -> impl Future<Output = Result<RandomIntegers, Error>> + Send {
+        ready(self.integers(min, max, count))
+    }
+
+    fn health(&self) -> impl Future<Output = Result<Health, Error>> + Send {
+        ready(self.request().map(|_| self.health.clone()))
+    }
+
+    fn version(&self) -> impl Future<Output = Result<Version, Error>> + Send {
+        ready(self.request().map(|_| self.version.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Code under test only knows the trait
+    async fn roll<A: EntropyApi>(api: &A) -> Result<i64, Error> {
+        Ok(api.random_integers(1, 6, 2).await?.integers.iter().sum())
+    }
+
+    #[tokio::test]
+    async fn serves_the_same_draws_for_the_same_seed() {
+        let (first, second) = (MockClient::new(7), MockClient::new(7));
+        let bytes = first.random_bytes(40).await.unwrap();
+        assert_eq!(bytes.bytes, second.random_bytes(40).await.unwrap().bytes);
+        assert_ne!(bytes.bytes, MockClient::new(8).random_bytes(40).await.unwrap().bytes);
+
+        let total = roll(&first).await.unwrap();
+        assert!((2..=12).contains(&total));
+        assert_eq!(total, roll(&second).await.unwrap());
+        let extremes = first.random_integers(i64::MIN, i64::MAX, 4).await.unwrap();
+        assert_eq!(extremes.integers.len(), 4);
+        assert!(first.random_floats(3).await.unwrap().iter().all(|f| (0.0..1.0).contains(f)));
+        assert_eq!(first.random_uuid().await.unwrap().get_version_num(), 4);
+    }
+
+    #[tokio::test]
+    async fn returns_queued_failures_in_order() {
+        let mock = MockClient::new(0);
+        mock.fail_next(Error::Decode("first".to_string()));
+        mock.fail_next(Error::Api {
+            status: 503,
+            message: "Starting up".to_string(),
+            retry_after: None,
+        });
+        assert!(matches!(mock.random_bytes(1).await, Err(Error::Decode(_))));
+        assert!(matches!(roll(&mock).await, Err(Error::Api { status: 503, .. })));
+        assert!(mock.health().await.unwrap().is_healthy());
+        assert!(matches!(mock.random_integers(6, 1, 1).await, Err(Error::Api { status: 200, .. })));
+        assert_eq!(mock.requests(), 4);
+    }
+}
@@ -0,0 +1,150 @@
+//! Serving small draws from bytes fetched ahead
+//!
+//! With prefetching on, a background task keeps up to the configured number
+//! of bytes fetched with the server's default processing. Draws of up to
+//! [`MAX_PREFETCHED_DRAW`] bytes are served from them without a round trip
+//! while enough are buffered, and from the server otherwise. The task tops
+//! the buffer up once it has drained below half.
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::Notify;
+
+use crate::{Client, RandomBytes, Source};
+
+/// Largest draw served from prefetched bytes
+pub const MAX_PREFETCHED_DRAW: usize = 1024;
+
+/// Largest single fetch, the server's default `/random/bytes` limit
+const MAX_FETCH: usize = 65536;
+
+#[derive(Debug)]
+struct Buffered {
+    bytes: VecDeque<u8>,
+    /// How the buffered bytes were produced
+    correction: String,
+    source: Source,
+    refilling: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct Prefetcher {
+    size: usize,
+    buffered: Mutex<Buffered>,
+    /// Wakes the refill task, also when the prefetcher is dropped
+    wake: Arc<Notify>,
+}
+
+impl Prefetcher {
+    pub(crate) fn new(size: usize) -> Self {
+        Self {
+            size,
+            buffered: Mutex::new(Buffered {
+                bytes: VecDeque::with_capacity(size),
+                correction: String::new(),
+                source: Source::default(),
+                refilling: false,
+            }),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// `count` prefetched bytes, or None if the draw must go to the server
+    ///
+    /// Starts the refill task on first use, from `client`'s runtime.
+    pub(crate) fn take(self: &Arc<Self>, client: &Client, count: usize) -> Option<RandomBytes> {
+        if count > MAX_PREFETCHED_DRAW {
+            return None;
+        }
+        let mut buffered = self.buffered.lock().unwrap();
+        if buffered.bytes.len() - count.min(buffered.bytes.len()) < self.size / 2 {
+            if !buffered.refilling {
+                buffered.refilling = true;
+                tokio::spawn(refill(Arc::downgrade(self), self.wake.clone(), client.without_prefetch()));
+            }
+            self.wake.notify_one();
+        }
+        if buffered.bytes.len() < count {
+            return None;
+        }
+
+        let bytes: Vec<u8> = buffered.bytes.drain(..count).collect();
+        Some(RandomBytes {
+            sha256: hex::encode(Sha256::digest(&bytes)),
+            bytes,
+            correction: buffered.correction.clone(),
+            source: buffered.source,
+            device: None,
+            verified: None,
+        })
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.wake.notify_one();
+    }
+}
+
+/// Top the buffer up whenever woken, until the prefetcher is dropped
+///
+/// A failed fetch is given up after the client's retries; the next draw
+/// wakes the task again.
+async fn refill(prefetcher: Weak<Prefetcher>, wake: Arc<Notify>, client: Client) {
+    loop {
+        wake.notified().await;
+        let Some(missing) = prefetcher.upgrade().map(|prefetcher| {
+            let buffered = prefetcher.buffered.lock().unwrap();
+            prefetcher.size.saturating_sub(buffered.bytes.len()).min(MAX_FETCH)
+        }) else {
+            return;
+        };
+        if missing == 0 {
+            continue;
+        }
+
+        let Ok(fetched) = client.random_bytes(missing).await else {
+            continue;
+        };
+        let Some(prefetcher) = prefetcher.upgrade() else {
+            return;
+        };
+        let mut buffered = prefetcher.buffered.lock().unwrap();
+        let room = prefetcher.size.saturating_sub(buffered.bytes.len());
+        buffered.bytes.extend(fetched.bytes.into_iter().take(room));
+        buffered.correction = fetched.correction;
+        buffered.source = fetched.source;
+        // Buffers larger than one fetch take several
+        if buffered.bytes.len() < prefetcher.size {
+            wake.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::serve, RetryPolicy};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serves_small_draws_from_prefetched_bytes() {
+        let body = r#"{"success":true,"data":{"bytes":"0102030405060708","count":8,"format":"hex",
+            "correction":"sha3","source":"conditioned","sha256":"from-server"},"error":null}"#;
+        let base_url = serve(&[("200 OK", "", body); 3]).await;
+        let client = Client::builder(&base_url).retry(RetryPolicy::never()).prefetch(8).build().unwrap();
+
+        // Nothing is buffered until the first draw starts the refill
+        let direct = client.random_bytes(4).await.unwrap();
+        assert_eq!(direct.sha256, "from-server");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let local = client.random_bytes(4).await.unwrap();
+        assert_eq!(local.bytes, [1, 2, 3, 4]);
+        assert_eq!(local.correction, "sha3");
+        assert_eq!(local.sha256, hex::encode(Sha256::digest([1, 2, 3, 4])));
+    }
+}
@@ -0,0 +1,87 @@
+//! Retrying requests the server could not serve yet
+//!
+//! Rate-limited (429) and unavailable (503) responses and timed-out or
+//! refused connections are retried with exponential backoff and full
+//! jitter, so clients that failed together do not retry together. A
+//! `Retry-After` from the server takes the place of the backoff.
+
+use std::time::Duration;
+
+use crate::Error;
+
+/// When and how often failed requests are retried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 to never retry
+    pub max_retries: u32,
+    /// Longest delay before the first retry, doubled for each further one
+    pub initial_backoff: Duration,
+    /// Longest delay before any retry, also capping `Retry-After`
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Fail on the first error
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry `attempt` (from 0) after `error`, None to give up
+    pub(crate) fn delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_retries || !error.is_retryable() {
+            return None;
+        }
+        if let Some(retry_after) = error.retry_after() {
+            return Some(retry_after.min(self.max_backoff));
+        }
+        let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_backoff);
+        Some(backoff.mul_f64(fastrand::f64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unavailable(retry_after: Option<u64>) -> Error {
+        Error::Api {
+            status: 503,
+            message: "Starting up".to_string(),
+            retry_after: retry_after.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_limits() {
+        let policy = RetryPolicy::default();
+        for attempt in 0..3 {
+            let delay = policy.delay(attempt, &unavailable(None)).unwrap();
+            assert!(delay <= policy.initial_backoff * 2u32.pow(attempt));
+        }
+        assert_eq!(policy.delay(3, &unavailable(None)), None);
+
+        assert_eq!(policy.delay(0, &unavailable(Some(2))), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(0, &unavailable(Some(60))), Some(policy.max_backoff));
+
+        let refused = Error::Api {
+            status: 400,
+            message: "Count must be between 1 and 65536".to_string(),
+            retry_after: None,
+        };
+        assert_eq!(policy.delay(0, &refused), None);
+        assert_eq!(RetryPolicy::never().delay(0, &unavailable(None)), None);
+    }
+}
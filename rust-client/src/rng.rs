@@ -0,0 +1,136 @@
+//! `rand` integration
+//!
+//! [`QuantumRng`] implements `RngCore` and `CryptoRng` over bytes fetched
+//! from the server, so it can stand in for `OsRng` in `rand`-based code. A
+//! background thread keeps a few chunks fetched ahead; draws are served from
+//! memory and block only while the thread is behind.
+
+use rand_core::{impls, CryptoRng, RngCore};
+use std::{
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+use crate::Client;
+
+/// Default bytes fetched per request
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// Chunks fetched ahead of the draws
+const CHUNKS_AHEAD: usize = 2;
+
+/// Random number generator drawing from the server
+///
+/// Failed fetches are retried as the client's retry policy says; an error
+/// that remains is returned by `try_fill_bytes` and makes the other methods
+/// panic, like other `RngCore` implementations.
+pub struct QuantumRng {
+    chunks: Receiver<Result<Vec<u8>, rand_core::Error>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl QuantumRng {
+    /// Draw from `client` in requests of `chunk_size` bytes
+    pub fn new(client: Client, chunk_size: usize) -> Self {
+        let (sender, chunks) = mpsc::sync_channel(CHUNKS_AHEAD);
+        thread::Builder::new()
+            .name("quantum-rng".to_string())
+            .spawn(move || fetch(client, chunk_size.max(1), sender))
+            .expect("spawn the quantum-rng thread");
+        Self {
+            chunks,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+/// Fetch chunks until the generator is dropped
+fn fetch(client: Client, chunk_size: usize, chunks: SyncSender<Result<Vec<u8>, rand_core::Error>>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let _ = chunks.send(Err(rand_core::Error::new(e)));
+            return;
+        }
+    };
+    loop {
+        let chunk = runtime
+            .block_on(client.random_bytes(chunk_size))
+            .map(|random| random.bytes)
+            .map_err(rand_core::Error::new);
+        if chunks.send(chunk).is_err() {
+            return;
+        }
+    }
+}
+
+impl RngCore for QuantumRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = self.try_fill_bytes(dest) {
+            panic!("QuantumRng failed to fetch entropy: {}", e);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.position == self.chunk.len() {
+                self.chunk = self.chunks.recv().map_err(|_| {
+                    rand_core::Error::new(std::io::Error::other("quantum-rng thread stopped"))
+                })??;
+                self.position = 0;
+            }
+            let take = (dest.len() - filled).min(self.chunk.len() - self.position);
+            dest[filled..filled + take].copy_from_slice(&self.chunk[self.position..self.position + take]);
+            // Served bytes are not kept around
+            self.chunk[self.position..self.position + take].fill(0);
+            self.position += take;
+            filled += take;
+        }
+        Ok(())
+    }
+}
+
+impl CryptoRng for QuantumRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::serve, RetryPolicy};
+    use rand::Rng;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn draws_span_fetched_chunks() {
+        let chunk = r#"{"success":true,"data":{"bytes":"0102030405060708","count":8,"format":"hex",
+            "correction":"none","source":"conditioned","sha256":"abc"},"error":null}"#;
+        let base_url = serve(&[("200 OK", "", chunk); 4]).await;
+        let client = Client::builder(&base_url).retry(RetryPolicy::never()).build().unwrap();
+        let mut rng = QuantumRng::new(client, 8);
+
+        let mut bytes = [0; 12];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4]);
+        assert_eq!(rng.next_u32(), u32::from_le_bytes([5, 6, 7, 8]));
+        assert!((1..=8).contains(&rng.gen_range(1..=8)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_failed_fetches() {
+        let client = Client::builder("http://127.0.0.1:1/api/v1")
+            .retry(RetryPolicy::never())
+            .build()
+            .unwrap();
+        let mut rng = QuantumRng::new(client, 8);
+        assert!(rng.try_fill_bytes(&mut [0; 4]).is_err());
+    }
+}
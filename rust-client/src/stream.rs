@@ -0,0 +1,66 @@
+//! Streaming entropy to consumers that keep reading
+//!
+//! [`Client::stream_bytes`] reads one large `/random/bytes` response as it
+//! arrives; the server streams counts above its `max_bytes` in pieces.
+//! [`Client::continuous_bytes`] repeats draws for as long as it is polled.
+//! Both only read when polled, so a slow consumer holds the server back
+//! instead of piling bytes up in memory.
+
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use std::pin::Pin;
+
+use crate::{Client, Error};
+
+/// Bytes as they arrive, ending early on an error
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
+
+impl Client {
+    /// `count` random bytes, yielded as they arrive
+    ///
+    /// Counts the server does not stream must fit in one response.
+    pub async fn stream_bytes(&self, count: usize) -> Result<ByteStream, Error> {
+        let query = [("count", count.to_string()), ("format", "binary".to_string())];
+        let response = self.send_streaming("random/bytes", &query).await?;
+        Ok(response.bytes_stream().map_err(Error::Transport).boxed())
+    }
+
+    /// Endless draws of `chunk_size` random bytes, each fetched when the
+    /// previous one has been taken
+    pub fn continuous_bytes(&self, chunk_size: usize) -> ByteStream {
+        stream::unfold(self.clone(), move |client| async move {
+            let drawn = client.random_bytes(chunk_size).await.map(|random| Bytes::from(random.bytes));
+            Some((drawn, client))
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::serve, RetryPolicy};
+
+    #[tokio::test]
+    async fn streams_binary_responses() {
+        let base_url = serve(&[("200 OK", "", "quantum")]).await;
+        let client = Client::new(&base_url).unwrap();
+        let received: Vec<Bytes> = client.stream_bytes(7).await.unwrap().try_collect().await.unwrap();
+        assert_eq!(received.concat(), b"quantum");
+
+        let refused = r#"{"success":false,"data":null,"error":"Count must be between 1 and 65536"}"#;
+        let base_url = serve(&[("400 Bad Request", "", refused)]).await;
+        let client = Client::builder(&base_url).retry(RetryPolicy::never()).build().unwrap();
+        assert!(matches!(client.stream_bytes(1 << 30).await, Err(Error::Api { status: 400, .. })));
+    }
+
+    #[tokio::test]
+    async fn continuous_draws_follow_the_reader() {
+        let body = r#"{"success":true,"data":{"bytes":"0102","count":2,"format":"hex","correction":"none",
+            "source":"conditioned","sha256":"abc"},"error":null}"#;
+        let base_url = serve(&[("200 OK", "", body); 2]).await;
+        let client = Client::new(&base_url).unwrap();
+        let drawn: Vec<Bytes> = client.continuous_bytes(2).take(2).try_collect().await.unwrap();
+        assert_eq!(drawn, [Bytes::from_static(&[1, 2]), Bytes::from_static(&[1, 2])]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Typed API responses
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::verify::{SignatureData, Verified};
+
+/// Envelope around every `/random` response
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub error: Option<String>,
+}
+
+/// Where requested bytes come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Device output without post-processing
+    Raw,
+    /// Device output through the correction pipeline
+    #[default]
+    Conditioned,
+    /// Device-seeded CTR_DRBG output
+    Drbg,
+    /// Operating system randomness the client fell back to; not quantum,
+    /// and not a source the server can be asked for
+    Os,
+}
+
+impl Source {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Conditioned => "conditioned",
+            Self::Drbg => "drbg",
+            Self::Os => "os",
+        }
+    }
+}
+
+/// `/random/bytes` output as sent by the server
+#[derive(Debug, Deserialize)]
+pub(crate) struct BytesData {
+    pub bytes: String,
+    pub correction: String,
+    pub source: Source,
+    pub sha256: String,
+    pub device: Option<String>,
+    pub signature: Option<SignatureData>,
+}
+
+/// Random bytes with how they were produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RandomBytes {
+    pub bytes: Vec<u8>,
+    /// Post-processing pipeline applied
+    pub correction: String,
+    pub source: Source,
+    /// Hex SHA-256 of `bytes`, as computed by the server
+    pub sha256: String,
+    /// Serial of the device read, when one was pinned
+    pub device: Option<String>,
+    /// Checked server signature, when the client verifies signatures
+    pub verified: Option<Verified>,
+}
+
+/// Random integers with how they were produced
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RandomIntegers {
+    pub integers: Vec<i64>,
+    pub min: i64,
+    pub max: i64,
+    pub source: Source,
+    pub device: Option<String>,
+}
+
+/// Summary of `/health`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Health {
+    /// `healthy`, `starting` or `unhealthy`
+    pub status: String,
+    /// `connected` or `unavailable`
+    pub device: String,
+    pub buffer_available: usize,
+    /// Why the server is unhealthy
+    pub reason: Option<String>,
+}
+
+impl Health {
+    pub fn is_healthy(&self) -> bool {
+        self.status == "healthy"
+    }
+}
+
+/// What the server is running, from `/version`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Version {
+    pub version: String,
+    pub git_commit: String,
+    /// Unix time of the build
+    pub build_timestamp: Option<u64>,
+    pub features: Vec<String>,
+    pub buffer_backend: String,
+    /// Backend of the active device, None without one
+    pub device_backend: Option<String>,
+}
+
+/// Identity of a device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DeviceInfo {
+    pub product: String,
+    pub serial: String,
+    pub version: String,
+}
+
+/// The active device and buffer, from `/device/info`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ActiveDevice {
+    pub device: DeviceInfo,
+    pub buffer_size: usize,
+    pub buffer_available: usize,
+}
+
+/// A device known to the server, from `/devices`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Device {
+    pub index: usize,
+    pub serial: String,
+    pub product: String,
+    pub version: String,
+    /// `healthy`, `failed` or `disconnected`
+    pub state: String,
+    /// `active` or `standby`
+    pub role: String,
+    pub consecutive_errors: u32,
+}
+
+/// Outcome of one SP 800-22 test
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TestResult {
+    pub test: String,
+    pub p_value: f64,
+    pub passed: bool,
+}
+
+/// SP 800-22 suite run, from `/tests/sp800-22`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TestReport {
+    /// Sample size in bits
+    pub bits: usize,
+    pub passed: bool,
+    pub results: Vec<TestResult>,
+    pub device: Option<String>,
+}
+
+/// Lowest min-entropy estimate over a sample, from `/entropy/estimate`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EntropyEstimate {
+    /// Estimator giving the lowest estimate
+    pub estimator: String,
+    pub min_entropy_per_bit: f64,
+    /// Unix timestamp of the assessment
+    pub at: u64,
+    pub device: Option<String>,
+}
+
+/// Health test result, from `/entropy/audit`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AuditRecord {
+    /// Unix timestamp of the result
+    pub at: u64,
+    /// Where the test ran, e.g. `startup` or `continuous`
+    pub category: String,
+    pub test: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuditData {
+    pub records: Vec<AuditRecord>,
+}
+
+/// A commit-reveal draw, from `/commitments`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Commitment {
+    pub id: Uuid,
+    /// Hex SHA-256 over the nonce followed by the value
+    pub commitment: String,
+    /// Value length in bytes
+    pub count: usize,
+    /// Unix timestamp of the commitment
+    pub committed_at: u64,
+    pub revealed_at: Option<u64>,
+    /// Hex value, once revealed
+    pub value: Option<String>,
+    /// Hex nonce, once revealed
+    pub nonce: Option<String>,
+}
+
+impl Commitment {
+    /// Whether the revealed value and nonce match the commitment; false
+    /// before the reveal
+    pub fn verify(&self) -> bool {
+        let (Some(value), Some(nonce)) = (&self.value, &self.nonce) else {
+            return false;
+        };
+        let (Ok(value), Ok(nonce)) = (hex::decode(value), hex::decode(nonce)) else {
+            return false;
+        };
+        let digest = Sha256::new().chain_update(nonce).chain_update(value).finalize();
+        hex::encode(digest).eq_ignore_ascii_case(&self.commitment)
+    }
+}
+
+/// VRF proof by the server key, from `/vrf`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VrfProof {
+    pub suite: String,
+    /// Hex public key the proof verifies under
+    pub public_key: String,
+    /// Hex alpha string the proof covers
+    pub alpha: String,
+    pub proof: String,
+    /// Hex VRF output (beta)
+    pub output: String,
+}
+
+/// Outcome of `/vrf/verify`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VrfVerification {
+    pub valid: bool,
+    /// Hex VRF output when the proof is valid
+    pub output: Option<String>,
+}
+
+/// Key signing attested outputs
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SigningKey {
+    pub key_id: String,
+    pub algorithm: String,
+    /// Hex public key
+    pub public_key: String,
+    /// Unix timestamp of the key's creation
+    pub created_at: u64,
+    /// Unix timestamp of the rotation that replaced the key
+    pub retired_at: Option<u64>,
+}
+
+/// Beacon chain parameters in drand's format, from `/beacon/info`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BeaconInfo {
+    /// Hex BLS12-381 public key on G2
+    pub public_key: String,
+    /// Seconds between rounds
+    pub period: u64,
+    /// Unix time of round 1
+    pub genesis_time: u64,
+    /// Hex chain hash
+    pub hash: String,
+    #[serde(rename = "groupHash")]
+    pub group_hash: String,
+    #[serde(rename = "schemeID")]
+    pub scheme_id: String,
+}
+
+/// One beacon round in drand's format
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BeaconRound {
+    pub round: u64,
+    /// Hex SHA-256 of the signature
+    pub randomness: String,
+    /// Hex BLS signature of the round number
+    pub signature: String,
+}
+
+/// Server public keys, from `/pubkey`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PublicKeys {
+    pub suite: String,
+    /// Hex VRF public key
+    pub public_key: String,
+    /// Current and retired signing keys, newest first
+    pub signing_keys: Vec<SigningKey>,
+}
@@ -0,0 +1,68 @@
+//! Commit-reveal, verifiable random function and beacon endpoints
+
+use uuid::Uuid;
+
+use crate::{
+    BeaconInfo, BeaconRound, Client, Commitment, Error, PublicKeys, Reply, VrfProof, VrfVerification,
+};
+
+impl Client {
+    /// Draw `count` bytes and publish only their commitment; needs the
+    /// crypto role
+    ///
+    /// Not retried, so a lost reply cannot leave a second draw behind.
+    pub async fn commit(&self, count: usize) -> Result<Commitment, Error> {
+        self.post("commitments", &[("count", count.to_string())], Reply::data).await
+    }
+
+    /// Reveal the value and nonce of commitment `id`; needs the crypto role
+    pub async fn reveal(&self, id: Uuid) -> Result<Commitment, Error> {
+        self.post(&format!("commitments/{}/reveal", id), &[], Reply::data).await
+    }
+
+    /// Commitment `id`, with its value once revealed
+    pub async fn commitment(&self, id: Uuid) -> Result<Commitment, Error> {
+        self.get(&format!("commitments/{}", id), &[], Reply::data).await
+    }
+
+    /// Prove `alpha` with the server's VRF key; needs the crypto role
+    pub async fn vrf_prove(&self, alpha: &[u8]) -> Result<VrfProof, Error> {
+        self.get("vrf", &[("alpha_hex", hex::encode(alpha))], Reply::data).await
+    }
+
+    /// Check a hex `proof` of `alpha` under the hex `public_key`
+    pub async fn vrf_verify(
+        &self,
+        public_key: &str,
+        alpha: &[u8],
+        proof: &str,
+    ) -> Result<VrfVerification, Error> {
+        let query = [
+            ("public_key", public_key.to_string()),
+            ("alpha_hex", hex::encode(alpha)),
+            ("proof", proof.to_string()),
+        ];
+        self.get("vrf/verify", &query, Reply::data).await
+    }
+
+    /// VRF public key and the keys signing attested outputs
+    pub async fn public_keys(&self) -> Result<PublicKeys, Error> {
+        self.get("pubkey", &[], Reply::data).await
+    }
+
+    /// Parameters of the server's drand-compatible beacon chain
+    pub async fn beacon_info(&self) -> Result<BeaconInfo, Error> {
+        self.get("beacon/info", &[], Reply::plain).await
+    }
+
+    /// Most recent beacon round
+    pub async fn beacon_latest(&self) -> Result<BeaconRound, Error> {
+        self.get("beacon/public/latest", &[], Reply::plain).await
+    }
+
+    /// Beacon round `round`; a round not yet published fails with its
+    /// publication time in `retry_after`
+    pub async fn beacon_round(&self, round: u64) -> Result<BeaconRound, Error> {
+        self.get(&format!("beacon/public/{}", round), &[], Reply::plain).await
+    }
+}
@@ -0,0 +1,160 @@
+//! Checking server signatures on random bytes
+//!
+//! The server signs a context string, the Unix time of signing and the
+//! SHA-256 of the bytes with an Ed25519 key it publishes at `/pubkey`.
+
+use ed25519_dalek::{Signature, Verifier as _, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{Client, Error};
+
+/// Context signed ahead of `/random/bytes` output
+const BYTES_CONTEXT: &[u8] = b"quantis/random-bytes/v1";
+
+/// Signature scheme of the server's keys
+const ALGORITHM: &str = "ed25519";
+
+/// Signature sent along with `/random/bytes` output
+#[derive(Debug, Deserialize)]
+pub(crate) struct SignatureData {
+    pub key_id: String,
+    pub algorithm: String,
+    /// Hex signature
+    pub signature: String,
+    pub signed_at: u64,
+}
+
+/// Marks bytes whose server signature checked out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verified {
+    /// Key that signed the bytes, as listed by `/pubkey`
+    pub key_id: String,
+    /// Unix timestamp of the signature
+    pub signed_at: u64,
+}
+
+/// Signing keys from `/pubkey`, by key id
+#[derive(Debug, Default)]
+pub(crate) struct Verifier {
+    keys: Mutex<HashMap<String, VerifyingKey>>,
+}
+
+impl Verifier {
+    /// Check `signature` over `bytes`, fetching the keys again when it
+    /// names one not seen yet, as after a rotation
+    pub(crate) async fn verify(
+        &self,
+        client: &Client,
+        bytes: &[u8],
+        signature: Option<SignatureData>,
+    ) -> Result<Verified, Error> {
+        let signature = signature.ok_or_else(|| Error::Signature("response is not signed".to_string()))?;
+        if signature.algorithm != ALGORITHM {
+            return Err(Error::Signature(format!("unsupported algorithm {}", signature.algorithm)));
+        }
+        let key = match self.key(&signature.key_id) {
+            Some(key) => key,
+            None => {
+                self.refresh(client).await?;
+                self.key(&signature.key_id)
+                    .ok_or_else(|| Error::Signature(format!("unknown signing key {}", signature.key_id)))?
+            }
+        };
+        let signed = hex::decode(&signature.signature)
+            .ok()
+            .and_then(|signed| <[u8; 64]>::try_from(signed).ok())
+            .ok_or_else(|| Error::Signature("signature is not 64 hex-encoded bytes".to_string()))?;
+
+        let mut message = BYTES_CONTEXT.to_vec();
+        message.extend_from_slice(&signature.signed_at.to_be_bytes());
+        message.extend_from_slice(&Sha256::digest(bytes));
+        key.verify(&message, &Signature::from_bytes(&signed))
+            .map_err(|_| Error::Signature("signature does not match the bytes".to_string()))?;
+        Ok(Verified {
+            key_id: signature.key_id,
+            signed_at: signature.signed_at,
+        })
+    }
+
+    fn key(&self, key_id: &str) -> Option<VerifyingKey> {
+        self.keys.lock().unwrap().get(key_id).copied()
+    }
+
+    async fn refresh(&self, client: &Client) -> Result<(), Error> {
+        let mut keys = HashMap::new();
+        for listed in client.public_keys().await?.signing_keys {
+            let key = hex::decode(&listed.public_key)
+                .ok()
+                .and_then(|key| <[u8; 32]>::try_from(key).ok())
+                .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+                .ok_or_else(|| Error::Decode(format!("signing key {}", listed.key_id)))?;
+            // Ids derive from the key, so a listing cannot pass one key off as another
+            if hex::encode(&Sha256::digest(key.as_bytes())[..8]) != listed.key_id {
+                return Err(Error::Decode(format!("signing key {} does not match its id", listed.key_id)));
+            }
+            keys.insert(listed.key_id, key);
+        }
+        *self.keys.lock().unwrap() = keys;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::serve;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signed_bytes(key: &SigningKey, bytes: &[u8], signed_at: u64) -> String {
+        let mut message = BYTES_CONTEXT.to_vec();
+        message.extend_from_slice(&signed_at.to_be_bytes());
+        message.extend_from_slice(&Sha256::digest(bytes));
+        format!(
+            r#"{{"success":true,"data":{{"bytes":"{}","correction":"none","source":"conditioned",
+            "sha256":"","signature":{{"key_id":"{}","algorithm":"ed25519","signature":"{}",
+            "signed_at":{}}}}},"error":null}}"#,
+            hex::encode(bytes),
+            key_id(key),
+            hex::encode(key.sign(&message).to_bytes()),
+            signed_at
+        )
+    }
+
+    fn key_id(key: &SigningKey) -> String {
+        hex::encode(&Sha256::digest(key.verifying_key().as_bytes())[..8])
+    }
+
+    #[tokio::test]
+    async fn checks_signatures_against_published_keys() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let public_keys = format!(
+            r#"{{"success":true,"data":{{"suite":"","public_key":"","signing_keys":[{{"key_id":"{}",
+            "algorithm":"ed25519","public_key":"{}","created_at":1}}]}},"error":null}}"#,
+            key_id(&key),
+            hex::encode(key.verifying_key().as_bytes())
+        );
+        // Signed by the right key over other bytes
+        let forged = signed_bytes(&key, &[1, 2], 5).replace("\"0102\"", "\"0103\"");
+        let base_url = serve(&[
+            ("200 OK", "", &signed_bytes(&key, &[42], 5)),
+            ("200 OK", "", &public_keys),
+            ("200 OK", "", &forged),
+            ("200 OK", "", &signed_bytes(&SigningKey::from_bytes(&[8; 32]), &[42], 5)),
+            ("200 OK", "", &public_keys),
+        ])
+        .await;
+        let client = Client::builder(&base_url).verify_signatures().prefetch(64).build().unwrap();
+
+        let bytes = client.random_bytes(1).await.unwrap();
+        let verified = Verified {
+            key_id: key_id(&key),
+            signed_at: 5,
+        };
+        assert_eq!(bytes.verified, Some(verified));
+        assert!(matches!(client.random_bytes(2).await, Err(Error::Signature(_))));
+        let unknown = client.random_bytes(1).await.unwrap_err();
+        assert!(matches!(unknown, Error::Signature(message) if message.starts_with("unknown signing key")));
+    }
+}
@@ -0,0 +1,143 @@
+//! Client side of the server's EGD socket
+
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Most bytes one EGD request carries
+const MAX_REQUEST: usize = 255;
+
+/// Blocking read of the given count
+const READ_BLOCKING: u8 = 0x02;
+
+/// Entropy offered to the server
+const WRITE_ENTROPY: u8 = 0x03;
+
+/// Connection to the server's `egd.socket`
+///
+/// A request that fails part way leaves replies the server may still send,
+/// so the connection is dropped and the next request opens a new one.
+pub struct EgdConnection {
+    path: PathBuf,
+    timeout: Duration,
+    stream: Option<UnixStream>,
+}
+
+impl EgdConnection {
+    /// Connect to `path`, giving up on any request after `timeout`
+    pub fn connect(path: &Path, timeout: Duration) -> io::Result<Self> {
+        let mut connection = Self {
+            path: path.to_path_buf(),
+            timeout,
+            stream: None,
+        };
+        connection.stream()?;
+        Ok(connection)
+    }
+
+    /// Fill `output`, waiting while the server's buffer refills
+    ///
+    /// The server withholds output during a health test failure, so this
+    /// fails with `TimedOut` when `output` is not filled within the timeout.
+    pub fn read(&mut self, output: &mut [u8]) -> io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        let result = self.read_until(output, deadline);
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    /// Offer `seed` to the server, crediting it no entropy
+    pub fn seed(&mut self, seed: &[u8]) -> io::Result<()> {
+        let result = self.write_seed(seed);
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    /// The open stream, reconnecting if the last request failed
+    fn stream(&mut self) -> io::Result<&mut UnixStream> {
+        if self.stream.is_none() {
+            let stream = UnixStream::connect(&self.path)?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            self.stream = Some(stream);
+        }
+        Ok(self.stream.as_mut().expect("just connected"))
+    }
+
+    fn read_until(&mut self, output: &mut [u8], deadline: Instant) -> io::Result<()> {
+        let stream = self.stream()?;
+        for chunk in output.chunks_mut(MAX_REQUEST) {
+            stream.write_all(&[READ_BLOCKING, chunk.len() as u8])?;
+            let mut filled = 0;
+            while filled < chunk.len() {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                stream.set_read_timeout(Some(remaining))?;
+                match stream.read(&mut chunk[filled..]) {
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(read) => filled += read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        return Err(io::ErrorKind::TimedOut.into())
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_seed(&mut self, seed: &[u8]) -> io::Result<()> {
+        let stream = self.stream()?;
+        for chunk in seed.chunks(MAX_REQUEST) {
+            stream.write_all(&[WRITE_ENTROPY, 0, 0, 0, 0, chunk.len() as u8])?;
+            stream.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn times_out_and_reconnects() {
+        let path = std::env::temp_dir().join(format!("quantis-pkcs11-egd-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || {
+            // The first connection sends part of a reply, then holds back
+            // the rest as during a health test failure
+            let (mut stalled, _) = listener.accept().unwrap();
+            let mut command = [0u8; 2];
+            stalled.read_exact(&mut command).unwrap();
+            stalled.write_all(&[9; 3]).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            while stream.read_exact(&mut command).is_ok() {
+                stream.write_all(&vec![7; command[1].into()]).unwrap();
+            }
+            drop(stalled);
+        });
+
+        let mut egd = EgdConnection::connect(&path, Duration::from_millis(100)).unwrap();
+        let mut output = [0u8; 8];
+        let started = Instant::now();
+        assert_eq!(egd.read(&mut output).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The late bytes of the abandoned request are not read as output
+        egd.read(&mut output).unwrap();
+        assert_eq!(output, [7; 8]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
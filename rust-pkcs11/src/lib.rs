@@ -0,0 +1,592 @@
+//! PKCS#11 module drawing random numbers from a Quantis QRNG server
+//!
+//! Applications that source randomness through PKCS#11 load this library
+//! as a module and get one token in slot 0 with `C_GenerateRandom` and
+//! `C_SeedRandom`. Each session reads conditioned entropy from the
+//! server's buffer over its EGD socket, found at `QUANTIS_EGD_SOCKET` or
+//! `/run/quantis/egd-pool`. A read the server cannot answer within
+//! `QUANTIS_EGD_TIMEOUT` seconds, 10 by default, fails with
+//! `CKR_DEVICE_ERROR`. The token has no objects, keys or mechanisms;
+//! everything else returns `CKR_FUNCTION_NOT_SUPPORTED`.
+
+// Each function's pointer requirements are those the PKCS#11 specification states
+#![allow(clippy::missing_safety_doc)]
+
+pub mod egd;
+pub mod types;
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use egd::EgdConnection;
+use types::*;
+
+/// Variable naming the server's EGD socket
+pub const SOCKET_ENV: &str = "QUANTIS_EGD_SOCKET";
+
+/// Socket used when the variable is unset
+pub const DEFAULT_SOCKET: &str = "/run/quantis/egd-pool";
+
+/// Variable giving the seconds a request may wait on the server
+pub const TIMEOUT_ENV: &str = "QUANTIS_EGD_TIMEOUT";
+
+/// Wait used when the variable is unset or invalid
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The only slot
+const SLOT: CK_SLOT_ID = 0;
+
+const CRYPTOKI_VERSION: CK_VERSION = CK_VERSION { major: 2, minor: 40 };
+const MANUFACTURER: &str = "Quantis QRNG";
+
+struct Session {
+    flags: CK_FLAGS,
+    egd: EgdConnection,
+    finding: bool,
+}
+
+/// State between `C_Initialize` and `C_Finalize`
+struct Module {
+    socket: PathBuf,
+    timeout: Duration,
+    sessions: HashMap<CK_SESSION_HANDLE, Arc<Mutex<Session>>>,
+    next_handle: CK_SESSION_HANDLE,
+}
+
+static MODULE: Mutex<Option<Module>> = Mutex::new(None);
+
+fn module() -> MutexGuard<'static, Option<Module>> {
+    // A panic elsewhere leaves the session table itself intact
+    MODULE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Run `f` on an open session, without holding the module lock
+fn with_session(handle: CK_SESSION_HANDLE, f: impl FnOnce(&mut Session) -> CK_RV) -> CK_RV {
+    let session = match module().as_ref() {
+        None => return CKR_CRYPTOKI_NOT_INITIALIZED,
+        Some(module) => match module.sessions.get(&handle) {
+            Some(session) => session.clone(),
+            None => return CKR_SESSION_HANDLE_INVALID,
+        },
+    };
+    let mut session = session.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut session)
+}
+
+fn initialized() -> CK_RV {
+    match module().is_some() {
+        true => CKR_OK,
+        false => CKR_CRYPTOKI_NOT_INITIALIZED,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_Initialize(_args: CK_VOID_PTR) -> CK_RV {
+    // Locking is always done with OS primitives, whatever the caller offers
+    let mut module = module();
+    if module.is_some() {
+        return CKR_CRYPTOKI_ALREADY_INITIALIZED;
+    }
+    let socket = std::env::var_os(SOCKET_ENV).map_or_else(|| PathBuf::from(DEFAULT_SOCKET), PathBuf::from);
+    let timeout = std::env::var(TIMEOUT_ENV)
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .filter(|secs| *secs > 0.0)
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs_f64);
+    *module = Some(Module {
+        socket,
+        timeout,
+        sessions: HashMap::new(),
+        next_handle: 1,
+    });
+    CKR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_Finalize(reserved: CK_VOID_PTR) -> CK_RV {
+    if !reserved.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    match module().take() {
+        Some(_) => CKR_OK,
+        None => CKR_CRYPTOKI_NOT_INITIALIZED,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetInfo(info: *mut CK_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let version = |part: &str| part.parse().unwrap_or(0);
+    let mut parts = env!("CARGO_PKG_VERSION").split('.');
+    info.write(CK_INFO {
+        cryptokiVersion: CRYPTOKI_VERSION,
+        manufacturerID: padded(MANUFACTURER),
+        flags: 0,
+        libraryDescription: padded("Quantis QRNG server"),
+        libraryVersion: CK_VERSION {
+            major: version(parts.next().unwrap_or("0")),
+            minor: version(parts.next().unwrap_or("0")),
+        },
+    });
+    initialized()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetFunctionList(list: *mut *const CK_FUNCTION_LIST) -> CK_RV {
+    if list.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    list.write(&FUNCTION_LIST);
+    CKR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetSlotList(
+    _present: CK_BBOOL,
+    slots: *mut CK_SLOT_ID,
+    count: *mut CK_ULONG,
+) -> CK_RV {
+    if count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if module().is_none() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    if !slots.is_null() {
+        if count.read() < 1 {
+            count.write(1);
+            return CKR_BUFFER_TOO_SMALL;
+        }
+        slots.write(SLOT);
+    }
+    count.write(1);
+    CKR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetSlotInfo(slot: CK_SLOT_ID, info: *mut CK_SLOT_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    info.write(CK_SLOT_INFO {
+        slotDescription: padded("Quantis QRNG server"),
+        manufacturerID: padded(MANUFACTURER),
+        flags: CKF_TOKEN_PRESENT | CKF_HW_SLOT,
+        hardwareVersion: CK_VERSION { major: 1, minor: 0 },
+        firmwareVersion: CK_VERSION { major: 1, minor: 0 },
+    });
+    initialized()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetTokenInfo(slot: CK_SLOT_ID, info: *mut CK_TOKEN_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    let (sessions, rw_sessions) = match module().as_ref() {
+        None => return CKR_CRYPTOKI_NOT_INITIALIZED,
+        Some(module) => {
+            let rw = module.sessions.values().filter(|session| {
+                let session = session.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                session.flags & CKF_RW_SESSION != 0
+            });
+            (module.sessions.len() as CK_ULONG, rw.count() as CK_ULONG)
+        }
+    };
+    info.write(CK_TOKEN_INFO {
+        label: padded("Quantis QRNG"),
+        manufacturerID: padded(MANUFACTURER),
+        model: padded("EGD bridge"),
+        serialNumber: padded("0"),
+        flags: CKF_RNG | CKF_WRITE_PROTECTED | CKF_TOKEN_INITIALIZED,
+        ulMaxSessionCount: 0,
+        ulSessionCount: sessions,
+        ulMaxRwSessionCount: 0,
+        ulRwSessionCount: rw_sessions,
+        ulMaxPinLen: 0,
+        ulMinPinLen: 0,
+        ulTotalPublicMemory: CK_UNAVAILABLE_INFORMATION,
+        ulFreePublicMemory: CK_UNAVAILABLE_INFORMATION,
+        ulTotalPrivateMemory: CK_UNAVAILABLE_INFORMATION,
+        ulFreePrivateMemory: CK_UNAVAILABLE_INFORMATION,
+        hardwareVersion: CK_VERSION { major: 1, minor: 0 },
+        firmwareVersion: CK_VERSION { major: 1, minor: 0 },
+        utcTime: [b' '; 16],
+    });
+    CKR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetMechanismList(
+    slot: CK_SLOT_ID,
+    _list: *mut CK_ULONG,
+    count: *mut CK_ULONG,
+) -> CK_RV {
+    if count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    count.write(0);
+    initialized()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetMechanismInfo(
+    slot: CK_SLOT_ID,
+    _mechanism: CK_ULONG,
+    _info: CK_VOID_PTR,
+) -> CK_RV {
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    CKR_MECHANISM_INVALID
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_OpenSession(
+    slot: CK_SLOT_ID,
+    flags: CK_FLAGS,
+    _application: CK_VOID_PTR,
+    _notify: CK_VOID_PTR,
+    handle: *mut CK_SESSION_HANDLE,
+) -> CK_RV {
+    if handle.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    if flags & CKF_SERIAL_SESSION == 0 {
+        return CKR_SESSION_PARALLEL_NOT_SUPPORTED;
+    }
+    let (socket, timeout) = match module().as_ref() {
+        Some(module) => (module.socket.clone(), module.timeout),
+        None => return CKR_CRYPTOKI_NOT_INITIALIZED,
+    };
+    // Connecting can wait on the server, so it happens outside the lock
+    let Ok(egd) = EgdConnection::connect(&socket, timeout) else {
+        return CKR_DEVICE_ERROR;
+    };
+    let mut module = module();
+    let Some(module) = module.as_mut() else {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    };
+    let session = Session {
+        flags,
+        egd,
+        finding: false,
+    };
+    module.sessions.insert(module.next_handle, Arc::new(Mutex::new(session)));
+    handle.write(module.next_handle);
+    module.next_handle += 1;
+    CKR_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_CloseSession(handle: CK_SESSION_HANDLE) -> CK_RV {
+    match module().as_mut() {
+        None => CKR_CRYPTOKI_NOT_INITIALIZED,
+        Some(module) => match module.sessions.remove(&handle) {
+            Some(_) => CKR_OK,
+            None => CKR_SESSION_HANDLE_INVALID,
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_CloseAllSessions(slot: CK_SLOT_ID) -> CK_RV {
+    if slot != SLOT {
+        return CKR_SLOT_ID_INVALID;
+    }
+    match module().as_mut() {
+        None => CKR_CRYPTOKI_NOT_INITIALIZED,
+        Some(module) => {
+            module.sessions.clear();
+            CKR_OK
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetSessionInfo(handle: CK_SESSION_HANDLE, info: *mut CK_SESSION_INFO) -> CK_RV {
+    if info.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_session(handle, |session| {
+        let state = match session.flags & CKF_RW_SESSION {
+            0 => CKS_RO_PUBLIC_SESSION,
+            _ => CKS_RW_PUBLIC_SESSION,
+        };
+        info.write(CK_SESSION_INFO {
+            slotID: SLOT,
+            state,
+            flags: session.flags,
+            ulDeviceError: 0,
+        });
+        CKR_OK
+    })
+}
+
+/// Object searches always come back empty
+#[no_mangle]
+pub unsafe extern "C" fn C_FindObjectsInit(
+    handle: CK_SESSION_HANDLE,
+    _template: CK_VOID_PTR,
+    _count: CK_ULONG,
+) -> CK_RV {
+    with_session(handle, |session| {
+        session.finding = true;
+        CKR_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_FindObjects(
+    handle: CK_SESSION_HANDLE,
+    _objects: *mut CK_ULONG,
+    _max: CK_ULONG,
+    count: *mut CK_ULONG,
+) -> CK_RV {
+    if count.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    with_session(handle, |session| match session.finding {
+        true => {
+            count.write(0);
+            CKR_OK
+        }
+        false => CKR_OPERATION_NOT_INITIALIZED,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_FindObjectsFinal(handle: CK_SESSION_HANDLE) -> CK_RV {
+    with_session(handle, |session| match std::mem::take(&mut session.finding) {
+        true => CKR_OK,
+        false => CKR_OPERATION_NOT_INITIALIZED,
+    })
+}
+
+/// The seed is passed on to the server, which mixes in nothing it is
+/// given: output comes from the device alone
+#[no_mangle]
+pub unsafe extern "C" fn C_SeedRandom(handle: CK_SESSION_HANDLE, seed: *const u8, len: CK_ULONG) -> CK_RV {
+    if seed.is_null() && len > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let seed = match len {
+        0 => &[][..],
+        _ => std::slice::from_raw_parts(seed, len as usize),
+    };
+    with_session(handle, |session| match session.egd.seed(seed) {
+        Ok(()) => CKR_OK,
+        Err(_) => CKR_DEVICE_ERROR,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GenerateRandom(
+    handle: CK_SESSION_HANDLE,
+    output: *mut u8,
+    len: CK_ULONG,
+) -> CK_RV {
+    if output.is_null() && len > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let output = match len {
+        0 => &mut [][..],
+        _ => std::slice::from_raw_parts_mut(output, len as usize),
+    };
+    with_session(handle, |session| match session.egd.read(output) {
+        Ok(()) => CKR_OK,
+        Err(_) => CKR_DEVICE_ERROR,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_GetFunctionStatus(_handle: CK_SESSION_HANDLE) -> CK_RV {
+    CKR_FUNCTION_NOT_PARALLEL
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn C_CancelFunction(_handle: CK_SESSION_HANDLE) -> CK_RV {
+    CKR_FUNCTION_NOT_PARALLEL
+}
+
+unsafe extern "C" fn not_supported() -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+static FUNCTION_LIST: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+    version: CRYPTOKI_VERSION,
+    C_Initialize,
+    C_Finalize,
+    C_GetInfo,
+    C_GetFunctionList,
+    C_GetSlotList,
+    C_GetSlotInfo,
+    C_GetTokenInfo,
+    C_GetMechanismList,
+    C_GetMechanismInfo,
+    C_InitToken: not_supported,
+    C_InitPIN: not_supported,
+    C_SetPIN: not_supported,
+    C_OpenSession,
+    C_CloseSession,
+    C_CloseAllSessions,
+    C_GetSessionInfo,
+    C_GetOperationState: not_supported,
+    C_SetOperationState: not_supported,
+    C_Login: not_supported,
+    C_Logout: not_supported,
+    C_CreateObject: not_supported,
+    C_CopyObject: not_supported,
+    C_DestroyObject: not_supported,
+    C_GetObjectSize: not_supported,
+    C_GetAttributeValue: not_supported,
+    C_SetAttributeValue: not_supported,
+    C_FindObjectsInit,
+    C_FindObjects,
+    C_FindObjectsFinal,
+    C_EncryptInit: not_supported,
+    C_Encrypt: not_supported,
+    C_EncryptUpdate: not_supported,
+    C_EncryptFinal: not_supported,
+    C_DecryptInit: not_supported,
+    C_Decrypt: not_supported,
+    C_DecryptUpdate: not_supported,
+    C_DecryptFinal: not_supported,
+    C_DigestInit: not_supported,
+    C_Digest: not_supported,
+    C_DigestUpdate: not_supported,
+    C_DigestKey: not_supported,
+    C_DigestFinal: not_supported,
+    C_SignInit: not_supported,
+    C_Sign: not_supported,
+    C_SignUpdate: not_supported,
+    C_SignFinal: not_supported,
+    C_SignRecoverInit: not_supported,
+    C_SignRecover: not_supported,
+    C_VerifyInit: not_supported,
+    C_Verify: not_supported,
+    C_VerifyUpdate: not_supported,
+    C_VerifyFinal: not_supported,
+    C_VerifyRecoverInit: not_supported,
+    C_VerifyRecover: not_supported,
+    C_DigestEncryptUpdate: not_supported,
+    C_DecryptDigestUpdate: not_supported,
+    C_SignEncryptUpdate: not_supported,
+    C_DecryptVerifyUpdate: not_supported,
+    C_GenerateKey: not_supported,
+    C_GenerateKeyPair: not_supported,
+    C_WrapKey: not_supported,
+    C_UnwrapKey: not_supported,
+    C_DeriveKey: not_supported,
+    C_SeedRandom,
+    C_GenerateRandom,
+    C_GetFunctionStatus,
+    C_CancelFunction,
+    C_WaitForSlotEvent: not_supported,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+    };
+
+    /// Stand-in EGD server answering blocking reads with each byte's offset
+    /// in the stream
+    fn fake_server(path: &std::path::Path) {
+        let listener = UnixListener::bind(path).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                std::thread::spawn(move || {
+                    let mut sent = 0usize;
+                    let mut command = [0u8; 2];
+                    while stream.read_exact(&mut command).is_ok() {
+                        match command[0] {
+                            0x02 => {
+                                let count = usize::from(command[1]);
+                                let bytes: Vec<u8> = (sent..sent + count).map(|i| i as u8).collect();
+                                stream.write_all(&bytes).unwrap();
+                                sent += count;
+                            }
+                            // Bits and count of an offered seed, then the seed
+                            _ => {
+                                let mut rest = [0u8; 4];
+                                stream.read_exact(&mut rest).unwrap();
+                                let mut seed = vec![0; rest[3].into()];
+                                stream.read_exact(&mut seed).unwrap();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn generates_random_through_the_function_list() {
+        let path = std::env::temp_dir().join(format!("quantis-pkcs11-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        fake_server(&path);
+        std::env::set_var(SOCKET_ENV, &path);
+
+        unsafe {
+            let mut list = std::ptr::null();
+            assert_eq!(C_GetFunctionList(&mut list), CKR_OK);
+            let list = &*list;
+            let mut handle = 0;
+            let open = |handle| (list.C_OpenSession)(SLOT, CKF_SERIAL_SESSION, null(), null(), handle);
+            assert_eq!(open(&mut handle), CKR_CRYPTOKI_NOT_INITIALIZED);
+            assert_eq!((list.C_Initialize)(null()), CKR_OK);
+            assert_eq!((list.C_Initialize)(null()), CKR_CRYPTOKI_ALREADY_INITIALIZED);
+
+            let mut count = 0;
+            assert_eq!((list.C_GetSlotList)(1, std::ptr::null_mut(), &mut count), CKR_OK);
+            assert_eq!(count, 1);
+            let mut token: CK_TOKEN_INFO = std::mem::zeroed();
+            assert_eq!((list.C_GetTokenInfo)(SLOT, &mut token), CKR_OK);
+            assert_eq!(token.flags & CKF_RNG, CKF_RNG);
+
+            let parallel = (list.C_OpenSession)(SLOT, 0, null(), null(), &mut handle);
+            assert_eq!(parallel, CKR_SESSION_PARALLEL_NOT_SUPPORTED);
+            assert_eq!(open(&mut handle), CKR_OK);
+            // Split into EGD requests of 255 and 45 bytes
+            let mut output = [0u8; 300];
+            assert_eq!((list.C_GenerateRandom)(handle, output.as_mut_ptr(), 300), CKR_OK);
+            assert!(output.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+            assert_eq!((list.C_SeedRandom)(handle, [1, 2, 3].as_ptr(), 3), CKR_OK);
+            assert_eq!((list.C_GenerateRandom)(handle, output.as_mut_ptr(), 1), CKR_OK);
+            assert_eq!(output[0], 300usize as u8);
+
+            assert_eq!((list.C_Login)(), CKR_FUNCTION_NOT_SUPPORTED);
+            assert_eq!((list.C_CloseSession)(handle), CKR_OK);
+            assert_eq!((list.C_GenerateRandom)(handle, output.as_mut_ptr(), 1), CKR_SESSION_HANDLE_INVALID);
+            assert_eq!((list.C_Finalize)(null()), CKR_OK);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn null() -> CK_VOID_PTR {
+        std::ptr::null_mut()
+    }
+}
@@ -0,0 +1,191 @@
+//! The subset of the PKCS#11 v2.40 C interface this module uses
+//!
+//! Names follow the specification so they can be checked against
+//! `pkcs11t.h`. Structures use the platform's natural alignment, as on
+//! every Unix PKCS#11 implementation.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::ffi::{c_ulong, c_void};
+
+pub type CK_ULONG = c_ulong;
+pub type CK_RV = CK_ULONG;
+pub type CK_FLAGS = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_BBOOL = u8;
+pub type CK_VOID_PTR = *mut c_void;
+
+pub const CKR_OK: CK_RV = 0x000;
+pub const CKR_SLOT_ID_INVALID: CK_RV = 0x003;
+pub const CKR_ARGUMENTS_BAD: CK_RV = 0x007;
+pub const CKR_DEVICE_ERROR: CK_RV = 0x030;
+pub const CKR_FUNCTION_NOT_PARALLEL: CK_RV = 0x051;
+pub const CKR_FUNCTION_NOT_SUPPORTED: CK_RV = 0x054;
+pub const CKR_MECHANISM_INVALID: CK_RV = 0x070;
+pub const CKR_OPERATION_NOT_INITIALIZED: CK_RV = 0x091;
+pub const CKR_SESSION_HANDLE_INVALID: CK_RV = 0x0B3;
+pub const CKR_SESSION_PARALLEL_NOT_SUPPORTED: CK_RV = 0x0B4;
+pub const CKR_BUFFER_TOO_SMALL: CK_RV = 0x150;
+pub const CKR_CRYPTOKI_NOT_INITIALIZED: CK_RV = 0x190;
+pub const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x191;
+
+pub const CKF_TOKEN_PRESENT: CK_FLAGS = 0x001;
+pub const CKF_HW_SLOT: CK_FLAGS = 0x004;
+pub const CKF_RNG: CK_FLAGS = 0x001;
+pub const CKF_WRITE_PROTECTED: CK_FLAGS = 0x002;
+pub const CKF_TOKEN_INITIALIZED: CK_FLAGS = 0x400;
+pub const CKF_RW_SESSION: CK_FLAGS = 0x002;
+pub const CKF_SERIAL_SESSION: CK_FLAGS = 0x004;
+
+pub const CKS_RO_PUBLIC_SESSION: CK_ULONG = 0;
+pub const CKS_RW_PUBLIC_SESSION: CK_ULONG = 2;
+
+/// Count reported where a token does not track it
+pub const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CK_VERSION {
+    pub major: u8,
+    pub minor: u8,
+}
+
+#[repr(C)]
+pub struct CK_INFO {
+    pub cryptokiVersion: CK_VERSION,
+    pub manufacturerID: [u8; 32],
+    pub flags: CK_FLAGS,
+    pub libraryDescription: [u8; 32],
+    pub libraryVersion: CK_VERSION,
+}
+
+#[repr(C)]
+pub struct CK_SLOT_INFO {
+    pub slotDescription: [u8; 64],
+    pub manufacturerID: [u8; 32],
+    pub flags: CK_FLAGS,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+}
+
+#[repr(C)]
+pub struct CK_TOKEN_INFO {
+    pub label: [u8; 32],
+    pub manufacturerID: [u8; 32],
+    pub model: [u8; 16],
+    pub serialNumber: [u8; 16],
+    pub flags: CK_FLAGS,
+    pub ulMaxSessionCount: CK_ULONG,
+    pub ulSessionCount: CK_ULONG,
+    pub ulMaxRwSessionCount: CK_ULONG,
+    pub ulRwSessionCount: CK_ULONG,
+    pub ulMaxPinLen: CK_ULONG,
+    pub ulMinPinLen: CK_ULONG,
+    pub ulTotalPublicMemory: CK_ULONG,
+    pub ulFreePublicMemory: CK_ULONG,
+    pub ulTotalPrivateMemory: CK_ULONG,
+    pub ulFreePrivateMemory: CK_ULONG,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+    pub utcTime: [u8; 16],
+}
+
+#[repr(C)]
+pub struct CK_SESSION_INFO {
+    pub slotID: CK_SLOT_ID,
+    pub state: CK_ULONG,
+    pub flags: CK_FLAGS,
+    pub ulDeviceError: CK_ULONG,
+}
+
+/// Entry point whose arguments this module ignores
+///
+/// Functions it does not implement all share one stub returning
+/// `CKR_FUNCTION_NOT_SUPPORTED`; the C calling convention lets callers pass
+/// their arguments to it regardless.
+pub type CK_UNSUPPORTED = unsafe extern "C" fn() -> CK_RV;
+
+/// `CK_FUNCTION_LIST`: the version, then all 68 entry points in order
+#[repr(C)]
+pub struct CK_FUNCTION_LIST {
+    pub version: CK_VERSION,
+    pub C_Initialize: unsafe extern "C" fn(CK_VOID_PTR) -> CK_RV,
+    pub C_Finalize: unsafe extern "C" fn(CK_VOID_PTR) -> CK_RV,
+    pub C_GetInfo: unsafe extern "C" fn(*mut CK_INFO) -> CK_RV,
+    pub C_GetFunctionList: unsafe extern "C" fn(*mut *const CK_FUNCTION_LIST) -> CK_RV,
+    pub C_GetSlotList: unsafe extern "C" fn(CK_BBOOL, *mut CK_SLOT_ID, *mut CK_ULONG) -> CK_RV,
+    pub C_GetSlotInfo: unsafe extern "C" fn(CK_SLOT_ID, *mut CK_SLOT_INFO) -> CK_RV,
+    pub C_GetTokenInfo: unsafe extern "C" fn(CK_SLOT_ID, *mut CK_TOKEN_INFO) -> CK_RV,
+    pub C_GetMechanismList: unsafe extern "C" fn(CK_SLOT_ID, *mut CK_ULONG, *mut CK_ULONG) -> CK_RV,
+    pub C_GetMechanismInfo: unsafe extern "C" fn(CK_SLOT_ID, CK_ULONG, CK_VOID_PTR) -> CK_RV,
+    pub C_InitToken: CK_UNSUPPORTED,
+    pub C_InitPIN: CK_UNSUPPORTED,
+    pub C_SetPIN: CK_UNSUPPORTED,
+    pub C_OpenSession:
+        unsafe extern "C" fn(CK_SLOT_ID, CK_FLAGS, CK_VOID_PTR, CK_VOID_PTR, *mut CK_SESSION_HANDLE) -> CK_RV,
+    pub C_CloseSession: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    pub C_CloseAllSessions: unsafe extern "C" fn(CK_SLOT_ID) -> CK_RV,
+    pub C_GetSessionInfo: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_SESSION_INFO) -> CK_RV,
+    pub C_GetOperationState: CK_UNSUPPORTED,
+    pub C_SetOperationState: CK_UNSUPPORTED,
+    pub C_Login: CK_UNSUPPORTED,
+    pub C_Logout: CK_UNSUPPORTED,
+    pub C_CreateObject: CK_UNSUPPORTED,
+    pub C_CopyObject: CK_UNSUPPORTED,
+    pub C_DestroyObject: CK_UNSUPPORTED,
+    pub C_GetObjectSize: CK_UNSUPPORTED,
+    pub C_GetAttributeValue: CK_UNSUPPORTED,
+    pub C_SetAttributeValue: CK_UNSUPPORTED,
+    pub C_FindObjectsInit: unsafe extern "C" fn(CK_SESSION_HANDLE, CK_VOID_PTR, CK_ULONG) -> CK_RV,
+    pub C_FindObjects:
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_ULONG, CK_ULONG, *mut CK_ULONG) -> CK_RV,
+    pub C_FindObjectsFinal: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    pub C_EncryptInit: CK_UNSUPPORTED,
+    pub C_Encrypt: CK_UNSUPPORTED,
+    pub C_EncryptUpdate: CK_UNSUPPORTED,
+    pub C_EncryptFinal: CK_UNSUPPORTED,
+    pub C_DecryptInit: CK_UNSUPPORTED,
+    pub C_Decrypt: CK_UNSUPPORTED,
+    pub C_DecryptUpdate: CK_UNSUPPORTED,
+    pub C_DecryptFinal: CK_UNSUPPORTED,
+    pub C_DigestInit: CK_UNSUPPORTED,
+    pub C_Digest: CK_UNSUPPORTED,
+    pub C_DigestUpdate: CK_UNSUPPORTED,
+    pub C_DigestKey: CK_UNSUPPORTED,
+    pub C_DigestFinal: CK_UNSUPPORTED,
+    pub C_SignInit: CK_UNSUPPORTED,
+    pub C_Sign: CK_UNSUPPORTED,
+    pub C_SignUpdate: CK_UNSUPPORTED,
+    pub C_SignFinal: CK_UNSUPPORTED,
+    pub C_SignRecoverInit: CK_UNSUPPORTED,
+    pub C_SignRecover: CK_UNSUPPORTED,
+    pub C_VerifyInit: CK_UNSUPPORTED,
+    pub C_Verify: CK_UNSUPPORTED,
+    pub C_VerifyUpdate: CK_UNSUPPORTED,
+    pub C_VerifyFinal: CK_UNSUPPORTED,
+    pub C_VerifyRecoverInit: CK_UNSUPPORTED,
+    pub C_VerifyRecover: CK_UNSUPPORTED,
+    pub C_DigestEncryptUpdate: CK_UNSUPPORTED,
+    pub C_DecryptDigestUpdate: CK_UNSUPPORTED,
+    pub C_SignEncryptUpdate: CK_UNSUPPORTED,
+    pub C_DecryptVerifyUpdate: CK_UNSUPPORTED,
+    pub C_GenerateKey: CK_UNSUPPORTED,
+    pub C_GenerateKeyPair: CK_UNSUPPORTED,
+    pub C_WrapKey: CK_UNSUPPORTED,
+    pub C_UnwrapKey: CK_UNSUPPORTED,
+    pub C_DeriveKey: CK_UNSUPPORTED,
+    pub C_SeedRandom: unsafe extern "C" fn(CK_SESSION_HANDLE, *const u8, CK_ULONG) -> CK_RV,
+    pub C_GenerateRandom: unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV,
+    pub C_GetFunctionStatus: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    pub C_CancelFunction: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    pub C_WaitForSlotEvent: CK_UNSUPPORTED,
+}
+
+/// `text` padded with blanks to a fixed-width PKCS#11 string
+pub fn padded<const N: usize>(text: &str) -> [u8; N] {
+    let mut field = [b' '; N];
+    let len = text.len().min(N);
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+    field
+}
@@ -1,41 +1,196 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
-use quantis_server::utils::RingBuffer;
-
-fn benchmark_ring_buffer_write(c: &mut Criterion) {
-    let buffer = RingBuffer::new(16 * 1024 * 1024); // 16MB
-    let data = vec![0xAA; 4096]; // 4KB of data
-
-    let mut group = c.benchmark_group("ring_buffer_write");
-    group.throughput(Throughput::Bytes(data.len() as u64));
-    
-    group.bench_function("write_4kb", |b| {
-        b.iter(|| {
-            black_box(buffer.write(&data));
-        })
-    });
-    
-    group.finish();
-}
-
-fn benchmark_ring_buffer_read(c: &mut Criterion) {
-    let buffer = RingBuffer::new(16 * 1024 * 1024);
-    // Pre-fill buffer
-    let data = vec![0xAA; 1024 * 1024]; // 1MB
-    buffer.write(&data);
-
-    let mut group = c.benchmark_group("ring_buffer_read");
-    
-    for size in [32, 256, 1024, 4096].iter() {
-        group.throughput(Throughput::Bytes(*size as u64));
-        group.bench_function(format!("read_{}_bytes", size), |b| {
-            b.iter(|| {
-                black_box(buffer.read(*size));
-            })
-        });
-    }
-    
-    group.finish();
-}
-
-criterion_group!(benches, benchmark_ring_buffer_write, benchmark_ring_buffer_read);
-criterion_main!(benches);
\ No newline at end of file
+use axum::{body::Body, http::Request, Router};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use quantis_server::{
+    api::{
+        self,
+        keys::DeviceKey,
+        limits::{RouteLimits, RouteTimeouts},
+        readiness::Readiness,
+        tenants::Tenants,
+        throttle::Throttle,
+        AppStateInner,
+    },
+    commitment::CommitmentStore,
+    device::{mock::MockSource, pipeline::Pipeline, pool::DevicePool},
+    drbg::{self, DrbgExpander},
+    health::{credit::EntropyAccount, HealthState, DEFAULT_MIN_ENTROPY},
+    metrics::Metrics,
+    signing::Signer,
+    utils::{self, pools::PoolSet, RingBuffer},
+    vrf::VrfKey,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, sync::broadcast};
+use tower::ServiceExt;
+
+fn benchmark_ring_buffer_write(c: &mut Criterion) {
+    let buffer = RingBuffer::new(16 * 1024 * 1024); // 16MB
+    let data = vec![0xAA; 4096]; // 4KB of data
+
+    let mut group = c.benchmark_group("ring_buffer_write");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("write_4kb", |b| {
+        b.iter(|| {
+            black_box(buffer.write(&data));
+        })
+    });
+
+    group.finish();
+}
+
+fn benchmark_ring_buffer_read(c: &mut Criterion) {
+    let buffer = RingBuffer::new(16 * 1024 * 1024);
+    // Pre-fill buffer
+    let data = vec![0xAA; 1024 * 1024]; // 1MB
+    buffer.write(&data);
+
+    let mut group = c.benchmark_group("ring_buffer_read");
+
+    for size in [32, 256, 1024, 4096].iter() {
+        group.throughput(Throughput::Bytes(*size as u64));
+        group.bench_function(format!("read_{}_bytes", size), |b| {
+            b.iter(|| {
+                black_box(buffer.read(*size));
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Buffered bytes to wait for before measuring
+const PREFILL: usize = 4 * 1024 * 1024;
+
+/// Full API router over a mock device, with the background reader running
+///
+/// The mock stream is deterministic; it only stands in for the hardware.
+fn mock_router(runtime: &Runtime) -> Router {
+    let (events, _) = broadcast::channel(16);
+    let devices = Arc::new(DevicePool::new(events));
+    devices
+        .add(Box::new(MockSource::new("bench", 1)))
+        .expect("mock device");
+    let buffer = Arc::new(RingBuffer::new(16 * 1024 * 1024));
+    let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+
+    let reader = runtime.block_on(async {
+        let reader = utils::start_entropy_reader(devices.clone(), buffer.clone(), health.clone(), None).await;
+        // Measure a warm buffer, not the initial fill
+        while buffer.available() < PREFILL {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        reader
+    });
+
+    api::routes(Arc::new(AppStateInner {
+        devices,
+        buffer,
+        health,
+        correction: Pipeline::default(),
+        metrics: Metrics::new(),
+        drbg: DrbgExpander::new(drbg::DEFAULT_RESEED_INTERVAL),
+        fortuna: None,
+        credit: EntropyAccount::new(DEFAULT_MIN_ENTROPY),
+        pools: Arc::new(PoolSet::default()),
+        tenants: Arc::new(Tenants::default()),
+        commitments: CommitmentStore::new(),
+        vrf: DeviceKey::ready("VRF key", VrfKey::from_secret([7; 32])),
+        beacon: None,
+        signer: DeviceKey::ready("signing key", Signer::new([8; 32], 0)),
+        max_read_wait: Duration::from_millis(250),
+        route_limits: RouteLimits::default(),
+        route_timeouts: RouteTimeouts::default(),
+        request_limits: Default::default(),
+        interactive_reserve: utils::DEFAULT_INTERACTIVE_RESERVE,
+        throttle: Throttle::new(0),
+        readiness: Arc::new(Readiness::ready()),
+        reader: reader.status(),
+        admin_token: None,
+        jwt: None,
+        api_keys: Default::default(),
+        reloader: None,
+    }))
+}
+
+/// Send one GET through the router as a `tower::Service`
+async fn get(router: &Router, uri: &str) -> Duration {
+    let request = Request::get(uri).body(Body::empty()).expect("valid request");
+    let started = Instant::now();
+    let response = router.clone().oneshot(request).await.expect("infallible router");
+    assert!(response.status().is_success(), "{} returned {}", uri, response.status());
+    let _ = axum::body::to_bytes(response.into_body(), usize::MAX).await;
+    started.elapsed()
+}
+
+const ENDPOINTS: [(&str, &str); 4] = [
+    ("bytes_32_hex", "/random/bytes?count=32"),
+    ("bytes_4096_binary", "/random/bytes?count=4096&format=binary"),
+    ("bytes_32_sha3", "/random/bytes?count=32&correction=sha3"),
+    ("int_10", "/random/int?min=1&max=100&count=10"),
+];
+
+/// Requests per second through the full router
+fn benchmark_endpoints(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("tokio runtime");
+    let router = mock_router(&runtime);
+
+    let mut group = c.benchmark_group("endpoint");
+    group.throughput(Throughput::Elements(1));
+    for (name, uri) in ENDPOINTS {
+        group.bench_function(name, |b| b.iter(|| runtime.block_on(get(&router, uri))));
+    }
+    group.finish();
+}
+
+/// Tail latency under concurrent load, printed alongside the criterion report
+fn benchmark_latency(_: &mut Criterion) {
+    const CLIENTS: usize = 32;
+    const REQUESTS: usize = 200;
+
+    let runtime = Runtime::new().expect("tokio runtime");
+    let router = mock_router(&runtime);
+
+    for (name, uri) in ENDPOINTS {
+        let started = Instant::now();
+        let mut latencies: Vec<Duration> = runtime.block_on(async {
+            let clients: Vec<_> = (0..CLIENTS)
+                .map(|_| {
+                    let router = router.clone();
+                    tokio::spawn(async move {
+                        let mut latencies = Vec::with_capacity(REQUESTS);
+                        for _ in 0..REQUESTS {
+                            latencies.push(get(&router, uri).await);
+                        }
+                        latencies
+                    })
+                })
+                .collect();
+
+            let mut latencies = Vec::with_capacity(CLIENTS * REQUESTS);
+            for client in clients {
+                latencies.extend(client.await.expect("client task"));
+            }
+            latencies
+        });
+        let elapsed = started.elapsed();
+
+        latencies.sort();
+        let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+        println!(
+            "endpoint/{:<20} {} clients: {:>9.0} req/s  p50 {:>9.1?}  p99 {:>9.1?}",
+            name,
+            CLIENTS,
+            latencies.len() as f64 / elapsed.as_secs_f64(),
+            percentile(0.50),
+            percentile(0.99),
+        );
+    }
+}
+
+criterion_group!(benches, benchmark_ring_buffer_write, benchmark_ring_buffer_read);
+criterion_group!(endpoints, benchmark_endpoints, benchmark_latency);
+criterion_main!(benches, endpoints);
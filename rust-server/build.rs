@@ -0,0 +1,30 @@
+//! Build metadata for `/version`
+//!
+//! Records the git commit and the build time. `SOURCE_DATE_EPOCH` fixes the
+//! build time for reproducible builds; outside a git checkout the commit is
+//! reported as unknown.
+
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+        });
+
+    println!("cargo:rustc-env=QUANTIS_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=QUANTIS_BUILD_TIMESTAMP={}", built_at);
+}
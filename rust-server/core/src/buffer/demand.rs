@@ -0,0 +1,137 @@
+//! Demand-driven fill targets for the entropy buffer
+//!
+//! The reader keeps a few seconds of recent consumption buffered so bursts
+//! are served from memory, and settles at a lower fill level when idle so
+//! the device is not read for bytes nobody asks for.
+
+use std::time::{Duration, Instant};
+
+/// Weight of the newest sample in the moving average
+const SMOOTHING: f64 = 0.3;
+
+/// Shortest window measured as one consumption sample
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Seconds of recent demand kept buffered
+const HEADROOM_SECS: f64 = 2.0;
+
+/// Seconds of recent demand covered by one device read
+const READ_SECS: f64 = 0.1;
+
+/// Fill fraction kept when idle
+pub const MIN_TARGET: f64 = 0.5;
+
+/// Highest fill fraction aimed for under load
+pub const MAX_TARGET: f64 = 0.95;
+
+/// Device read size when idle
+pub const MIN_READ: usize = 64 * 1024;
+
+/// Largest single device read
+pub const MAX_READ: usize = 1024 * 1024;
+
+/// Recent consumption from the buffer, as seen by its writer
+#[derive(Debug)]
+pub struct Demand {
+    bytes_per_sec: Option<f64>,
+    consumed: usize,
+    since: Instant,
+    last_available: usize,
+}
+
+impl Demand {
+    pub fn new(available: usize, now: Instant) -> Self {
+        Self {
+            bytes_per_sec: None,
+            consumed: 0,
+            since: now,
+            last_available: available,
+        }
+    }
+
+    /// Account for the buffer level seen at `now`
+    ///
+    /// Any drop since the last observation, less what was written in
+    /// between, was consumed by readers.
+    pub fn observe(&mut self, available: usize, now: Instant) {
+        self.consumed += self.last_available.saturating_sub(available);
+        self.last_available = available;
+
+        let elapsed = now.saturating_duration_since(self.since);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let sample = self.consumed as f64 / elapsed.as_secs_f64();
+        self.bytes_per_sec = Some(match self.bytes_per_sec {
+            Some(rate) => rate + SMOOTHING * (sample - rate),
+            None => sample,
+        });
+        self.consumed = 0;
+        self.since = now;
+    }
+
+    /// Account for `written` bytes added by the reader
+    pub fn wrote(&mut self, written: usize) {
+        self.last_available += written;
+    }
+
+    /// Smoothed consumption, zero until a full sample has been taken
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_per_sec.unwrap_or(0.0)
+    }
+
+    /// Fill level to refill up to
+    pub fn target(&self, capacity: usize) -> usize {
+        let fraction = (self.bytes_per_sec() * HEADROOM_SECS / capacity as f64).clamp(MIN_TARGET, MAX_TARGET);
+        (capacity as f64 * fraction) as usize
+    }
+
+    /// Size of the next device read
+    ///
+    /// Reads grow with demand to amortize per-read overhead, but may run
+    /// past the target so the reader does not top up in slivers.
+    pub fn read_size(&self, available: usize, capacity: usize) -> usize {
+        let wanted = ((self.bytes_per_sec() * READ_SECS) as usize).clamp(MIN_READ, MAX_READ);
+        wanted.min(capacity.saturating_sub(available))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_follow_consumption() {
+        const CAPACITY: usize = 16 * 1024 * 1024;
+        let start = Instant::now();
+        let mut demand = Demand::new(CAPACITY, start);
+        assert_eq!(demand.target(CAPACITY), CAPACITY / 2);
+        assert_eq!(demand.read_size(0, CAPACITY), MIN_READ);
+
+        // Too short a window to judge
+        demand.observe(CAPACITY - 1000, start + Duration::from_millis(10));
+        assert_eq!(demand.bytes_per_sec(), 0.0);
+
+        // 4 MiB consumed over a second, with a 1 MiB refill in between
+        demand.wrote(1024 * 1024);
+        demand.observe(CAPACITY - 3 * 1024 * 1024 - 1000, start + Duration::from_secs(1));
+        assert_eq!(demand.bytes_per_sec(), (4 * 1024 * 1024 + 1000) as f64);
+        assert_eq!(demand.target(CAPACITY), CAPACITY / 2 + 2000);
+        assert_eq!(demand.read_size(0, CAPACITY), 419_530);
+
+        // Heavy demand is capped below a full buffer and a huge read
+        for second in 2..20 {
+            demand.observe(0, start + Duration::from_secs(second));
+            demand.wrote(CAPACITY);
+        }
+        assert_eq!(demand.target(CAPACITY), (CAPACITY as f64 * MAX_TARGET) as usize);
+        assert_eq!(demand.read_size(0, CAPACITY), MAX_READ);
+        assert_eq!(demand.read_size(CAPACITY - 10, CAPACITY), 10);
+
+        // Idle time decays it back down
+        for second in 20..60 {
+            demand.observe(CAPACITY, start + Duration::from_secs(second));
+        }
+        assert_eq!(demand.target(CAPACITY), CAPACITY / 2);
+    }
+}
@@ -0,0 +1,247 @@
+//! Entropy buffering
+//!
+//! The raw buffer between the device reader and its consumers, with the
+//! traffic counters, fill targets and memory protection it relies on.
+
+use bytes::Bytes;
+
+pub mod demand;
+pub mod secure;
+pub mod telemetry;
+
+#[cfg(not(feature = "ringbuf-buffer"))]
+mod ring_buffer;
+#[cfg(not(feature = "ringbuf-buffer"))]
+pub use ring_buffer::RingBuffer;
+
+#[cfg(feature = "ringbuf-buffer")]
+mod ringbuf_buffer;
+#[cfg(feature = "ringbuf-buffer")]
+pub use ringbuf_buffer::RingBuffer;
+
+/// Default entropy buffer size in MiB
+pub const DEFAULT_BUFFER_MIB: usize = 16;
+
+/// Smallest entropy buffer, one device read
+pub const MIN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Largest entropy buffer or pool
+pub const MAX_BUFFER_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Default buffered bytes held back for interactive requests
+pub const DEFAULT_INTERACTIVE_RESERVE: usize = 256 * 1024;
+
+impl RingBuffer {
+    /// Take exactly `size` bytes, returned to the buffer unless committed
+    ///
+    /// Concurrent reservations never overlap.
+    pub fn reserve(&self, size: usize) -> Option<Reservation<'_>> {
+        self.reserve_above(size, 0)
+    }
+
+    /// Reserve `size` bytes only if `floor` bytes stay buffered for others
+    pub fn reserve_above(&self, size: usize, floor: usize) -> Option<Reservation<'_>> {
+        self.read_above(size, floor).map(|bytes| Reservation {
+            buffer: Some(self),
+            bytes,
+        })
+    }
+}
+
+/// Bytes taken from a buffer for a request that may still fail
+///
+/// Dropping a reservation without committing it rolls it back: the bytes
+/// go back to the front of the buffer so they are not lost.
+pub struct Reservation<'a> {
+    buffer: Option<&'a RingBuffer>,
+    bytes: Bytes,
+}
+
+impl<'a> Reservation<'a> {
+    /// Bytes with no buffer to return to, such as a fresh device read
+    pub fn detached(bytes: Bytes) -> Self {
+        Self { buffer: None, bytes }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Consume the bytes for good
+    pub fn commit(mut self) -> Bytes {
+        self.buffer = None;
+        std::mem::take(&mut self.bytes)
+    }
+
+    /// Return the bytes to the buffer
+    pub fn roll_back(self) {}
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer {
+            buffer.restore(&self.bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn wraps_around_and_discards_newest() {
+        let buffer = RingBuffer::new(8);
+        assert_eq!(buffer.write(&[1, 2, 3, 4, 5, 6]), 6);
+        assert_eq!(buffer.read(4).unwrap()[..], [1, 2, 3, 4]);
+        assert_eq!(buffer.write(&[7, 8, 9, 10, 11, 12, 13]), 6);
+        assert_eq!(buffer.available(), 8);
+        assert!(buffer.read(9).is_none());
+
+        assert_eq!(buffer.discard_newest(3), 3);
+        assert_eq!(buffer.read(5).unwrap()[..], [5, 6, 7, 8, 9]);
+        assert_eq!(buffer.discard_newest(10), 0);
+        assert_eq!(buffer.write(&[14]), 1);
+        assert_eq!(buffer.read(1).unwrap()[..], [14]);
+    }
+
+    #[test]
+    fn resize_keeps_contents_in_order() {
+        let buffer = RingBuffer::new(8);
+        buffer.write(&[1, 2, 3, 4, 5, 6]);
+        buffer.read(4).unwrap();
+        buffer.write(&[7, 8, 9, 10]);
+
+        // Contents wrap in the old slots and not in the new ones
+        assert_eq!(buffer.resize(16), 0);
+        assert_eq!(buffer.capacity(), 16);
+        assert_eq!(buffer.write(&[11; 12]), 10);
+        assert_eq!(buffer.read(6).unwrap()[..], [5, 6, 7, 8, 9, 10]);
+
+        assert_eq!(buffer.resize(4), 6);
+        assert_eq!(buffer.available(), 4);
+        assert_eq!(buffer.read(4).unwrap()[..], [11; 4]);
+        assert_eq!(buffer.write(&[12; 5]), 4);
+    }
+
+    #[test]
+    fn rolled_back_reservations_are_served_again() {
+        let buffer = RingBuffer::new(8);
+        buffer.write(&[1, 2, 3, 4, 5, 6]);
+
+        let first = buffer.reserve(2).unwrap();
+        let second = buffer.reserve(3).unwrap();
+        assert_eq!(first.bytes()[..], [1, 2]);
+        assert_eq!(second.bytes()[..], [3, 4, 5]);
+        assert!(buffer.reserve(2).is_none());
+
+        assert_eq!(second.commit()[..], [3, 4, 5]);
+        first.roll_back();
+        assert_eq!(buffer.available(), 3);
+        assert_eq!(buffer.read(3).unwrap()[..], [1, 2, 6]);
+
+        // Only what fits beside newer data is restored
+        buffer.write(&[7; 7]);
+        let taken = buffer.reserve(2).unwrap();
+        assert_eq!(buffer.write(&[8; 4]), 3);
+        drop(taken);
+        assert_eq!(buffer.read(8).unwrap()[..], [7, 7, 7, 7, 7, 8, 8, 8]);
+
+        // Bulk reservations leave the floor in place
+        buffer.write(&[10; 4]);
+        assert!(buffer.reserve_above(2, 3).is_none());
+        assert_eq!(buffer.reserve_above(1, 3).unwrap().commit()[..], [10]);
+        assert_eq!(buffer.available(), 3);
+
+        // Detached bytes have nowhere to go back to
+        assert_eq!(Reservation::detached(Bytes::from_static(&[9; 4])).commit()[..], [9; 4]);
+    }
+
+    #[test]
+    fn counts_traffic() {
+        let buffer = RingBuffer::new(8);
+        buffer.write(&[1; 6]);
+        buffer.write(&[2; 4]);
+        drop(buffer.reserve(3));
+        buffer.read(5).unwrap();
+
+        assert_eq!(
+            buffer.traffic().snapshot(),
+            telemetry::BufferTraffic {
+                bytes_in: 8,
+                bytes_out: 8,
+                bytes_restored: 3,
+                overflows: 1,
+                overflowed_bytes: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_wake_a_waiting_writer() {
+        let buffer = Arc::new(RingBuffer::new(8));
+        buffer.write(&[0; 8]);
+
+        let waiter = tokio::spawn({
+            let buffer = buffer.clone();
+            async move { buffer.drained().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        buffer.read(4).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiter)
+            .await
+            .expect("writer woken")
+            .unwrap();
+
+        // A read with nobody waiting is not lost
+        buffer.read(4).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), buffer.drained())
+            .await
+            .expect("stored wakeup");
+    }
+
+    #[test]
+    fn concurrent_readers_see_each_byte_once_in_order() {
+        const TOTAL: usize = 1 << 18;
+        let buffer = Arc::new(RingBuffer::new(4096));
+
+        let writer = {
+            let buffer = buffer.clone();
+            std::thread::spawn(move || {
+                let mut next = 0usize;
+                while next < TOTAL {
+                    let chunk: Vec<u8> = (next..(next + 97).min(TOTAL)).map(|i| i as u8).collect();
+                    next += buffer.write(&chunk);
+                }
+            })
+        };
+
+        // Each reader gets a run of consecutive values, and together
+        // they see every byte exactly once
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let buffer = buffer.clone();
+                std::thread::spawn(move || {
+                    let mut read = 0;
+                    while read < TOTAL / 4 {
+                        if let Some(chunk) = buffer.read(64) {
+                            for pair in chunk.windows(2) {
+                                assert_eq!(pair[1], pair[0].wrapping_add(1));
+                            }
+                            read += chunk.len();
+                        }
+                    }
+                    read
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        let read: usize = readers.into_iter().map(|r| r.join().unwrap()).sum();
+        assert_eq!(read, TOTAL);
+        assert_eq!(buffer.available(), 0);
+    }
+}
@@ -0,0 +1,289 @@
+//! Built-in ring buffer
+
+use bytes::Bytes;
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+use tokio::sync::Notify;
+use zeroize::Zeroize;
+
+use super::{secure, telemetry::BufferCounters};
+
+/// Ring buffer for entropy storage
+///
+/// One side writes and the other reads concurrently without sharing a
+/// lock: `head` counts bytes ever written and `tail` bytes ever read, so
+/// `[tail, head)` holds readable data and `[head, tail + capacity)` is
+/// free. Writers are serialized by `producer`, readers by `consumer`.
+///
+/// Safety invariants:
+/// - Only the holder of `producer` writes slots, and only free ones.
+/// - Only the holder of `consumer` reads slots, and only readable ones.
+/// - `head` is published with Release after the slots are written and
+///   `tail` with Release after they are read; the other side loads them
+///   with Acquire, so a slot changes sides only once its access is done.
+/// - Moving `head` backwards (`discard_newest`), moving `tail` backwards
+///   (`restore`) or replacing the slots (`resize`) holds both locks.
+///
+/// The slots are locked into RAM where allowed. Read and discarded slots
+/// are zeroed straight away and all slots when they are released.
+///
+/// The counters would need 2^64 bytes of traffic to wrap on 64-bit targets.
+pub struct RingBuffer {
+    buffer: UnsafeCell<Box<[UnsafeCell<u8>]>>,
+    capacity: AtomicUsize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    producer: Mutex<()>,
+    consumer: Mutex<()>,
+    drained: Notify,
+    traffic: BufferCounters,
+}
+
+// SAFETY: slot access follows the invariants documented on the type
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    /// Create new ring buffer with given capacity
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "ring buffer capacity must be non-zero");
+        Self {
+            buffer: UnsafeCell::new(slots(capacity)),
+            capacity: AtomicUsize::new(capacity),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            producer: Mutex::new(()),
+            consumer: Mutex::new(()),
+            drained: Notify::new(),
+            traffic: BufferCounters::default(),
+        }
+    }
+
+    /// Get buffer capacity
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Acquire)
+    }
+
+    /// Traffic through the buffer since it was created
+    pub fn traffic(&self) -> &BufferCounters {
+        &self.traffic
+    }
+
+    /// Get available bytes
+    pub fn available(&self) -> usize {
+        // Tail first: it never passes the head loaded after it
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(tail).min(self.capacity())
+    }
+
+    /// Write data to buffer, returning how much fit
+    pub fn write(&self, data: &[u8]) -> usize {
+        let _producer = self.producer.lock().unwrap();
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let capacity = self.capacity.load(Ordering::Relaxed);
+
+        let free = capacity - head.wrapping_sub(tail);
+        let to_write = data.len().min(free);
+        let start = head % capacity;
+        let first = to_write.min(capacity - start);
+
+        // SAFETY: [head, head + to_write) is free space, which readers do
+        // not touch until `head` is published below
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.slot(start), first);
+            std::ptr::copy_nonoverlapping(data[first..].as_ptr(), self.slot(0), to_write - first);
+        }
+
+        self.head.store(head.wrapping_add(to_write), Ordering::Release);
+        self.traffic.wrote(data.len(), to_write);
+        to_write
+    }
+
+    /// Read exactly `size` bytes, or None if fewer are available
+    pub fn read(&self, size: usize) -> Option<Bytes> {
+        self.read_above(size, 0)
+    }
+
+    /// Read exactly `size` bytes, or None unless `floor` bytes would remain
+    pub fn read_above(&self, size: usize, floor: usize) -> Option<Bytes> {
+        let _consumer = self.consumer.lock().unwrap();
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) < size.saturating_add(floor) {
+            return None;
+        }
+
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut output = vec![0u8; size];
+        let start = tail % capacity;
+        let first = size.min(capacity - start);
+
+        // SAFETY: [tail, tail + size) is readable, and the writer does
+        // not reuse it until `tail` is published below
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.slot(start), output.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(self.slot(0), output[first..].as_mut_ptr(), size - first);
+            std::ptr::write_bytes(self.slot(start), 0, first);
+            std::ptr::write_bytes(self.slot(0), 0, size - first);
+        }
+
+        self.tail.store(tail.wrapping_add(size), Ordering::Release);
+        self.traffic.read(size);
+        self.drained.notify_one();
+        Some(secure::bytes(output))
+    }
+
+    /// Drop up to `count` of the most recently written bytes, zeroing them
+    ///
+    /// Returns the number of bytes dropped.
+    pub fn discard_newest(&self, count: usize) -> usize {
+        let _producer = self.producer.lock().unwrap();
+        let _consumer = self.consumer.lock().unwrap();
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let capacity = self.capacity.load(Ordering::Relaxed);
+
+        let count = count.min(head.wrapping_sub(tail));
+        let discarded = head.wrapping_sub(count);
+        for offset in 0..count {
+            // SAFETY: both locks are held and the slots were readable
+            unsafe {
+                *self.slot(discarded.wrapping_add(offset) % capacity) = 0;
+            }
+        }
+        self.head.store(discarded, Ordering::Release);
+        self.drained.notify_one();
+        count
+    }
+
+    /// Put bytes back in front of the buffered data, as far as space allows
+    ///
+    /// The bytes nearest the front are kept. Returns the number restored.
+    pub fn restore(&self, data: &[u8]) -> usize {
+        let _producer = self.producer.lock().unwrap();
+        let _consumer = self.consumer.lock().unwrap();
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let capacity = self.capacity.load(Ordering::Relaxed);
+
+        let count = data.len().min(capacity - head.wrapping_sub(tail));
+        let restored = tail.wrapping_sub(count);
+        for (offset, &byte) in data[data.len() - count..].iter().enumerate() {
+            // SAFETY: both locks are held and the slots were free
+            unsafe {
+                *self.slot(restored.wrapping_add(offset) % capacity) = byte;
+            }
+        }
+
+        self.tail.store(restored, Ordering::Release);
+        self.traffic.restored(count);
+        count
+    }
+
+    /// Change the capacity, keeping buffered bytes in order
+    ///
+    /// When shrinking below the buffered amount the newest bytes that no
+    /// longer fit are dropped. Returns the number of bytes dropped.
+    pub fn resize(&self, capacity: usize) -> usize {
+        assert!(capacity > 0, "ring buffer capacity must be non-zero");
+        let _producer = self.producer.lock().unwrap();
+        let _consumer = self.consumer.lock().unwrap();
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let old_capacity = self.capacity.load(Ordering::Relaxed);
+
+        let buffered = head.wrapping_sub(tail);
+        let kept = buffered.min(capacity);
+        let resized = slots(capacity);
+        for offset in 0..kept {
+            let index = tail.wrapping_add(offset);
+            // SAFETY: both locks are held, so no other access to the slots
+            // is in progress
+            unsafe {
+                *resized[index % capacity].get() = *self.slot(index % old_capacity);
+            }
+        }
+
+        // SAFETY: both locks are held, so nothing else borrows the slots
+        release(unsafe { &mut *self.buffer.get() });
+        unsafe {
+            *self.buffer.get() = resized;
+        }
+        self.capacity.store(capacity, Ordering::Release);
+        // Keeping `tail` leaves the counters monotonic for lock-free readers
+        self.head.store(tail.wrapping_add(kept), Ordering::Release);
+        self.drained.notify_one();
+        buffered - kept
+    }
+
+    /// Wait until a read, discard or resize may have freed space
+    ///
+    /// A wakeup is kept if space was freed while nobody was waiting, so
+    /// checking the fill level and then waiting cannot miss one.
+    pub async fn drained(&self) {
+        self.drained.notified().await
+    }
+
+    /// Pointer to the slot at `index`
+    ///
+    /// Callers must hold `producer` or `consumer`.
+    fn slot(&self, index: usize) -> *mut u8 {
+        // SAFETY: the slots are only replaced while holding both locks
+        let slots = unsafe { &*self.buffer.get() };
+        debug_assert!(index < slots.len());
+        UnsafeCell::raw_get(slots.as_ptr().wrapping_add(index))
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        release(self.buffer.get_mut());
+    }
+}
+
+fn slots(capacity: usize) -> Box<[UnsafeCell<u8>]> {
+    let slots: Box<[UnsafeCell<u8>]> = (0..capacity).map(|_| UnsafeCell::new(0)).collect();
+    secure::lock(&slots);
+    slots
+}
+
+/// Zero and unlock slots that are about to be freed
+fn release(slots: &mut [UnsafeCell<u8>]) {
+    // SAFETY: `UnsafeCell<u8>` has the layout of `u8`, and the exclusive
+    // borrow rules out any other access
+    let bytes = unsafe { &mut *(slots as *mut [UnsafeCell<u8>] as *mut [u8]) };
+    bytes.zeroize();
+    secure::unlock(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(buffer: &RingBuffer) -> Vec<u8> {
+        let _producer = buffer.producer.lock().unwrap();
+        (0..buffer.capacity()).map(|index| unsafe { *buffer.slot(index) }).collect()
+    }
+
+    #[test]
+    fn zeroes_read_and_discarded_slots() {
+        let buffer = RingBuffer::new(8);
+        buffer.write(&[1, 2, 3, 4, 5, 6]);
+        buffer.read(4).unwrap();
+        buffer.write(&[7, 8, 9, 10]);
+        assert_eq!(contents(&buffer), [9, 10, 0, 0, 5, 6, 7, 8]);
+
+        // The discard wraps back across the end of the slots
+        assert_eq!(buffer.discard_newest(3), 3);
+        assert_eq!(contents(&buffer), [0, 0, 0, 0, 5, 6, 7, 0]);
+        assert_eq!(buffer.read(3).unwrap()[..], [5, 6, 7]);
+        assert_eq!(contents(&buffer), [0; 8]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Entropy buffer backed by the `ringbuf` crate
+//!
+//! Selected with the `ringbuf-buffer` feature for deployments that prefer
+//! a widely used lock-free queue over the built-in unsafe code. This
+//! module itself contains no `unsafe`. Writers and readers each take
+//! their own lock around the queue halves, as with the built-in buffer.
+//!
+//! The queue's storage cannot be locked into RAM without `unsafe`, so
+//! only `--mlock-all` keeps it out of swap. It is zeroed when released,
+//! but read slots keep their bytes until overwritten.
+
+#![forbid(unsafe_code)]
+
+use bytes::Bytes;
+use ringbuf::{
+    traits::{Consumer, Observer, Producer, Split},
+    HeapCons, HeapProd, HeapRb, Obs,
+};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::Notify;
+use zeroize::Zeroizing;
+
+use super::{secure, telemetry::BufferCounters};
+
+/// Ring buffer for entropy storage
+pub struct RingBuffer {
+    producer: Mutex<HeapProd<u8>>,
+    consumer: Mutex<HeapCons<u8>>,
+    observer: RwLock<Obs<Arc<HeapRb<u8>>>>,
+    drained: Notify,
+    traffic: BufferCounters,
+}
+
+impl RingBuffer {
+    /// Create new ring buffer with given capacity
+    pub fn new(capacity: usize) -> Self {
+        let (producer, consumer) = HeapRb::<u8>::new(capacity).split();
+        Self {
+            observer: RwLock::new(producer.observe()),
+            producer: Mutex::new(producer),
+            consumer: Mutex::new(consumer),
+            drained: Notify::new(),
+            traffic: BufferCounters::default(),
+        }
+    }
+
+    /// Get buffer capacity
+    pub fn capacity(&self) -> usize {
+        self.observer.read().unwrap().capacity().get()
+    }
+
+    /// Traffic through the buffer since it was created
+    pub fn traffic(&self) -> &BufferCounters {
+        &self.traffic
+    }
+
+    /// Get available bytes
+    pub fn available(&self) -> usize {
+        self.observer.read().unwrap().occupied_len()
+    }
+
+    /// Write data to buffer, returning how much fit
+    pub fn write(&self, data: &[u8]) -> usize {
+        let written = self.producer.lock().unwrap().push_slice(data);
+        self.traffic.wrote(data.len(), written);
+        written
+    }
+
+    /// Read exactly `size` bytes, or None if fewer are available
+    pub fn read(&self, size: usize) -> Option<Bytes> {
+        self.read_above(size, 0)
+    }
+
+    /// Read exactly `size` bytes, or None unless `floor` bytes would remain
+    pub fn read_above(&self, size: usize, floor: usize) -> Option<Bytes> {
+        let mut consumer = self.consumer.lock().unwrap();
+        if consumer.occupied_len() < size.saturating_add(floor) {
+            return None;
+        }
+
+        let mut output = vec![0u8; size];
+        consumer.pop_slice(&mut output);
+        self.traffic.read(size);
+        self.drained.notify_one();
+        Some(secure::bytes(output))
+    }
+
+    /// Drop up to `count` of the most recently written bytes
+    ///
+    /// The queue cannot retract writes, so the older bytes are drained and
+    /// written back; quarantine is rare enough for the copy not to matter.
+    pub fn discard_newest(&self, count: usize) -> usize {
+        let mut producer = self.producer.lock().unwrap();
+        let mut consumer = self.consumer.lock().unwrap();
+
+        let mut held = Zeroizing::new(vec![0u8; consumer.occupied_len()]);
+        consumer.pop_slice(&mut held);
+        let count = count.min(held.len());
+        producer.push_slice(&held[..held.len() - count]);
+        self.drained.notify_one();
+        count
+    }
+
+    /// Wait until a read, discard or resize may have freed space
+    ///
+    /// A wakeup is kept if space was freed while nobody was waiting, so
+    /// checking the fill level and then waiting cannot miss one.
+    pub async fn drained(&self) {
+        self.drained.notified().await
+    }
+
+    /// Put bytes back in front of the buffered data, as far as space allows
+    ///
+    /// The bytes nearest the front are kept. Returns the number restored.
+    pub fn restore(&self, data: &[u8]) -> usize {
+        let mut producer = self.producer.lock().unwrap();
+        let mut consumer = self.consumer.lock().unwrap();
+
+        let mut held = Zeroizing::new(vec![0u8; consumer.occupied_len()]);
+        consumer.pop_slice(&mut held);
+        let count = data.len().min(producer.vacant_len() - held.len());
+        producer.push_slice(&data[data.len() - count..]);
+        producer.push_slice(&held);
+        self.traffic.restored(count);
+        count
+    }
+
+    /// Change the capacity, keeping buffered bytes in order
+    ///
+    /// When shrinking below the buffered amount the newest bytes that no
+    /// longer fit are dropped. Returns the number of bytes dropped.
+    pub fn resize(&self, capacity: usize) -> usize {
+        let mut producer = self.producer.lock().unwrap();
+        let mut consumer = self.consumer.lock().unwrap();
+        let mut observer = self.observer.write().unwrap();
+
+        let mut held = Zeroizing::new(vec![0u8; consumer.occupied_len()]);
+        consumer.pop_slice(&mut held);
+        let (mut resized, resized_consumer) = HeapRb::<u8>::new(capacity).split();
+        let kept = resized.push_slice(&held);
+
+        scrub(&mut producer, &mut consumer);
+        *observer = resized.observe();
+        *producer = resized;
+        *consumer = resized_consumer;
+        self.drained.notify_one();
+        held.len() - kept
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        scrub(self.producer.get_mut().unwrap(), self.consumer.get_mut().unwrap());
+    }
+}
+
+/// Overwrite every slot of a queue with zeros, leaving it empty
+fn scrub(producer: &mut HeapProd<u8>, consumer: &mut HeapCons<u8>) {
+    consumer.clear();
+    producer.push_iter(std::iter::repeat(0));
+    consumer.clear();
+}
@@ -0,0 +1,77 @@
+//! Keeping entropy out of swap and freed memory
+//!
+//! Buffers holding entropy are locked into RAM where the platform allows
+//! and zeroed before their memory is released, so output later used as
+//! key material is not written to swap or left behind in freed pages.
+
+use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+use zeroize::Zeroizing;
+
+/// Set once a failed lock has been reported
+static LOCK_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Lock `memory` into RAM, returning whether the OS allowed it
+///
+/// Locking fails beyond `RLIMIT_MEMLOCK`; the memory then stays swappable
+/// and the first failure is logged.
+pub fn lock<T>(memory: &[T]) -> bool {
+    #[cfg(unix)]
+    // SAFETY: mlock only changes the paging of the given range
+    let locked = unsafe { libc::mlock(memory.as_ptr().cast(), std::mem::size_of_val(memory)) == 0 };
+    #[cfg(not(unix))]
+    let locked = false;
+
+    if !locked && !LOCK_FAILED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "Could not lock {} bytes of entropy into RAM ({}), raise the memlock limit to keep it out of swap",
+            std::mem::size_of_val(memory),
+            std::io::Error::last_os_error()
+        );
+    }
+    locked
+}
+
+/// Undo [`lock`] before `memory` is freed
+pub fn unlock<T>(memory: &[T]) {
+    #[cfg(unix)]
+    // SAFETY: munlock only changes the paging of the given range
+    unsafe {
+        libc::munlock(memory.as_ptr().cast(), std::mem::size_of_val(memory));
+    }
+    #[cfg(not(unix))]
+    let _ = memory;
+}
+
+/// Lock all current and future memory of the process into RAM
+pub fn lock_all() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: mlockall only changes the paging of the process
+        if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+            return Ok(());
+        }
+        Err(std::io::Error::last_os_error())
+    }
+    #[cfg(not(unix))]
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "mlockall is unix-only"))
+}
+
+/// Hand out entropy as `Bytes` that are zeroed once the last handle drops
+pub fn bytes(data: Vec<u8>) -> Bytes {
+    Bytes::from_owner(Zeroizing::new(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_bytes_outlive_clones() {
+        let bytes = bytes(vec![7; 64]);
+        let slice = bytes.slice(8..16);
+        drop(bytes);
+        assert_eq!(slice[..], [7; 8]);
+    }
+}
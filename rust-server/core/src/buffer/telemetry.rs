@@ -0,0 +1,61 @@
+//! Buffer traffic counters
+//!
+//! Totals since startup, kept by each buffer so the reader, the pool filler
+//! and requests are all counted. Rates are left to whoever scrapes them.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Running totals of a buffer's traffic
+#[derive(Debug, Default)]
+pub struct BufferCounters {
+    written: AtomicU64,
+    read: AtomicU64,
+    restored: AtomicU64,
+    overflows: AtomicU64,
+    overflowed: AtomicU64,
+}
+
+impl BufferCounters {
+    /// Account for a write of `offered` bytes of which `written` fit
+    pub(super) fn wrote(&self, offered: usize, written: usize) {
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        if written < offered {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+            self.overflowed.fetch_add((offered - written) as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn read(&self, bytes: usize) {
+        self.read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn restored(&self, bytes: usize) {
+        self.restored.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> BufferTraffic {
+        BufferTraffic {
+            bytes_in: self.written.load(Ordering::Relaxed),
+            bytes_out: self.read.load(Ordering::Relaxed),
+            bytes_restored: self.restored.load(Ordering::Relaxed),
+            overflows: self.overflows.load(Ordering::Relaxed),
+            overflowed_bytes: self.overflowed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Buffer traffic since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BufferTraffic {
+    /// Bytes written
+    pub bytes_in: u64,
+    /// Bytes read, including reservations later rolled back
+    pub bytes_out: u64,
+    /// Bytes put back by rolled back reservations
+    pub bytes_restored: u64,
+    /// Writes that did not fit in full
+    pub overflows: u64,
+    /// Bytes discarded by those writes
+    pub overflowed_bytes: u64,
+}
@@ -0,0 +1,638 @@
+//! Bias correction algorithms
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes256,
+};
+use sha3::{
+    digest::{ExtendableOutput, Update, XofReader},
+    Digest, Sha3_256, Shake256,
+};
+
+/// SHA3-256 output block size in bytes
+pub const SHA3_BLOCK: usize = 32;
+
+/// Default SHA-3 compression ratio (input bytes per output byte)
+pub const SHA3_DEFAULT_RATIO: usize = 2;
+
+/// Default XOR-fold ratio (input bytes per output byte)
+pub const XOR_FOLD_DEFAULT_RATIO: usize = 2;
+
+/// AES block size in bytes
+pub const AES_BLOCK: usize = 16;
+
+/// Default CBC-MAC compression ratio (input blocks per output block)
+pub const CBC_MAC_DEFAULT_RATIO: usize = 2;
+
+/// Fixed CBC-MAC conditioning key
+///
+/// SP 800-90B permits an arbitrary, non-secret key for the vetted
+/// conditioning function. This is SHA-256 of the ASCII string
+/// `quantis-server SP 800-90B CBC-MAC key`.
+pub const CBC_MAC_DEFAULT_KEY: [u8; 32] = [
+    0x6b, 0x98, 0xdb, 0x4e, 0xc4, 0xd7, 0x48, 0x3c, 0xa9, 0xea, 0x1c, 0x72, 0x15, 0xa0, 0x05, 0xd4,
+    0x90, 0x90, 0xfd, 0x96, 0x1e, 0xff, 0x40, 0x98, 0x37, 0xc8, 0x66, 0xbb, 0x9d, 0x43, 0x3d, 0x3a,
+];
+
+/// Bits consumed and produced by a bit-level extractor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractorStats {
+    pub input_bits: u64,
+    pub output_bits: u64,
+    /// Input pairs rejected at the first level (00 or 11)
+    pub discarded_pairs: u64,
+    /// Extracted bits dropped because they did not fill a whole byte
+    pub partial_bits: u64,
+}
+
+impl ExtractorStats {
+    /// Output bits per input bit, counting only whole output bytes
+    pub fn efficiency(&self) -> f64 {
+        if self.input_bits == 0 {
+            return 0.0;
+        }
+        (self.output_bits - self.partial_bits) as f64 / self.input_bits as f64
+    }
+}
+
+/// MSB-first bits of a byte slice
+fn bits(input: &[u8]) -> impl Iterator<Item = bool> + '_ {
+    input
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+}
+
+/// Pack bits MSB-first, returning whole bytes and the dropped remainder
+fn pack(bits: &[bool]) -> (Vec<u8>, u64) {
+    let bytes = bits
+        .chunks_exact(8)
+        .map(|chunk| chunk.iter().fold(0u8, |byte, &bit| (byte << 1) | bit as u8))
+        .collect();
+    (bytes, (bits.len() % 8) as u64)
+}
+
+/// One level of Von Neumann extraction
+///
+/// Returns the extracted bits (10 -> 1, 01 -> 0) and the values of the
+/// rejected equal pairs (00 -> 0, 11 -> 1) in order.
+fn von_neumann_level(bits: &[bool]) -> (Vec<bool>, Vec<bool>) {
+    let mut extracted = Vec::with_capacity(bits.len() / 4);
+    let mut rejected = Vec::with_capacity(bits.len() / 4);
+    for pair in bits.chunks_exact(2) {
+        if pair[0] != pair[1] {
+            extracted.push(pair[0]);
+        } else {
+            rejected.push(pair[0]);
+        }
+    }
+    (extracted, rejected)
+}
+
+/// Von Neumann debiasing
+///
+/// Non-overlapping bit pairs map 10 -> 1 and 01 -> 0; 00 and 11 are
+/// discarded. Extracted bits that do not fill a final byte are dropped.
+pub fn von_neumann(input: &[u8]) -> Vec<u8> {
+    von_neumann_with_stats(input).0
+}
+
+/// Von Neumann debiasing with discard statistics
+pub fn von_neumann_with_stats(input: &[u8]) -> (Vec<u8>, ExtractorStats) {
+    iterated_von_neumann_with_stats(input, 1)
+}
+
+/// Default recursion depth of the iterated Von Neumann extractor
+pub const ITERATED_VN_DEFAULT_DEPTH: usize = 3;
+
+/// Iterated Von Neumann debiasing
+///
+/// The values of pairs rejected at one level (00 -> 0, 11 -> 1) form an
+/// independent biased sequence that is debiased again, up to `depth`
+/// levels. Depth 1 is plain Von Neumann.
+pub fn iterated_von_neumann(input: &[u8], depth: usize) -> Vec<u8> {
+    iterated_von_neumann_with_stats(input, depth).0
+}
+
+/// Iterated Von Neumann debiasing with discard statistics
+pub fn iterated_von_neumann_with_stats(input: &[u8], depth: usize) -> (Vec<u8>, ExtractorStats) {
+    let input_bits: Vec<bool> = bits(input).collect();
+    let mut output = Vec::with_capacity(input_bits.len() / 3);
+    let mut stats = ExtractorStats {
+        input_bits: input_bits.len() as u64,
+        ..ExtractorStats::default()
+    };
+
+    let mut level = input_bits;
+    for i in 0..depth.max(1) {
+        let (extracted, rejected) = von_neumann_level(&level);
+        if i == 0 {
+            stats.discarded_pairs = rejected.len() as u64;
+        }
+        output.extend(extracted);
+        level = rejected;
+    }
+
+    stats.output_bits = output.len() as u64;
+    let (bytes, partial_bits) = pack(&output);
+    stats.partial_bits = partial_bits;
+    (bytes, stats)
+}
+
+/// Default recursion depth of the Peres extractor
+pub const PERES_DEFAULT_DEPTH: usize = 6;
+
+/// Peres iterated extractor
+///
+/// Like iterated Von Neumann, but additionally recurses into the XOR of
+/// every input pair, which recovers most of the entropy Von Neumann
+/// throws away and approaches the Shannon bound as `depth` grows.
+pub fn peres(input: &[u8], depth: usize) -> Vec<u8> {
+    peres_with_stats(input, depth).0
+}
+
+/// Peres extraction with discard statistics
+pub fn peres_with_stats(input: &[u8], depth: usize) -> (Vec<u8>, ExtractorStats) {
+    let input_bits: Vec<bool> = bits(input).collect();
+    let mut output = Vec::with_capacity(input_bits.len() / 2);
+
+    let (extracted, rejected) = von_neumann_level(&input_bits);
+    let mut stats = ExtractorStats {
+        input_bits: input_bits.len() as u64,
+        discarded_pairs: rejected.len() as u64,
+        ..ExtractorStats::default()
+    };
+    output.extend(extracted);
+    if depth > 1 {
+        peres_recurse(&pair_xors(&input_bits), depth - 1, &mut output);
+        peres_recurse(&rejected, depth - 1, &mut output);
+    }
+
+    stats.output_bits = output.len() as u64;
+    let (bytes, partial_bits) = pack(&output);
+    stats.partial_bits = partial_bits;
+    (bytes, stats)
+}
+
+fn peres_recurse(bits: &[bool], depth: usize, output: &mut Vec<bool>) {
+    if depth == 0 || bits.len() < 2 {
+        return;
+    }
+    let (extracted, rejected) = von_neumann_level(bits);
+    output.extend(extracted);
+    peres_recurse(&pair_xors(bits), depth - 1, output);
+    peres_recurse(&rejected, depth - 1, output);
+}
+
+/// XOR of each non-overlapping bit pair
+fn pair_xors(bits: &[bool]) -> Vec<bool> {
+    bits.chunks_exact(2).map(|pair| pair[0] ^ pair[1]).collect()
+}
+
+/// How the two-source extractor combines device and OS entropy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoSourceMethod {
+    /// SHA-256 over aligned 32-byte blocks of both sources with a counter
+    Hash,
+    /// GF(2) inner product of 256-bit blocks, one output bit per block pair
+    InnerProduct,
+}
+
+/// Bytes of each source per inner product output bit
+pub const INNER_PRODUCT_BLOCK: usize = 32;
+
+/// Combine device output with an equal amount of OS entropy
+///
+/// The result stays unpredictable as long as either source is sound. If
+/// the OS generator fails nothing is returned.
+pub fn two_source(input: &[u8], method: TwoSourceMethod) -> Vec<u8> {
+    let mut os = vec![0u8; input.len()];
+    if let Err(e) = getrandom::getrandom(&mut os) {
+        tracing::error!("OS entropy unavailable for two-source extraction: {}", e);
+        return Vec::new();
+    }
+    two_source_with(input, &os, method)
+}
+
+/// Combine two independent equal-length streams
+pub fn two_source_with(device: &[u8], os: &[u8], method: TwoSourceMethod) -> Vec<u8> {
+    match method {
+        TwoSourceMethod::Hash => crate::device::mix::hash(&[device.to_vec(), os.to_vec()]),
+        TwoSourceMethod::InnerProduct => {
+            let products: Vec<bool> = device
+                .chunks_exact(INNER_PRODUCT_BLOCK)
+                .zip(os.chunks_exact(INNER_PRODUCT_BLOCK))
+                .map(|(x, y)| {
+                    let ones: u32 = x.iter().zip(y).map(|(a, b)| (a & b).count_ones()).sum();
+                    ones % 2 == 1
+                })
+                .collect();
+            pack(&products).0
+        }
+    }
+}
+
+/// Device bytes needed for `count` two-source output bytes
+pub fn two_source_input_len(count: usize, method: TwoSourceMethod) -> usize {
+    match method {
+        TwoSourceMethod::Hash => count,
+        TwoSourceMethod::InnerProduct => count * 8 * INNER_PRODUCT_BLOCK,
+    }
+}
+
+/// No correction - raw quantum data
+pub fn none(input: &[u8]) -> Vec<u8> {
+    input.to_vec()
+}
+
+/// SHA3-256 conditioning - each output block hashes `ratio` blocks of input
+///
+/// Trailing input shorter than a full input block is discarded.
+pub fn sha3(input: &[u8], ratio: usize) -> Vec<u8> {
+    let block_in = SHA3_BLOCK * ratio.max(1);
+    let mut output = Vec::with_capacity(input.len() / ratio.max(1));
+
+    for chunk in input.chunks_exact(block_in) {
+        output.extend_from_slice(&Sha3_256::digest(chunk));
+    }
+    output
+}
+
+/// Raw bytes needed for at least `count` bytes of SHA-3 conditioned output
+pub fn sha3_input_len(count: usize, ratio: usize) -> usize {
+    count.div_ceil(SHA3_BLOCK) * SHA3_BLOCK * ratio.max(1)
+}
+
+/// AES-256 CBC-MAC conditioning (NIST SP 800-90B section 3.1.5.1.1)
+///
+/// Each 16-byte output block is the CBC-MAC (zero IV) of `ratio` input
+/// blocks. Trailing input shorter than a full input block is discarded.
+pub fn aes_cbc_mac(input: &[u8], key: &[u8; 32], ratio: usize) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let block_in = AES_BLOCK * ratio.max(1);
+    let mut output = Vec::with_capacity(input.len() / ratio.max(1));
+
+    for chunk in input.chunks_exact(block_in) {
+        let mut state = GenericArray::from([0u8; AES_BLOCK]);
+        for block in chunk.chunks_exact(AES_BLOCK) {
+            for (s, b) in state.iter_mut().zip(block) {
+                *s ^= b;
+            }
+            cipher.encrypt_block(&mut state);
+        }
+        output.extend_from_slice(&state);
+    }
+    output
+}
+
+/// Raw bytes needed for at least `count` bytes of CBC-MAC conditioned output
+pub fn aes_cbc_mac_input_len(count: usize, ratio: usize) -> usize {
+    count.div_ceil(AES_BLOCK) * AES_BLOCK * ratio.max(1)
+}
+
+/// XOR-folding whitening - each output byte is the XOR of `ratio` adjacent
+/// input bytes (2 halves the output, 4 quarters it)
+///
+/// Cheap and size-predictable, but only reduces bias; it does not remove
+/// correlations. Trailing input shorter than `ratio` bytes is discarded.
+pub fn xor_fold(input: &[u8], ratio: usize) -> Vec<u8> {
+    input
+        .chunks_exact(ratio.max(1))
+        .map(|chunk| chunk.iter().fold(0u8, |acc, b| acc ^ b))
+        .collect()
+}
+
+/// Toeplitz input block size in bits
+pub const TOEPLITZ_INPUT_BITS: usize = 2048;
+
+/// Default Toeplitz output rate (output bits per input bit)
+pub const TOEPLITZ_DEFAULT_RATE: f64 = 0.5;
+
+/// Seeded Toeplitz-hashing extractor
+///
+/// Multiplies each `input_bits` block by a binary `output_bits x input_bits`
+/// Toeplitz matrix over GF(2). The matrix is defined by `input_bits +
+/// output_bits - 1` seed bits: `T[i][j] = seed[i - j + input_bits - 1]`.
+/// Unlike Von Neumann, this removes correlations between bits, as long as
+/// the output rate stays below the source's min-entropy per bit.
+#[derive(Debug, Clone)]
+pub struct Toeplitz {
+    input_bits: usize,
+    output_bits: usize,
+    seed: Vec<u64>,
+}
+
+impl Toeplitz {
+    /// Build an extractor from explicit seed bytes (MSB-first bits)
+    ///
+    /// Both sizes must be non-zero multiples of 64 bits (input) and 8 bits
+    /// (output), and the seed must hold at least `input_bits + output_bits - 1`
+    /// bits.
+    pub fn new(input_bits: usize, output_bits: usize, seed: &[u8]) -> Self {
+        assert!(input_bits > 0 && input_bits.is_multiple_of(64), "input_bits must be a multiple of 64");
+        assert!(output_bits > 0 && output_bits.is_multiple_of(8), "output_bits must be a multiple of 8");
+        assert!(
+            seed.len() * 8 >= input_bits + output_bits - 1,
+            "seed too short for matrix"
+        );
+
+        // One spare word so windows can always read one word ahead
+        let mut seed = pack_bits(seed);
+        seed.push(0);
+
+        Self {
+            input_bits,
+            output_bits,
+            seed,
+        }
+    }
+
+    /// Build an extractor for an output rate with the fixed default seed
+    ///
+    /// The seed is the SHAKE256 expansion of the ASCII string
+    /// `quantis-server toeplitz seed`, so output is reproducible.
+    pub fn with_rate(rate: f64) -> Self {
+        let output_bits = ((TOEPLITZ_INPUT_BITS as f64 * rate) as usize / 8).max(1) * 8;
+        let mut seed = vec![0u8; (TOEPLITZ_INPUT_BITS + output_bits).div_ceil(8)];
+
+        let mut shake = Shake256::default();
+        shake.update(b"quantis-server toeplitz seed");
+        shake.finalize_xof().read(&mut seed);
+
+        Self::new(TOEPLITZ_INPUT_BITS, output_bits, &seed)
+    }
+
+    /// Input bytes consumed per output block
+    pub fn input_block(&self) -> usize {
+        self.input_bits / 8
+    }
+
+    /// Output bytes produced per input block
+    pub fn output_block(&self) -> usize {
+        self.output_bits / 8
+    }
+
+    /// Raw bytes needed for at least `count` bytes of output
+    pub fn input_len(&self, count: usize) -> usize {
+        count.div_ceil(self.output_block()) * self.input_block()
+    }
+
+    /// Extract from every full input block; trailing input is discarded
+    pub fn extract(&self, input: &[u8]) -> Vec<u8> {
+        let words = self.input_bits / 64;
+        let mut output = Vec::with_capacity(input.len() / self.input_block() * self.output_block());
+        let mut window = vec![0u64; words];
+
+        for chunk in input.chunks_exact(self.input_block()) {
+            // out_i = XOR_k seed[i + k] * x[n - 1 - k], so reverse the block
+            let reversed: Vec<u8> = chunk.iter().rev().map(|b| b.reverse_bits()).collect();
+            let x = pack_bits(&reversed);
+
+            let mut out_byte = 0u8;
+            for i in 0..self.output_bits {
+                seed_window(&self.seed, i, &mut window);
+                let ones: u32 = window.iter().zip(&x).map(|(w, x)| (w & x).count_ones()).sum();
+                out_byte = (out_byte << 1) | (ones & 1) as u8;
+                if i % 8 == 7 {
+                    output.push(out_byte);
+                    out_byte = 0;
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Pack bytes into big-endian words, MSB-first bit order
+fn pack_bits(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u64::from_be_bytes(word)
+        })
+        .collect()
+}
+
+/// Copy `out.len()` words of `bits` starting at bit `offset`
+fn seed_window(bits: &[u64], offset: usize, out: &mut [u64]) {
+    let (q, r) = (offset / 64, offset % 64);
+    for (k, word) in out.iter_mut().enumerate() {
+        *word = if r == 0 {
+            bits[q + k]
+        } else {
+            (bits[q + k] << r) | (bits[q + k + 1] >> (64 - r))
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn von_neumann_emits_bits_msb_first() {
+        // Pairs 10,01,10,01 per byte extract 1,0,1,0
+        let (output, stats) = von_neumann_with_stats(&[0b1001_1001, 0b1001_1001]);
+        assert_eq!(output, [0b1010_1010]);
+        assert_eq!(stats.output_bits, 8);
+        assert_eq!(stats.discarded_pairs, 0);
+        assert_eq!(stats.partial_bits, 0);
+
+        // 00 and 11 are discarded; 01 yields a zero bit, not nothing
+        let (output, stats) = von_neumann_with_stats(&[0b0100_1101, 0b0101_0101, 0b0101_0101]);
+        assert_eq!(output, [0x00]);
+        assert_eq!(stats.discarded_pairs, 2);
+        assert_eq!(stats.output_bits, 10);
+        assert_eq!(stats.partial_bits, 2);
+    }
+
+    #[test]
+    fn iterated_von_neumann_recovers_discarded_pairs() {
+        // Level 1 rejects 00,11 pairs, whose values 0,1 yield another bit
+        let input = [0b0011_0011; 16];
+        assert!(von_neumann(&input).is_empty());
+        let (output, stats) = iterated_von_neumann_with_stats(&input, 2);
+        assert_eq!(stats.output_bits, 32);
+        assert_eq!(output, [0x00; 4]);
+
+        let (_, plain) = von_neumann_with_stats(&[0x6c; 256]);
+        let (_, iterated) = iterated_von_neumann_with_stats(&[0x6c; 256], 3);
+        assert!(iterated.efficiency() > plain.efficiency());
+    }
+
+    /// Bits that are one with probability `p_one`
+    fn biased_bytes(len: usize, p_one: f64) -> Vec<u8> {
+        use crate::device::{mock::MockSource, EntropySource};
+        let threshold = (p_one * 256.0) as u8;
+        let uniform = MockSource::new("biased", 11).read(len * 8).unwrap();
+        uniform
+            .chunks_exact(8)
+            .map(|chunk| chunk.iter().fold(0u8, |byte, &u| (byte << 1) | (u < threshold) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn peres_extracts_more_than_von_neumann() {
+        for p_one in [0.5, 0.3] {
+            let input = biased_bytes(1 << 14, p_one);
+            let (_, vn) = von_neumann_with_stats(&input);
+            let (_, iterated) = iterated_von_neumann_with_stats(&input, 3);
+            let (peres_out, peres) = peres_with_stats(&input, PERES_DEFAULT_DEPTH);
+
+            assert!(iterated.efficiency() > vn.efficiency());
+            assert!(peres.efficiency() > iterated.efficiency());
+
+            // Shannon bound per input bit
+            let h = -(p_one * p_one.log2() + (1.0 - p_one) * (1.0 - p_one).log2());
+            assert!(peres.efficiency() < h);
+
+            let ones: u32 = peres_out.iter().map(|b| b.count_ones()).sum();
+            let balance = ones as f64 / (peres_out.len() * 8) as f64;
+            assert!((balance - 0.5).abs() < 0.02, "balance {}", balance);
+        }
+    }
+
+    #[test]
+    fn peres_depth_one_is_von_neumann() {
+        let input = biased_bytes(256, 0.4);
+        assert_eq!(peres(&input, 1), von_neumann(&input));
+    }
+
+    #[test]
+    fn two_source_output_depends_on_both_sources() {
+        let device = vec![0x3cu8; 64];
+        let os = vec![0x5au8; 64];
+        let mut other_os = os.clone();
+        other_os[0] ^= 1;
+
+        let hashed = two_source_with(&device, &os, TwoSourceMethod::Hash);
+        assert_eq!(hashed.len(), 64);
+        assert_ne!(hashed, two_source_with(&device, &other_os, TwoSourceMethod::Hash));
+
+        // 0x3c & 0x5a = 0x18 has two bits set, so 32 bytes give even parity
+        let device = vec![0x3cu8; 8 * INNER_PRODUCT_BLOCK];
+        let os = vec![0x5au8; 8 * INNER_PRODUCT_BLOCK];
+        assert_eq!(two_source_with(&device, &os, TwoSourceMethod::InnerProduct), [0x00]);
+        let mut odd = os.clone();
+        odd[0] = 0x08;
+        assert_eq!(two_source_with(&device, &odd, TwoSourceMethod::InnerProduct), [0x80]);
+
+        // A broken device stream still yields OS-strength output
+        let broken = [0u8; 64];
+        assert_ne!(
+            two_source(&broken, TwoSourceMethod::Hash),
+            two_source(&broken, TwoSourceMethod::Hash)
+        );
+    }
+
+    #[test]
+    fn sha3_matches_reference_digests() {
+        assert_eq!(
+            hex::encode(sha3(&[0u8; 64], 2)),
+            "070fa1ab6fcc557ed14d42941f1967693048551eb9042a8d0a057afbd75e81e0"
+        );
+        let counting: Vec<u8> = (0..64).collect();
+        assert_eq!(
+            hex::encode(sha3(&counting, 2)),
+            "c8ad478f4e1dd9d47dfc3b985708d92db1f8db48fe9cddd459e63c321f490402"
+        );
+    }
+
+    #[test]
+    fn sha3_compresses_by_ratio() {
+        let input = vec![0xa5u8; sha3_input_len(100, 4)];
+        assert_eq!(input.len(), 4 * 128);
+        assert_eq!(sha3(&input, 4).len(), 128);
+        // A partial trailing block produces no output
+        assert_eq!(sha3(&input[..127], 4).len(), 0);
+    }
+
+    #[test]
+    fn aes_cbc_mac_matches_reference_vectors() {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+
+        // Single block: CBC-MAC is plain AES-256 (FIPS-197 appendix C.3)
+        let block = hex::decode("00112233445566778899aabbccddeeff").unwrap();
+        assert_eq!(
+            hex::encode(aes_cbc_mac(&block, &key, 1)),
+            "8ea2b7ca516745bfeafc49904b496089"
+        );
+
+        let counting: Vec<u8> = (0..64).collect();
+        assert_eq!(
+            hex::encode(aes_cbc_mac(&counting[..32], &key, 2)),
+            "c77147ebd5121de8d0fae7762423b6bf"
+        );
+        assert_eq!(
+            hex::encode(aes_cbc_mac(&counting, &key, 4)),
+            "7c8ab8ef66c293ca63ff37a35ec1c2ba"
+        );
+        assert_eq!(
+            hex::encode(aes_cbc_mac(&[0u8; 32], &CBC_MAC_DEFAULT_KEY, 2)),
+            "142040cc0bab910d6de4ed32c1ae31d4"
+        );
+    }
+
+    #[test]
+    fn aes_cbc_mac_compresses_by_ratio() {
+        let input = vec![0x3cu8; aes_cbc_mac_input_len(20, 3)];
+        assert_eq!(input.len(), 3 * 32);
+        assert_eq!(aes_cbc_mac(&input, &CBC_MAC_DEFAULT_KEY, 3).len(), 32);
+    }
+
+    /// Bit-by-bit matrix product, straight from the definition
+    fn toeplitz_reference(n: usize, m: usize, seed: &[u8], input: &[u8]) -> Vec<u8> {
+        let bit = |bytes: &[u8], i: usize| (bytes[i / 8] >> (7 - i % 8)) & 1;
+        let mut output = vec![0u8; m / 8];
+        for i in 0..m {
+            let mut acc = 0;
+            for j in 0..n {
+                acc ^= bit(seed, i + n - 1 - j) & bit(input, j);
+            }
+            output[i / 8] |= acc << (7 - i % 8);
+        }
+        output
+    }
+
+    #[test]
+    fn toeplitz_matches_matrix_definition() {
+        let seed: Vec<u8> = (0..40u8).map(|i| i.wrapping_mul(97).wrapping_add(13)).collect();
+        let input: Vec<u8> = (0..16u8).map(|i| i.wrapping_mul(31) ^ 0x5c).collect();
+
+        let extractor = Toeplitz::new(128, 64, &seed);
+        assert_eq!(extractor.extract(&input), toeplitz_reference(128, 64, &seed, &input));
+    }
+
+    #[test]
+    fn toeplitz_is_linear_and_rate_sized() {
+        let extractor = Toeplitz::with_rate(0.75);
+        assert_eq!(extractor.input_block(), 256);
+        assert_eq!(extractor.output_block(), 192);
+
+        let a: Vec<u8> = (0..512).map(|i| (i * 7) as u8).collect();
+        let b: Vec<u8> = (0..512).map(|i| (i * 13 + 5) as u8).collect();
+        let ab: Vec<u8> = a.iter().zip(&b).map(|(x, y)| x ^ y).collect();
+
+        let (ea, eb) = (extractor.extract(&a), extractor.extract(&b));
+        let expected: Vec<u8> = ea.iter().zip(&eb).map(|(x, y)| x ^ y).collect();
+        assert_eq!(extractor.extract(&ab), expected);
+        assert_eq!(ea.len(), 384);
+        assert_eq!(extractor.input_len(385), 768);
+    }
+
+    #[test]
+    fn xor_fold_halves_and_quarters() {
+        let input = [0b1100_0000, 0b1010_0000, 0x0f, 0xff, 0x01];
+        assert_eq!(xor_fold(&input, 2), vec![0b0110_0000, 0xf0]);
+        assert_eq!(xor_fold(&input, 4), vec![0b0110_0000 ^ 0xf0]);
+    }
+
+    #[test]
+    fn xor_fold_output_size_is_predictable() {
+        let alternating: Vec<u8> = (0..64).map(|i| if i % 2 == 0 { 0xee } else { 0x77 }).collect();
+        assert_eq!(xor_fold(&alternating, 2), vec![0x99; 32]);
+        assert_eq!(xor_fold(&alternating, 4), vec![0x00; 16]);
+        assert_eq!(xor_fold(&alternating[..63], 4).len(), 15);
+    }
+}
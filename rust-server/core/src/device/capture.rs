@@ -0,0 +1,280 @@
+//! Recording and replay of raw device output
+//!
+//! A capture is a JSON-lines file with one record per device read: when it
+//! happened, which devices were serving, how the read fared in the health
+//! tests, and the bytes themselves in hex. `ReplaySource` serves the bytes
+//! of a capture again, in order, so a run can be analysed offline or a bug
+//! reproduced with the output that triggered it.
+//!
+//! A capture holds raw output that went on to be served, so it is created
+//! readable only by its owner, and a replay is not random to anyone who
+//! has the file.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+use super::{DeviceInfo, EntropySource, QuantisError};
+
+/// How a recorded read fared in the continuous health tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureHealth {
+    /// Passed and buffered
+    Passed,
+    /// Failed and discarded
+    Failed,
+    /// Read while quarantined, as a recovery probe
+    Quarantined,
+}
+
+/// One recorded device read
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Unix time of the read in milliseconds
+    pub at_ms: u64,
+    /// Serials of the devices serving the read
+    pub devices: Vec<String>,
+    pub health: CaptureHealth,
+    /// The failed test, or whether a probe recovered
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub data: Vec<u8>,
+}
+
+fn to_hex<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(data))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    hex::decode(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+struct Recording {
+    file: File,
+    size: u64,
+    full: bool,
+}
+
+/// Appends device reads to a capture file, up to a size limit
+pub struct CaptureWriter {
+    path: PathBuf,
+    max_size: u64,
+    recording: Mutex<Recording>,
+}
+
+impl CaptureWriter {
+    /// Append to the capture at `path`, stopping once it reaches `max_size`
+    /// bytes
+    pub fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            recording: Mutex::new(Recording { file, size, full: false }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a read stamped with the current time
+    pub fn record(
+        &self,
+        data: &[u8],
+        devices: Vec<String>,
+        health: CaptureHealth,
+        detail: impl Into<String>,
+    ) {
+        let mut recording = self.recording.lock().unwrap();
+        if recording.full {
+            return;
+        }
+        let record = CaptureRecord {
+            at_ms: unix_millis(),
+            devices,
+            health,
+            detail: detail.into(),
+            data: data.to_vec(),
+        };
+        let mut line = serde_json::to_vec(&record).expect("capture record serializes");
+        line.push(b'\n');
+        if recording.size + line.len() as u64 > self.max_size {
+            warn!("Capture {} reached its size limit, recording stopped", self.path.display());
+            recording.full = true;
+            return;
+        }
+        match recording.file.write_all(&line) {
+            Ok(()) => recording.size += line.len() as u64,
+            Err(e) => warn!("Failed to record to capture {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Entropy source serving the bytes of a capture
+pub struct ReplaySource {
+    path: PathBuf,
+    lines: BufReader<File>,
+    pending: Vec<u8>,
+    offset: usize,
+    looping: bool,
+}
+
+impl ReplaySource {
+    /// Replay the capture at `path`, from the start again at its end if
+    /// `looping`
+    pub fn open(path: &Path, looping: bool) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines: BufReader::new(File::open(path)?),
+            pending: Vec::new(),
+            offset: 0,
+            looping,
+        })
+    }
+
+    /// Data of the next record, `None` at the end of the capture
+    fn next_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.lines.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            // Skip lines cut short by a crash mid-write
+            if let Ok(record) = serde_json::from_str::<CaptureRecord>(&line) {
+                if !record.data.is_empty() {
+                    return Ok(Some(record.data));
+                }
+            }
+        }
+    }
+
+    fn exhausted(&self) -> QuantisError {
+        QuantisError::DeviceUnavailable(format!("replay of {} (capture exhausted)", self.path.display()))
+    }
+}
+
+impl EntropySource for ReplaySource {
+    fn info(&mut self) -> Result<DeviceInfo, QuantisError> {
+        let name = self.path.file_name().unwrap_or(self.path.as_os_str());
+        Ok(DeviceInfo {
+            product: "Capture Replay".to_string(),
+            serial: format!("replay:{}", name.to_string_lossy()),
+            version: "0.0".to_string(),
+        })
+    }
+
+    fn read(&mut self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let mut output = Vec::with_capacity(size);
+        // Rewinding only after a record was served keeps an empty capture
+        // from looping forever
+        let mut rewound = false;
+        while output.len() < size {
+            if self.offset == self.pending.len() {
+                match self.next_record()? {
+                    Some(data) => {
+                        self.pending = data;
+                        self.offset = 0;
+                        rewound = false;
+                    }
+                    None if self.looping && !rewound => {
+                        self.lines.rewind()?;
+                        rewound = true;
+                        continue;
+                    }
+                    None => return Err(self.exhausted()),
+                }
+            }
+            let take = (size - output.len()).min(self.pending.len() - self.offset);
+            output.extend_from_slice(&self.pending[self.offset..self.offset + take]);
+            self.offset += take;
+        }
+        Ok(output)
+    }
+
+    fn backend(&self) -> &'static str {
+        "replay"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("quantis-capture-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    #[test]
+    fn replays_recorded_reads_in_order() {
+        let path = temp_path("replay");
+        let _ = std::fs::remove_file(&path);
+        let writer = CaptureWriter::open(&path, 1 << 20).unwrap();
+        writer.record(&[1, 2, 3], vec!["A".to_string()], CaptureHealth::Passed, "");
+        writer.record(&[4, 5], vec!["A".to_string()], CaptureHealth::Failed, "repetition count");
+        drop(writer);
+        // A line cut short by a crash is skipped
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"at_ms\":1,\"dev")
+            .unwrap();
+
+        let first: CaptureRecord =
+            serde_json::from_str(std::fs::read_to_string(&path).unwrap().lines().next().unwrap()).unwrap();
+        assert_eq!(first.data, [1, 2, 3]);
+        assert_eq!(first.health, CaptureHealth::Passed);
+        assert!(first.at_ms > 0);
+
+        let mut replay = ReplaySource::open(&path, false).unwrap();
+        assert!(replay.info().unwrap().serial.starts_with("replay:"));
+        assert_eq!(replay.read(4).unwrap(), [1, 2, 3, 4]);
+        assert!(replay.read(2).is_err());
+
+        let mut looping = ReplaySource::open(&path, true).unwrap();
+        assert_eq!(looping.read(7).unwrap(), [1, 2, 3, 4, 5, 1, 2]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn stops_recording_at_the_size_limit() {
+        let path = temp_path("limit");
+        let _ = std::fs::remove_file(&path);
+        let writer = CaptureWriter::open(&path, 256).unwrap();
+        for _ in 0..10 {
+            writer.record(&[7; 32], Vec::new(), CaptureHealth::Passed, "");
+        }
+        let size = std::fs::metadata(&path).unwrap().len();
+        assert!(size > 0 && size <= 256);
+
+        // An empty capture fails rather than looping forever
+        std::fs::write(&path, "").unwrap();
+        assert!(ReplaySource::open(&path, true).unwrap().read(1).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
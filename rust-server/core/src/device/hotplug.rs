@@ -0,0 +1,153 @@
+//! USB hotplug notifications for Quantis devices
+//!
+//! Where libusb has no hotplug support, as on Windows, the device list is
+//! scanned every `POLL_INTERVAL` instead and the differences published.
+
+use rusb::{Context, Device, Hotplug, HotplugBuilder, UsbContext};
+use std::{collections::BTreeSet, time::Duration};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use super::{find_devices, DeviceEvent, QuantisError, PRODUCT_ID, VENDOR_ID};
+
+/// Pause between scans of the device list without hotplug support
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+struct HotplugCallback {
+    events: broadcast::Sender<DeviceEvent>,
+}
+
+impl Hotplug<Context> for HotplugCallback {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        // No receivers is fine, the event is simply dropped
+        let _ = self.events.send(DeviceEvent::Arrived {
+            bus: device.bus_number(),
+            address: device.address(),
+        });
+    }
+
+    fn device_left(&mut self, device: Device<Context>) {
+        let _ = self.events.send(DeviceEvent::Left {
+            bus: device.bus_number(),
+            address: device.address(),
+        });
+    }
+}
+
+/// Start watching for Quantis arrival/removal on a dedicated thread
+///
+/// Events are published on `events` as soon as libusb reports them.
+/// Returns `Ok(false)` if the platform's libusb has no hotplug support and
+/// the device list is polled instead.
+pub fn start_hotplug_monitor(events: broadcast::Sender<DeviceEvent>) -> Result<bool, QuantisError> {
+    if !rusb::has_hotplug() {
+        start_polling(events)?;
+        return Ok(false);
+    }
+
+    let context = Context::new()?;
+    let mut builder = HotplugBuilder::new();
+    builder
+        .vendor_id(VENDOR_ID)
+        .product_id(PRODUCT_ID)
+        .enumerate(false);
+    let registration = builder.register(&context, Box::new(HotplugCallback { events }))?;
+
+    std::thread::Builder::new()
+        .name("usb-hotplug".to_string())
+        .spawn(move || {
+            // Keep the callback registered for the lifetime of the thread
+            let _registration = registration;
+            loop {
+                if let Err(e) = context.handle_events(None) {
+                    error!("USB event handling failed, hotplug monitoring stopped: {}", e);
+                    break;
+                }
+            }
+        })?;
+
+    Ok(true)
+}
+
+/// Bus and address of every attached Quantis device
+fn locations(context: &Context) -> Result<BTreeSet<(u8, u8)>, QuantisError> {
+    Ok(find_devices(context)?
+        .iter()
+        .map(|device| (device.bus_number(), device.address()))
+        .collect())
+}
+
+/// Departures and arrivals between two scans
+fn changes(before: &BTreeSet<(u8, u8)>, after: &BTreeSet<(u8, u8)>) -> Vec<DeviceEvent> {
+    let left = before
+        .difference(after)
+        .map(|&(bus, address)| DeviceEvent::Left { bus, address });
+    let arrived = after
+        .difference(before)
+        .map(|&(bus, address)| DeviceEvent::Arrived { bus, address });
+    left.chain(arrived).collect()
+}
+
+/// Scan the device list every `POLL_INTERVAL` on a dedicated thread
+fn start_polling(events: broadcast::Sender<DeviceEvent>) -> Result<(), QuantisError> {
+    let context = Context::new()?;
+    let mut known = locations(&context)?;
+
+    std::thread::Builder::new()
+        .name("usb-poll".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(POLL_INTERVAL);
+            match locations(&context) {
+                Ok(current) => {
+                    for event in changes(&known, &current) {
+                        let _ = events.send(event);
+                    }
+                    known = current;
+                }
+                Err(e) => warn!("Failed to scan for USB devices: {}", e),
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Log every device event until the channel closes
+pub async fn log_device_events(mut events: broadcast::Receiver<DeviceEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(DeviceEvent::Arrived { bus, address }) => {
+                info!("Quantis device attached (bus {} address {})", bus, address);
+            }
+            Ok(DeviceEvent::Left { bus, address }) => {
+                warn!("Quantis device removed (bus {} address {})", bus, address);
+            }
+            Ok(DeviceEvent::Failed { serial }) => {
+                error!("Quantis device {} marked failed", serial);
+            }
+            Ok(DeviceEvent::Failover { from, to }) => {
+                warn!("Failed over from device {} to {}", from, to);
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} device events", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_report_departures_and_arrivals() {
+        let before = BTreeSet::from([(1, 4), (1, 5)]);
+        let after = BTreeSet::from([(1, 5), (2, 3)]);
+        let events = changes(&before, &after);
+        assert!(matches!(events[..], [
+            DeviceEvent::Left { bus: 1, address: 4 },
+            DeviceEvent::Arrived { bus: 2, address: 3 },
+        ]));
+        assert!(changes(&after, &after).is_empty());
+    }
+}
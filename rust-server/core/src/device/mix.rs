@@ -0,0 +1,84 @@
+//! Mixing of entropy from several devices
+//!
+//! Combining independent sources means a single faulty or compromised
+//! unit cannot control the output on its own.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How output from multiple devices is combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum MixMode {
+    /// Read from the active device only
+    #[default]
+    None,
+    /// XOR equal-length streams from every healthy device
+    Xor,
+    /// SHA-256 over aligned blocks from every healthy device
+    Hash,
+}
+
+/// Combine equal-length streams
+pub fn mix(mode: MixMode, streams: &[Vec<u8>]) -> Vec<u8> {
+    match mode {
+        MixMode::None => streams.first().cloned().unwrap_or_default(),
+        MixMode::Xor => xor(streams),
+        MixMode::Hash => hash(streams),
+    }
+}
+
+/// XOR all streams together
+pub fn xor(streams: &[Vec<u8>]) -> Vec<u8> {
+    let len = streams.iter().map(Vec::len).min().unwrap_or(0);
+    let mut output = vec![0u8; len];
+
+    for stream in streams {
+        for (out, byte) in output.iter_mut().zip(stream) {
+            *out ^= byte;
+        }
+    }
+    output
+}
+
+/// Hash aligned 32-byte blocks of all streams with a block counter
+pub fn hash(streams: &[Vec<u8>]) -> Vec<u8> {
+    let len = streams.iter().map(Vec::len).min().unwrap_or(0);
+    let mut output = Vec::with_capacity(len);
+
+    for (counter, start) in (0..len).step_by(32).enumerate() {
+        let end = (start + 32).min(len);
+        let mut hasher = Sha256::new();
+        hasher.update((counter as u64).to_be_bytes());
+        for stream in streams {
+            hasher.update(&stream[start..end]);
+        }
+        output.extend_from_slice(&hasher.finalize()[..end - start]);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xor_cancels_identical_streams() {
+        let a = vec![0x5a; 40];
+        assert_eq!(xor(&[a.clone(), a.clone()]), vec![0u8; 40]);
+        assert_eq!(xor(&[a.clone(), vec![0u8; 40]]), a);
+    }
+
+    #[test]
+    fn hash_preserves_length_and_depends_on_every_source() {
+        let a = vec![1u8; 70];
+        let b = vec![2u8; 70];
+        let mut c = b.clone();
+        c[69] ^= 1;
+
+        let mixed = hash(&[a.clone(), b]);
+        assert_eq!(mixed.len(), 70);
+        assert_ne!(mixed, hash(&[a, c]));
+    }
+}
@@ -0,0 +1,80 @@
+//! Mock entropy source for tests and benchmarks
+//!
+//! Produces a deterministic xorshift stream. It is NOT random and must
+//! never back a production deployment.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use super::{DeviceInfo, EntropySource, QuantisError};
+
+pub struct MockSource {
+    serial: String,
+    state: u64,
+    failing: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+}
+
+impl MockSource {
+    /// Create a mock source with the given serial and seed
+    pub fn new(serial: impl Into<String>, seed: u64) -> Self {
+        Self {
+            serial: serial.into(),
+            // xorshift never leaves the all-zero state
+            state: seed.max(1),
+            failing: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Switch that makes every read fail while set
+    pub fn fail_switch(&self) -> Arc<AtomicBool> {
+        self.failing.clone()
+    }
+
+    /// Flag set once the source is closed
+    pub fn closed_flag(&self) -> Arc<AtomicBool> {
+        self.closed.clone()
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl EntropySource for MockSource {
+    fn info(&mut self) -> Result<DeviceInfo, QuantisError> {
+        Ok(DeviceInfo {
+            product: "Mock Entropy Source".to_string(),
+            serial: self.serial.clone(),
+            version: "0.0".to_string(),
+        })
+    }
+
+    fn read(&mut self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(QuantisError::Timeout);
+        }
+
+        let mut output = Vec::with_capacity(size + 8);
+        while output.len() < size {
+            let word = self.next();
+            output.extend_from_slice(&word.to_le_bytes());
+        }
+        output.truncate(size);
+        Ok(output)
+    }
+
+    fn backend(&self) -> &'static str {
+        "mock"
+    }
+
+    fn close(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,370 @@
+//! Quantis device interface
+
+use rusb::{Context, Device, DeviceHandle, UsbContext};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+pub mod bias_correction;
+pub mod capture;
+pub mod hotplug;
+pub mod mix;
+pub mod mock;
+pub mod pipeline;
+pub mod pool;
+pub mod rate;
+#[cfg(target_os = "linux")]
+pub mod udev;
+
+const VENDOR_ID: u16 = 0x0aba;
+const PRODUCT_ID: u16 = 0x0102;
+const ENDPOINT_IN: u8 = 0x81;
+const TIMEOUT_MS: u64 = 5000;
+const INTERFACE: u8 = 0;
+const CONFIGURATION: u8 = 1;
+const CLAIM_ATTEMPTS: u32 = 5;
+const CLAIM_RETRY_DELAY_MS: u64 = 200;
+
+#[derive(Error, Debug)]
+pub enum QuantisError {
+    #[error("USB error: {0}")]
+    Usb(#[from] rusb::Error),
+    
+    #[error("Device not found")]
+    DeviceNotFound,
+    
+    #[error("Device {0} is not available")]
+    DeviceUnavailable(String),
+
+    #[error("Device {0} is not among the configured serials")]
+    NotSelected(String),
+    
+    #[error("Read timeout")]
+    Timeout,
+
+    #[error("Device busy with another read")]
+    Busy,
+    
+    #[error("Invalid response from device")]
+    InvalidResponse,
+
+    #[error("Failed to open USB device: {source}{}", open_hint(.source))]
+    Open {
+        #[source]
+        source: rusb::Error,
+    },
+
+    #[error("Failed to claim USB interface after {attempts} attempt(s): {source}{}", claim_hint(.source))]
+    Claim {
+        attempts: u32,
+        #[source]
+        source: rusb::Error,
+    },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub product: String,
+    pub serial: String,
+    pub version: String,
+}
+
+/// Device lifecycle and failover events
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    Arrived { bus: u8, address: u8 },
+    Left { bus: u8, address: u8 },
+    Failed { serial: String },
+    Failover { from: String, to: String },
+}
+
+/// A source of raw entropy
+pub trait EntropySource: Send {
+    /// Get device information
+    fn info(&mut self) -> Result<DeviceInfo, QuantisError>;
+
+    /// Read raw entropy
+    fn read(&mut self, size: usize) -> Result<Vec<u8>, QuantisError>;
+
+    /// How the source is accessed, e.g. `usb`
+    fn backend(&self) -> &'static str;
+
+    /// Check if the source is healthy
+    fn health_check(&mut self) -> Result<bool, QuantisError> {
+        match self.read(16) {
+            Ok(data) => {
+                let first = data[0];
+                Ok(!data.iter().all(|&b| b == first))
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// USB bus number and address, for matching hotplug events
+    fn location(&self) -> Option<(u8, u8)> {
+        None
+    }
+
+    /// Let go of the underlying device before the server exits
+    fn close(&mut self) {}
+}
+
+pub struct QuantisDevice {
+    handle: DeviceHandle<Context>,
+    timeout: std::time::Duration,
+}
+
+/// Find all attached Quantis devices
+fn find_devices(context: &Context) -> Result<Vec<Device<Context>>, QuantisError> {
+    Ok(context
+        .devices()?
+        .iter()
+        .filter(|device| {
+            if let Ok(desc) = device.device_descriptor() {
+                desc.vendor_id() == VENDOR_ID && desc.product_id() == PRODUCT_ID
+            } else {
+                false
+            }
+        })
+        .collect())
+}
+
+impl QuantisDevice {
+    /// Number of attached Quantis devices
+    pub fn count() -> Result<usize, QuantisError> {
+        let context = Context::new()?;
+        Ok(find_devices(&context)?.len())
+    }
+
+    /// Open a Quantis device by index
+    pub fn open(index: usize) -> Result<Self, QuantisError> {
+        let context = Context::new()?;
+        let devices = find_devices(&context)?;
+        
+        if devices.is_empty() {
+            return Err(QuantisError::DeviceNotFound);
+        }
+        
+        if index >= devices.len() {
+            return Err(QuantisError::DeviceNotFound);
+        }
+        
+        Self::from_device(&devices[index])
+    }
+
+    /// Open the Quantis device at a USB bus/address
+    pub fn open_at(bus: u8, address: u8) -> Result<Self, QuantisError> {
+        let context = Context::new()?;
+        let device = find_devices(&context)?
+            .into_iter()
+            .find(|device| device.bus_number() == bus && device.address() == address)
+            .ok_or(QuantisError::DeviceNotFound)?;
+        
+        Self::from_device(&device)
+    }
+
+    fn from_device(device: &Device<Context>) -> Result<Self, QuantisError> {
+        let mut handle = device.open().map_err(|source| QuantisError::Open { source })?;
+        
+        // Devices no driver has matched can be left unconfigured (macOS)
+        if let Ok(0) = handle.active_configuration() {
+            if let Err(e) = handle.set_active_configuration(CONFIGURATION) {
+                warn!("Could not configure the device: {}", e);
+            }
+        }
+
+        // Let libusb detach a kernel driver bound to the interface (Linux,
+        // and macOS when running as root)
+        if rusb::supports_detach_kernel_driver() {
+            if let Err(e) = handle.set_auto_detach_kernel_driver(true) {
+                warn!("Could not enable kernel driver auto-detach: {}", e);
+            }
+        }
+        
+        claim_interface(&mut handle)?;
+        
+        Ok(Self {
+            handle,
+            timeout: std::time::Duration::from_millis(TIMEOUT_MS),
+        })
+    }
+    
+    /// Get device information
+    pub fn info(&mut self) -> Result<DeviceInfo, QuantisError> {
+        let device = self.handle.device();
+        let desc = device.device_descriptor()?;
+        
+        let product = self.handle
+            .read_product_string_ascii(&desc)
+            .unwrap_or_else(|_| "Unknown".to_string());
+            
+        let serial = self.handle
+            .read_serial_number_string_ascii(&desc)
+            .unwrap_or_else(|_| "Unknown".to_string());
+            
+        Ok(DeviceInfo {
+            product,
+            serial,
+            version: format!("{}.{}", desc.device_version().0, desc.device_version().1),
+        })
+    }
+    
+    /// Read raw entropy from the device
+    pub fn read(&mut self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let mut buffer = vec![0u8; size];
+        let mut total_read = 0;
+        
+        while total_read < size {
+            let chunk_size = (size - total_read).min(65536); // Max 64KB per transfer
+            let bytes_read = self.handle.read_bulk(
+                ENDPOINT_IN,
+                &mut buffer[total_read..total_read + chunk_size],
+                self.timeout,
+            )?;
+            
+            if bytes_read == 0 {
+                return Err(QuantisError::Timeout);
+            }
+            
+            total_read += bytes_read;
+        }
+        
+        Ok(buffer)
+    }
+    
+    /// Check if device is healthy
+    pub fn health_check(&mut self) -> Result<bool, QuantisError> {
+        // Try to read a small amount of data
+        match self.read(16) {
+            Ok(data) => {
+                // Basic entropy check - at least some variation
+                let first = data[0];
+                Ok(!data.iter().all(|&b| b == first))
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// USB bus number and address
+    pub fn location(&self) -> (u8, u8) {
+        let device = self.handle.device();
+        (device.bus_number(), device.address())
+    }
+}
+
+impl EntropySource for QuantisDevice {
+    fn info(&mut self) -> Result<DeviceInfo, QuantisError> {
+        QuantisDevice::info(self)
+    }
+
+    fn read(&mut self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        QuantisDevice::read(self, size)
+    }
+
+    fn backend(&self) -> &'static str {
+        "usb"
+    }
+
+    fn health_check(&mut self) -> Result<bool, QuantisError> {
+        QuantisDevice::health_check(self)
+    }
+
+    fn location(&self) -> Option<(u8, u8)> {
+        Some(QuantisDevice::location(self))
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.handle.release_interface(INTERFACE) {
+            warn!("Failed to release USB interface: {}", e);
+        }
+    }
+}
+
+/// Claim the data interface, retrying while it is busy
+fn claim_interface(handle: &mut DeviceHandle<Context>) -> Result<(), QuantisError> {
+    let mut attempts = 0;
+    
+    loop {
+        attempts += 1;
+        match handle.claim_interface(INTERFACE) {
+            Ok(()) => {
+                if attempts > 1 {
+                    info!("Claimed USB interface after {} attempts", attempts);
+                }
+                return Ok(());
+            }
+            Err(rusb::Error::Busy) if attempts < CLAIM_ATTEMPTS => {
+                warn!(
+                    "USB interface busy (attempt {}/{}), retrying",
+                    attempts, CLAIM_ATTEMPTS
+                );
+                std::thread::sleep(std::time::Duration::from_millis(
+                    CLAIM_RETRY_DELAY_MS * attempts as u64,
+                ));
+            }
+            Err(source) => return Err(QuantisError::Claim { attempts, source }),
+        }
+    }
+}
+
+/// How to give the server access to Quantis devices on this platform
+#[cfg(target_os = "linux")]
+pub const SETUP_HINT: &str = "To install the udev rule, run: sudo quantis-server setup-udev --write";
+
+/// How to give the server access to Quantis devices on this platform
+#[cfg(windows)]
+pub const SETUP_HINT: &str = "Bind the WinUSB driver to the device (USB ID 0ABA:0102), e.g. with Zadig \
+    (https://zadig.akeo.ie); the vendor's own driver cannot be used through libusb";
+
+/// How to give the server access to Quantis devices on this platform
+#[cfg(target_os = "macos")]
+pub const SETUP_HINT: &str = "Quit other programs using the device, or run as root so libusb can detach \
+    a driver holding it; sandboxed builds need the com.apple.security.device.usb entitlement";
+
+/// How to give the server access to Quantis devices on this platform
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub const SETUP_HINT: &str = "Make sure your user may access USB devices";
+
+/// Guidance appended when access to the device is denied
+#[cfg(target_os = "linux")]
+const ACCESS_HINT: &str = " (insufficient permissions, run `quantis-server setup-udev --write` as root)";
+
+/// Guidance appended when access to the device is denied
+#[cfg(windows)]
+const ACCESS_HINT: &str = " (in use by another program, WinUSB lets only one open the device)";
+
+/// Guidance appended when access to the device is denied
+#[cfg(target_os = "macos")]
+const ACCESS_HINT: &str = " (held by a macOS driver or another program, quit it or run as root so \
+    libusb can detach the driver; sandboxed builds need the com.apple.security.device.usb entitlement)";
+
+/// Guidance appended when access to the device is denied
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+const ACCESS_HINT: &str = " (insufficient permissions)";
+
+/// Guidance appended when Windows has no libusb-compatible driver bound
+#[cfg(windows)]
+const DRIVER_HINT: &str = " (no WinUSB driver bound to the device, install it for USB ID 0ABA:0102 \
+    with Zadig, https://zadig.akeo.ie)";
+
+/// Operator guidance appended to open failures
+fn open_hint(error: &rusb::Error) -> &'static str {
+    match error {
+        rusb::Error::Access => ACCESS_HINT,
+        #[cfg(windows)]
+        rusb::Error::NotSupported | rusb::Error::NotFound => DRIVER_HINT,
+        _ => "",
+    }
+}
+
+/// Operator guidance appended to claim failures
+fn claim_hint(error: &rusb::Error) -> &'static str {
+    match error {
+        rusb::Error::Busy => " (interface held by a kernel driver or another process)",
+        _ => open_hint(error),
+    }
+}
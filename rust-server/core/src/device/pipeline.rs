@@ -0,0 +1,308 @@
+//! Post-processing pipelines
+//!
+//! A pipeline is a `|`-separated chain of extractor stages applied in
+//! order, e.g. `von_neumann|sha3:4`. Stages taking a parameter accept it
+//! after a colon; otherwise the request-level default (or the stage's
+//! built-in default) is used. `none` is the empty pipeline.
+
+use std::fmt;
+
+use super::bias_correction::{self, Toeplitz, TwoSourceMethod};
+
+/// Maximum number of stages in a pipeline
+pub const MAX_STAGES: usize = 8;
+
+/// Output size used to measure a stage's compression ratio
+const RATE_PROBE: usize = 4096;
+
+/// Output entropy cap for non-vetted conditioning (SP 800-90B 3.1.5.2)
+const NON_VETTED_CAP: f64 = 0.85 * 8.0;
+
+/// A single post-processing stage
+#[derive(Debug, Clone)]
+pub enum Stage {
+    VonNeumann,
+    IteratedVonNeumann { depth: usize },
+    Peres { depth: usize },
+    TwoSource { method: TwoSourceMethod },
+    Sha3 { ratio: usize },
+    AesCbcMac { ratio: usize },
+    XorFold { ratio: usize },
+    Toeplitz { rate: f64, extractor: Toeplitz },
+}
+
+impl Stage {
+    /// Apply the stage
+    pub fn apply(&self, input: &[u8]) -> Vec<u8> {
+        match self {
+            Stage::VonNeumann => bias_correction::von_neumann(input),
+            Stage::IteratedVonNeumann { depth } => bias_correction::iterated_von_neumann(input, *depth),
+            Stage::Peres { depth } => bias_correction::peres(input, *depth),
+            Stage::TwoSource { method } => bias_correction::two_source(input, *method),
+            Stage::Sha3 { ratio } => bias_correction::sha3(input, *ratio),
+            Stage::AesCbcMac { ratio } => {
+                bias_correction::aes_cbc_mac(input, &bias_correction::CBC_MAC_DEFAULT_KEY, *ratio)
+            }
+            Stage::XorFold { ratio } => bias_correction::xor_fold(input, *ratio),
+            Stage::Toeplitz { extractor, .. } => extractor.extract(input),
+        }
+    }
+
+    /// Input bytes expected to yield `count` output bytes
+    fn input_len(&self, count: usize) -> usize {
+        match self {
+            // Unbiased input keeps 1 bit in 4 on average (about 1 in 3
+            // iterated); Peres approaches every bit as depth grows
+            Stage::VonNeumann => count * 4,
+            Stage::IteratedVonNeumann { .. } => count * 3,
+            Stage::Peres { .. } => count * 2,
+            Stage::TwoSource { method } => bias_correction::two_source_input_len(count, *method),
+            Stage::Sha3 { ratio } => bias_correction::sha3_input_len(count, *ratio),
+            Stage::AesCbcMac { ratio } => bias_correction::aes_cbc_mac_input_len(count, *ratio),
+            Stage::XorFold { ratio } => count * ratio,
+            Stage::Toeplitz { extractor, .. } => extractor.input_len(count),
+        }
+    }
+
+    /// Min-entropy per output byte given `input` bits per input byte
+    ///
+    /// Von Neumann style extractors yield unbiased bits from IID input;
+    /// the other stages can at most concentrate the input entropy.
+    pub fn entropy_rate(&self, input: f64) -> f64 {
+        let ratio = self.input_len(RATE_PROBE) as f64 / RATE_PROBE as f64;
+        match self {
+            Stage::VonNeumann | Stage::IteratedVonNeumann { .. } | Stage::Peres { .. } => 8.0,
+            Stage::XorFold { .. } => (input * ratio).min(NON_VETTED_CAP),
+            _ => (input * ratio).min(8.0),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::VonNeumann => write!(f, "von_neumann"),
+            Stage::IteratedVonNeumann { depth } => write!(f, "iterated_von_neumann:{}", depth),
+            Stage::Peres { depth } => write!(f, "peres:{}", depth),
+            Stage::TwoSource { method: TwoSourceMethod::Hash } => write!(f, "two_source:hash"),
+            Stage::TwoSource {
+                method: TwoSourceMethod::InnerProduct,
+            } => write!(f, "two_source:inner_product"),
+            Stage::Sha3 { ratio } => write!(f, "sha3:{}", ratio),
+            Stage::AesCbcMac { ratio } => write!(f, "aes_cbc_mac:{}", ratio),
+            Stage::XorFold { ratio } => write!(f, "xor_fold:{}", ratio),
+            Stage::Toeplitz { rate, .. } => write!(f, "toeplitz:{}", rate),
+        }
+    }
+}
+
+/// Defaults for stages whose parameter is not given in the spec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageDefaults {
+    pub ratio: Option<usize>,
+    pub rate: Option<f64>,
+}
+
+/// Chain of post-processing stages
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Parse a pipeline specification
+    pub fn parse(spec: &str, defaults: StageDefaults) -> Result<Self, String> {
+        let mut stages = Vec::new();
+
+        for part in spec.split('|').map(str::trim) {
+            let (name, param) = match part.split_once(':') {
+                Some((name, param)) => (name, Some(param)),
+                None => (part, None),
+            };
+
+            let ratio = |default: usize| -> Result<usize, String> {
+                let ratio = match param {
+                    Some(p) => p.parse().map_err(|_| format!("Invalid ratio for {}: {}", name, p))?,
+                    None => defaults.ratio.unwrap_or(default),
+                };
+                if !(1..=16).contains(&ratio) {
+                    return Err("ratio must be between 1 and 16".to_string());
+                }
+                Ok(ratio)
+            };
+
+            let depth = |default: usize| -> Result<usize, String> {
+                let depth = match param {
+                    Some(p) => p.parse().map_err(|_| format!("Invalid depth for {}: {}", name, p))?,
+                    None => default,
+                };
+                if !(1..=16).contains(&depth) {
+                    return Err("depth must be between 1 and 16".to_string());
+                }
+                Ok(depth)
+            };
+
+            let stage = match name {
+                "none" if param.is_none() => continue,
+                "von_neumann" if param.is_none() => Stage::VonNeumann,
+                "iterated_von_neumann" => Stage::IteratedVonNeumann {
+                    depth: depth(bias_correction::ITERATED_VN_DEFAULT_DEPTH)?,
+                },
+                "peres" => Stage::Peres {
+                    depth: depth(bias_correction::PERES_DEFAULT_DEPTH)?,
+                },
+                "sha3" => Stage::Sha3 {
+                    ratio: ratio(bias_correction::SHA3_DEFAULT_RATIO)?,
+                },
+                "aes_cbc_mac" => Stage::AesCbcMac {
+                    ratio: ratio(bias_correction::CBC_MAC_DEFAULT_RATIO)?,
+                },
+                "xor_fold" => Stage::XorFold {
+                    ratio: ratio(bias_correction::XOR_FOLD_DEFAULT_RATIO)?,
+                },
+                "two_source" => Stage::TwoSource {
+                    method: match param {
+                        None | Some("hash") => TwoSourceMethod::Hash,
+                        Some("inner_product") => TwoSourceMethod::InnerProduct,
+                        Some(p) => return Err(format!("Invalid method for two_source: {}", p)),
+                    },
+                },
+                "toeplitz" => {
+                    let rate = match param {
+                        Some(p) => p.parse().map_err(|_| format!("Invalid rate for toeplitz: {}", p))?,
+                        None => defaults.rate.unwrap_or(bias_correction::TOEPLITZ_DEFAULT_RATE),
+                    };
+                    if !(rate > 0.0 && rate <= 1.0) {
+                        return Err("rate must be greater than 0 and at most 1".to_string());
+                    }
+                    Stage::Toeplitz {
+                        rate,
+                        extractor: Toeplitz::with_rate(rate),
+                    }
+                }
+                _ => return Err(format!("Invalid correction stage: {}", part)),
+            };
+            stages.push(stage);
+        }
+
+        if stages.len() > MAX_STAGES {
+            return Err(format!("At most {} correction stages are allowed", MAX_STAGES));
+        }
+        Ok(Self { stages })
+    }
+
+    /// The stages in application order
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Whether the pipeline passes data through unchanged
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run every stage in order
+    pub fn apply(&self, input: &[u8]) -> Vec<u8> {
+        match self.stages.split_first() {
+            None => bias_correction::none(input),
+            Some((first, rest)) => rest
+                .iter()
+                .fold(first.apply(input), |data, stage| stage.apply(&data)),
+        }
+    }
+
+    /// Raw bytes expected to yield `count` output bytes
+    ///
+    /// Exact for deterministic stages; Von Neumann output depends on the
+    /// data, so callers must be prepared to draw more.
+    pub fn input_len(&self, count: usize) -> usize {
+        self.stages
+            .iter()
+            .rev()
+            .fold(count, |needed, stage| stage.input_len(needed))
+    }
+
+    /// Min-entropy per output byte given `min_entropy` bits per raw byte
+    pub fn entropy_rate(&self, min_entropy: f64) -> f64 {
+        self.stages
+            .iter()
+            .fold(min_entropy, |rate, stage| stage.entropy_rate(rate))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.stages.is_empty() {
+            return write!(f, "none");
+        }
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, "|")?;
+            }
+            write!(f, "{}", stage)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_normalizes_specs() {
+        let defaults = StageDefaults::default();
+        assert_eq!(Pipeline::parse("none", defaults).unwrap().to_string(), "none");
+        assert_eq!(
+            Pipeline::parse("von_neumann|sha3", defaults).unwrap().to_string(),
+            "von_neumann|sha3:2"
+        );
+        assert_eq!(
+            Pipeline::parse("xor_fold:4 | toeplitz:0.75", defaults).unwrap().to_string(),
+            "xor_fold:4|toeplitz:0.75"
+        );
+
+        let overridden = StageDefaults {
+            ratio: Some(3),
+            rate: None,
+        };
+        assert_eq!(
+            Pipeline::parse("sha3|aes_cbc_mac:5", overridden).unwrap().to_string(),
+            "sha3:3|aes_cbc_mac:5"
+        );
+
+        assert!(Pipeline::parse("md5", defaults).is_err());
+        assert!(Pipeline::parse("sha3:0", defaults).is_err());
+        assert!(Pipeline::parse("toeplitz:1.5", defaults).is_err());
+        assert!(Pipeline::parse("von_neumann:2", defaults).is_err());
+        assert_eq!(
+            Pipeline::parse("iterated_von_neumann", defaults).unwrap().to_string(),
+            "iterated_von_neumann:3"
+        );
+        assert_eq!(Pipeline::parse("peres:4|sha3", defaults).unwrap().to_string(), "peres:4|sha3:2");
+        assert!(Pipeline::parse("peres:0", defaults).is_err());
+        assert_eq!(
+            Pipeline::parse("two_source", defaults).unwrap().to_string(),
+            "two_source:hash"
+        );
+    }
+
+    #[test]
+    fn input_len_chains_stages_backwards() {
+        let pipeline = Pipeline::parse("xor_fold:2|sha3:2", StageDefaults::default()).unwrap();
+        // 32 output bytes need 64 bytes into sha3, 128 into xor_fold
+        assert_eq!(pipeline.input_len(32), 128);
+        assert_eq!(pipeline.apply(&[0x42; 128]).len(), 32);
+    }
+
+    #[test]
+    fn entropy_rate_follows_conditioning() {
+        let defaults = StageDefaults::default();
+        assert_eq!(Pipeline::parse("none", defaults).unwrap().entropy_rate(7.0), 7.0);
+        assert_eq!(Pipeline::parse("sha3:2", defaults).unwrap().entropy_rate(3.0), 6.0);
+        assert_eq!(Pipeline::parse("sha3:2", defaults).unwrap().entropy_rate(7.0), 8.0);
+        assert_eq!(Pipeline::parse("xor_fold:4", defaults).unwrap().entropy_rate(7.0), 6.8);
+        assert_eq!(Pipeline::parse("von_neumann", defaults).unwrap().entropy_rate(2.0), 8.0);
+        assert_eq!(Pipeline::parse("toeplitz:0.5", defaults).unwrap().entropy_rate(3.0), 6.0);
+    }
+}
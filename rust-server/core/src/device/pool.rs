@@ -0,0 +1,646 @@
+//! Multi-device pool with automatic failover
+//!
+//! Reads go to the active device. After `FAILOVER_THRESHOLD` consecutive
+//! errors the device is marked failed and reads move to the first healthy
+//! standby, so the entropy buffer keeps filling without operator action.
+//! With a `MixMode` set, reads instead combine all healthy devices.
+
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+use tracing::{error, info, warn};
+
+use super::{
+    mix::{self, MixMode},
+    rate::ReadRate,
+    DeviceEvent, DeviceInfo, EntropySource, QuantisDevice, QuantisError,
+};
+
+/// Consecutive read errors before the active device is failed over
+pub const FAILOVER_THRESHOLD: u32 = 3;
+
+/// Default longest wait for a device another read is holding
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceState {
+    Healthy,
+    Failed,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceRole {
+    Active,
+    Standby,
+}
+
+struct SlotStatus {
+    state: DeviceState,
+    location: Option<(u8, u8)>,
+    consecutive_errors: u32,
+}
+
+/// A device known to the pool
+pub struct DeviceSlot {
+    index: usize,
+    info: DeviceInfo,
+    backend: &'static str,
+    source: Mutex<Box<dyn EntropySource>>,
+    /// Shared with the pool, in milliseconds
+    lock_timeout: Arc<AtomicU64>,
+    status: std::sync::Mutex<SlotStatus>,
+    rate: ReadRate,
+}
+
+impl DeviceSlot {
+    /// Position of the device in the pool
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Device information captured when the device was opened
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// How the device is accessed, e.g. `usb`
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
+    /// Current device state
+    pub fn state(&self) -> DeviceState {
+        self.status.lock().unwrap().state
+    }
+
+    /// Consecutive failed reads
+    pub fn consecutive_errors(&self) -> u32 {
+        self.status.lock().unwrap().consecutive_errors
+    }
+
+    fn set_state(&self, state: DeviceState) {
+        self.status.lock().unwrap().state = state;
+    }
+
+    fn location(&self) -> Option<(u8, u8)> {
+        self.status.lock().unwrap().location
+    }
+
+    /// Average read throughput in bytes per second, once measured
+    pub fn read_rate(&self) -> Option<f64> {
+        self.rate.bytes_per_sec()
+    }
+
+    /// Wait for reads in progress, giving up after the pool's lock timeout
+    ///
+    /// A stuck transfer holds the device until the USB timeout, so waiting
+    /// behind it unbounded would stall every read queued after it.
+    async fn lock(&self) -> Result<MutexGuard<'_, Box<dyn EntropySource>>, QuantisError> {
+        let timeout = Duration::from_millis(self.lock_timeout.load(Ordering::Relaxed));
+        tokio::time::timeout(timeout, self.source.lock()).await.map_err(|_| QuantisError::Busy)
+    }
+
+    /// Read raw entropy, tracking consecutive errors and throughput
+    ///
+    /// Giving up on a busy device does not count as an error.
+    pub async fn read(&self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let mut source = self.lock().await?;
+        let started = Instant::now();
+        let result = source.read(size);
+        drop(source);
+        if let Ok(data) = &result {
+            self.rate.record(data.len(), started.elapsed());
+        }
+
+        let mut status = self.status.lock().unwrap();
+        match result {
+            Ok(_) => status.consecutive_errors = 0,
+            Err(_) => status.consecutive_errors += 1,
+        }
+        result
+    }
+
+    /// Check if the device is healthy
+    pub async fn health_check(&self) -> Result<bool, QuantisError> {
+        self.lock().await?.health_check()
+    }
+}
+
+/// All opened Quantis devices
+pub struct DevicePool {
+    slots: RwLock<Vec<Arc<DeviceSlot>>>,
+    active: AtomicUsize,
+    mix: std::sync::Mutex<MixMode>,
+    lock_timeout: Arc<AtomicU64>,
+    events: broadcast::Sender<DeviceEvent>,
+    serials: Vec<String>,
+}
+
+impl DevicePool {
+    /// Create an empty pool publishing events on `events`
+    pub fn new(events: broadcast::Sender<DeviceEvent>) -> Self {
+        Self {
+            slots: RwLock::new(Vec::new()),
+            active: AtomicUsize::new(0),
+            mix: std::sync::Mutex::new(MixMode::None),
+            lock_timeout: Arc::new(AtomicU64::new(DEFAULT_LOCK_TIMEOUT.as_millis() as u64)),
+            events,
+            serials: Vec::new(),
+        }
+    }
+
+    /// Only accept devices with these serials, or any if empty
+    pub fn with_serials(mut self, serials: Vec<String>) -> Self {
+        self.serials = serials;
+        self
+    }
+
+    /// Open every attached Quantis device in `serials` (all if empty); the
+    /// first becomes active
+    pub fn open_all(
+        events: broadcast::Sender<DeviceEvent>,
+        serials: &[String],
+    ) -> Result<Self, QuantisError> {
+        let pool = Self::new(events).with_serials(serials.to_vec());
+        let mut last_error = QuantisError::DeviceNotFound;
+
+        for index in 0..QuantisDevice::count()? {
+            let opened = QuantisDevice::open(index)
+                .and_then(|device| pool.add(Box::new(device)));
+            if let Err(e) = opened {
+                warn!("Failed to open Quantis device {}: {}", index, e);
+                last_error = e;
+            }
+        }
+
+        if pool.is_empty() {
+            return Err(last_error);
+        }
+        Ok(pool)
+    }
+
+    /// Open only the device at USB enumeration `index`
+    ///
+    /// The pool then accepts no other serial, so a replugged unit comes
+    /// back but other devices are ignored.
+    pub fn open_index(
+        events: broadcast::Sender<DeviceEvent>,
+        index: usize,
+    ) -> Result<Self, QuantisError> {
+        let mut device = QuantisDevice::open(index)?;
+        let serial = device.info()?.serial;
+        let pool = Self::new(events).with_serials(vec![serial]);
+        pool.add(Box::new(device))?;
+        Ok(pool)
+    }
+
+    /// Add a source as a standby device (active if the pool was empty)
+    pub fn add(&self, mut source: Box<dyn EntropySource>) -> Result<usize, QuantisError> {
+        let info = source.info()?;
+        if !self.serials.is_empty() && !self.serials.contains(&info.serial) {
+            return Err(QuantisError::NotSelected(info.serial));
+        }
+        let location = source.location();
+
+        let mut slots = self.slots.write().unwrap();
+        let index = slots.len();
+        slots.push(Arc::new(DeviceSlot {
+            index,
+            info,
+            backend: source.backend(),
+            source: Mutex::new(source),
+            lock_timeout: self.lock_timeout.clone(),
+            status: std::sync::Mutex::new(SlotStatus {
+                state: DeviceState::Healthy,
+                location,
+                consecutive_errors: 0,
+            }),
+            rate: ReadRate::default(),
+        }));
+        Ok(index)
+    }
+
+    /// Attach a (re)connected source
+    ///
+    /// A device whose serial is already known takes over its old slot,
+    /// closing the source it replaces. If the active device is unusable,
+    /// the attached one becomes active.
+    pub async fn attach(&self, mut source: Box<dyn EntropySource>) -> Result<usize, QuantisError> {
+        let serial = source.info()?.serial;
+        let existing = self.find(&serial);
+
+        let index = match existing {
+            Some(slot) => {
+                let location = source.location();
+                let mut replaced = std::mem::replace(&mut *slot.source.lock().await, source);
+                replaced.close();
+                let mut status = slot.status.lock().unwrap();
+                status.state = DeviceState::Healthy;
+                status.location = location;
+                status.consecutive_errors = 0;
+                slot.index
+            }
+            None => self.add(source)?,
+        };
+
+        if let Some(active) = self.active() {
+            if active.index != index && active.state() != DeviceState::Healthy {
+                self.switch(active.index, index);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Number of devices in the pool
+    pub fn len(&self) -> usize {
+        self.slots.read().unwrap().len()
+    }
+
+    /// Whether the pool has no devices
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All devices in pool order
+    pub fn slots(&self) -> Vec<Arc<DeviceSlot>> {
+        self.slots.read().unwrap().clone()
+    }
+
+    /// The device currently serving reads
+    pub fn active(&self) -> Option<Arc<DeviceSlot>> {
+        let index = self.active.load(Ordering::Acquire);
+        self.slots.read().unwrap().get(index).cloned()
+    }
+
+    /// Role of a device in the pool
+    pub fn role(&self, slot: &DeviceSlot) -> DeviceRole {
+        if self.active.load(Ordering::Acquire) == slot.index {
+            DeviceRole::Active
+        } else {
+            DeviceRole::Standby
+        }
+    }
+
+    /// Find a device by serial
+    pub fn find(&self, serial: &str) -> Option<Arc<DeviceSlot>> {
+        self.slots()
+            .into_iter()
+            .find(|slot| slot.info.serial == serial)
+    }
+
+    /// Read raw entropy from a specific device, without failover
+    pub async fn read_from(&self, serial: &str, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let slot = self.find(serial).ok_or(QuantisError::DeviceNotFound)?;
+        if slot.state() != DeviceState::Healthy {
+            return Err(QuantisError::DeviceUnavailable(serial.to_string()));
+        }
+        slot.read(size).await
+    }
+
+    /// How reads combine output from multiple devices
+    pub fn mix_mode(&self) -> MixMode {
+        *self.mix.lock().unwrap()
+    }
+
+    /// Expected read throughput in bytes per second, once measured
+    ///
+    /// Mixed reads go to each healthy device in turn, so their times add.
+    pub fn read_rate(&self) -> Option<f64> {
+        let healthy: Vec<_> = self
+            .slots()
+            .into_iter()
+            .filter(|slot| slot.state() == DeviceState::Healthy)
+            .collect();
+        if self.mix_mode() != MixMode::None && healthy.len() >= 2 {
+            let seconds_per_byte = healthy
+                .iter()
+                .map(|slot| slot.read_rate().map(|rate| 1.0 / rate))
+                .sum::<Option<f64>>()?;
+            return Some(1.0 / seconds_per_byte);
+        }
+        self.active()?.read_rate()
+    }
+
+    /// Serials of the devices unpinned reads currently come from
+    pub fn serving_serials(&self) -> Vec<String> {
+        let healthy: Vec<_> = self
+            .slots()
+            .into_iter()
+            .filter(|slot| slot.state() == DeviceState::Healthy)
+            .map(|slot| slot.info.serial.clone())
+            .collect();
+        if self.mix_mode() != MixMode::None && healthy.len() >= 2 {
+            return healthy;
+        }
+        self.active().map(|slot| slot.info.serial.clone()).into_iter().collect()
+    }
+
+    /// Change how reads combine output from multiple devices
+    pub fn set_mix_mode(&self, mode: MixMode) {
+        *self.mix.lock().unwrap() = mode;
+    }
+
+    /// Change how long reads wait for a device another read is holding
+    pub fn set_lock_timeout(&self, timeout: Duration) {
+        self.lock_timeout.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Read raw entropy, mixing healthy devices if enabled
+    ///
+    /// Without mixing (or with fewer than two healthy devices) reads come
+    /// from the active device, failing over if needed.
+    pub async fn read(&self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let mode = self.mix_mode();
+        if mode != MixMode::None {
+            let healthy: Vec<_> = self
+                .slots()
+                .into_iter()
+                .filter(|slot| slot.state() == DeviceState::Healthy)
+                .collect();
+            if healthy.len() >= 2 {
+                return self.read_mixed(mode, &healthy, size).await;
+            }
+        }
+
+        self.read_active(size).await
+    }
+
+    async fn read_active(&self, size: usize) -> Result<Vec<u8>, QuantisError> {
+        let slot = self.active().ok_or(QuantisError::DeviceNotFound)?;
+
+        match slot.read(size).await {
+            Ok(data) => Ok(data),
+            Err(e) => {
+                if !self.record_failure(&slot) {
+                    return Err(e);
+                }
+
+                match self.active().filter(|next| next.state() == DeviceState::Healthy) {
+                    Some(next) if next.index != slot.index => next.read(size).await,
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+
+    async fn read_mixed(
+        &self,
+        mode: MixMode,
+        slots: &[Arc<DeviceSlot>],
+        size: usize,
+    ) -> Result<Vec<u8>, QuantisError> {
+        let mut streams = Vec::with_capacity(slots.len());
+        let mut last_error = None;
+
+        for slot in slots {
+            match slot.read(size).await {
+                Ok(data) => streams.push(data),
+                Err(e) => {
+                    self.record_failure(slot);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match streams.len() {
+            0 => Err(last_error.unwrap_or(QuantisError::DeviceNotFound)),
+            1 => {
+                warn!("Only one device produced data, output is not mixed");
+                Ok(streams.swap_remove(0))
+            }
+            _ => Ok(mix::mix(mode, &streams)),
+        }
+    }
+
+    /// Fail a device that reached the error threshold
+    ///
+    /// Returns true if the device is (now) failed. An active device that
+    /// fails is replaced by the first healthy standby.
+    fn record_failure(&self, slot: &DeviceSlot) -> bool {
+        if slot.consecutive_errors() < FAILOVER_THRESHOLD {
+            return false;
+        }
+
+        if slot.state() == DeviceState::Healthy {
+            slot.set_state(DeviceState::Failed);
+            let _ = self.events.send(DeviceEvent::Failed {
+                serial: slot.info.serial.clone(),
+            });
+        }
+        if self.role(slot) == DeviceRole::Active {
+            self.failover(slot.index);
+        }
+        true
+    }
+
+    /// Get information about the active device
+    pub async fn info(&self) -> Result<DeviceInfo, QuantisError> {
+        let slot = self.active().ok_or(QuantisError::DeviceNotFound)?;
+        let info = slot.lock().await?.info();
+        info
+    }
+
+    /// Check if the active device is healthy
+    pub async fn health_check(&self) -> Result<bool, QuantisError> {
+        match self.active() {
+            Some(slot) if slot.state() == DeviceState::Healthy => slot.health_check().await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Apply a hotplug event to the pool
+    pub async fn handle_event(&self, event: &DeviceEvent) {
+        match *event {
+            DeviceEvent::Left { bus, address } => {
+                let slot = self
+                    .slots()
+                    .into_iter()
+                    .find(|slot| slot.location() == Some((bus, address)));
+                if let Some(slot) = slot {
+                    {
+                        let mut status = slot.status.lock().unwrap();
+                        status.state = DeviceState::Disconnected;
+                        status.location = None;
+                    }
+                    if self.role(&slot) == DeviceRole::Active {
+                        self.failover(slot.index);
+                    }
+                }
+            }
+            DeviceEvent::Arrived { bus, address } => {
+                let opened =
+                    tokio::task::spawn_blocking(move || QuantisDevice::open_at(bus, address)).await;
+                match opened {
+                    Ok(Ok(device)) => match self.attach(Box::new(device)).await {
+                        Ok(index) => info!("Attached Quantis device as pool slot {}", index),
+                        Err(e) => warn!("Failed to attach Quantis device: {}", e),
+                    },
+                    Ok(Err(e)) => warn!("Failed to open arrived Quantis device: {}", e),
+                    Err(e) => error!("Device open task failed: {}", e),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Close every device, waiting for reads in progress to finish
+    ///
+    /// For shutdown, once nothing reads from the pool any more.
+    pub async fn close(&self) {
+        for slot in self.slots() {
+            let mut source = slot.source.lock().await;
+            source.close();
+            slot.set_state(DeviceState::Disconnected);
+        }
+    }
+
+    /// Keep the pool in sync with hotplug events until the channel closes
+    pub async fn watch(self: Arc<Self>, mut events: broadcast::Receiver<DeviceEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.handle_event(&event).await,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Move reads off `from` to the first healthy standby
+    fn failover(&self, from: usize) {
+        let standby = self
+            .slots()
+            .into_iter()
+            .find(|slot| slot.index != from && slot.state() == DeviceState::Healthy);
+
+        match standby {
+            Some(standby) => self.switch(from, standby.index),
+            None => error!("No healthy standby device available for failover"),
+        }
+    }
+
+    fn switch(&self, from: usize, to: usize) {
+        // Only one concurrent failure gets to perform the switch
+        if self
+            .active
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            let slots = self.slots.read().unwrap();
+            let _ = self.events.send(DeviceEvent::Failover {
+                from: slots[from].info.serial.clone(),
+                to: slots[to].info.serial.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockSource;
+
+    #[tokio::test]
+    async fn fails_over_to_standby_after_threshold() {
+        let (events, mut rx) = broadcast::channel(16);
+        let pool = DevicePool::new(events);
+
+        let primary = MockSource::new("primary", 1);
+        let fail = primary.fail_switch();
+        pool.add(Box::new(primary)).unwrap();
+        pool.add(Box::new(MockSource::new("standby", 2))).unwrap();
+
+        fail.store(true, Ordering::Relaxed);
+        for _ in 1..FAILOVER_THRESHOLD {
+            assert!(pool.read(32).await.is_err());
+        }
+
+        // The threshold read switches over and is served by the standby
+        assert_eq!(pool.read(32).await.unwrap().len(), 32);
+        let active = pool.active().unwrap();
+        assert_eq!(active.info().serial, "standby");
+        assert_eq!(pool.slots()[0].state(), DeviceState::Failed);
+
+        assert!(matches!(rx.recv().await.unwrap(), DeviceEvent::Failed { .. }));
+        match rx.recv().await.unwrap() {
+            DeviceEvent::Failover { from, to } => {
+                assert_eq!(from, "primary");
+                assert_eq!(to, "standby");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_selected_serials_are_added() {
+        let (events, _rx) = broadcast::channel(16);
+        let pool = DevicePool::new(events).with_serials(vec!["b".to_string()]);
+
+        assert!(matches!(
+            pool.add(Box::new(MockSource::new("a", 1))),
+            Err(QuantisError::NotSelected(serial)) if serial == "a"
+        ));
+        assert_eq!(pool.attach(Box::new(MockSource::new("b", 2))).await.unwrap(), 0);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mixed_reads_combine_all_healthy_devices() {
+        let (events, _rx) = broadcast::channel(16);
+        let pool = DevicePool::new(events);
+        pool.add(Box::new(MockSource::new("a", 7))).unwrap();
+        pool.add(Box::new(MockSource::new("b", 7))).unwrap();
+
+        // Identical streams cancel out under XOR
+        pool.set_mix_mode(MixMode::Xor);
+        assert_eq!(pool.read(64).await.unwrap(), vec![0u8; 64]);
+
+        pool.set_mix_mode(MixMode::None);
+        assert_ne!(pool.read(64).await.unwrap(), vec![0u8; 64]);
+    }
+
+    #[tokio::test]
+    async fn reattached_device_replaces_failed_active() {
+        let (events, _rx) = broadcast::channel(16);
+        let pool = DevicePool::new(events);
+
+        let primary = MockSource::new("primary", 1);
+        let (fail, closed) = (primary.fail_switch(), primary.closed_flag());
+        pool.add(Box::new(primary)).unwrap();
+
+        fail.store(true, Ordering::Relaxed);
+        for _ in 0..FAILOVER_THRESHOLD {
+            assert!(pool.read(32).await.is_err());
+        }
+        assert!(!pool.health_check().await.unwrap());
+
+        let index = pool.attach(Box::new(MockSource::new("primary", 3))).await.unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(pool.len(), 1);
+        assert!(closed.load(Ordering::Relaxed), "the replaced source is closed");
+        assert!(pool.read(32).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reads_give_up_on_a_held_device() {
+        let (events, _rx) = broadcast::channel(16);
+        let pool = DevicePool::new(events);
+        pool.add(Box::new(MockSource::new("a", 1))).unwrap();
+        pool.set_lock_timeout(Duration::from_millis(20));
+
+        let slot = pool.active().unwrap();
+        let held = slot.source.lock().await;
+        assert!(matches!(pool.read(32).await, Err(QuantisError::Busy)));
+        assert_eq!(slot.consecutive_errors(), 0);
+        drop(held);
+        assert!(pool.read(32).await.is_ok());
+    }
+}
@@ -0,0 +1,62 @@
+//! Device read throughput
+//!
+//! Measured per device while its lock is held, so time spent queueing
+//! behind other readers does not count against the device.
+
+use std::{sync::Mutex, time::Duration};
+
+/// Weight of the newest read in the moving average
+const SMOOTHING: f64 = 0.2;
+
+/// Exponentially weighted read throughput
+#[derive(Debug, Default)]
+pub struct ReadRate {
+    bytes_per_sec: Mutex<Option<f64>>,
+}
+
+impl ReadRate {
+    /// Account for a read of `bytes` that took `elapsed`
+    pub fn record(&self, bytes: usize, elapsed: Duration) {
+        // Reads too short to time say nothing about the device
+        if bytes == 0 || elapsed.is_zero() {
+            return;
+        }
+        let sample = bytes as f64 / elapsed.as_secs_f64();
+        let mut rate = self.bytes_per_sec.lock().unwrap();
+        *rate = Some(match *rate {
+            Some(rate) => rate + SMOOTHING * (sample - rate),
+            None => sample,
+        });
+    }
+
+    /// Average throughput, once a read has been timed
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        *self.bytes_per_sec.lock().unwrap()
+    }
+}
+
+/// Expected time to read `bytes` at `bytes_per_sec`
+pub fn read_time(bytes: usize, bytes_per_sec: f64) -> Duration {
+    Duration::from_secs_f64(bytes as f64 / bytes_per_sec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_timed_reads() {
+        let rate = ReadRate::default();
+        assert!(rate.bytes_per_sec().is_none());
+
+        rate.record(1000, Duration::ZERO);
+        assert!(rate.bytes_per_sec().is_none());
+
+        rate.record(1000, Duration::from_millis(10));
+        assert_eq!(rate.bytes_per_sec(), Some(100_000.0));
+        rate.record(1000, Duration::from_millis(5));
+        assert!((rate.bytes_per_sec().unwrap() - 120_000.0).abs() < 1e-6);
+
+        assert_eq!(read_time(50_000, 100_000.0), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,40 @@
+//! udev rule generation for Quantis devices (Linux)
+
+use std::{io, path::Path, process::Command};
+
+use super::{PRODUCT_ID, VENDOR_ID};
+
+/// Default location for the installed rule
+pub const DEFAULT_RULES_PATH: &str = "/etc/udev/rules.d/99-quantis.rules";
+
+/// Render the udev rule granting `group` access to Quantis devices
+pub fn rule(group: &str, mode: &str) -> String {
+    format!(
+        "# ID Quantique Quantis QRNG (generated by quantis-server setup-udev)\n\
+         SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", \
+         MODE=\"{}\", GROUP=\"{}\", TAG+=\"uaccess\"\n",
+        VENDOR_ID, PRODUCT_ID, mode, group
+    )
+}
+
+/// Write the rule to `path` and ask udev to apply it
+///
+/// Reloading is best effort; the returned list holds any udevadm
+/// invocations that failed so the caller can tell the operator.
+pub fn install(path: &Path, rule: &str) -> io::Result<Vec<String>> {
+    std::fs::write(path, rule)?;
+
+    let mut failed = Vec::new();
+    for args in [&["control", "--reload-rules"][..], &["trigger"][..]] {
+        let ok = Command::new("udevadm")
+            .args(args)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !ok {
+            failed.push(format!("udevadm {}", args.join(" ")));
+        }
+    }
+
+    Ok(failed)
+}
@@ -0,0 +1,26 @@
+//! Structured alarms raised by the stream monitors
+
+use serde::Serialize;
+
+/// A monitored statistic crossed its threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct Alarm {
+    pub kind: &'static str,
+    pub detail: String,
+    pub value: f64,
+    pub threshold: f64,
+    /// Unix timestamp of the alarm
+    pub at: u64,
+}
+
+impl Alarm {
+    pub fn new(kind: &'static str, detail: String, value: f64, threshold: f64) -> Self {
+        Self {
+            kind,
+            detail,
+            value,
+            threshold,
+            at: super::unix_time(),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Health test audit trail
+//!
+//! Startup, continuous and on-demand self-test results are kept in memory
+//! and, when a path is configured, appended as JSON lines so the trail
+//! survives restarts. Records are queried by time range and test.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+use tracing::warn;
+
+/// Records kept in memory for queries
+pub const MAX_RECORDS: usize = 100_000;
+
+/// Where a test ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCategory {
+    /// Tests on the first output after the server starts
+    Startup,
+    /// Tests over all raw output while running
+    Continuous,
+    /// Statistical tests and estimates run on demand
+    SelfTest,
+    /// Stream monitor alarms
+    Alarm,
+    /// Operator actions such as acknowledging a failure
+    Operator,
+}
+
+/// A single audited result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix timestamp of the result
+    pub at: u64,
+    pub category: AuditCategory,
+    pub test: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Filter for `AuditLog::query`
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Earliest timestamp, inclusive
+    pub from: Option<u64>,
+    /// Latest timestamp, inclusive
+    pub to: Option<u64>,
+    pub category: Option<AuditCategory>,
+    pub test: Option<String>,
+}
+
+impl AuditFilter {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.from.is_none_or(|from| record.at >= from)
+            && self.to.is_none_or(|to| record.at <= to)
+            && self.category.is_none_or(|category| record.category == category)
+            && self.test.as_ref().is_none_or(|test| &record.test == test)
+    }
+}
+
+/// Bounded in-memory trail with optional append-only persistence
+pub struct AuditLog {
+    records: Mutex<VecDeque<AuditRecord>>,
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Trail kept in memory only
+    pub fn new() -> Self {
+        Self {
+            records: Mutex::new(VecDeque::new()),
+            file: None,
+        }
+    }
+
+    /// Trail persisted to `path`, loading the records already there
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut records = VecDeque::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // Skip lines cut short by a crash mid-write
+                if let Ok(record) = serde_json::from_str(&line?) {
+                    push(&mut records, record);
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            records: Mutex::new(records),
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    /// Append a result stamped with the current time
+    pub fn record(&self, category: AuditCategory, test: &str, passed: bool, detail: impl Into<String>) {
+        let record = AuditRecord {
+            at: super::unix_time(),
+            category,
+            test: test.to_string(),
+            passed,
+            detail: detail.into(),
+        };
+
+        if let Some(file) = &self.file {
+            let line = serde_json::to_string(&record).expect("audit record serializes");
+            if let Err(e) = writeln!(file.lock().unwrap(), "{}", line) {
+                warn!("Failed to persist audit record: {}", e);
+            }
+        }
+        push(&mut self.records.lock().unwrap(), record);
+    }
+
+    /// The newest `limit` records matching `filter`, oldest first
+    pub fn query(&self, filter: &AuditFilter, limit: usize) -> Vec<AuditRecord> {
+        let records = self.records.lock().unwrap();
+        let mut matched: Vec<AuditRecord> = records
+            .iter()
+            .rev()
+            .filter(|record| filter.matches(record))
+            .take(limit)
+            .cloned()
+            .collect();
+        matched.reverse();
+        matched
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push(records: &mut VecDeque<AuditRecord>, record: AuditRecord) {
+    if records.len() == MAX_RECORDS {
+        records.pop_front();
+    }
+    records.push_back(record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_and_persists_records() {
+        let path = std::env::temp_dir().join(format!("quantis-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let log = AuditLog::open(&path).unwrap();
+        log.record(AuditCategory::Startup, "startup", true, "1024 samples passed");
+        log.record(AuditCategory::Continuous, "repetition_count", false, "stuck");
+        log.record(AuditCategory::SelfTest, "frequency", true, "p = 0.5");
+
+        let failed = AuditFilter {
+            category: Some(AuditCategory::Continuous),
+            ..AuditFilter::default()
+        };
+        assert_eq!(log.query(&failed, 10).len(), 1);
+        assert_eq!(log.query(&AuditFilter::default(), 2)[1].test, "frequency");
+        let future = AuditFilter {
+            from: Some(u64::MAX),
+            ..AuditFilter::default()
+        };
+        assert!(log.query(&future, 10).is_empty());
+
+        // Reopening restores the trail
+        drop(log);
+        let reopened = AuditLog::open(&path).unwrap();
+        let by_test = AuditFilter {
+            test: Some("startup".to_string()),
+            ..AuditFilter::default()
+        };
+        assert_eq!(reopened.query(&by_test, 10).len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,174 @@
+//! Bit-level autocorrelation of the raw stream
+//!
+//! Correlation between nearby bits is the most common failure mode of
+//! hardware generators. Lags 1 to `MAX_LAG` are tracked over the most
+//! recent `WINDOW_BITS` raw bits; once the window has filled, a lag whose
+//! correlation magnitude exceeds the threshold raises an alarm until it
+//! falls back.
+
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Largest tracked lag, in bits
+pub const MAX_LAG: usize = 8;
+
+/// Raw bits covered by the rolling estimate
+pub const WINDOW_BITS: u64 = 8 * 1024 * 1024;
+
+/// Default correlation magnitude that raises an alarm
+///
+/// About six standard deviations for a full window of unbiased bits.
+pub const DEFAULT_THRESHOLD: f64 = 0.002;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    bits: u64,
+    agree: [u64; MAX_LAG],
+    pairs: [u64; MAX_LAG],
+}
+
+/// Current autocorrelation estimates
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AutocorrelationStats {
+    pub window_bits: u64,
+    /// Correlation in [-1, 1] for lags 1 to `MAX_LAG`
+    pub lags: Vec<f64>,
+    /// Lags currently past the threshold
+    pub alarms: Vec<usize>,
+}
+
+struct State {
+    previous: Option<u8>,
+    chunks: VecDeque<Counts>,
+    total: Counts,
+    stats: AutocorrelationStats,
+}
+
+/// Rolling lag-1..8 autocorrelation monitor
+pub struct AutocorrelationMonitor {
+    threshold: f64,
+    state: Mutex<State>,
+}
+
+impl AutocorrelationMonitor {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            state: Mutex::new(State {
+                previous: None,
+                chunks: VecDeque::new(),
+                total: Counts::default(),
+                stats: AutocorrelationStats::default(),
+            }),
+        }
+    }
+
+    /// Correlation magnitude that raises an alarm
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Latest estimates
+    pub fn stats(&self) -> AutocorrelationStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Account for raw data; returns lags whose alarm was just raised
+    pub fn observe(&self, data: &[u8]) -> Vec<(usize, f64)> {
+        if data.is_empty() {
+            return Vec::new();
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let mut counts = Counts {
+            bits: data.len() as u64 * 8,
+            ..Counts::default()
+        };
+
+        // Compare each byte with the bits up to MAX_LAG positions earlier;
+        // pairs span chunk boundaries through the previous byte
+        let mut previous = state.previous;
+        for &byte in data {
+            if let Some(prev) = previous {
+                let window = (prev as u16) << 8 | byte as u16;
+                for lag in 1..=MAX_LAG {
+                    let differ = ((window >> lag) as u8 ^ byte).count_ones() as u64;
+                    counts.pairs[lag - 1] += 8;
+                    counts.agree[lag - 1] += 8 - differ;
+                }
+            }
+            previous = Some(byte);
+        }
+        state.previous = previous;
+
+        add(&mut state.total, &counts, true);
+        state.chunks.push_back(counts);
+        while state.chunks.len() > 1 && state.total.bits - state.chunks[0].bits >= WINDOW_BITS {
+            let oldest = state.chunks.pop_front().unwrap();
+            add(&mut state.total, &oldest, false);
+        }
+
+        let lags: Vec<f64> = (0..MAX_LAG)
+            .map(|i| {
+                let pairs = state.total.pairs[i];
+                if pairs == 0 {
+                    0.0
+                } else {
+                    (2.0 * state.total.agree[i] as f64 - pairs as f64) / pairs as f64
+                }
+            })
+            .collect();
+        // Smaller samples are too noisy to judge against the threshold
+        let alarms: Vec<usize> = (1..=MAX_LAG)
+            .filter(|&lag| state.total.bits >= WINDOW_BITS && lags[lag - 1].abs() > self.threshold)
+            .collect();
+
+        let raised = alarms
+            .iter()
+            .filter(|lag| !state.stats.alarms.contains(lag))
+            .map(|&lag| (lag, lags[lag - 1]))
+            .collect();
+
+        state.stats = AutocorrelationStats {
+            window_bits: state.total.bits,
+            lags,
+            alarms,
+        };
+        raised
+    }
+}
+
+fn add(total: &mut Counts, counts: &Counts, add: bool) {
+    let apply = |a: &mut u64, b: u64| if add { *a += b } else { *a -= b };
+    apply(&mut total.bits, counts.bits);
+    for lag in 0..MAX_LAG {
+        apply(&mut total.agree[lag], counts.agree[lag]);
+        apply(&mut total.pairs[lag], counts.pairs[lag]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{mock::MockSource, EntropySource};
+
+    #[test]
+    fn flags_periodic_stream_but_not_random_data() {
+        let monitor = AutocorrelationMonitor::new(DEFAULT_THRESHOLD);
+        let mut source = MockSource::new("mock", 5);
+        for _ in 0..32 {
+            assert!(monitor.observe(&source.read(65536).unwrap()).is_empty());
+        }
+        assert!(monitor.stats().alarms.is_empty());
+
+        // Alternating bits are perfectly anti-correlated at odd lags
+        let monitor = AutocorrelationMonitor::new(DEFAULT_THRESHOLD);
+        let raised = monitor.observe(&[0x55; WINDOW_BITS as usize / 8]);
+        assert_eq!(raised.len(), MAX_LAG);
+        assert!((raised[0].1 + 1.0).abs() < 1e-9);
+        assert!((raised[1].1 - 1.0).abs() < 1e-9);
+
+        // Alarms are only reported when first raised
+        assert!(monitor.observe(&[0x55; 16]).is_empty());
+    }
+}
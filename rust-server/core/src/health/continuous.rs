@@ -0,0 +1,170 @@
+//! SP 800-90B section 4.4 continuous health tests
+//!
+//! Both tests treat each raw byte as one 8-bit sample. Cutoffs are derived
+//! from the assessed min-entropy per sample `H` and the false positive
+//! probability `alpha = 2^-ALPHA_EXPONENT`.
+
+/// False positive probability exponent (alpha = 2^-40)
+///
+/// SP 800-90B allows 2^-20 to 2^-40; at device rates of several MB/s the
+/// weaker bound would raise false alarms within minutes.
+pub const ALPHA_EXPONENT: u32 = 40;
+
+/// Adaptive Proportion Test window size for non-binary samples
+pub const APT_WINDOW: usize = 512;
+
+/// Repetition Count Test (SP 800-90B 4.4.1)
+///
+/// Fails when one sample value repeats `cutoff` times in a row, which
+/// catches a source that has become stuck.
+#[derive(Debug, Clone)]
+pub struct RepetitionCountTest {
+    cutoff: usize,
+    last: Option<u8>,
+    run: usize,
+}
+
+impl RepetitionCountTest {
+    /// Create the test for `min_entropy` bits per byte
+    pub fn new(min_entropy: f64) -> Self {
+        Self {
+            cutoff: 1 + (ALPHA_EXPONENT as f64 / min_entropy).ceil() as usize,
+            last: None,
+            run: 0,
+        }
+    }
+
+    /// Run length that trips the test
+    pub fn cutoff(&self) -> usize {
+        self.cutoff
+    }
+
+    /// Length of the current run of identical samples
+    pub fn run(&self) -> usize {
+        self.run
+    }
+
+    /// Feed one sample; returns false if the test fails
+    pub fn feed(&mut self, sample: u8) -> bool {
+        if self.last == Some(sample) {
+            self.run += 1;
+        } else {
+            self.last = Some(sample);
+            self.run = 1;
+        }
+        self.run < self.cutoff
+    }
+}
+
+/// Adaptive Proportion Test (SP 800-90B 4.4.2)
+///
+/// Fails when the first sample of a `APT_WINDOW` window reappears `cutoff`
+/// or more times within that window, which catches a large loss of entropy.
+#[derive(Debug, Clone)]
+pub struct AdaptiveProportionTest {
+    cutoff: usize,
+    first: u8,
+    count: usize,
+    seen: usize,
+}
+
+impl AdaptiveProportionTest {
+    /// Create the test for `min_entropy` bits per byte
+    pub fn new(min_entropy: f64) -> Self {
+        Self {
+            cutoff: 1 + critical_binomial(APT_WINDOW, 2f64.powf(-min_entropy)),
+            first: 0,
+            count: 0,
+            seen: 0,
+        }
+    }
+
+    /// Occurrence count that trips the test
+    pub fn cutoff(&self) -> usize {
+        self.cutoff
+    }
+
+    /// Samples of the current window fed so far, including the last one
+    pub fn window_position(&self) -> usize {
+        // A window that just completed is reset to zero
+        if self.seen == 0 {
+            APT_WINDOW
+        } else {
+            self.seen
+        }
+    }
+
+    /// Feed one sample; returns false if the test fails
+    pub fn feed(&mut self, sample: u8) -> bool {
+        if self.seen == 0 {
+            self.first = sample;
+            self.count = 1;
+            self.seen = 1;
+            return true;
+        }
+
+        self.seen += 1;
+        if sample == self.first {
+            self.count += 1;
+        }
+        if self.seen == APT_WINDOW {
+            self.seen = 0;
+        }
+        self.count < self.cutoff
+    }
+}
+
+/// Smallest `k` with `P(X > k) <= alpha` for `X ~ Binomial(n, p)`
+///
+/// Equivalent to Excel's CRITBINOM(n, p, 1 - alpha). The upper tail is
+/// summed directly since 1 - alpha is not representable for tiny alpha.
+fn critical_binomial(n: usize, p: f64) -> usize {
+    let alpha = 2f64.powi(-(ALPHA_EXPONENT as i32));
+
+    let mut pmf = Vec::with_capacity(n + 1);
+    let mut term = (1.0 - p).powi(n as i32);
+    for i in 0..=n {
+        pmf.push(term);
+        term *= (n - i) as f64 / (i + 1) as f64 * p / (1.0 - p);
+    }
+
+    let mut tail = 0.0;
+    for k in (0..=n).rev() {
+        if tail + pmf[k] > alpha {
+            return k;
+        }
+        tail += pmf[k];
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cutoffs_follow_min_entropy() {
+        assert_eq!(RepetitionCountTest::new(8.0).cutoff(), 6);
+        assert_eq!(RepetitionCountTest::new(7.0).cutoff(), 7);
+        // Lower assessed entropy tolerates more repetition
+        assert!(AdaptiveProportionTest::new(4.0).cutoff() > AdaptiveProportionTest::new(7.0).cutoff());
+        assert!(AdaptiveProportionTest::new(7.0).cutoff() < APT_WINDOW);
+    }
+
+    #[test]
+    fn rct_trips_on_stuck_source() {
+        let mut rct = RepetitionCountTest::new(7.0);
+        let results: Vec<bool> = std::iter::repeat_n(0xaa, 7).map(|b| rct.feed(b)).collect();
+        assert_eq!(results, [true, true, true, true, true, true, false]);
+    }
+
+    #[test]
+    fn apt_trips_on_dominant_value_and_passes_varied_data() {
+        let mut apt = AdaptiveProportionTest::new(7.0);
+        assert!((0..APT_WINDOW * 4).all(|i| apt.feed((i * 37 % 256) as u8)));
+
+        let mut apt = AdaptiveProportionTest::new(7.0);
+        let mut biased = (0..APT_WINDOW).map(|i| if i % 4 == 0 { 0 } else { i as u8 });
+        assert!(!biased.all(|b| apt.feed(b)));
+    }
+}
@@ -0,0 +1,113 @@
+//! Entropy credit accounting
+//!
+//! Every buffered raw byte is credited with the assessed min-entropy.
+//! Conditioning can concentrate that credit but never add to it, so the
+//! credit available to a request is the buffered entropy expressed as
+//! output of the request's pipeline at that pipeline's entropy rate.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::device::pipeline::Pipeline;
+
+/// Credit available for a pipeline over the current buffer
+#[derive(Debug, Clone, Serialize)]
+pub struct CreditReport {
+    pub pipeline: String,
+    /// Raw bytes in the buffer
+    pub buffered_bytes: usize,
+    /// Min-entropy of the buffered raw bytes, in bits
+    pub buffered_bits: f64,
+    /// Min-entropy per output byte of the pipeline
+    pub output_rate: f64,
+    /// Output bytes the buffer can back with full entropy
+    pub full_entropy_bytes: usize,
+    /// Credit debited by served requests since startup, in bits
+    pub debited_bits: u64,
+    /// Whether requests beyond the credit are refused
+    pub enforced: bool,
+}
+
+/// Tracks credited and spent entropy
+pub struct EntropyAccount {
+    min_entropy: f64,
+    enforce: bool,
+    debited: AtomicU64,
+}
+
+impl EntropyAccount {
+    pub fn new(min_entropy: f64) -> Self {
+        Self {
+            min_entropy,
+            enforce: false,
+            debited: AtomicU64::new(0),
+        }
+    }
+
+    /// Refuse buffered requests that exceed the available credit
+    pub fn enforcing(mut self) -> Self {
+        self.enforce = true;
+        self
+    }
+
+    /// Whether requests beyond the credit are refused
+    pub fn enforced(&self) -> bool {
+        self.enforce
+    }
+
+    /// Entropy, in bits, that `buffered` raw bytes provide through `pipeline`
+    pub fn available_bits(&self, buffered: usize, pipeline: &Pipeline) -> f64 {
+        let ratio = pipeline.input_len(buffered.max(1)) as f64 / buffered.max(1) as f64;
+        buffered as f64 / ratio * pipeline.entropy_rate(self.min_entropy)
+    }
+
+    /// Whether `buffered` raw bytes back `count` full-entropy output bytes
+    pub fn covers(&self, buffered: usize, pipeline: &Pipeline, count: usize) -> bool {
+        self.available_bits(buffered, pipeline) >= count as f64 * 8.0
+    }
+
+    /// Record `count` output bytes served through `pipeline`
+    pub fn debit(&self, pipeline: &Pipeline, count: usize) {
+        let bits = count as f64 * pipeline.entropy_rate(self.min_entropy);
+        self.debited.fetch_add(bits as u64, Ordering::Relaxed);
+    }
+
+    /// Current credit for `pipeline`
+    pub fn report(&self, buffered: usize, pipeline: &Pipeline) -> CreditReport {
+        let available = self.available_bits(buffered, pipeline);
+        CreditReport {
+            pipeline: pipeline.to_string(),
+            buffered_bytes: buffered,
+            buffered_bits: buffered as f64 * self.min_entropy,
+            output_rate: pipeline.entropy_rate(self.min_entropy),
+            full_entropy_bytes: (available / 8.0) as usize,
+            debited_bits: self.debited.load(Ordering::Relaxed),
+            enforced: self.enforce,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::pipeline::StageDefaults;
+
+    #[test]
+    fn credit_depends_on_conditioning() {
+        let account = EntropyAccount::new(4.0);
+        let raw = Pipeline::default();
+        let sha3 = Pipeline::parse("sha3:2", StageDefaults::default()).unwrap();
+
+        // Raw output carries half the claimed entropy
+        assert_eq!(account.available_bits(1024, &raw), 4096.0);
+        assert!(account.covers(1024, &raw, 512));
+        assert!(!account.covers(1024, &raw, 513));
+
+        // Compressing 2:1 yields full-entropy output from the same credit
+        assert_eq!(account.available_bits(1024, &sha3), 4096.0);
+        assert_eq!(account.report(1024, &sha3).full_entropy_bytes, 512);
+
+        account.debit(&sha3, 100);
+        assert_eq!(account.report(0, &sha3).debited_bits, 800);
+    }
+}
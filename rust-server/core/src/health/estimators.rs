@@ -0,0 +1,361 @@
+//! SP 800-90B min-entropy estimators
+//!
+//! The most common value estimate runs over bytes and bits; the non-IID
+//! collision, Markov and compression estimates (6.3.2 - 6.3.4) run over
+//! the bit stream. The assessed entropy rate is the lowest of them.
+
+use serde::Serialize;
+
+/// Two-sided 99% normal quantile used for upper confidence bounds
+const Z_ALPHA: f64 = 2.576;
+
+/// Sample size SP 800-90B asks for, in bytes
+pub const RECOMMENDED_SAMPLES: usize = 1_000_000;
+
+/// Bisection steps when solving for the most likely bit probability
+const SEARCH_STEPS: usize = 64;
+
+/// Bits per symbol in the compression estimate
+const COMPRESSION_BITS: usize = 6;
+
+/// Symbols that initialize the compression estimate's dictionary
+const COMPRESSION_DICTIONARY: usize = 1000;
+
+/// Result of the most common value estimate
+#[derive(Debug, Clone, Serialize)]
+pub struct McvEstimate {
+    pub samples: usize,
+    pub bits_per_sample: u32,
+    pub most_common: u8,
+    /// Observed proportion of the most common value
+    pub p_hat: f64,
+    /// 99% upper confidence bound on that proportion
+    pub p_upper: f64,
+    /// Estimated min-entropy per sample
+    pub min_entropy: f64,
+    /// Estimated min-entropy per bit
+    pub min_entropy_per_bit: f64,
+}
+
+/// Most common value estimate (SP 800-90B 6.3.1) over byte samples
+pub fn most_common_value(data: &[u8]) -> McvEstimate {
+    let mut counts = [0u64; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    mcv(&counts, data.len(), 8)
+}
+
+/// Most common value estimate treating every bit as a binary sample
+pub fn most_common_value_bits(data: &[u8]) -> McvEstimate {
+    let ones: u64 = data.iter().map(|b| b.count_ones() as u64).sum();
+    let samples = data.len() * 8;
+    mcv(&[samples as u64 - ones, ones], samples, 1)
+}
+
+fn mcv(counts: &[u64], samples: usize, bits_per_sample: u32) -> McvEstimate {
+    let (most_common, &max) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, count)| count)
+        .unwrap_or((0, &0));
+
+    let l = samples as f64;
+    let p_hat = if samples == 0 { 1.0 } else { max as f64 / l };
+    let p_upper = if samples < 2 {
+        1.0
+    } else {
+        (p_hat + Z_ALPHA * (p_hat * (1.0 - p_hat) / (l - 1.0)).sqrt()).min(1.0)
+    };
+    let min_entropy = -p_upper.log2();
+
+    McvEstimate {
+        samples,
+        bits_per_sample,
+        most_common: most_common as u8,
+        p_hat,
+        p_upper,
+        min_entropy,
+        min_entropy_per_bit: min_entropy / bits_per_sample as f64,
+    }
+}
+
+/// Result of a binary non-IID estimator
+#[derive(Debug, Clone, Serialize)]
+pub struct BitEstimate {
+    pub estimator: &'static str,
+    /// Binary samples used
+    pub samples: usize,
+    /// Estimated min-entropy per bit
+    pub min_entropy_per_bit: f64,
+}
+
+/// Every estimator run over one capture
+#[derive(Debug, Clone, Serialize)]
+pub struct Assessment {
+    /// Estimator giving the lowest estimate
+    pub estimator: &'static str,
+    /// Min-entropy per bit, the lowest of all estimates
+    pub min_entropy_per_bit: f64,
+    pub bytes: McvEstimate,
+    pub bits: McvEstimate,
+    pub non_iid: Vec<BitEstimate>,
+    /// Unix timestamp of the assessment
+    pub at: u64,
+}
+
+/// Run every estimator over `data` and take the lowest estimate
+pub fn assess(data: &[u8]) -> Assessment {
+    let bytes = most_common_value(data);
+    let bits = most_common_value_bits(data);
+    let non_iid = vec![collision(data), markov(data), compression(data)];
+
+    let (estimator, min_entropy_per_bit) = [
+        ("most_common_value", bytes.min_entropy_per_bit),
+        ("most_common_value_bits", bits.min_entropy_per_bit),
+    ]
+    .into_iter()
+    .chain(non_iid.iter().map(|e| (e.estimator, e.min_entropy_per_bit)))
+    .fold(("most_common_value", f64::INFINITY), |lowest, candidate| {
+        if candidate.1 < lowest.1 {
+            candidate
+        } else {
+            lowest
+        }
+    });
+
+    Assessment {
+        estimator,
+        min_entropy_per_bit,
+        bytes,
+        bits,
+        non_iid,
+        at: super::unix_time(),
+    }
+}
+
+/// Bits of `data`, most significant first
+fn bit_samples(data: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    data.iter().flat_map(|&b| (0..8).rev().map(move |i| (b >> i) & 1))
+}
+
+/// Mean and sample standard deviation
+fn mean_and_deviation(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
+
+/// Largest p in [low, 1] whose expected statistic still reaches `target`
+///
+/// `expected` must decrease in p. Returns None when even `low` falls
+/// short, meaning the data looks better than full entropy.
+fn solve_decreasing(target: f64, low: f64, expected: impl Fn(f64) -> f64) -> Option<f64> {
+    if expected(low) < target {
+        return None;
+    }
+    let (mut lo, mut hi) = (low, 1.0);
+    for _ in 0..SEARCH_STEPS {
+        let mid = (lo + hi) / 2.0;
+        if expected(mid) >= target {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Some(lo)
+}
+
+fn bit_estimate(estimator: &'static str, samples: usize, p: Option<f64>, bits_per_symbol: f64) -> BitEstimate {
+    let min_entropy_per_bit = match p {
+        Some(p) => (-p.log2() / bits_per_symbol).clamp(0.0, 1.0),
+        None => 1.0,
+    };
+    BitEstimate {
+        estimator,
+        samples,
+        min_entropy_per_bit,
+    }
+}
+
+/// Collision estimate (SP 800-90B 6.3.2) over the bit stream
+pub fn collision(data: &[u8]) -> BitEstimate {
+    let bits: Vec<u8> = bit_samples(data).collect();
+
+    // Samples until the first repeated value: 2 or, for binary data, 3
+    let mut times = Vec::new();
+    let mut i = 0;
+    while i + 1 < bits.len() {
+        if bits[i] == bits[i + 1] {
+            times.push(2.0);
+            i += 2;
+        } else if i + 2 < bits.len() {
+            times.push(3.0);
+            i += 3;
+        } else {
+            break;
+        }
+    }
+    if times.len() < 2 {
+        return bit_estimate("collision", bits.len(), Some(1.0), 1.0);
+    }
+
+    let (mean, deviation) = mean_and_deviation(&times);
+    let target = mean - Z_ALPHA * deviation / (times.len() as f64).sqrt();
+
+    // Expected collision time when one value has probability p;
+    // F(q) = Γ(3, 1/q) q^-3 e^(1/q) in closed form
+    let expected = |p: f64| {
+        let q = 1.0 - p;
+        if q <= 0.0 {
+            return 2.0;
+        }
+        let z = 1.0 / q;
+        let f = (2.0 + 2.0 * z + z * z) / (z * z * z);
+        let half_diff = 0.5 * (1.0 / p - 1.0 / q);
+        p / (q * q) * (1.0 + half_diff) * f - p / q * half_diff
+    };
+
+    bit_estimate("collision", bits.len(), solve_decreasing(target, 0.5, expected), 1.0)
+}
+
+/// Markov estimate (SP 800-90B 6.3.3) over the bit stream
+pub fn markov(data: &[u8]) -> BitEstimate {
+    let bits: Vec<u8> = bit_samples(data).collect();
+    if bits.len() < 2 {
+        return bit_estimate("markov", bits.len(), Some(1.0), 1.0);
+    }
+
+    let ones = bits.iter().filter(|&&b| b == 1).count() as f64;
+    let p1 = ones / bits.len() as f64;
+    let p0 = 1.0 - p1;
+
+    let mut transitions = [[0f64; 2]; 2];
+    for pair in bits.windows(2) {
+        transitions[pair[0] as usize][pair[1] as usize] += 1.0;
+    }
+    let row = |from: usize, to: usize| {
+        let total = transitions[from][0] + transitions[from][1];
+        if total == 0.0 {
+            0.0
+        } else {
+            transitions[from][to] / total
+        }
+    };
+    let (p00, p01, p10, p11) = (row(0, 0), row(0, 1), row(1, 0), row(1, 1));
+
+    // Most likely 128-bit sequences under the fitted first-order chain
+    let log = |p: f64| p.log2();
+    let candidates = [
+        log(p0) + 127.0 * log(p00),
+        log(p0) + 64.0 * log(p01) + 63.0 * log(p10),
+        log(p0) + log(p01) + 126.0 * log(p11),
+        log(p1) + log(p10) + 126.0 * log(p00),
+        log(p1) + 64.0 * log(p10) + 63.0 * log(p01),
+        log(p1) + 127.0 * log(p11),
+    ];
+    let log_max = candidates.into_iter().fold(f64::NEG_INFINITY, f64::max);
+
+    bit_estimate("markov", bits.len(), Some(log_max.exp2()), 128.0)
+}
+
+/// Compression estimate (SP 800-90B 6.3.4) over 6-bit symbols
+pub fn compression(data: &[u8]) -> BitEstimate {
+    let bits: Vec<u8> = bit_samples(data).collect();
+    let symbols: Vec<usize> = bits
+        .chunks_exact(COMPRESSION_BITS)
+        .map(|chunk| chunk.iter().fold(0, |acc, &b| acc << 1 | b as usize))
+        .collect();
+    if symbols.len() < COMPRESSION_DICTIONARY + 2 {
+        return bit_estimate("compression", bits.len(), Some(1.0), 1.0);
+    }
+
+    // Maurer-style distances to each symbol's previous occurrence
+    let mut dictionary = [0usize; 1 << COMPRESSION_BITS];
+    for (i, &symbol) in symbols[..COMPRESSION_DICTIONARY].iter().enumerate() {
+        dictionary[symbol] = i + 1;
+    }
+    let mut distances = Vec::with_capacity(symbols.len() - COMPRESSION_DICTIONARY);
+    for (i, &symbol) in symbols.iter().enumerate().skip(COMPRESSION_DICTIONARY) {
+        let index = i + 1;
+        let previous = dictionary[symbol];
+        distances.push(((index - previous) as f64).log2());
+        dictionary[symbol] = index;
+    }
+
+    let total = symbols.len();
+    let tested = distances.len() as f64;
+    let mean = distances.iter().sum::<f64>() / tested;
+    let squares = distances.iter().map(|d| d * d).sum::<f64>() / (tested - 1.0);
+    let deviation = 0.5907 * (squares - mean * mean).max(0.0).sqrt();
+    let target = mean - Z_ALPHA * deviation / tested.sqrt();
+
+    // G(z): expected log2 distance when a symbol has probability z,
+    // summed per distance u rather than per test position
+    let g = |z: f64| {
+        let mut sum = 0.0;
+        let mut tail = 1.0;
+        for u in 1..=total {
+            let log_u = (u as f64).log2();
+            // Test positions t > u that can see distance u
+            let later = if u <= COMPRESSION_DICTIONARY {
+                total - COMPRESSION_DICTIONARY
+            } else {
+                total - u
+            };
+            sum += log_u * z * z * tail * later as f64;
+            if u > COMPRESSION_DICTIONARY {
+                sum += log_u * z * tail;
+            }
+            tail *= 1.0 - z;
+            if tail < 1e-300 {
+                break;
+            }
+        }
+        sum / tested
+    };
+    let others = ((1 << COMPRESSION_BITS) - 1) as f64;
+    let expected = |p: f64| g(p) + others * g((1.0 - p) / others);
+
+    let p = solve_decreasing(target, 1.0 / (1 << COMPRESSION_BITS) as f64, expected);
+    bit_estimate("compression", bits.len(), p, COMPRESSION_BITS as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcv_bounds_entropy_of_uniform_and_constant_data() {
+        let uniform: Vec<u8> = (0..=255).cycle().take(1 << 16).collect();
+        let estimate = most_common_value(&uniform);
+        assert!(estimate.min_entropy > 7.0 && estimate.min_entropy < 8.0);
+        assert_eq!(most_common_value_bits(&uniform).p_hat, 0.5);
+
+        let constant = most_common_value(&[0x42; 1024]);
+        assert_eq!(constant.most_common, 0x42);
+        assert_eq!(constant.min_entropy, 0.0);
+    }
+
+    #[test]
+    fn non_iid_estimators_separate_random_and_structured_data() {
+        use crate::device::{mock::MockSource, EntropySource};
+
+        let random = MockSource::new("mock", 11).read(1 << 16).unwrap();
+        for estimate in [collision(&random), markov(&random), compression(&random)] {
+            assert!(estimate.min_entropy_per_bit > 0.75, "{:?}", estimate);
+        }
+
+        let constant = [0u8; 4096];
+        assert!(collision(&constant).min_entropy_per_bit < 1e-6);
+        assert_eq!(markov(&constant).min_entropy_per_bit, 0.0);
+
+        // Alternating bits are perfectly predictable from the previous bit
+        assert!(markov(&[0x55; 4096]).min_entropy_per_bit < 0.01);
+
+        let assessment = assess(&[0x55; 4096]);
+        assert!(assessment.min_entropy_per_bit < 0.01);
+        assert_eq!(assessment.non_iid.len(), 3);
+    }
+}
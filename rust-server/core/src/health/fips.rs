@@ -0,0 +1,101 @@
+//! FIPS 140-2 continuous random number generator test (4.9.2)
+//!
+//! Conditioned output is split into fixed-size blocks and each block is
+//! compared with the one generated before it. A block equal to its
+//! predecessor fails the test and is never emitted. The very first block
+//! only seeds the comparison and is discarded as well.
+
+use serde::Serialize;
+
+/// Default block size in bytes (128 bits)
+pub const DEFAULT_BLOCK_SIZE: usize = 16;
+
+/// Counters reported by the test
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CrngtStatus {
+    pub block_size: usize,
+    pub blocks_tested: u64,
+    pub failures: u64,
+    /// Unix timestamp of the most recent failure
+    pub last_failure: Option<u64>,
+}
+
+/// Block comparison test over conditioned output
+#[derive(Debug, Clone)]
+pub struct ContinuousRngTest {
+    previous: Option<Vec<u8>>,
+    status: CrngtStatus,
+}
+
+impl ContinuousRngTest {
+    /// Create the test for `block_size`-byte blocks
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            previous: None,
+            status: CrngtStatus {
+                block_size,
+                ..CrngtStatus::default()
+            },
+        }
+    }
+
+    /// Block size in bytes
+    pub fn block_size(&self) -> usize {
+        self.status.block_size
+    }
+
+    /// Current counters
+    pub fn status(&self) -> &CrngtStatus {
+        &self.status
+    }
+
+    /// Return only the blocks of `data` that pass the test
+    ///
+    /// A trailing partial block cannot be tested and is dropped.
+    pub fn filter(&mut self, data: &[u8], now: u64) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len());
+
+        for block in data.chunks_exact(self.status.block_size) {
+            let Some(previous) = self.previous.as_mut() else {
+                self.previous = Some(block.to_vec());
+                continue;
+            };
+
+            self.status.blocks_tested += 1;
+            if previous.as_slice() == block {
+                self.status.failures += 1;
+                self.status.last_failure = Some(now);
+            } else {
+                previous.copy_from_slice(block);
+                output.extend_from_slice(block);
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_first_and_repeated_blocks() {
+        let mut test = ContinuousRngTest::new(4);
+        let data = [
+            [1u8, 2, 3, 4],
+            [5, 6, 7, 8],
+            [5, 6, 7, 8],
+            [9, 9, 9, 9],
+        ]
+        .concat();
+
+        assert_eq!(test.filter(&data, 42), [5, 6, 7, 8, 9, 9, 9, 9]);
+        assert_eq!(test.status().blocks_tested, 3);
+        assert_eq!(test.status().failures, 1);
+        assert_eq!(test.status().last_failure, Some(42));
+
+        // The comparison carries over between calls; partial blocks are dropped
+        assert_eq!(test.filter(&[9, 9, 9, 9, 1, 2], 43), Vec::<u8>::new());
+        assert_eq!(test.status().failures, 2);
+    }
+}
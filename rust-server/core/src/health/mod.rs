@@ -0,0 +1,424 @@
+//! Entropy source health testing
+
+use serde::Serialize;
+use std::{
+    sync::{Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use tracing::{error, warn};
+
+pub mod alarm;
+pub mod audit;
+pub mod autocorrelation;
+pub mod continuous;
+pub mod credit;
+pub mod estimators;
+pub mod fips;
+pub mod monitor;
+pub mod sp800_22;
+
+use alarm::Alarm;
+use audit::{AuditCategory, AuditLog};
+use autocorrelation::AutocorrelationMonitor;
+use estimators::Assessment;
+use continuous::{AdaptiveProportionTest, RepetitionCountTest};
+use fips::{ContinuousRngTest, CrngtStatus};
+use monitor::{AlarmThresholds, RollingMonitor};
+
+/// Default assessed min-entropy of the raw stream, in bits per byte
+pub const DEFAULT_MIN_ENTROPY: f64 = 7.0;
+
+/// A tripped health test
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthFailure {
+    pub test: String,
+    pub detail: String,
+    /// Unix timestamp of the failure
+    pub at: u64,
+    /// Bytes of earlier output inside the failing test window
+    pub unconfirmed: usize,
+}
+
+impl HealthFailure {
+    fn new(test: &str, detail: String, unconfirmed: usize) -> Self {
+        Self {
+            test: test.to_string(),
+            detail,
+            at: unix_time(),
+            unconfirmed,
+        }
+    }
+}
+
+/// Consecutive raw bytes that must pass fresh tests before auto-recovery
+pub const RECOVERY_BYTES: usize = 1024 * 1024;
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl std::fmt::Display for HealthFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed: {}", self.test, self.detail)
+    }
+}
+
+/// Raw bytes passing the continuous tests between audited summaries
+pub const AUDIT_SUMMARY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Raw samples tested at startup (SP 800-90B 4.3 asks for at least 1024)
+pub const STARTUP_SAMPLES: usize = 4096;
+
+struct ContinuousTests {
+    rct: RepetitionCountTest,
+    apt: AdaptiveProportionTest,
+    /// Bytes passed since the last audited summary
+    passed: usize,
+}
+
+impl ContinuousTests {
+    fn new(min_entropy: f64) -> Self {
+        Self {
+            rct: RepetitionCountTest::new(min_entropy),
+            apt: AdaptiveProportionTest::new(min_entropy),
+            passed: 0,
+        }
+    }
+
+    /// Run the tests over `data`, returning the first failure
+    fn run(&mut self, data: &[u8]) -> Result<(), HealthFailure> {
+        for (i, &sample) in data.iter().enumerate() {
+            let (test, detail, suspect) = if !self.rct.feed(sample) {
+                (
+                    "repetition_count",
+                    format!("value {:#04x} repeated {} times", sample, self.rct.cutoff()),
+                    self.rct.run(),
+                )
+            } else if !self.apt.feed(sample) {
+                (
+                    "adaptive_proportion",
+                    format!(
+                        "value {:#04x} seen {} times in a {} sample window",
+                        sample,
+                        self.apt.cutoff(),
+                        continuous::APT_WINDOW
+                    ),
+                    self.apt.window_position(),
+                )
+            } else {
+                continue;
+            };
+
+            // Suspect samples before this call came from earlier output
+            return Err(HealthFailure::new(test, detail, suspect.saturating_sub(i + 1)));
+        }
+        Ok(())
+    }
+}
+
+/// Continuous health tests over all raw device output
+///
+/// A failure quarantines the source: no entropy is served until an
+/// operator acknowledges the failure or, with auto-recovery enabled,
+/// `RECOVERY_BYTES` of fresh output pass a new set of tests.
+/// In FIPS mode conditioned output additionally goes through the
+/// continuous RNG test, which drops failing blocks without latching.
+pub struct HealthState {
+    min_entropy: f64,
+    tests: Mutex<ContinuousTests>,
+    failure: RwLock<Option<HealthFailure>>,
+    crngt: Option<Mutex<ContinuousRngTest>>,
+    monitor: RollingMonitor,
+    autocorrelation: AutocorrelationMonitor,
+    alarms: broadcast::Sender<Alarm>,
+    auto_recovery: bool,
+    /// Fresh tests and bytes passed while quarantined
+    probation: Mutex<Option<(ContinuousTests, usize)>>,
+    audit: AuditLog,
+    assessment: RwLock<Option<Assessment>>,
+}
+
+impl HealthState {
+    /// Create the tests for a source assessed at `min_entropy` bits per byte
+    pub fn new(min_entropy: f64) -> Self {
+        Self {
+            min_entropy,
+            tests: Mutex::new(ContinuousTests::new(min_entropy)),
+            failure: RwLock::new(None),
+            crngt: None,
+            monitor: RollingMonitor::new(AlarmThresholds::default()),
+            autocorrelation: AutocorrelationMonitor::new(autocorrelation::DEFAULT_THRESHOLD),
+            alarms: broadcast::channel(16).0,
+            auto_recovery: false,
+            probation: Mutex::new(None),
+            audit: AuditLog::new(),
+            assessment: RwLock::new(None),
+        }
+    }
+
+    /// Record results to `audit` instead of an in-memory trail
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Trail of test results
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Resume automatically once fresh output passes the tests again
+    pub fn with_auto_recovery(mut self) -> Self {
+        self.auto_recovery = true;
+        self
+    }
+
+    /// Whether quarantine ends without operator action
+    pub fn auto_recovery(&self) -> bool {
+        self.auto_recovery
+    }
+
+    /// End the quarantine, returning the failure that caused it
+    ///
+    /// The continuous tests restart from a clean state.
+    pub fn recover(&self) -> Option<HealthFailure> {
+        *self.tests.lock().unwrap() = ContinuousTests::new(self.min_entropy);
+        *self.probation.lock().unwrap() = None;
+        self.failure.write().unwrap().take()
+    }
+
+    /// Test raw output read while quarantined
+    ///
+    /// Returns true once `RECOVERY_BYTES` in a row have passed and the
+    /// quarantine was lifted. Any failure restarts the count.
+    pub fn probe(&self, data: &[u8]) -> bool {
+        if self.failure().is_none() {
+            return true;
+        }
+
+        let mut probation = self.probation.lock().unwrap();
+        let (tests, passed) =
+            probation.get_or_insert_with(|| (ContinuousTests::new(self.min_entropy), 0));
+        match tests.run(data) {
+            Ok(()) => *passed += data.len(),
+            Err(_) => {
+                *probation = None;
+                return false;
+            }
+        }
+        if *passed < RECOVERY_BYTES {
+            return false;
+        }
+
+        drop(probation);
+        if let Some(failure) = self.recover() {
+            warn!("Recovered from health test failure ({}) after {} passing bytes", failure, RECOVERY_BYTES);
+            self.audit.record(
+                AuditCategory::Continuous,
+                "auto_recovery",
+                true,
+                format!("{} fresh bytes passed after {}", RECOVERY_BYTES, failure),
+            );
+        }
+        true
+    }
+
+    /// Use a custom autocorrelation alarm threshold
+    pub fn with_autocorrelation_threshold(mut self, threshold: f64) -> Self {
+        self.autocorrelation = AutocorrelationMonitor::new(threshold);
+        self
+    }
+
+    /// Autocorrelation of the raw stream
+    pub fn autocorrelation(&self) -> &AutocorrelationMonitor {
+        &self.autocorrelation
+    }
+
+    /// Receive alarms raised by the stream monitors
+    pub fn subscribe_alarms(&self) -> broadcast::Receiver<Alarm> {
+        self.alarms.subscribe()
+    }
+
+    /// Feed raw output that passed the continuous tests to the monitors
+    pub fn observe_raw(&self, data: &[u8]) {
+        let threshold = self.autocorrelation.threshold();
+        for (lag, value) in self.autocorrelation.observe(data) {
+            warn!(lag, value, threshold, "Raw stream autocorrelation alarm raised");
+            let detail = format!("lag {} autocorrelation {:.5} exceeds {}", lag, value, threshold);
+            self.audit.record(AuditCategory::Alarm, "autocorrelation", false, detail.clone());
+            let _ = self.alarms.send(Alarm::new("autocorrelation", detail, value, threshold));
+        }
+    }
+
+    /// Use custom alarm thresholds for the rolling buffer statistics
+    pub fn with_alarm_thresholds(mut self, thresholds: AlarmThresholds) -> Self {
+        self.monitor = RollingMonitor::new(thresholds);
+        self
+    }
+
+    /// Rolling statistics over buffered data
+    pub fn monitor(&self) -> &RollingMonitor {
+        &self.monitor
+    }
+
+    /// Enable FIPS mode with `block_size`-byte comparison blocks
+    pub fn with_fips(mut self, block_size: usize) -> Self {
+        self.crngt = Some(Mutex::new(ContinuousRngTest::new(block_size)));
+        self
+    }
+
+    /// Comparison block size if FIPS mode is enabled
+    pub fn fips_block_size(&self) -> Option<usize> {
+        self.crngt.as_ref().map(|t| t.lock().unwrap().block_size())
+    }
+
+    /// FIPS continuous RNG test counters if FIPS mode is enabled
+    pub fn fips_status(&self) -> Option<CrngtStatus> {
+        self.crngt.as_ref().map(|t| t.lock().unwrap().status().clone())
+    }
+
+    /// Pass conditioned output through the FIPS continuous RNG test
+    ///
+    /// Returns the data unchanged when FIPS mode is off.
+    pub fn fips_filter(&self, data: Vec<u8>) -> Vec<u8> {
+        let Some(crngt) = &self.crngt else {
+            return data;
+        };
+
+        let mut test = crngt.lock().unwrap();
+        let failures = test.status().failures;
+        let output = test.filter(&data, unix_time());
+        if test.status().failures > failures {
+            let detail = format!("dropped {} repeated block(s)", test.status().failures - failures);
+            warn!("FIPS continuous RNG test {}", detail);
+            self.audit.record(AuditCategory::Continuous, "fips_crngt", false, detail);
+        }
+        output
+    }
+
+    /// Assessed min-entropy in bits per byte
+    pub fn min_entropy(&self) -> f64 {
+        self.min_entropy
+    }
+
+    /// Keep and audit the latest estimator run
+    ///
+    /// The estimate should support the min-entropy the health tests assume.
+    pub fn record_assessment(&self, assessment: Assessment) {
+        let per_byte = assessment.min_entropy_per_bit * 8.0;
+        let passed = per_byte >= self.min_entropy;
+        if !passed {
+            warn!(
+                "Estimated min-entropy {:.4} bits per byte ({}) is below the assessed {}",
+                per_byte, assessment.estimator, self.min_entropy
+            );
+        }
+        self.audit.record(
+            AuditCategory::SelfTest,
+            "entropy_assessment",
+            passed,
+            format!(
+                "{:.4} bits per byte from {} over {} samples, assessed {}",
+                per_byte, assessment.estimator, assessment.bytes.samples, self.min_entropy
+            ),
+        );
+        *self.assessment.write().unwrap() = Some(assessment);
+    }
+
+    /// Latest estimator run, if any
+    pub fn assessment(&self) -> Option<Assessment> {
+        self.assessment.read().unwrap().clone()
+    }
+
+    /// Run the continuous tests over raw device output
+    ///
+    /// Returns the failure if this data (or earlier data) tripped a test;
+    /// the data must then be discarded.
+    pub fn check(&self, data: &[u8]) -> Result<(), HealthFailure> {
+        if let Some(failure) = self.failure() {
+            return Err(failure);
+        }
+
+        let mut tests = self.tests.lock().unwrap();
+        let result = tests.run(data);
+        match &result {
+            Ok(()) => {
+                tests.passed += data.len();
+                if tests.passed >= AUDIT_SUMMARY_BYTES {
+                    let detail = format!("{} bytes passed repetition_count and adaptive_proportion", tests.passed);
+                    self.audit.record(AuditCategory::Continuous, "continuous", true, detail);
+                    tests.passed = 0;
+                }
+            }
+            Err(failure) => {
+                error!("Continuous health test failure: {}", failure);
+                self.audit
+                    .record(AuditCategory::Continuous, &failure.test, false, failure.detail.clone());
+                *self.failure.write().unwrap() = Some(failure.clone());
+            }
+        }
+        result
+    }
+
+    /// Run the startup tests over the first raw output
+    ///
+    /// A failure quarantines the source like a continuous test failure.
+    pub fn startup(&self, data: &[u8]) -> Result<(), HealthFailure> {
+        let result = self.check(data);
+        let detail = match &result {
+            Ok(()) => format!("{} samples passed repetition_count and adaptive_proportion", data.len()),
+            Err(failure) => failure.to_string(),
+        };
+        self.audit.record(AuditCategory::Startup, "startup", result.is_ok(), detail);
+        result
+    }
+
+    /// The failure that quarantined the source, if any
+    pub fn failure(&self) -> Option<HealthFailure> {
+        self.failure.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{mock::MockSource, EntropySource};
+
+    #[test]
+    fn failure_reports_earlier_suspect_bytes_and_recovers() {
+        let health = HealthState::new(DEFAULT_MIN_ENTROPY);
+        let mut source = MockSource::new("mock", 3);
+        assert!(health.check(&source.read(100).unwrap()).is_ok());
+
+        // The stuck run starts in the previous call's output
+        let cutoff = health.tests.lock().unwrap().rct.cutoff();
+        assert!(health.check(&[0x11, 0xaa, 0xaa]).is_ok());
+        let failure = health.check(&vec![0xaa; cutoff]).unwrap_err();
+        assert_eq!(failure.test, "repetition_count");
+        assert_eq!(failure.unconfirmed, 2);
+        assert!(health.check(&source.read(100).unwrap()).is_err());
+
+        // Manual recovery clears the failure and restarts the tests
+        assert!(health.recover().is_some());
+        assert!(health.check(&source.read(100).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn probation_requires_passing_output() {
+        let health = HealthState::new(DEFAULT_MIN_ENTROPY).with_auto_recovery();
+        let mut source = MockSource::new("mock", 4);
+        assert!(health.check(&[0x00; 64]).is_err());
+
+        assert!(!health.probe(&[0x00; 64]));
+        for _ in 0..RECOVERY_BYTES / 65536 - 1 {
+            assert!(!health.probe(&source.read(65536).unwrap()));
+        }
+        assert!(health.probe(&source.read(65536).unwrap()));
+        assert!(health.failure().is_none());
+    }
+}
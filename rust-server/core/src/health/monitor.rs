@@ -0,0 +1,251 @@
+//! Rolling statistics over buffered entropy
+//!
+//! Tracks byte chi-square, mean bit bias and serial correlation over the
+//! most recent `ROLLING_WINDOW` bytes written to the buffer. Once the
+//! window has filled, values past their alarm threshold are flagged and
+//! logged but do not stop serving; the continuous health tests remain
+//! the hard gate.
+
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex};
+use tracing::{info, warn};
+
+/// Bytes covered by the rolling statistics
+pub const ROLLING_WINDOW: usize = 1024 * 1024;
+
+/// Alarm thresholds for the rolling statistics
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AlarmThresholds {
+    /// Byte chi-square (255 degrees of freedom); 330.5 is p = 0.001
+    pub chi_square: f64,
+    /// Absolute deviation of the mean bit value from 0.5
+    pub bias: f64,
+    /// Absolute lag-1 serial correlation between bytes
+    pub correlation: f64,
+}
+
+impl Default for AlarmThresholds {
+    fn default() -> Self {
+        Self {
+            chi_square: 330.5,
+            bias: 0.001,
+            correlation: 0.005,
+        }
+    }
+}
+
+/// Additive statistics of a run of bytes
+#[derive(Debug, Clone)]
+struct Sums {
+    counts: [u64; 256],
+    bytes: u64,
+    ones: u64,
+    sum: f64,
+    sum_squares: f64,
+    sum_products: f64,
+}
+
+impl Default for Sums {
+    fn default() -> Self {
+        Self {
+            counts: [0; 256],
+            bytes: 0,
+            ones: 0,
+            sum: 0.0,
+            sum_squares: 0.0,
+            sum_products: 0.0,
+        }
+    }
+}
+
+impl Sums {
+    fn of(data: &[u8]) -> Self {
+        let mut sums = Self::default();
+        for (i, &b) in data.iter().enumerate() {
+            let x = b as f64;
+            sums.counts[b as usize] += 1;
+            sums.ones += b.count_ones() as u64;
+            sums.sum += x;
+            sums.sum_squares += x * x;
+            if let Some(&next) = data.get(i + 1) {
+                sums.sum_products += x * next as f64;
+            }
+        }
+        sums.bytes = data.len() as u64;
+        sums
+    }
+
+    fn add(&mut self, other: &Sums) {
+        for (count, &other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.bytes += other.bytes;
+        self.ones += other.ones;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.sum_products += other.sum_products;
+    }
+
+    fn remove(&mut self, other: &Sums) {
+        for (count, &other) in self.counts.iter_mut().zip(&other.counts) {
+            *count -= other;
+        }
+        self.bytes -= other.bytes;
+        self.ones -= other.ones;
+        self.sum -= other.sum;
+        self.sum_squares -= other.sum_squares;
+        self.sum_products -= other.sum_products;
+    }
+}
+
+/// Current rolling values
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollingStats {
+    pub window_bytes: u64,
+    pub chi_square: f64,
+    /// Mean bit value minus 0.5
+    pub bias: f64,
+    pub serial_correlation: f64,
+    /// Statistics currently past their threshold
+    pub alarms: Vec<&'static str>,
+}
+
+struct MonitorState {
+    chunks: VecDeque<Sums>,
+    total: Sums,
+    stats: RollingStats,
+}
+
+/// Rolling chi-square, bias and serial correlation monitor
+pub struct RollingMonitor {
+    thresholds: AlarmThresholds,
+    state: Mutex<MonitorState>,
+}
+
+impl RollingMonitor {
+    pub fn new(thresholds: AlarmThresholds) -> Self {
+        Self {
+            thresholds,
+            state: Mutex::new(MonitorState {
+                chunks: VecDeque::new(),
+                total: Sums::default(),
+                stats: RollingStats::default(),
+            }),
+        }
+    }
+
+    /// Configured alarm thresholds
+    pub fn thresholds(&self) -> AlarmThresholds {
+        self.thresholds
+    }
+
+    /// Latest rolling values
+    pub fn stats(&self) -> RollingStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Account for data entering the buffer
+    pub fn observe(&self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let chunk = Sums::of(data);
+        state.total.add(&chunk);
+        state.chunks.push_back(chunk);
+
+        // Drop old chunks while the rest still fills the window
+        while state.chunks.len() > 1
+            && state.total.bytes - state.chunks[0].bytes >= ROLLING_WINDOW as u64
+        {
+            let oldest = state.chunks.pop_front().unwrap();
+            state.total.remove(&oldest);
+        }
+
+        let stats = self.evaluate(&state.total);
+        for alarm in &stats.alarms {
+            if !state.stats.alarms.contains(alarm) {
+                warn!("Rolling {} alarm raised: {:?}", alarm, stats);
+            }
+        }
+        for alarm in &state.stats.alarms {
+            if !stats.alarms.contains(alarm) {
+                info!("Rolling {} alarm cleared", alarm);
+            }
+        }
+        state.stats = stats;
+    }
+
+    fn evaluate(&self, total: &Sums) -> RollingStats {
+        let n = total.bytes as f64;
+        let expected = n / 256.0;
+        let chi_square = total
+            .counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum();
+        let bias = total.ones as f64 / (8.0 * n) - 0.5;
+
+        // Same estimator as `ent`, pairs within each chunk
+        let numerator = n * total.sum_products - total.sum * total.sum;
+        let denominator = n * total.sum_squares - total.sum * total.sum;
+        let serial_correlation = if denominator == 0.0 { 1.0 } else { numerator / denominator };
+
+        // Smaller samples are too noisy to judge against the thresholds
+        let mut alarms = Vec::new();
+        if total.bytes < ROLLING_WINDOW as u64 {
+            return RollingStats {
+                window_bytes: total.bytes,
+                chi_square,
+                bias,
+                serial_correlation,
+                alarms,
+            };
+        }
+        if chi_square > self.thresholds.chi_square {
+            alarms.push("chi_square");
+        }
+        if bias.abs() > self.thresholds.bias {
+            alarms.push("bias");
+        }
+        if serial_correlation.abs() > self.thresholds.correlation {
+            alarms.push("serial_correlation");
+        }
+
+        RollingStats {
+            window_bytes: total.bytes,
+            chi_square,
+            bias,
+            serial_correlation,
+            alarms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{mock::MockSource, EntropySource};
+
+    #[test]
+    fn random_data_stays_quiet_and_stuck_bits_alarm() {
+        let monitor = RollingMonitor::new(AlarmThresholds::default());
+        let mut source = MockSource::new("mock", 9);
+        for _ in 0..(2 * ROLLING_WINDOW / 65536) {
+            monitor.observe(&source.read(65536).unwrap());
+        }
+        let stats = monitor.stats();
+        assert_eq!(stats.window_bytes, ROLLING_WINDOW as u64);
+        assert!(stats.alarms.is_empty(), "{:?}", stats);
+
+        // Forcing the top bit on skews the distribution and the bit balance
+        for _ in 0..(ROLLING_WINDOW / 65536) {
+            let data: Vec<u8> = source.read(65536).unwrap().iter().map(|b| b | 0x80).collect();
+            monitor.observe(&data);
+        }
+        let stats = monitor.stats();
+        assert!(stats.alarms.contains(&"chi_square"));
+        assert!(stats.alarms.contains(&"bias"));
+    }
+}
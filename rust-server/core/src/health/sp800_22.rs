@@ -0,0 +1,313 @@
+//! NIST SP 800-22 statistical test suite
+//!
+//! Core tests from SP 800-22 rev 1a section 2, run over a bit sequence
+//! taken MSB-first from the input bytes. Parameters follow the NIST
+//! recommendations for the sample size.
+
+use serde::Serialize;
+use statrs::function::{erf::erfc, gamma::gamma_ur};
+use std::f64::consts::{LN_2, SQRT_2};
+
+/// Significance level used for pass/fail decisions
+pub const ALPHA: f64 = 0.01;
+
+/// Smallest sample the suite accepts, in bits
+pub const MIN_BITS: usize = 1 << 16;
+
+/// Block length for the block frequency test
+const BLOCK_FREQUENCY_M: usize = 128;
+
+/// Outcome of one test
+#[derive(Debug, Clone, Serialize)]
+pub struct TestResult {
+    pub test: &'static str,
+    pub p_value: f64,
+    pub passed: bool,
+}
+
+impl TestResult {
+    fn new(test: &'static str, p_value: f64) -> Self {
+        Self {
+            test,
+            p_value,
+            passed: p_value >= ALPHA,
+        }
+    }
+}
+
+/// Bit view over a byte slice
+struct Bits<'a>(&'a [u8]);
+
+impl Bits<'_> {
+    fn len(&self) -> usize {
+        self.0.len() * 8
+    }
+
+    fn get(&self, i: usize) -> u8 {
+        (self.0[i / 8] >> (7 - i % 8)) & 1
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// Counts of every overlapping `m`-bit pattern, wrapping at the end
+    fn pattern_counts(&self, m: usize) -> Vec<u64> {
+        let mut counts = vec![0u64; 1 << m];
+        if m == 0 {
+            counts[0] = self.len() as u64;
+            return counts;
+        }
+
+        let n = self.len();
+        let mask = (1usize << m) - 1;
+        let mut window = (0..m - 1).fold(0usize, |w, i| (w << 1) | self.get(i) as usize);
+        for i in 0..n {
+            window = ((window << 1) | self.get((i + m - 1) % n) as usize) & mask;
+            counts[window] += 1;
+        }
+        counts
+    }
+}
+
+/// Run every test in the suite
+///
+/// Returns an error if the sample is smaller than `MIN_BITS`.
+pub fn run_all(data: &[u8]) -> Result<Vec<TestResult>, String> {
+    let bits = Bits(data);
+    let n = bits.len();
+    if n < MIN_BITS {
+        return Err(format!("SP 800-22 needs at least {} bits, got {}", MIN_BITS, n));
+    }
+
+    // Pattern lengths must stay below log2(n) - 5 and log2(n) - 2
+    let log2n = n.ilog2() as usize;
+    let apen_m = (log2n - 6).min(10);
+    let serial_m = (log2n - 3).min(16);
+
+    let (serial_1, serial_2) = serial(&bits, serial_m);
+    let (cusum_forward, cusum_reverse) = cumulative_sums(&bits);
+
+    Ok(vec![
+        TestResult::new("frequency", frequency(&bits)),
+        TestResult::new("block_frequency", block_frequency(&bits, BLOCK_FREQUENCY_M)),
+        TestResult::new("runs", runs(&bits)),
+        TestResult::new("longest_run", longest_run(&bits)),
+        TestResult::new("approximate_entropy", approximate_entropy(&bits, apen_m)),
+        TestResult::new("cumulative_sums_forward", cusum_forward),
+        TestResult::new("cumulative_sums_reverse", cusum_reverse),
+        TestResult::new("serial_1", serial_1),
+        TestResult::new("serial_2", serial_2),
+    ])
+}
+
+/// Upper regularized incomplete gamma function
+fn igamc(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        1.0
+    } else {
+        gamma_ur(a, x)
+    }
+}
+
+/// Standard normal cumulative distribution function
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+/// Frequency (monobit) test, 2.1
+fn frequency(bits: &Bits) -> f64 {
+    let n = bits.len() as f64;
+    let sum: i64 = bits.iter().map(|b| 2 * b as i64 - 1).sum();
+    erfc(sum.unsigned_abs() as f64 / n.sqrt() / SQRT_2)
+}
+
+/// Frequency test within a block, 2.2
+fn block_frequency(bits: &Bits, m: usize) -> f64 {
+    let blocks = bits.len() / m;
+    let chi_squared: f64 = (0..blocks)
+        .map(|block| {
+            let ones: usize = (block * m..(block + 1) * m).map(|i| bits.get(i) as usize).sum();
+            let pi = ones as f64 / m as f64;
+            (pi - 0.5).powi(2)
+        })
+        .sum::<f64>()
+        * 4.0
+        * m as f64;
+    igamc(blocks as f64 / 2.0, chi_squared / 2.0)
+}
+
+/// Runs test, 2.3
+fn runs(bits: &Bits) -> f64 {
+    let n = bits.len() as f64;
+    let pi = bits.iter().map(f64::from).sum::<f64>() / n;
+
+    // Prerequisite frequency test
+    if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+        return 0.0;
+    }
+
+    let transitions = (1..bits.len())
+        .filter(|&i| bits.get(i) != bits.get(i - 1))
+        .count();
+    let observed = transitions as f64 + 1.0;
+    let spread = pi * (1.0 - pi);
+    erfc((observed - 2.0 * n * spread).abs() / (2.0 * (2.0 * n).sqrt() * spread))
+}
+
+/// Test for the longest run of ones in a block, 2.4
+fn longest_run(bits: &Bits) -> f64 {
+    let n = bits.len();
+    // (block length, first class, class probabilities)
+    let (m, first, pi): (usize, usize, &[f64]) = if n >= 750_000 {
+        (10_000, 10, &[0.0882, 0.2092, 0.2483, 0.1933, 0.1208, 0.0675, 0.0727])
+    } else if n >= 6272 {
+        (128, 4, &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124])
+    } else {
+        (8, 1, &[0.2148, 0.3672, 0.2305, 0.1875])
+    };
+
+    let blocks = n / m;
+    let mut classes = vec![0u64; pi.len()];
+    for block in 0..blocks {
+        let (mut longest, mut run) = (0, 0);
+        for i in block * m..(block + 1) * m {
+            if bits.get(i) == 1 {
+                run += 1;
+                longest = longest.max(run);
+            } else {
+                run = 0;
+            }
+        }
+        let class = longest.clamp(first, first + pi.len() - 1) - first;
+        classes[class] += 1;
+    }
+
+    let chi_squared: f64 = classes
+        .iter()
+        .zip(pi)
+        .map(|(&observed, &p)| {
+            let expected = blocks as f64 * p;
+            (observed as f64 - expected).powi(2) / expected
+        })
+        .sum();
+    igamc((pi.len() - 1) as f64 / 2.0, chi_squared / 2.0)
+}
+
+/// Approximate entropy test, 2.12
+fn approximate_entropy(bits: &Bits, m: usize) -> f64 {
+    let n = bits.len() as f64;
+    let phi = |m: usize| -> f64 {
+        bits.pattern_counts(m)
+            .into_iter()
+            .filter(|&c| c > 0)
+            .map(|c| {
+                let c = c as f64 / n;
+                c * c.ln()
+            })
+            .sum()
+    };
+
+    let apen = phi(m) - phi(m + 1);
+    let chi_squared = 2.0 * n * (LN_2 - apen);
+    igamc(2f64.powi(m as i32 - 1), chi_squared / 2.0)
+}
+
+/// Cumulative sums test, 2.13, in forward and reverse mode
+fn cumulative_sums(bits: &Bits) -> (f64, f64) {
+    let n = bits.len();
+    let (mut sum, mut forward_max) = (0i64, 0i64);
+    for b in bits.iter() {
+        sum += 2 * b as i64 - 1;
+        forward_max = forward_max.max(sum.abs());
+    }
+
+    // The reverse walk visits the partial sums total - S_k
+    let total = sum;
+    let (mut sum, mut reverse_max) = (0i64, total.abs());
+    for b in bits.iter() {
+        sum += 2 * b as i64 - 1;
+        reverse_max = reverse_max.max((total - sum).abs());
+    }
+
+    (cusum_p_value(n, forward_max), cusum_p_value(n, reverse_max))
+}
+
+fn cusum_p_value(n: usize, z: i64) -> f64 {
+    let (n, z) = (n as f64, z as f64);
+    let sqrt_n = n.sqrt();
+
+    let mut first = 0.0;
+    let mut k = ((-n / z + 1.0) / 4.0).trunc();
+    while k <= ((n / z - 1.0) / 4.0).trunc() {
+        first += normal_cdf((4.0 * k + 1.0) * z / sqrt_n) - normal_cdf((4.0 * k - 1.0) * z / sqrt_n);
+        k += 1.0;
+    }
+
+    let mut second = 0.0;
+    let mut k = ((-n / z - 3.0) / 4.0).trunc();
+    while k <= ((n / z - 1.0) / 4.0).trunc() {
+        second += normal_cdf((4.0 * k + 3.0) * z / sqrt_n) - normal_cdf((4.0 * k + 1.0) * z / sqrt_n);
+        k += 1.0;
+    }
+
+    (1.0 - first + second).clamp(0.0, 1.0)
+}
+
+/// Serial test, 2.11
+fn serial(bits: &Bits, m: usize) -> (f64, f64) {
+    let n = bits.len() as f64;
+    let psi_squared = |m: usize| -> f64 {
+        if m == 0 {
+            return 0.0;
+        }
+        let sum: f64 = bits
+            .pattern_counts(m)
+            .into_iter()
+            .map(|c| (c as f64).powi(2))
+            .sum();
+        2f64.powi(m as i32) / n * sum - n
+    };
+
+    let (psi_m, psi_m1, psi_m2) = (psi_squared(m), psi_squared(m - 1), psi_squared(m - 2));
+    let delta = psi_m - psi_m1;
+    let delta_squared = psi_m - 2.0 * psi_m1 + psi_m2;
+    (
+        igamc(2f64.powi(m as i32 - 2), delta / 2.0),
+        igamc(2f64.powi(m as i32 - 3), delta_squared / 2.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack a string of '0'/'1' characters MSB-first
+    fn pack(bits: &str) -> Vec<u8> {
+        bits.as_bytes()
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0u8, |byte, &c| (byte << 1) | (c - b'0')))
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn frequency_and_runs_match_reference_values() {
+        // First 64 bits of the NIST frequency test example (binary expansion of pi)
+        let data = pack("1100100100001111110110101010001000100001011010001100001000110100");
+        let bits = Bits(&data);
+        assert!(close(frequency(&bits), 0.211300));
+        assert!(close(runs(&bits), 0.476024));
+    }
+
+    #[test]
+    fn rejects_constant_and_short_input() {
+        assert!(run_all(&[0u8; 64]).is_err());
+
+        let results = run_all(&[0u8; MIN_BITS / 8]).unwrap();
+        assert!(results.iter().all(|r| !r.passed));
+    }
+}
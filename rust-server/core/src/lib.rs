@@ -0,0 +1,18 @@
+//! Quantis QRNG core
+//!
+//! Device backends, randomness extractors, the entropy buffer and the
+//! health tests, with no web stack. The `quantis-server` crate serves
+//! them over HTTP; embedded users can drive a device directly:
+//!
+//! ```no_run
+//! use quantis_core::device::QuantisDevice;
+//!
+//! let mut device = QuantisDevice::open(0)?;
+//! let raw = device.read(1024)?;
+//! # Ok::<(), quantis_core::device::QuantisError>(())
+//! ```
+
+pub mod buffer;
+pub mod device;
+pub mod health;
+pub mod reader;
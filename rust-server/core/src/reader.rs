@@ -0,0 +1,269 @@
+//! Background tasks reading the devices
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::buffer::{demand::Demand, RingBuffer};
+use crate::device::{
+    capture::{CaptureHealth, CaptureWriter},
+    pool::DevicePool,
+};
+use crate::health::{estimators, unix_time, HealthState};
+
+/// Start background entropy reader
+///
+/// The reader runs until stopped through the returned handle. Every read
+/// it makes is appended to `capture`, if given, with its health outcome.
+pub async fn start_entropy_reader(
+    devices: Arc<DevicePool>,
+    buffer: Arc<RingBuffer>,
+    health: Arc<HealthState>,
+    capture: Option<CaptureWriter>,
+) -> EntropyReader {
+    let (stop, mut stopping) = watch::channel(false);
+    let status = Arc::new(ReaderStatus::default());
+    let reported = status.clone();
+    let task = tokio::spawn(async move {
+        let stopped = async move {
+            // A dropped handle leaves the reader running
+            if stopping.wait_for(|stop| *stop).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        tokio::select! {
+            _ = fill_buffer(devices, buffer, health, capture, &reported) => {}
+            _ = stopped => info!("Entropy reader stopped"),
+        }
+        reported.stopped.store(true, Ordering::Relaxed);
+    });
+    EntropyReader { stop, task, status }
+}
+
+/// What the background reader is doing, for `/health`
+#[derive(Debug, Default)]
+pub struct ReaderStatus {
+    stopped: AtomicBool,
+    consecutive_errors: AtomicU32,
+    /// Unix timestamp of the last successful device read, 0 before one
+    last_read: AtomicU64,
+}
+
+impl ReaderStatus {
+    /// Whether the reader task is still running
+    pub fn is_alive(&self) -> bool {
+        !self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Failed device reads since the last successful one
+    pub fn consecutive_errors(&self) -> u32 {
+        self.consecutive_errors.load(Ordering::Relaxed)
+    }
+
+    /// Unix timestamp of the last successful device read
+    pub fn last_read(&self) -> Option<u64> {
+        Some(self.last_read.load(Ordering::Relaxed)).filter(|at| *at > 0)
+    }
+
+    fn read_succeeded(&self) {
+        self.consecutive_errors.store(0, Ordering::Relaxed);
+        self.last_read.store(unix_time(), Ordering::Relaxed);
+    }
+
+    /// Count a failed read, returning the failures in a row
+    fn read_failed(&self) -> u32 {
+        self.consecutive_errors.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Handle to the background entropy reader
+pub struct EntropyReader {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+    status: Arc<ReaderStatus>,
+}
+
+impl EntropyReader {
+    /// Liveness and read history of the reader
+    pub fn status(&self) -> Arc<ReaderStatus> {
+        self.status.clone()
+    }
+
+    /// Stop reading at the next wait and return once the reader has exited
+    ///
+    /// A device read already in progress is allowed to finish.
+    pub async fn stop(self) {
+        let _ = self.stop.send(true);
+        if let Err(e) = self.task.await {
+            error!("Entropy reader task failed: {}", e);
+        }
+    }
+}
+
+/// Keep the buffer filled from the devices until reads keep failing
+async fn fill_buffer(
+    devices: Arc<DevicePool>,
+    buffer: Arc<RingBuffer>,
+    health: Arc<HealthState>,
+    capture: Option<CaptureWriter>,
+    status: &ReaderStatus,
+) {
+    let record = |data: &[u8], outcome: CaptureHealth, detail: &str| {
+        if let Some(capture) = &capture {
+            capture.record(data, devices.serving_serials(), outcome, detail);
+        }
+    };
+    info!("Starting entropy reader thread");
+    let mut demand = Demand::new(buffer.available(), Instant::now());
+    
+    loop {
+        // A failed health test stops all buffering until recovery
+        if health.failure().is_some() {
+            if !health.auto_recovery() {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                continue;
+            }
+            match devices.read(65536).await {
+                Ok(data) => {
+                    status.read_succeeded();
+                    let recovered = health.probe(&data);
+                    record(&data, CaptureHealth::Quarantined, if recovered { "recovered" } else { "" });
+                    if recovered {
+                        info!("Resuming entropy buffering");
+                    }
+                }
+                Err(e) => {
+                    status.read_failed();
+                    warn!("Failed to read from device while quarantined: {}", e);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                }
+            }
+            continue;
+        }
+
+        // Refill up to a level that tracks recent consumption
+        let available = buffer.available();
+        let capacity = buffer.capacity();
+        demand.observe(available, Instant::now());
+
+        if available < demand.target(capacity) {
+            let read_size = demand.read_size(available, capacity);
+
+            match devices.read(read_size).await {
+                Ok(data) => {
+                    status.read_succeeded();
+                    if let Err(failure) = health.check(&data) {
+                        record(&data, CaptureHealth::Failed, &failure.to_string());
+                        // Buffered bytes in the failing test window are suspect too
+                        let quarantined = buffer.discard_newest(failure.unconfirmed);
+                        error!(
+                            "Discarding {} bytes and {} buffered bytes: {}",
+                            data.len(),
+                            quarantined,
+                            failure
+                        );
+                        continue;
+                    }
+                    record(&data, CaptureHealth::Passed, "");
+                    health.observe_raw(&data);
+
+                    let written = buffer.write(&data);
+                    demand.wrote(written);
+                    health.monitor().observe(&data[..written]);
+                    if written < data.len() {
+                        warn!("Buffer overflow, discarded {} bytes", data.len() - written);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to read from device: {}", e);
+                    if status.read_failed() > 10 {
+                        error!("Too many consecutive errors, stopping entropy reader");
+                        break;
+                    }
+                    
+                    // Back off on errors
+                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                }
+            }
+        } else {
+            // Buffer is at its target, sleep until readers drain it
+            buffer.drained().await;
+        }
+    }
+}
+
+/// Run the entropy estimators on a fresh raw capture every `interval`
+pub fn start_entropy_assessment(
+    devices: Arc<DevicePool>,
+    health: Arc<HealthState>,
+    interval: std::time::Duration,
+    samples: usize,
+) {
+    tokio::spawn(async move {
+        info!("Assessing {} raw bytes every {:?}", samples, interval);
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            if health.failure().is_some() {
+                continue;
+            }
+
+            let sample = match devices.read(samples).await {
+                Ok(sample) => sample,
+                Err(e) => {
+                    warn!("Failed to read entropy assessment capture: {}", e);
+                    continue;
+                }
+            };
+            if health.check(&sample).is_err() {
+                continue;
+            }
+
+            match tokio::task::spawn_blocking(move || estimators::assess(&sample)).await {
+                Ok(assessment) => {
+                    info!(
+                        "Assessed {:.4} bits of min-entropy per byte ({})",
+                        assessment.min_entropy_per_bit * 8.0,
+                        assessment.estimator
+                    );
+                    health.record_assessment(assessment);
+                }
+                Err(e) => error!("Entropy assessment failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn entropy_reader_stops_on_request() {
+        use crate::{buffer::MIN_BUFFER_SIZE, device::mock::MockSource};
+
+        let devices = Arc::new(DevicePool::new(tokio::sync::broadcast::channel(16).0));
+        devices.add(Box::new(MockSource::new("mock", 1))).unwrap();
+        let buffer = Arc::new(RingBuffer::new(MIN_BUFFER_SIZE));
+        let health = Arc::new(HealthState::new(crate::health::DEFAULT_MIN_ENTROPY));
+        let reader = start_entropy_reader(devices, buffer.clone(), health, None).await;
+        while buffer.available() == 0 {
+            tokio::task::yield_now().await;
+        }
+        let status = reader.status();
+        assert!(status.is_alive() && status.last_read().is_some());
+        assert_eq!(status.consecutive_errors(), 0);
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), reader.stop())
+            .await
+            .expect("reader stopped");
+        assert!(!status.is_alive());
+    }
+}
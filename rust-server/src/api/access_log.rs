@@ -0,0 +1,372 @@
+//! Access log of entropy consumption
+//!
+//! One JSON line per request with who made it, how it ended and how many
+//! bytes of entropy it was served, kept apart from the diagnostic log so
+//! consumption can be audited. The file is rotated by size, keeping a fixed
+//! number of older files as `<path>.1`, `<path>.2` and so on.
+//!
+//! What handlers report here is shared with the [dispensing
+//! log](super::dispensing), whichever of the two is enabled. A body still
+//! produced after the response starts, e.g. a stream, is logged once done.
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Instant,
+};
+use tokio::sync::watch;
+use tracing::warn;
+
+use super::{peer::Peer, request_id::REQUEST_ID_HEADER, OutputSource};
+use crate::{health::unix_time, tls::ClientIdentity};
+
+tokio::task_local! {
+    /// What is learned about the request being handled
+    static CURRENT: Arc<Tally>;
+}
+
+/// Details filled in by inner layers and handlers
+#[derive(Default)]
+pub(super) struct Tally {
+    entropy_bytes: AtomicU64,
+    api_key: OnceLock<String>,
+    subject: OnceLock<String>,
+    deliveries: Mutex<Vec<Delivery>>,
+    /// Closed once a body produced after the response is done
+    body: OnceLock<watch::Receiver<()>>,
+}
+
+impl Tally {
+    pub(super) fn api_key(&self) -> Option<&str> {
+        self.api_key.get().map(String::as_str)
+    }
+
+    pub(super) fn subject(&self) -> Option<&str> {
+        self.subject.get().map(String::as_str)
+    }
+
+    pub(super) fn take_deliveries(&self) -> Vec<Delivery> {
+        std::mem::take(&mut self.deliveries.lock().unwrap())
+    }
+
+    /// Whether the body is still produced after the response
+    pub(super) fn streams(&self) -> bool {
+        self.body.get().is_some()
+    }
+
+    /// Wait until a body produced after the response is done
+    pub(super) async fn body_done(&self) {
+        if let Some(mut done) = self.body.get().cloned() {
+            let _ = done.changed().await;
+        }
+    }
+}
+
+/// Entropy handed to a request from one source
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub bytes: usize,
+    pub correction: String,
+    pub source: OutputSource,
+    /// Serials of the devices the bytes came from
+    pub devices: Vec<String>,
+}
+
+/// One request, as written to the log
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessRecord {
+    /// Unix timestamp the request arrived
+    pub at: u64,
+    pub request_id: Option<String>,
+    pub remote: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Name of the API key used
+    pub api_key: Option<String>,
+    /// JWT subject
+    pub subject: Option<String>,
+    /// Client certificate common name
+    pub client_cert: Option<String>,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// Bytes of entropy served
+    pub entropy_bytes: u64,
+}
+
+/// Size-rotated JSON lines file
+pub struct AccessLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Mutex<(File, u64)>,
+}
+
+impl AccessLog {
+    /// Append to `path`, rotating it once it passes `max_bytes` and keeping
+    /// `keep` rotated files
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    pub fn write(&self, record: &AccessRecord) {
+        let mut line = serde_json::to_vec(record).expect("access record serializes");
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.1 > 0 && file.1 + line.len() as u64 > self.max_bytes {
+            match self.rotate() {
+                Ok(fresh) => *file = (fresh, 0),
+                Err(e) => warn!("Failed to rotate {}: {}", self.path.display(), e),
+            }
+        }
+        match file.0.write_all(&line) {
+            Ok(()) => file.1 += line.len() as u64,
+            Err(e) => warn!("Failed to write access log: {}", e),
+        }
+    }
+
+    /// Shift the rotated files up by one and start an empty log
+    fn rotate(&self) -> io::Result<File> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match fs::rename(rotated(n), rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        OpenOptions::new().create(true).append(true).open(&self.path)
+    }
+}
+
+/// Count `bytes` of entropy served to the current request
+pub fn record_entropy(bytes: usize) {
+    let _ = CURRENT.try_with(|tally| tally.entropy_bytes.fetch_add(bytes as u64, Ordering::Relaxed));
+}
+
+/// Count `delivery` as served to the current request
+pub fn record_delivery(delivery: Delivery) {
+    record_entropy(delivery.bytes);
+    let _ = CURRENT.try_with(|tally| tally.deliveries.lock().unwrap().push(delivery));
+}
+
+/// Note the name of the API key the current request used
+pub fn record_api_key(name: &str) {
+    let _ = CURRENT.try_with(|tally| tally.api_key.set(name.to_string()));
+}
+
+/// Note the JWT subject of the current request
+pub fn record_subject(subject: &str) {
+    let _ = CURRENT.try_with(|tally| tally.subject.set(subject.to_string()));
+}
+
+/// Run `future`, which produces the current request's body after the
+/// response, with the request's tally still in scope
+///
+/// The request is logged once `future` finishes, with what it reported.
+pub fn with_tally<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let tally = CURRENT.try_with(Arc::clone).ok();
+    let done = tally.as_ref().map(|tally| {
+        let (done, receiver) = watch::channel(());
+        let _ = tally.body.set(receiver);
+        done
+    });
+    async move {
+        let _done = done;
+        match tally {
+            Some(tally) => CURRENT.scope(tally, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Write an access record for every request of `router` to `log`
+pub fn log_access(router: Router, log: AccessLog) -> Router {
+    router.layer(middleware::from_fn_with_state(Arc::new(log), log_request))
+}
+
+async fn log_request(State(log): State<Arc<AccessLog>>, request: Request, next: Next) -> Response {
+    let (at, started) = (unix_time(), Instant::now());
+    let request_id = request.headers().get(REQUEST_ID_HEADER).and_then(|id| id.to_str().ok());
+    let mut record = AccessRecord {
+        at,
+        request_id: request_id.map(str::to_string),
+        remote: Peer::of(request.extensions()).map(|peer| peer.to_string()),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        api_key: None,
+        subject: None,
+        client_cert: request.extensions().get::<ClientIdentity>().and_then(|id| id.common_name.clone()),
+        status: 0,
+        latency_ms: 0.0,
+        entropy_bytes: 0,
+    };
+
+    let (response, tally) = tallied(request, next).await;
+    record.status = response.status().as_u16();
+    record.latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let streams = tally.streams();
+    let finish = async move {
+        tally.body_done().await;
+        record.entropy_bytes = tally.entropy_bytes.load(Ordering::Relaxed);
+        record.api_key = tally.api_key().map(str::to_string);
+        record.subject = tally.subject().map(str::to_string);
+        log.write(&record);
+    };
+    if streams {
+        tokio::spawn(finish);
+    } else {
+        finish.await;
+    }
+    response
+}
+
+/// Run the rest of the stack with a tally in scope, joining one set up by
+/// an outer layer
+pub(super) async fn tallied(request: Request, next: Next) -> (Response, Arc<Tally>) {
+    if let Ok(tally) = CURRENT.try_with(Arc::clone) {
+        return (next.run(request).await, tally);
+    }
+    let tally = Arc::new(Tally::default());
+    let response = CURRENT.scope(tally.clone(), next.run(request)).await;
+    (response, tally)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("quantis-access-{}-{}.log", name, std::process::id()))
+    }
+
+    fn lines(path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn records_callers_and_entropy_served() {
+        let path = temp_path("requests");
+        let log = AccessLog::open(&path, 1 << 20, 1).unwrap();
+        let handler = || async {
+            record_api_key("sensor");
+            record_entropy(32);
+            record_entropy(32);
+        };
+        let router = log_access(Router::new().route("/random", get(handler)), log);
+        let request = Request::get("/random")
+            .header(REQUEST_ID_HEADER, "abc")
+            .extension(ClientIdentity {
+                common_name: Some("lab".to_string()),
+            })
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap();
+        router.oneshot(Request::get("/missing").body(Body::empty()).unwrap()).await.unwrap();
+
+        let records = lines(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["request_id"], "abc");
+        assert_eq!(records[0]["api_key"], "sensor");
+        assert_eq!(records[0]["client_cert"], "lab");
+        assert_eq!(records[0]["entropy_bytes"], 64);
+        assert_eq!(records[0]["status"], 200);
+        assert_eq!(records[1]["path"], "/missing");
+        assert_eq!(records[1]["status"], 404);
+        assert_eq!(records[1]["entropy_bytes"], 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn logs_streams_once_they_end() {
+        let path = temp_path("stream");
+        let log = AccessLog::open(&path, 1 << 20, 1).unwrap();
+        let (end, ended) = tokio::sync::oneshot::channel::<()>();
+        let ended = Arc::new(Mutex::new(Some(ended)));
+        let handler = move || {
+            let ended = ended.lock().unwrap().take().unwrap();
+            async move {
+                tokio::spawn(with_tally(async move {
+                    let _ = ended.await;
+                    record_entropy(48);
+                }));
+            }
+        };
+        let router = log_access(Router::new().route("/random", get(handler)), log);
+        let response = router.oneshot(Request::get("/random").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        end.send(()).unwrap();
+        for _ in 0..100 {
+            if !fs::read_to_string(&path).unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(lines(&path)[0]["entropy_bytes"], 48);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let path = temp_path("rotation");
+        let log = AccessLog::open(&path, 300, 2).unwrap();
+        let record = AccessRecord {
+            at: 0,
+            request_id: None,
+            remote: None,
+            method: "GET".to_string(),
+            path: "/api/v1/random/bytes".to_string(),
+            api_key: None,
+            subject: None,
+            client_cert: None,
+            status: 200,
+            latency_ms: 1.0,
+            entropy_bytes: 32,
+        };
+        // Each line is about 200 bytes, so every write after the first rotates
+        for _ in 0..4 {
+            log.write(&record);
+        }
+
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+        assert_eq!(lines(&path).len(), 1);
+        assert_eq!(lines(&rotated(1)).len(), 1);
+        assert_eq!(lines(&rotated(2)).len(), 1);
+        assert!(!rotated(3).exists());
+        for path in [path.clone(), rotated(1), rotated(2)] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Operator endpoints
+//!
+//! They are served under `/api/v1/admin` on a listener of their own, bound
+//! to loopback by default, and never on the public one. Every request must
+//! carry `Authorization: Bearer <token>` with either the configured admin
+//! token or, with JWT authentication on, a token with the admin role, or
+//! else an `X-API-Key` with the admin role. Without any of these the
+//! endpoints are disabled.
+//!
+//! Signing key rotation also lives here, shared by the endpoint and the
+//! schedule in [`start_key_rotation`].
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::{
+    ensure_healthy, full_entropy, jwt, ratelimit::API_KEY_HEADER, roles::Role, ApiError, ApiResponse,
+    AppState,
+};
+use crate::config::reload::ReloadOutcome;
+use crate::device::pipeline::{Pipeline, StageDefaults};
+use crate::health::{audit::AuditCategory, unix_time, HealthFailure};
+use crate::signing::{PublicKeyInfo, SECRET_LEN};
+use crate::utils::{
+    pools::{PoolStatus, MIN_POOL_SIZE},
+    MAX_BUFFER_SIZE, MIN_BUFFER_SIZE,
+};
+
+/// Create the admin listener's router
+pub fn router(state: AppState) -> Router {
+    let routes = Router::new()
+        .route("/health/ack", post(acknowledge_failure))
+        .route("/buffers", get(buffers))
+        .route("/buffer", put(resize_buffer))
+        .route("/pools", put(resize_pool))
+        .route("/config/reload", post(reload_config))
+        .route("/signing/rotate", post(rotate_signing_key));
+    Router::new().nest("/api/v1/admin", routes).with_state(state)
+}
+
+/// Whether any way of authorizing admin requests is configured
+pub fn enabled(state: &AppState) -> bool {
+    state.admin_token.is_some() || state.jwt.is_some() || state.api_keys.any_granted(Role::Admin)
+}
+
+/// Reject requests without the admin token, an admin JWT or an admin API key
+async fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    if let Some(key) = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+        return match state.api_keys.find(key) {
+            Some(caller) if caller.role() == Role::Admin => Ok(()),
+            Some(_) => Err(ApiError::forbidden("API key lacks the admin role")),
+            None => Err(ApiError::unauthorized("Unknown API key")),
+        };
+    }
+    let provided = jwt::bearer(headers);
+    if let Some(expected) = state.admin_token.as_deref() {
+        if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            return Ok(());
+        }
+    }
+    let Some(validator) = &state.jwt else {
+        return match state.admin_token {
+            Some(_) => Err(ApiError::forbidden("Invalid admin token")),
+            None => Err(ApiError::unauthorized("Missing admin API key")),
+        };
+    };
+
+    let claims = validator.validate(provided).await?;
+    if validator.role(&claims) == Role::Admin {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden(format!(
+            "Token lacks the admin role or the {} scope",
+            validator.admin_scope()
+        )))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct AckResponse {
+    /// Failure that was cleared, if the source was quarantined
+    pub cleared: Option<HealthFailure>,
+}
+
+/// Acknowledge a health test failure and resume serving entropy
+async fn acknowledge_failure(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<AckResponse>>, ApiError> {
+    authorize(&state, &headers).await?;
+
+    let cleared = state.health.recover();
+    if let Some(failure) = &cleared {
+        warn!("Health test failure acknowledged by operator: {}", failure);
+        state
+            .health
+            .audit()
+            .record(AuditCategory::Operator, "acknowledge", true, failure.to_string());
+    }
+    Ok(Json(ApiResponse::success(AckResponse { cleared })))
+}
+
+/// Fill level of the main entropy buffer
+#[derive(Debug, Serialize)]
+pub struct BufferStatus {
+    pub capacity: usize,
+    pub available: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuffersResponse {
+    pub buffer: BufferStatus,
+    pub pools: Vec<PoolStatus>,
+}
+
+/// Current buffer and pool sizes
+async fn buffers(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<BuffersResponse>>, ApiError> {
+    authorize(&state, &headers).await?;
+
+    Ok(Json(ApiResponse::success(BuffersResponse {
+        buffer: BufferStatus {
+            capacity: state.buffer.capacity(),
+            available: state.buffer.available(),
+        },
+        pools: state.pools.status(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResizeBufferRequest {
+    /// New capacity in bytes
+    pub capacity: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResizeBufferResponse {
+    pub buffer: BufferStatus,
+    /// Newest buffered bytes that no longer fit
+    pub dropped: usize,
+}
+
+/// Grow or shrink the entropy buffer, keeping its contents
+async fn resize_buffer(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ResizeBufferRequest>,
+) -> Result<Json<ApiResponse<ResizeBufferResponse>>, ApiError> {
+    authorize(&state, &headers).await?;
+    if !(MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&request.capacity) {
+        return Err(ApiError::failed(format!(
+            "capacity must be between {} and {} bytes",
+            MIN_BUFFER_SIZE, MAX_BUFFER_SIZE
+        )));
+    }
+    if request.capacity <= state.interactive_reserve {
+        return Err(ApiError::failed(format!(
+            "capacity must exceed the {} byte interactive reserve",
+            state.interactive_reserve
+        )));
+    }
+
+    let previous = state.buffer.capacity();
+    let dropped = state.buffer.resize(request.capacity);
+    info!("Entropy buffer resized from {} to {} bytes", previous, request.capacity);
+    state.health.audit().record(
+        AuditCategory::Operator,
+        "resize_buffer",
+        true,
+        format!("{} -> {} bytes, {} dropped", previous, request.capacity, dropped),
+    );
+
+    Ok(Json(ApiResponse::success(ResizeBufferResponse {
+        buffer: BufferStatus {
+            capacity: state.buffer.capacity(),
+            available: state.buffer.available(),
+        },
+        dropped,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResizePoolRequest {
+    /// Pipeline of the pool, e.g. `von_neumann`
+    pub pipeline: String,
+    /// New capacity in bytes (unchanged if omitted)
+    pub capacity: Option<usize>,
+    /// Refill watermark in bytes (half the capacity if omitted)
+    pub refill_below: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResizePoolResponse {
+    pub pool: PoolStatus,
+    /// Newest pooled bytes that no longer fit
+    pub dropped: usize,
+}
+
+/// Change a pool's capacity or refill watermark, keeping its contents
+async fn resize_pool(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ResizePoolRequest>,
+) -> Result<Json<ApiResponse<ResizePoolResponse>>, ApiError> {
+    authorize(&state, &headers).await?;
+    let pipeline = Pipeline::parse(&request.pipeline, StageDefaults::default()).map_err(ApiError::failed)?;
+    let Some(pool) = state.pools.find(&pipeline) else {
+        return Err(ApiError::not_found(format!("No pool for pipeline {}", pipeline)));
+    };
+
+    let capacity = request.capacity.unwrap_or_else(|| pool.buffer().capacity());
+    if !(MIN_POOL_SIZE..=MAX_BUFFER_SIZE).contains(&capacity) {
+        return Err(ApiError::failed(format!(
+            "capacity must be between {} and {} bytes",
+            MIN_POOL_SIZE, MAX_BUFFER_SIZE
+        )));
+    }
+    if request.refill_below.is_some_and(|mark| mark > capacity) {
+        return Err(ApiError::failed("refill_below must not exceed the capacity"));
+    }
+
+    let dropped = pool.resize(capacity, request.refill_below);
+    info!(
+        "Pool {} resized to {} bytes, refilled below {}",
+        pipeline,
+        capacity,
+        pool.refill_below()
+    );
+    state.health.audit().record(
+        AuditCategory::Operator,
+        "resize_pool",
+        true,
+        format!(
+            "{}: {} bytes, refill below {}, {} dropped",
+            pipeline,
+            capacity,
+            pool.refill_below(),
+            dropped
+        ),
+    );
+
+    Ok(Json(ApiResponse::success(ResizePoolResponse {
+        pool: pool.status(),
+        dropped,
+    })))
+}
+
+/// Re-read the configuration and apply what can change without a restart
+async fn reload_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ReloadOutcome>>, ApiError> {
+    authorize(&state, &headers).await?;
+    let Some(reloader) = &state.reloader else {
+        return Err(ApiError::unavailable("Configuration reload is not available"));
+    };
+
+    let outcome = reloader.reload();
+    let detail = match &outcome {
+        Ok(outcome) if outcome.restart_required.is_empty() => "applied".to_string(),
+        Ok(outcome) => format!("applied, restart needed for {}", outcome.restart_required.join(", ")),
+        Err(e) => e.to_string(),
+    };
+    state.health.audit().record(AuditCategory::Operator, "reload_config", outcome.is_ok(), detail);
+    match outcome {
+        Ok(outcome) => Ok(Json(ApiResponse::success(outcome))),
+        Err(e) => Err(ApiError::failed(format!("Configuration not reloaded: {:#}", e))),
+    }
+}
+
+/// Replace the signing key with one from fresh device entropy
+///
+/// `reason` is recorded in the audit trail.
+pub async fn rotate_signing(state: &AppState, reason: &str) -> Result<PublicKeyInfo, ApiError> {
+    // Like the VRF key, the signing key must not come from a quarantined source
+    ensure_healthy(state)?;
+    let signer = state.signer.get()?;
+    let secret = full_entropy::<SECRET_LEN>(state).await?;
+    let outcome = signer.rotate(*secret);
+    let detail = match &outcome {
+        Ok(key) => format!("{}: new key {}", reason, key.key_id),
+        Err(e) => format!("{}: {}", reason, e),
+    };
+    state.health.audit().record(AuditCategory::Operator, "rotate_signing_key", outcome.is_ok(), detail);
+    match outcome {
+        Ok(key) => {
+            info!("Signing key rotated ({}), now {}", reason, key.key_id);
+            Ok(key)
+        }
+        Err(e) => Err(ApiError::failed(format!("Signing key not rotated: {}", e))),
+    }
+}
+
+/// Rotate the signing key whenever it grows older than `max_age`
+///
+/// Age counts from the key's creation, so restarts do not postpone it.
+/// Failed rotations are retried after a minute.
+pub fn start_key_rotation(state: AppState, max_age: Duration) {
+    tokio::spawn(async move {
+        loop {
+            // A signing key created from device entropy may still be pending
+            let Ok(signer) = state.signer.get() else {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            };
+            let due = signer.current().created_at.saturating_add(max_age.as_secs());
+            let wait = Duration::from_secs(due.saturating_sub(unix_time()));
+            tokio::time::sleep(wait).await;
+            if let Err(e) = rotate_signing(&state, "scheduled").await {
+                warn!("Scheduled signing key rotation failed: {}", e.message);
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    });
+}
+
+/// Replace the signing key now, keeping the old public key published
+async fn rotate_signing_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<PublicKeyInfo>>, ApiError> {
+    authorize(&state, &headers).await?;
+    Ok(Json(ApiResponse::success(rotate_signing(&state, "operator").await?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_comparison() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
@@ -0,0 +1,77 @@
+//! drand-compatible beacon endpoints
+//!
+//! drand clients take `/api/v1/beacon` as the chain's base URL. The same
+//! routes answer below `/{chain_hash}` for clients that address a chain by
+//! its hash. Bodies are drand's JSON, without the API's `success` envelope.
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    routing::get,
+    Router,
+};
+use std::collections::HashMap;
+
+use super::{ApiError, AppState};
+use crate::beacon::{Beacon, ChainInfo, Round};
+use crate::health::unix_time;
+
+/// Create beacon routes
+pub fn routes() -> Router<AppState> {
+    let chain = Router::new()
+        .route("/info", get(info))
+        .route("/public/latest", get(latest))
+        .route("/public/:round", get(round));
+    Router::new().merge(chain.clone()).nest("/:chain_hash", chain)
+}
+
+type Params = Path<HashMap<String, String>>;
+
+/// The beacon, if it is enabled and has the chain hash the path names
+fn chain<'a>(state: &'a AppState, params: &HashMap<String, String>) -> Result<&'a Beacon, ApiError> {
+    let beacon = state.beacon.as_ref().ok_or_else(|| ApiError::not_found("The beacon is not enabled"))?.get()?;
+    match params.get("chain_hash") {
+        Some(hash) if !hash.eq_ignore_ascii_case(&hex::encode(beacon.chain_hash())) => {
+            Err(ApiError::not_found(format!("No chain {}", hash)))
+        }
+        _ => Ok(beacon),
+    }
+}
+
+/// Latest published round, or an error before genesis
+fn latest_round(beacon: &Beacon) -> Result<u64, ApiError> {
+    match beacon.latest_round(unix_time()) {
+        0 => Err(ApiError::not_found("The beacon has not reached its genesis time")),
+        round => Ok(round),
+    }
+}
+
+/// Chain parameters
+async fn info(State(state): State<AppState>, Path(params): Params) -> Result<Json<ChainInfo>, ApiError> {
+    Ok(Json(chain(&state, &params)?.info()))
+}
+
+/// Most recent round
+async fn latest(State(state): State<AppState>, Path(params): Params) -> Result<Json<Round>, ApiError> {
+    let beacon = chain(&state, &params)?;
+    Ok(Json(beacon.round(latest_round(beacon)?)))
+}
+
+/// A published round; round 0 is the latest, as in drand
+async fn round(State(state): State<AppState>, Path(params): Params) -> Result<Json<Round>, ApiError> {
+    let beacon = chain(&state, &params)?;
+    let requested = &params["round"];
+    let round: u64 = requested
+        .parse()
+        .map_err(|_| ApiError::not_found(format!("No round {}", requested)))?;
+    let latest = latest_round(beacon)?;
+    if round > latest {
+        return Err(ApiError::not_found(format!(
+            "Round {} is published at {}",
+            round,
+            beacon.round_time(round)
+        ))
+        .with_retry_after(beacon.round_time(round).saturating_sub(unix_time()).max(1)));
+    }
+    Ok(Json(beacon.round(if round == 0 { latest } else { round })))
+}
@@ -0,0 +1,119 @@
+//! Per-client authorization by certificate name
+//!
+//! With mutual TLS each request carries the common name of the verified
+//! client certificate. An access map lists the paths below `/api/v1` each
+//! name may call; names it does not list are refused.
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use std::{collections::HashMap, sync::Arc};
+
+use super::ApiError;
+use crate::tls::ClientIdentity;
+
+/// Prefix the access map's paths are relative to
+const API_PREFIX: &str = "/api/v1";
+
+/// Paths each client certificate name may call
+#[derive(Debug, Clone, Default)]
+pub struct ClientAccess(HashMap<String, Vec<String>>);
+
+impl ClientAccess {
+    /// Allow each common name the listed paths and everything below them
+    pub fn new(access: impl IntoIterator<Item = (String, Vec<String>)>) -> Self {
+        Self(access.into_iter().collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check the client `identity` may call `path`
+    pub fn check(&self, identity: Option<&ClientIdentity>, path: &str) -> Result<(), ApiError> {
+        let Some(name) = identity.and_then(|identity| identity.common_name.as_deref()) else {
+            return Err(ApiError::forbidden("A client certificate with a common name is required"));
+        };
+        let Some(allowed) = self.0.get(name) else {
+            return Err(ApiError::forbidden(format!("Client {} is not authorized", name)));
+        };
+        let path = path.strip_prefix(API_PREFIX).unwrap_or(path);
+        if allowed.iter().any(|prefix| covers(prefix, path)) {
+            Ok(())
+        } else {
+            Err(ApiError::forbidden(format!("Client {} may not call {}", name, path)))
+        }
+    }
+}
+
+/// Whether `path` is `prefix` or lies below it
+fn covers(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Refuse requests from clients `access` does not allow, unless it is empty
+pub fn authorize_clients(router: Router, access: ClientAccess) -> Router {
+    if access.is_empty() {
+        return router;
+    }
+    router.layer(middleware::from_fn_with_state(Arc::new(access), check_client))
+}
+
+async fn check_client(
+    State(access): State<Arc<ClientAccess>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    access.check(request.extensions().get::<ClientIdentity>(), request.uri().path())?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn client(name: &str) -> ClientIdentity {
+        ClientIdentity {
+            common_name: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn paths_are_allowed_per_name() {
+        let access = ClientAccess::new([
+            ("sensor".to_string(), vec!["/random".to_string(), "/stats".to_string()]),
+            ("ops".to_string(), vec!["/".to_string()]),
+        ]);
+
+        assert!(access.check(Some(&client("sensor")), "/api/v1/random/bytes").is_ok());
+        assert!(access.check(Some(&client("sensor")), "/api/v1/stats").is_ok());
+        assert!(access.check(Some(&client("sensor")), "/api/v1/randomness").is_err());
+        assert!(access.check(Some(&client("sensor")), "/api/v1/admin/buffer").is_err());
+        assert!(access.check(Some(&client("ops")), "/api/v1/admin/buffer").is_ok());
+        assert!(access.check(Some(&client("guest")), "/api/v1/stats").is_err());
+        assert!(access.check(Some(&ClientIdentity::default()), "/api/v1/stats").is_err());
+        assert!(access.check(None, "/api/v1/stats").is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_unlisted_clients() {
+        let access = ClientAccess::new([("sensor".to_string(), vec!["/stats".to_string()])]);
+        let router = authorize_clients(Router::new().route("/api/v1/stats", get(|| async {})), access);
+        let request = |name: &str| {
+            Request::get("/api/v1/stats")
+                .extension(client(name))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(router.clone().oneshot(request("sensor")).await.unwrap().status(), 200);
+        assert_eq!(router.oneshot(request("guest")).await.unwrap().status(), 403);
+    }
+}
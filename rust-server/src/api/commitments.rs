@@ -0,0 +1,94 @@
+//! Commit-reveal draw endpoints
+
+use axum::{
+    extract::{Path, Query, State},
+    middleware,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{
+    roles::{require_role, Role},
+    sourced_entropy, ApiError, ApiResponse, AppState, OutputSource,
+};
+use crate::commitment::{Commitment, MAX_VALUE_LEN, NONCE_LEN};
+
+/// Create commitment routes
+///
+/// Committing and revealing need the crypto role; looking up a commitment
+/// is open to readers.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(commit))
+        .route("/:id/reveal", post(reveal))
+        .route_layer(middleware::from_fn_with_state(Role::Crypto, require_role))
+        .route("/:id", get(lookup))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitQuery {
+    /// Value length in bytes
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+fn default_count() -> usize { 32 }
+
+/// Draw a value and publish only its commitment
+async fn commit(
+    Query(params): Query<CommitQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Commitment>>, ApiError> {
+    if params.count == 0 || params.count > MAX_VALUE_LEN {
+        return Ok(Json(ApiResponse::error(format!(
+            "Count must be between 1 and {}",
+            MAX_VALUE_LEN
+        ))));
+    }
+
+    // Value and nonce both come from conditioned device output
+    let value = sourced_entropy(
+        &state,
+        OutputSource::Conditioned,
+        params.count + NONCE_LEN,
+        &state.correction,
+        None,
+    )
+    .await?;
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&value[params.count..]);
+
+    let commitment = state
+        .commitments
+        .commit(value[..params.count].to_vec(), nonce)
+        .map_err(|e| ApiError::internal(format!("Failed to store the commitment: {}", e)))?;
+    Ok(Json(ApiResponse::success(commitment)))
+}
+
+/// Reveal a committed value and its nonce
+async fn reveal(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Commitment>>, ApiError> {
+    state
+        .commitments
+        .reveal(id)
+        .map_err(|e| ApiError::internal(format!("Failed to store the reveal: {}", e)))?
+        .map(|record| Json(ApiResponse::success(record)))
+        .ok_or_else(|| ApiError::not_found(format!("No commitment {}", id)))
+}
+
+/// Retrieve a commitment, including the value once revealed
+async fn lookup(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Commitment>>, ApiError> {
+    state
+        .commitments
+        .get(id)
+        .map(|record| Json(ApiResponse::success(record)))
+        .ok_or_else(|| ApiError::not_found(format!("No commitment {}", id)))
+}
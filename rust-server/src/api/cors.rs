@@ -0,0 +1,73 @@
+//! CORS with origins that can be replaced while the server runs
+
+use axum::http::{request::Parts, HeaderValue};
+use std::sync::{Arc, RwLock};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// Origins allowed to call the API from a browser
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Allowed {
+    Any,
+    Only(Vec<HeaderValue>),
+}
+
+/// Allowed origins, shared with the CORS layer
+#[derive(Debug)]
+pub struct CorsOrigins(RwLock<Allowed>);
+
+impl CorsOrigins {
+    /// Origins from their configured form, `*` allowing any
+    pub fn new(origins: &[String]) -> Result<Self, String> {
+        Ok(Self(RwLock::new(parse(origins)?)))
+    }
+
+    /// Switch to `origins`, keeping the current ones if any is invalid
+    pub fn set(&self, origins: &[String]) -> Result<(), String> {
+        *self.0.write().unwrap() = parse(origins)?;
+        Ok(())
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        match &*self.0.read().unwrap() {
+            Allowed::Any => true,
+            Allowed::Only(origins) => origins.contains(origin),
+        }
+    }
+}
+
+fn parse(origins: &[String]) -> Result<Allowed, String> {
+    if origins.iter().any(|origin| origin == "*") {
+        return Ok(Allowed::Any);
+    }
+    let origins = origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin).map_err(|_| format!("Invalid CORS origin {}", origin)))
+        .collect::<Result<_, _>>()?;
+    Ok(Allowed::Only(origins))
+}
+
+/// CORS layer allowing any method and header from `origins`
+pub fn layer(origins: Arc<CorsOrigins>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _: &Parts| origins.allows(origin)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_allowed_origins() {
+        let origins = CorsOrigins::new(&["https://app.example".to_string()]).unwrap();
+        assert!(origins.allows(&HeaderValue::from_static("https://app.example")));
+        assert!(!origins.allows(&HeaderValue::from_static("https://other.example")));
+
+        assert!(origins.set(&["bad\norigin".to_string()]).is_err());
+        assert!(origins.allows(&HeaderValue::from_static("https://app.example")));
+
+        origins.set(&["*".to_string()]).unwrap();
+        assert!(origins.allows(&HeaderValue::from_static("https://other.example")));
+    }
+}
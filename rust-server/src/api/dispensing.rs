@@ -0,0 +1,273 @@
+//! Audit log of entropy dispensed
+//!
+//! One JSON line per delivery of entropy: when, to whom, through which
+//! endpoint, how many bytes, how they were corrected and which devices they
+//! came from. The file is only ever appended to. With hash chaining each
+//! line also carries the SHA-256 of the previous line's hash and its own
+//! contents, so removing or editing a line breaks every hash after it; see
+//! [`verify`].
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tracing::warn;
+
+use super::{
+    access_log::{self, Delivery},
+    peer::Peer,
+    request_id::REQUEST_ID_HEADER,
+    OutputSource,
+};
+use crate::{health::unix_time, tls::ClientIdentity};
+
+/// Hash the first record of a chain follows
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One delivery, as written to the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DispenseRecord {
+    /// Position in the log, from 0
+    pub seq: u64,
+    /// Unix timestamp of the delivery
+    pub at: u64,
+    pub request_id: Option<String>,
+    pub remote: Option<String>,
+    /// Name of the API key used
+    pub api_key: Option<String>,
+    /// JWT subject
+    pub subject: Option<String>,
+    /// Client certificate common name
+    pub client_cert: Option<String>,
+    pub endpoint: String,
+    pub bytes: usize,
+    pub correction: String,
+    pub source: OutputSource,
+    /// Serials of the devices the bytes came from
+    pub devices: Vec<String>,
+    /// Hash of the previous record, when chained
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+    /// SHA-256 of `prev` and this record without `hash`, when chained
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl DispenseRecord {
+    /// Hash of this record following `prev`
+    fn digest(&self) -> String {
+        let unhashed = DispenseRecord {
+            hash: None,
+            ..self.clone()
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev.as_deref().unwrap_or_default());
+        hasher.update(serde_json::to_vec(&unhashed).expect("dispense record serializes"));
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// End of the log, where the next record goes
+struct Tail {
+    file: File,
+    seq: u64,
+    /// Hash of the last record, when chained
+    hash: Option<String>,
+}
+
+/// Append-only JSON lines file, optionally hash-chained
+pub struct DispenseLog {
+    tail: Mutex<Tail>,
+    chained: bool,
+}
+
+impl DispenseLog {
+    /// Append to `path`, carrying on the sequence and chain found there
+    pub fn open(path: &Path, chained: bool) -> io::Result<Self> {
+        let mut seq = 0;
+        let mut hash = chained.then(|| GENESIS.to_string());
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // Lines cut short by a crash are left for `verify` to report
+                if let Ok(record) = serde_json::from_str::<DispenseRecord>(&line?) {
+                    seq = record.seq + 1;
+                    if chained {
+                        hash = Some(record.hash.unwrap_or_else(|| GENESIS.to_string()));
+                    }
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            tail: Mutex::new(Tail { file, seq, hash }),
+            chained,
+        })
+    }
+
+    /// Number, chain and append `record`
+    pub fn write(&self, mut record: DispenseRecord) {
+        let mut tail = self.tail.lock().unwrap();
+        record.seq = tail.seq;
+        if self.chained {
+            record.prev = tail.hash.clone();
+            record.hash = Some(record.digest());
+        }
+        let mut line = serde_json::to_vec(&record).expect("dispense record serializes");
+        line.push(b'\n');
+        match tail.file.write_all(&line) {
+            Ok(()) => {
+                tail.seq += 1;
+                tail.hash = record.hash;
+            }
+            Err(e) => warn!("Failed to write dispensing log: {}", e),
+        }
+    }
+}
+
+/// Why a dispensing log failed verification
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("Failed to read the log: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Line {line} is not a dispense record")]
+    Malformed { line: usize },
+
+    #[error("Record {seq} is out of sequence")]
+    Sequence { seq: u64 },
+
+    #[error("Record {seq} does not match its hash or the one before it")]
+    Chain { seq: u64 },
+}
+
+/// Check the sequence and, for chained records, the hashes of the log at
+/// `path`, returning how many records it holds
+pub fn verify(path: &Path) -> Result<u64, VerifyError> {
+    let mut expected = 0;
+    let mut prev = GENESIS.to_string();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let record: DispenseRecord =
+            serde_json::from_str(&line?).map_err(|_| VerifyError::Malformed { line: index + 1 })?;
+        if record.seq != expected {
+            return Err(VerifyError::Sequence { seq: record.seq });
+        }
+        if let Some(hash) = &record.hash {
+            if record.prev.as_deref() != Some(prev.as_str()) || *hash != record.digest() {
+                return Err(VerifyError::Chain { seq: record.seq });
+            }
+            prev.clone_from(hash);
+        }
+        expected += 1;
+    }
+    Ok(expected)
+}
+
+/// Record every delivery of entropy by `router` that succeeds in `log`
+pub fn log_dispensing(router: Router, log: DispenseLog) -> Router {
+    router.layer(middleware::from_fn_with_state(Arc::new(log), log_request))
+}
+
+async fn log_request(State(log): State<Arc<DispenseLog>>, request: Request, next: Next) -> Response {
+    let at = unix_time();
+    let request_id = request.headers().get(REQUEST_ID_HEADER).and_then(|id| id.to_str().ok());
+    let request_id = request_id.map(str::to_string);
+    let remote = Peer::of(request.extensions()).map(|peer| peer.to_string());
+    let client_cert = request.extensions().get::<ClientIdentity>().and_then(|id| id.common_name.clone());
+    let endpoint = request.uri().path().to_string();
+
+    let (response, tally) = access_log::tallied(request, next).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let streams = tally.streams();
+    let finish = async move {
+        // A stream reports what it delivered once it is done
+        tally.body_done().await;
+        for Delivery {
+            bytes,
+            correction,
+            source,
+            devices,
+        } in tally.take_deliveries()
+        {
+            log.write(DispenseRecord {
+                seq: 0,
+                at,
+                request_id: request_id.clone(),
+                remote: remote.clone(),
+                api_key: tally.api_key().map(str::to_string),
+                subject: tally.subject().map(str::to_string),
+                client_cert: client_cert.clone(),
+                endpoint: endpoint.clone(),
+                bytes,
+                correction,
+                source,
+                devices,
+                prev: None,
+                hash: None,
+            });
+        }
+    };
+    if streams {
+        tokio::spawn(finish);
+    } else {
+        finish.await;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use std::fs;
+    use tower::ServiceExt;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("quantis-dispense-{}-{}.log", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn chains_deliveries_across_reopening() {
+        let path = temp_path("chain");
+        let _ = fs::remove_file(&path);
+        let handler = || async {
+            access_log::record_api_key("sensor");
+            access_log::record_delivery(Delivery {
+                bytes: 32,
+                correction: "sha3".to_string(),
+                source: OutputSource::Conditioned,
+                devices: vec!["QRNG-1".to_string()],
+            });
+        };
+        for _ in 0..2 {
+            let log = DispenseLog::open(&path, true).unwrap();
+            let router = log_dispensing(Router::new().route("/random", get(handler)), log);
+            router.clone().oneshot(Request::get("/random").body(Body::empty()).unwrap()).await.unwrap();
+            router.oneshot(Request::get("/missing").body(Body::empty()).unwrap()).await.unwrap();
+        }
+        assert_eq!(verify(&path).unwrap(), 2);
+
+        let text = fs::read_to_string(&path).unwrap();
+        let first: DispenseRecord = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first.api_key.as_deref(), Some("sensor"));
+        assert_eq!(first.devices, ["QRNG-1"]);
+        assert_eq!(first.prev.as_deref(), Some(GENESIS));
+
+        // Editing a record breaks the chain
+        fs::write(&path, text.replacen("\"bytes\":32", "\"bytes\":64", 1)).unwrap();
+        assert!(matches!(verify(&path), Err(VerifyError::Chain { seq: 0 })));
+        fs::remove_file(&path).unwrap();
+    }
+}
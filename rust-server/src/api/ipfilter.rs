@@ -0,0 +1,159 @@
+//! Allow and deny lists of client networks
+//!
+//! Rules are CIDR blocks, single addresses, `vsock:<cid>` for one guest VM
+//! or `vsock` for every guest. A peer on the deny list is refused;
+//! otherwise, when the allow list is not empty, only peers on it are
+//! served. Refused requests get 403 before any other check. The lists can
+//! be replaced while the server runs.
+
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use ipnet::IpNet;
+use std::{
+    net::IpAddr,
+    sync::{Arc, RwLock},
+};
+
+use super::{peer::Peer, ApiError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Net(IpNet),
+    /// One guest's context ID, or every guest
+    Vsock(Option<u32>),
+}
+
+impl Rule {
+    fn matches(&self, peer: Peer) -> bool {
+        match (self, peer) {
+            (Self::Net(net), Peer::Ip(ip)) => net.contains(&ip.to_canonical()),
+            (Self::Vsock(cid), Peer::Vsock(guest)) => cid.is_none_or(|cid| cid == guest),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Rules {
+    allow: Vec<Rule>,
+    deny: Vec<Rule>,
+}
+
+/// Networks allowed to and barred from calling the API
+#[derive(Debug, Default)]
+pub struct IpFilter(RwLock<Rules>);
+
+impl IpFilter {
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self, String> {
+        Ok(Self(RwLock::new(parse(allow, deny)?)))
+    }
+
+    /// Switch to new lists, keeping the current ones if any rule is invalid
+    pub fn set(&self, allow: &[String], deny: &[String]) -> Result<(), String> {
+        *self.0.write().unwrap() = parse(allow, deny)?;
+        Ok(())
+    }
+
+    /// Whether a client at `ip` may be served
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allows_peer(Peer::Ip(ip))
+    }
+
+    /// Whether a client at `peer`, an address or a vsock guest, may be served
+    pub fn allows_peer(&self, peer: Peer) -> bool {
+        let rules = self.0.read().unwrap();
+        let listed = |rules: &[Rule]| rules.iter().any(|rule| rule.matches(peer));
+        !listed(&rules.deny) && (rules.allow.is_empty() || listed(&rules.allow))
+    }
+}
+
+fn parse_rule(rule: &str) -> Option<Rule> {
+    if rule == "vsock" {
+        return Some(Rule::Vsock(None));
+    }
+    if let Some(cid) = rule.strip_prefix("vsock:") {
+        return cid.parse().ok().map(|cid| Rule::Vsock(Some(cid)));
+    }
+    let net = rule.parse::<IpNet>().or_else(|_| rule.parse::<IpAddr>().map(IpNet::from));
+    net.ok().map(Rule::Net)
+}
+
+fn parse(allow: &[String], deny: &[String]) -> Result<Rules, String> {
+    let rules = |rules: &[String]| {
+        rules
+            .iter()
+            .map(|rule| parse_rule(rule).ok_or_else(|| format!("Invalid network {}", rule)))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(Rules {
+        allow: rules(allow)?,
+        deny: rules(deny)?,
+    })
+}
+
+/// Refuse requests to `router` from peers `filter` does not allow
+///
+/// Needs the server's connect info or vsock peer; requests with neither
+/// are let through.
+pub fn filter_addresses(router: Router, filter: Arc<IpFilter>) -> Router {
+    router.layer(middleware::from_fn_with_state(filter, check_address))
+}
+
+async fn check_address(
+    State(filter): State<Arc<IpFilter>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(peer) = Peer::of(request.extensions()) {
+        if !filter.allows_peer(peer) {
+            return Err(ApiError::forbidden(format!("Address {} is not allowed", peer)));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> Vec<String> {
+        rules.iter().map(|rule| rule.to_string()).collect()
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let allow = rules(&["10.0.0.0/8", "2001:db8::/32"]);
+        let filter = IpFilter::new(&allow, &rules(&["10.0.5.0/24"])).unwrap();
+        assert!(filter.allows("10.1.2.3".parse().unwrap()));
+        assert!(!filter.allows("10.0.5.7".parse().unwrap()));
+        assert!(!filter.allows("192.0.2.1".parse().unwrap()));
+        assert!(filter.allows("2001:db8::1".parse().unwrap()));
+        // IPv4 clients on a dual-stack socket match IPv4 rules
+        assert!(filter.allows("::ffff:10.1.2.3".parse().unwrap()));
+
+        assert!(filter.set(&[], &rules(&["10.0.0.0/33"])).is_err());
+        filter.set(&[], &rules(&["192.0.2.1"])).unwrap();
+        assert!(!filter.allows("192.0.2.1".parse().unwrap()));
+        assert!(filter.allows("10.0.5.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn matches_vsock_guests() {
+        // An allow list of networks shuts out guests not listed with them
+        let filter = IpFilter::new(&rules(&["10.0.0.0/8"]), &[]).unwrap();
+        assert!(!filter.allows_peer(Peer::Vsock(3)));
+
+        filter.set(&rules(&["10.0.0.0/8", "vsock:3"]), &[]).unwrap();
+        assert!(filter.allows_peer(Peer::Vsock(3)));
+        assert!(!filter.allows_peer(Peer::Vsock(4)));
+
+        filter.set(&[], &rules(&["vsock"])).unwrap();
+        assert!(!filter.allows_peer(Peer::Vsock(3)));
+        assert!(filter.allows("10.1.2.3".parse().unwrap()));
+        assert!(filter.set(&rules(&["vsock:guest"]), &[]).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! JWT bearer token authentication
+//!
+//! Lets the service sit behind an existing OIDC provider instead of, or as
+//! well as, client certificates. Tokens are HS256 with a shared secret, or
+//! RS256 with a PEM public key or keys fetched from a JWKS URL. The issuer
+//! and audience are checked when configured.
+//!
+//! A token's [role](super::roles) is the highest named in its `roles` claim
+//! or granted by its scopes: `quantis:reader`, `quantis:crypto` and the
+//! admin scope. Only the admin scope grants `admin`; the `roles` claim can
+//! name `reader` or `crypto`. Tokens naming none get the default role.
+
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use super::{
+    access_log,
+    roles::{self, Role},
+    ApiError, AppState,
+};
+
+/// Scope that grants the admin endpoints unless another is configured
+pub const DEFAULT_ADMIN_SCOPE: &str = "quantis:admin";
+
+/// Prefix of the scopes granting the other roles, e.g. `quantis:crypto`
+const ROLE_SCOPE_PREFIX: &str = "quantis:";
+
+/// Shortest time between JWKS fetches triggered by unknown key ids
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum JwtError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid JWT key: {0}")]
+    Key(#[from] jsonwebtoken::errors::Error),
+
+    #[error("Failed to fetch JWKS from {url}: {source}")]
+    Fetch {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+}
+
+/// Claims the server reads from a validated token
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Claims {
+    pub sub: Option<String>,
+    /// Space-separated OAuth scopes
+    #[serde(default)]
+    pub scope: String,
+    /// Role names, e.g. `["crypto"]`
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl Claims {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|granted| granted == scope)
+    }
+}
+
+/// Where verification keys come from
+enum Keys {
+    Fixed(DecodingKey),
+    Jwks(Jwks),
+}
+
+/// RS256 keys by key id, refetched when a token names an unknown one
+struct Jwks {
+    url: String,
+    client: reqwest::Client,
+    keys: RwLock<HashMap<String, DecodingKey>>,
+    fetched: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl Jwks {
+    async fn key(&self, kid: &str) -> Option<DecodingKey> {
+        if let Some(key) = self.keys.read().unwrap().get(kid) {
+            return Some(key.clone());
+        }
+
+        // One fetch at a time, and not more often than JWKS_MIN_REFRESH
+        let mut fetched = self.fetched.lock().await;
+        if let Some(key) = self.keys.read().unwrap().get(kid) {
+            return Some(key.clone());
+        }
+        if fetched.is_none_or(|at| at.elapsed() >= JWKS_MIN_REFRESH) {
+            *fetched = Some(Instant::now());
+            if let Err(e) = self.refresh().await {
+                warn!("{}", e);
+            }
+        }
+        self.keys.read().unwrap().get(kid).cloned()
+    }
+
+    async fn refresh(&self) -> Result<(), JwtError> {
+        let fetch_error = |source| JwtError::Fetch {
+            url: self.url.clone(),
+            source,
+        };
+        let set: JwkSet = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(fetch_error)?
+            .json()
+            .await
+            .map_err(fetch_error)?;
+
+        let keys: HashMap<_, _> = set
+            .keys
+            .iter()
+            .filter_map(|jwk| Some((jwk.common.key_id.clone()?, DecodingKey::from_jwk(jwk).ok()?)))
+            .collect();
+        info!("Loaded {} JWT verification keys from {}", keys.len(), self.url);
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+}
+
+/// Checks bearer tokens against the configured keys and claims
+pub struct JwtValidator {
+    keys: Keys,
+    validation: Validation,
+    admin_scope: String,
+    default_role: Role,
+}
+
+impl JwtValidator {
+    /// HS256 tokens signed with `secret`
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::new(Keys::Fixed(DecodingKey::from_secret(secret)), Algorithm::HS256)
+    }
+
+    /// RS256 tokens verified with the PEM public key in `path`
+    pub fn rs256_pem(path: &Path) -> Result<Self, JwtError> {
+        let pem = std::fs::read(path).map_err(|source| JwtError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self::new(Keys::Fixed(DecodingKey::from_rsa_pem(&pem)?), Algorithm::RS256))
+    }
+
+    /// RS256 tokens verified with the keys published at `url`
+    pub fn jwks(url: String) -> Self {
+        let jwks = Jwks {
+            url,
+            client: reqwest::Client::new(),
+            keys: RwLock::default(),
+            fetched: tokio::sync::Mutex::default(),
+        };
+        Self::new(Keys::Jwks(jwks), Algorithm::RS256)
+    }
+
+    fn new(keys: Keys, algorithm: Algorithm) -> Self {
+        let mut validation = Validation::new(algorithm);
+        validation.validate_aud = false;
+        Self {
+            keys,
+            validation,
+            admin_scope: DEFAULT_ADMIN_SCOPE.to_string(),
+            default_role: Role::default(),
+        }
+    }
+
+    /// Accept only tokens from `issuer`
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// Accept only tokens for `audience`
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self.validation.validate_aud = true;
+        self
+    }
+
+    /// Scope a token needs for the admin endpoints
+    pub fn with_admin_scope(mut self, scope: String) -> Self {
+        self.admin_scope = scope;
+        self
+    }
+
+    pub fn admin_scope(&self) -> &str {
+        &self.admin_scope
+    }
+
+    /// Role for tokens that name none
+    pub fn with_default_role(mut self, role: Role) -> Self {
+        self.default_role = role;
+        self
+    }
+
+    /// Highest role `claims` name or grant through their scopes
+    ///
+    /// Admin comes only from the configured admin scope, never the `roles` claim.
+    pub fn role(&self, claims: &Claims) -> Role {
+        let scoped = |role: Role| match role {
+            Role::Admin => claims.has_scope(&self.admin_scope),
+            _ => claims.has_scope(&format!("{}{}", ROLE_SCOPE_PREFIX, role)),
+        };
+        let named = claims
+            .roles
+            .iter()
+            .filter_map(|name| Role::from_name(name))
+            .filter(|role| *role != Role::Admin);
+        named
+            .chain(Role::ALL.into_iter().filter(|role| scoped(*role)))
+            .max()
+            .unwrap_or(self.default_role)
+    }
+
+    /// Verify `token` and return its claims
+    pub async fn validate(&self, token: &str) -> Result<Claims, ApiError> {
+        let invalid =
+            |e: jsonwebtoken::errors::Error| ApiError::unauthorized(format!("Invalid token: {}", e));
+        let key = match &self.keys {
+            Keys::Fixed(key) => key.clone(),
+            Keys::Jwks(jwks) => {
+                let kid = jsonwebtoken::decode_header(token)
+                    .map_err(invalid)?
+                    .kid
+                    .ok_or_else(|| ApiError::unauthorized("Token has no key id"))?;
+                jwks.key(&kid)
+                    .await
+                    .ok_or_else(|| ApiError::unauthorized(format!("Unknown token key id {}", kid)))?
+            }
+        };
+        jsonwebtoken::decode::<Claims>(token, &key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(invalid)
+    }
+}
+
+/// Token from an `Authorization: Bearer` header, empty if there is none
+pub(super) fn bearer(headers: &HeaderMap) -> &str {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+}
+
+/// Refuse requests without a valid token when JWT authentication is on
+///
+/// The token's claims are added to the request for handlers to inspect, and
+/// its role is granted to the request.
+pub async fn require_jwt(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(validator) = &state.jwt {
+        let claims = validator.validate(bearer(request.headers())).await?;
+        if let Some(subject) = &claims.sub {
+            access_log::record_subject(subject);
+        }
+        roles::grant(&mut request, validator.role(&claims));
+        request.extensions_mut().insert(claims);
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn token(claims: serde_json::Value) -> String {
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(b"shared")).unwrap()
+    }
+
+    fn expires() -> u64 {
+        jsonwebtoken::get_current_timestamp() + 600
+    }
+
+    #[tokio::test]
+    async fn validates_signature_and_claims() {
+        let validator = JwtValidator::hs256(b"shared")
+            .with_issuer("https://idp.example")
+            .with_audience("quantis");
+
+        let valid = token(json!({
+            "sub": "seeder", "iss": "https://idp.example", "aud": "quantis",
+            "exp": expires(), "scope": "entropy quantis:admin",
+        }));
+        let claims = validator.validate(&valid).await.unwrap();
+        assert_eq!(claims.sub.as_deref(), Some("seeder"));
+        assert!(claims.has_scope(DEFAULT_ADMIN_SCOPE));
+        assert!(!claims.has_scope("quantis"));
+
+        let wrong_audience = token(json!({ "iss": "https://idp.example", "aud": "other", "exp": expires() }));
+        let wrong_issuer =
+            token(json!({ "iss": "https://evil.example", "aud": "quantis", "exp": expires() }));
+        let expired = token(json!({ "iss": "https://idp.example", "aud": "quantis", "exp": 1 }));
+        for rejected in [wrong_audience, wrong_issuer, expired, "not.a.token".to_string()] {
+            let error = validator.validate(&rejected).await.unwrap_err();
+            assert_eq!(error.status, 401);
+        }
+
+        let other_secret = JwtValidator::hs256(b"different");
+        assert!(other_secret.validate(&valid).await.is_err());
+    }
+
+    #[test]
+    fn roles_from_claims_and_scopes() {
+        let validator = JwtValidator::hs256(b"shared").with_admin_scope("ops".to_string());
+        let claims = |scope: &str, roles: &[&str]| Claims {
+            scope: scope.to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(validator.role(&claims("", &[])), Role::Reader);
+        assert_eq!(validator.role(&claims("openid quantis:reader", &[])), Role::Reader);
+        assert_eq!(validator.role(&claims("quantis:reader", &["crypto"])), Role::Crypto);
+        assert_eq!(validator.role(&claims("ops", &[])), Role::Admin);
+        // The admin scope is the configured one
+        assert_eq!(validator.role(&claims("quantis:admin", &["root"])), Role::Reader);
+        // Naming admin in the roles claim does not bypass the admin scope
+        assert_eq!(validator.role(&claims("", &["admin"])), Role::Reader);
+        assert_eq!(validator.role(&claims("quantis:reader", &["admin"])), Role::Reader);
+        assert_eq!(validator.role(&claims("ops", &["admin"])), Role::Admin);
+
+        let trusted = validator.with_default_role(Role::Crypto);
+        assert_eq!(trusted.role(&claims("", &[])), Role::Crypto);
+    }
+}
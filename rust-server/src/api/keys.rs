@@ -0,0 +1,93 @@
+//! Keys created from device entropy
+//!
+//! A VRF, signing or beacon key with no file to load from is created once
+//! the server is ready, so from buffered output of a source that passed its
+//! startup tests. A quarantined source keeps the server up, reporting the
+//! failure on `/health`, and routes needing the key answer 503 until then.
+
+use std::{sync::OnceLock, time::Duration};
+use tracing::warn;
+use zeroize::Zeroizing;
+
+use super::{ensure_healthy, full_entropy, ApiError, AppState};
+
+/// How often a pending key checks whether the source can back it
+const KEY_POLL: Duration = Duration::from_millis(500);
+
+/// Key loaded at startup or still waiting for a healthy source
+pub struct DeviceKey<T> {
+    key: OnceLock<T>,
+    what: &'static str,
+}
+
+impl<T> DeviceKey<T> {
+    /// Key to be created later, named `what` in errors
+    pub fn pending(what: &'static str) -> Self {
+        Self {
+            key: OnceLock::new(),
+            what,
+        }
+    }
+
+    /// Key available from the start
+    pub fn ready(what: &'static str, key: T) -> Self {
+        let pending = Self::pending(what);
+        pending.set(key);
+        pending
+    }
+
+    /// Provide the created key; later calls are ignored
+    pub fn set(&self, key: T) {
+        let _ = self.key.set(key);
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.key.get().is_none()
+    }
+
+    /// The key, or 503 while it waits for the source
+    pub fn get(&self) -> Result<&T, ApiError> {
+        self.key.get().ok_or_else(|| {
+            ApiError::unavailable(format!(
+                "The {} is created once the entropy source passes its health tests",
+                self.what
+            ))
+            .with_retry_after(1)
+        })
+    }
+}
+
+/// Full-entropy secret for the new key `what`
+///
+/// Waits for the server to become ready and retries while the source is
+/// failing its health tests or the draw fails.
+pub async fn device_secret<const N: usize>(state: &AppState, what: &str) -> Zeroizing<[u8; N]> {
+    loop {
+        if state.readiness.is_ready() && ensure_healthy(state).is_ok() {
+            match full_entropy::<N>(state).await {
+                Ok(secret) => return secret,
+                Err(e) => warn!("Failed to draw the {}, retrying: {}", what, e.message),
+            }
+        }
+        tokio::time::sleep(KEY_POLL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn pending_key_is_unavailable_until_set() {
+        let key = DeviceKey::pending("VRF key");
+        let error = key.get().unwrap_err();
+        assert_eq!(error.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.retry_after, Some(1));
+
+        key.set(1);
+        key.set(2);
+        assert!(!key.is_pending());
+        assert_eq!(*key.get().unwrap(), 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! Request size limits, concurrency limits, timeouts and load shedding
+//!
+//! Requests beyond a concurrency limit are rejected with 503 straight away
+//! instead of queueing behind the device lock, so a burst of large requests
+//! cannot build an unbounded backlog. A request holds its place until its
+//! response body is sent, so streams count for as long as they run.
+//! Requests still running after their route's timeout are abandoned with
+//! 503 as well.
+
+use axum::{
+    body::{Body, Bytes},
+    error_handling::HandleErrorLayer,
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    routing::MethodRouter,
+    BoxError, Router,
+};
+use http_body::{Frame, SizeHint};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{timeout::error::Elapsed, ServiceBuilder};
+
+use super::{ApiError, AppState};
+
+/// Default limit on requests in flight across all routes
+pub const DEFAULT_MAX_CONCURRENCY: usize = 1024;
+
+/// Routes that draw entropy and accept their own limit
+pub const LIMITED_ROUTES: [&str; 4] = [
+    "/random/bytes",
+    "/random/int",
+    "/tests/sp800-22",
+    "/entropy/estimate",
+];
+
+/// Default largest `/random/bytes` response sent in one piece
+pub const DEFAULT_MAX_BYTES: usize = 65536;
+
+/// Default most integers from one `/random/int` request
+pub const DEFAULT_MAX_INTEGERS: usize = 1000;
+
+/// How much a single request may ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Largest `/random/bytes` count answered in one piece
+    pub max_bytes: usize,
+    /// Largest `/random/bytes` count streamed in chunks once above
+    /// `max_bytes`, no streaming when unset
+    pub max_stream_bytes: Option<usize>,
+    pub max_integers: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_stream_bytes: None,
+            max_integers: DEFAULT_MAX_INTEGERS,
+        }
+    }
+}
+
+impl RequestLimits {
+    /// Whether a `/random/bytes` request for `count` bytes is streamed,
+    /// or an error if it is out of bounds
+    pub fn streams(&self, count: usize) -> Result<bool, ApiError> {
+        let max = self.max_stream_bytes.unwrap_or(self.max_bytes).max(self.max_bytes);
+        if count == 0 || count > max {
+            return Err(ApiError::failed(format!("Count must be between 1 and {}", max)));
+        }
+        Ok(count > self.max_bytes)
+    }
+
+    /// Reason these limits cannot be served, if any
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.max_bytes == 0 || self.max_integers == 0 {
+            return Err("request sizes must be at least 1");
+        }
+        if self.max_stream_bytes.is_some_and(|max| max <= self.max_bytes) {
+            return Err("max_stream_bytes must be above max_bytes");
+        }
+        Ok(())
+    }
+}
+
+/// Per-route limits, keyed by path below `/api/v1`
+#[derive(Debug, Clone, Default)]
+pub struct RouteLimits(HashMap<String, usize>);
+
+impl RouteLimits {
+    /// Parse `path=limit` entries, e.g. `/random/bytes=64`
+    pub fn parse<S: AsRef<str>>(specs: &[S]) -> Result<Self, String> {
+        let mut limits = Vec::new();
+        for spec in specs {
+            let spec = spec.as_ref();
+            let (path, limit) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("Expected path=limit, got {}", spec))?;
+            let limit = limit
+                .parse()
+                .map_err(|_| format!("Invalid limit for {}: {}", path, limit))?;
+            limits.push((path.to_string(), limit));
+        }
+        Self::from_limits(limits)
+    }
+
+    /// Check each path takes a limit and each limit is non-zero
+    pub fn from_limits(limits: impl IntoIterator<Item = (String, usize)>) -> Result<Self, String> {
+        let mut checked = HashMap::new();
+        for (path, limit) in limits {
+            if limit == 0 {
+                return Err(format!("Invalid limit for {}: 0", path));
+            }
+            if !LIMITED_ROUTES.contains(&path.as_str()) {
+                return Err(format!("{} does not take a limit, expected one of {:?}", path, LIMITED_ROUTES));
+            }
+            checked.insert(path, limit);
+        }
+        Ok(Self(checked))
+    }
+
+    pub fn get(&self, path: &str) -> Option<usize> {
+        self.0.get(path).copied()
+    }
+}
+
+/// Default time a request may take
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Per-route request timeouts, keyed by path below `/api/v1`
+#[derive(Debug, Clone)]
+pub struct RouteTimeouts {
+    default: Duration,
+    routes: HashMap<String, Duration>,
+}
+
+impl Default for RouteTimeouts {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_REQUEST_TIMEOUT,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl RouteTimeouts {
+    /// `default` for every route without its own timeout in `routes`, which
+    /// only entropy routes take
+    pub fn new(
+        default: Duration,
+        routes: impl IntoIterator<Item = (String, Duration)>,
+    ) -> Result<Self, String> {
+        let mut checked = HashMap::new();
+        for (path, timeout) in routes {
+            if timeout.is_zero() {
+                return Err(format!("Invalid timeout for {}: 0", path));
+            }
+            if !LIMITED_ROUTES.contains(&path.as_str()) {
+                return Err(format!("{} does not take a timeout, expected one of {:?}", path, LIMITED_ROUTES));
+            }
+            checked.insert(path, timeout);
+        }
+        if default.is_zero() {
+            return Err("Invalid default timeout: 0".to_string());
+        }
+        Ok(Self {
+            default,
+            routes: checked,
+        })
+    }
+
+    pub fn default_timeout(&self) -> Duration {
+        self.default
+    }
+
+    pub fn get(&self, path: &str) -> Duration {
+        self.routes.get(path).copied().unwrap_or(self.default)
+    }
+}
+
+/// Answer requests to `route` still running after `timeout` with 503
+pub fn timeout_route<S>(route: MethodRouter<S>, timeout: Duration) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    route.layer(ServiceBuilder::new().layer(HandleErrorLayer::new(refused)).timeout(timeout))
+}
+
+/// Answer requests to `router` still running after `timeout` with 503
+pub fn timeout_router<S>(router: Router<S>, timeout: Duration) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(ServiceBuilder::new().layer(HandleErrorLayer::new(refused)).timeout(timeout))
+}
+
+/// Shed requests to `route` beyond `max` in flight
+pub fn limit_route(route: MethodRouter<AppState>, max: Option<usize>) -> MethodRouter<AppState> {
+    let Some(max) = max else {
+        return route;
+    };
+    route.layer(middleware::from_fn_with_state(Arc::new(Semaphore::new(max)), shed))
+}
+
+/// Shed requests to the whole router beyond `max` in flight
+pub fn limit_router(router: Router, max: usize) -> Router {
+    router.layer(middleware::from_fn_with_state(Arc::new(Semaphore::new(max)), shed))
+}
+
+/// Refuse a request when no permit is free, else hold one until its
+/// response body is done
+async fn shed(State(permits): State<Arc<Semaphore>>, request: Request, next: Next) -> Result<Response, ApiError> {
+    let permit = permits
+        .try_acquire_owned()
+        .map_err(|_| ApiError::unavailable("Server is at its concurrency limit").with_retry_after(1))?;
+    let response = next.run(request).await;
+    Ok(response.map(|body| Body::new(Permitted { body, _permit: permit })))
+}
+
+/// Response body that releases its concurrency permit once dropped
+struct Permitted {
+    body: Body,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl http_body::Body for Permitted {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+async fn refused(error: BoxError) -> ApiError {
+    if error.is::<Elapsed>() {
+        ApiError::unavailable("Request timed out").with_retry_after(1)
+    } else {
+        ApiError::internal(format!("Unhandled internal error: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get};
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+    use tower::ServiceExt;
+
+    #[test]
+    fn large_requests_stream_up_to_their_ceiling() {
+        let limits = RequestLimits::default();
+        assert!(!limits.streams(DEFAULT_MAX_BYTES).unwrap());
+        assert!(limits.streams(DEFAULT_MAX_BYTES + 1).is_err());
+        assert!(limits.streams(0).is_err());
+
+        let limits = RequestLimits {
+            max_stream_bytes: Some(1 << 20),
+            ..limits
+        };
+        assert!(limits.streams(DEFAULT_MAX_BYTES + 1).unwrap());
+        assert!(limits.streams(1 << 20).unwrap());
+        let refused = limits.streams((1 << 20) + 1).unwrap_err();
+        assert_eq!(refused.message, "Count must be between 1 and 1048576");
+    }
+
+    #[test]
+    fn parses_route_limits() {
+        let limits = RouteLimits::parse(&["/random/bytes=64", "/random/int=8"]).unwrap();
+        assert_eq!(limits.get("/random/bytes"), Some(64));
+        assert_eq!(limits.get("/stats"), None);
+
+        assert!(RouteLimits::parse(&["/random/bytes"]).is_err());
+        assert!(RouteLimits::parse(&["/random/bytes=0"]).is_err());
+        assert!(RouteLimits::parse(&["/stats=4"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn abandons_requests_past_their_timeout() {
+        let timeouts = RouteTimeouts::new(
+            Duration::from_secs(30),
+            [("/random/bytes".to_string(), Duration::from_millis(20))],
+        )
+        .unwrap();
+        assert_eq!(timeouts.get("/stats"), Duration::from_secs(30));
+        let stats = [("/stats".to_string(), Duration::from_secs(1))];
+        assert!(RouteTimeouts::new(Duration::from_secs(30), stats).is_err());
+
+        let slow = get(|| tokio::time::sleep(Duration::from_secs(5)));
+        let router = Router::new().route("/", timeout_route(slow, timeouts.get("/random/bytes")));
+        let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["retry-after"], "1");
+    }
+
+    #[tokio::test]
+    async fn sheds_requests_beyond_the_limit() {
+        // Handlers wait until a permit is released, then let every later one through
+        let release = Arc::new(Semaphore::new(0));
+        let router = limit_router(
+            Router::new().route(
+                "/",
+                get({
+                    let release = release.clone();
+                    move || {
+                        let release = release.clone();
+                        async move { drop(release.acquire().await) }
+                    }
+                }),
+            ),
+            1,
+        );
+
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+        let held = tokio::spawn(router.clone().oneshot(request()));
+        tokio::task::yield_now().await;
+
+        let shed = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(shed.status(), 503);
+        assert_eq!(shed.headers()["retry-after"], "1");
+
+        release.add_permits(1);
+        assert_eq!(held.await.unwrap().unwrap().status(), 200);
+        assert_eq!(router.oneshot(request()).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn streams_hold_their_place_until_sent() {
+        let stream = || async {
+            let pieces = tokio_stream::iter([Ok::<_, std::io::Error>(Bytes::from_static(b"piece"))]);
+            Body::from_stream(pieces)
+        };
+        let router = limit_router(Router::new().route("/", get(stream)), 1);
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+
+        let streaming = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(router.clone().oneshot(request()).await.unwrap().status(), 503);
+
+        let body = axum::body::to_bytes(streaming.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "piece");
+        assert_eq!(router.oneshot(request()).await.unwrap().status(), 200);
+    }
+}
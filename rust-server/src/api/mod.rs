@@ -1,255 +1,1424 @@
-//! REST API endpoints
-
-use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::get,
-    Router,
-};
-use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-use crate::device::{bias_correction, QuantisDevice};
-use crate::utils::RingBuffer;
-
-#[derive(Debug, Serialize)]
-pub struct ApiResponse<T> {
-    pub success: bool,
-    pub data: Option<T>,
-    pub error: Option<String>,
-}
-
-impl<T> ApiResponse<T> {
-    pub fn success(data: T) -> Self {
-        Self {
-            success: true,
-            data: Some(data),
-            error: None,
-        }
-    }
-
-    pub fn error(msg: impl Into<String>) -> Self {
-        Self {
-            success: false,
-            data: None,
-            error: Some(msg.into()),
-        }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-pub struct BytesQuery {
-    #[serde(default = "default_count")]
-    pub count: usize,
-    #[serde(default = "default_format")]
-    pub format: String,
-    #[serde(default = "default_correction")]
-    pub correction: String,
-}
-
-fn default_count() -> usize { 32 }
-fn default_format() -> String { "hex".to_string() }
-fn default_correction() -> String { "none".to_string() }
-
-#[derive(Debug, Serialize)]
-pub struct BytesResponse {
-    pub bytes: String,
-    pub count: usize,
-    pub format: String,
-    pub correction: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct IntegersQuery {
-    pub min: i64,
-    pub max: i64,
-    #[serde(default = "default_int_count")]
-    pub count: usize,
-}
-
-fn default_int_count() -> usize { 1 }
-
-#[derive(Debug, Serialize)]
-pub struct IntegersResponse {
-    pub integers: Vec<i64>,
-    pub min: i64,
-    pub max: i64,
-    pub count: usize,
-}
-
-pub type AppState = Arc<AppStateInner>;
-
-pub struct AppStateInner {
-    pub device: Arc<Mutex<QuantisDevice>>,
-    pub buffer: Arc<RingBuffer>,
-}
-
-/// Create API routes
-pub fn routes(device: Arc<Mutex<QuantisDevice>>, buffer: Arc<RingBuffer>) -> Router {
-    let state = Arc::new(AppStateInner { device, buffer });
-
-    Router::new()
-        .route("/", get(root))
-        .route("/health", get(health))
-        .route("/random/bytes", get(random_bytes))
-        .route("/random/int", get(random_integers))
-        .route("/device/info", get(device_info))
-        .with_state(state)
-}
-
-/// Root endpoint
-async fn root() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "service": "Quantis QRNG API",
-        "version": "1.0.0",
-        "endpoints": [
-            "/api/v1/health",
-            "/api/v1/random/bytes",
-            "/api/v1/random/int",
-            "/api/v1/device/info"
-        ]
-    }))
-}
-
-/// Health check endpoint
-async fn health(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
-    let mut device = state.device.lock().await;
-    
-    match device.health_check() {
-        Ok(true) => Ok(Json(serde_json::json!({
-            "status": "healthy",
-            "device": "connected",
-            "buffer_available": state.buffer.available()
-        }))),
-        Ok(false) => Err(StatusCode::SERVICE_UNAVAILABLE),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
-    }
-}
-
-/// Generate random bytes
-async fn random_bytes(
-    Query(params): Query<BytesQuery>,
-    State(state): State<AppState>,
-) -> Result<Json<ApiResponse<BytesResponse>>, StatusCode> {
-    // Validate parameters
-    if params.count == 0 || params.count > 65536 {
-        return Ok(Json(ApiResponse::error("Count must be between 1 and 65536")));
-    }
-
-    // Try buffer first
-    let raw_bytes = if let Some(bytes) = state.buffer.read(params.count) {
-        bytes
-    } else {
-        // Fall back to direct device read
-        let mut device = state.device.lock().await;
-        match device.read(params.count) {
-            Ok(bytes) => bytes,
-            Err(e) => return Ok(Json(ApiResponse::error(format!("Device error: {}", e)))),
-        }
-    };
-
-    // Apply bias correction
-    let corrected_bytes = match params.correction.as_str() {
-        "none" => bias_correction::none(&raw_bytes),
-        "von_neumann" => {
-            let corrected = bias_correction::von_neumann(&raw_bytes);
-            if corrected.len() < params.count {
-                // Need more raw data for von_neumann
-                return Ok(Json(ApiResponse::error(
-                    "Insufficient entropy after von_neumann correction, try larger count"
-                )));
-            }
-            corrected
-        }
-        _ => return Ok(Json(ApiResponse::error("Invalid correction method"))),
-    };
-
-    // Format output
-    let formatted = match params.format.as_str() {
-        "hex" => hex::encode(&corrected_bytes[..params.count]),
-        "base64" => base64::encode(&corrected_bytes[..params.count]),
-        _ => return Ok(Json(ApiResponse::error("Invalid format"))),
-    };
-
-    Ok(Json(ApiResponse::success(BytesResponse {
-        bytes: formatted,
-        count: params.count,
-        format: params.format,
-        correction: params.correction,
-    })))
-}
-
-/// Generate random integers
-async fn random_integers(
-    Query(params): Query<IntegersQuery>,
-    State(state): State<AppState>,
-) -> Result<Json<ApiResponse<IntegersResponse>>, StatusCode> {
-    // Validate parameters
-    if params.min >= params.max {
-        return Ok(Json(ApiResponse::error("min must be less than max")));
-    }
-    if params.count == 0 || params.count > 1000 {
-        return Ok(Json(ApiResponse::error("count must be between 1 and 1000")));
-    }
-
-    let range = (params.max - params.min + 1) as u64;
-    let bytes_per_int = ((range as f64).ln() / 256f64.ln()).ceil() as usize;
-    let total_bytes = bytes_per_int * params.count * 2; // Extra for rejection sampling
-
-    // Get random bytes
-    let raw_bytes = if let Some(bytes) = state.buffer.read(total_bytes) {
-        bytes
-    } else {
-        let mut device = state.device.lock().await;
-        match device.read(total_bytes) {
-            Ok(bytes) => bytes,
-            Err(e) => return Ok(Json(ApiResponse::error(format!("Device error: {}", e)))),
-        }
-    };
-
-    // Generate integers using rejection sampling
-    let mut integers = Vec::with_capacity(params.count);
-    let mut byte_offset = 0;
-
-    while integers.len() < params.count && byte_offset + bytes_per_int <= raw_bytes.len() {
-        let mut value = 0u64;
-        for i in 0..bytes_per_int {
-            value = (value << 8) | raw_bytes[byte_offset + i] as u64;
-        }
-
-        // Rejection sampling for uniform distribution
-        let max_valid = u64::MAX - (u64::MAX % range);
-        if value < max_valid {
-            integers.push(params.min + (value % range) as i64);
-        }
-
-        byte_offset += bytes_per_int;
-    }
-
-    if integers.len() < params.count {
-        return Ok(Json(ApiResponse::error("Insufficient entropy for requested integers")));
-    }
-
-    Ok(Json(ApiResponse::success(IntegersResponse {
-        integers: integers.into_iter().take(params.count).collect(),
-        min: params.min,
-        max: params.max,
-        count: params.count,
-    })))
-}
-
-/// Get device information
-async fn device_info(State(state): State<AppState>) -> Result<Json<ApiResponse<serde_json::Value>>, StatusCode> {
-    let mut device = state.device.lock().await;
-    
-    match device.info() {
-        Ok(info) => Ok(Json(ApiResponse::success(serde_json::json!({
-            "device": info,
-            "buffer_size": state.buffer.capacity(),
-            "buffer_available": state.buffer.available(),
-        })))),
-        Err(e) => Ok(Json(ApiResponse::error(format!("Failed to get device info: {}", e)))),
-    }
-}
\ No newline at end of file
+//! REST API endpoints
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Extension, Router,
+};
+use base64::Engine;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use zeroize::Zeroizing;
+
+use crate::beacon::Beacon;
+use crate::commitment::CommitmentStore;
+use crate::config::reload::ConfigReloader;
+use crate::device::{
+    bias_correction,
+    pipeline::{Pipeline, StageDefaults},
+    pool::{DevicePool, DeviceRole, DeviceState},
+    rate, QuantisError,
+};
+use crate::drbg::{fortuna::Fortuna, shake, DrbgExpander, SEED_LEN};
+use crate::health::{
+    audit::{AuditCategory, AuditFilter, AuditRecord},
+    credit::EntropyAccount,
+    estimators::{self, Assessment},
+    sp800_22::{self, TestResult},
+    unix_time, HealthState,
+};
+use crate::metrics::Metrics;
+use crate::signing::{self, Signer};
+use crate::utils::{demand, pools::PoolSet, secure, ReaderStatus, Reservation, RingBuffer};
+use crate::vrf::VrfKey;
+use jwt::JwtValidator;
+use keys::DeviceKey;
+use limits::{RequestLimits, RouteLimits, RouteTimeouts};
+use ratelimit::ApiKeys;
+use readiness::Readiness;
+use roles::{Granted, Role};
+use tenants::{Tenant, Tenants};
+use throttle::Throttle;
+
+pub mod access_log;
+pub mod admin;
+pub mod beacon;
+pub mod clients;
+pub mod commitments;
+pub mod cors;
+pub mod dispensing;
+pub mod ipfilter;
+pub mod jwt;
+pub mod keys;
+pub mod limits;
+pub mod panics;
+pub mod peer;
+pub mod quota;
+pub mod ratelimit;
+pub mod readiness;
+pub mod request_id;
+pub mod roles;
+pub mod security_headers;
+pub mod tenants;
+pub mod throttle;
+pub mod vrf;
+
+#[derive(Debug, Serialize)]
+pub struct ApiResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub error: Option<String>,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// Error returned by API handlers
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    /// Seconds the client should wait before retrying
+    pub retry_after: Option<u64>,
+    /// Per-second limit the client exceeded
+    pub rate_limit: Option<u64>,
+}
+
+impl ApiError {
+    /// Request failure reported as `success: false` with 200 OK
+    pub fn failed(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::OK,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Entropy cannot be served at the moment
+    pub fn unavailable(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Requested record does not exist
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Unexpected server-side failure
+    pub fn internal(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Caller did not prove who it is
+    pub fn unauthorized(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Caller exceeded `limit` per second and may retry after `seconds`
+    pub fn rate_limited(msg: impl Into<String>, limit: u64, seconds: u64) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: msg.into(),
+            retry_after: Some(seconds),
+            rate_limit: Some(limit),
+        }
+    }
+
+    /// Caller is not allowed to perform the request
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: msg.into(),
+            retry_after: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Ask the client to retry after `seconds`
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(ApiResponse::<()>::error(self.message))).into_response();
+        let headers = response.headers_mut();
+        if let Some(seconds) = self.retry_after {
+            headers.insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        // IETF draft RateLimit header fields
+        if let Some(limit) = self.rate_limit {
+            headers.insert(HeaderName::from_static("ratelimit-limit"), HeaderValue::from(limit));
+            headers.insert(HeaderName::from_static("ratelimit-remaining"), HeaderValue::from(0));
+            let reset = self.retry_after.unwrap_or(1);
+            headers.insert(HeaderName::from_static("ratelimit-reset"), HeaderValue::from(reset));
+        }
+        response
+    }
+}
+
+/// Where requested bytes come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSource {
+    /// Device output without post-processing
+    Raw,
+    /// Device output through the correction pipeline
+    #[default]
+    Conditioned,
+    /// Device-seeded CTR_DRBG output
+    Drbg,
+    /// Fortuna generator over the device, the OS RNG and CPU jitter
+    Fortuna,
+}
+
+impl OutputSource {
+    /// Parse a request's `source`, defaulting to conditioned output
+    fn parse(name: Option<&str>, device: Option<&str>) -> Result<Self, String> {
+        let source = match name {
+            None | Some("conditioned") | Some("direct") => Self::Conditioned,
+            Some("raw") => Self::Raw,
+            Some("drbg") => Self::Drbg,
+            Some("fortuna") => Self::Fortuna,
+            Some(other) => return Err(format!("Invalid source: {}", other)),
+        };
+        if source.is_generated() && device.is_some() {
+            return Err(format!("source={} cannot be pinned to a device", name.unwrap_or_default()));
+        }
+        Ok(source)
+    }
+
+    /// Whether bytes come from a generator rather than the buffer
+    fn is_generated(self) -> bool {
+        matches!(self, Self::Drbg | Self::Fortuna)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BytesQuery {
+    #[serde(default = "default_count")]
+    pub count: usize,
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Post-processing pipeline, e.g. `von_neumann|sha3` (server default if unset)
+    pub correction: Option<String>,
+    /// Default ratio for ratio-based stages without an explicit one
+    pub ratio: Option<usize>,
+    /// Default output rate for Toeplitz stages without an explicit one
+    pub rate: Option<f64>,
+    /// Serial of the device to read from
+    pub device: Option<String>,
+    /// Output source: `raw`, `conditioned` (default), `drbg` or `fortuna`
+    #[serde(alias = "mode")]
+    pub source: Option<String>,
+    /// Expand a seed from the source instead: `shake256`
+    pub expand: Option<String>,
+    /// Seed strength in bits when expanding
+    #[serde(default = "default_strength")]
+    pub strength: usize,
+    /// Sign the bytes with the server's signing key
+    #[serde(default)]
+    pub sign: bool,
+}
+
+fn default_count() -> usize { 32 }
+fn default_strength() -> usize { shake::DEFAULT_STRENGTH }
+
+/// Largest expanded response
+const MAX_EXPANDED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Bytes drawn per piece of a streamed response, a multiple of 3 so base64
+/// pieces join without padding
+const STREAM_CHUNK: usize = 48 * 1024;
+
+/// How expanded output was produced
+#[derive(Debug, Serialize)]
+pub struct Expansion {
+    pub method: &'static str,
+    /// Min-entropy of the seed, in bits; the output is no stronger
+    pub seed_bits: usize,
+    pub seed_bytes: usize,
+}
+fn default_format() -> String { "hex".to_string() }
+
+#[derive(Debug, Serialize)]
+pub struct BytesResponse {
+    pub bytes: String,
+    pub count: usize,
+    pub format: String,
+    pub correction: String,
+    pub source: OutputSource,
+    /// Hex SHA-256 of the bytes before encoding
+    pub sha256: String,
+    /// Present when the bytes were expanded from a shorter seed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded: Option<Expansion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Present when the bytes were signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BytesSignature>,
+}
+
+/// Server signature over `/random/bytes` output
+///
+/// It covers [`signing::bytes_message`] of `signed_at` and the bytes'
+/// SHA-256, and verifies against the key with `key_id` from `/pubkey`.
+#[derive(Debug, Serialize)]
+pub struct BytesSignature {
+    #[serde(flatten)]
+    pub signature: signing::Signature,
+    /// Unix timestamp of the signature
+    pub signed_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntegersQuery {
+    pub min: i64,
+    pub max: i64,
+    #[serde(default = "default_int_count")]
+    pub count: usize,
+    /// Post-processing pipeline (server default if unset)
+    pub correction: Option<String>,
+    /// Serial of the device to read from
+    pub device: Option<String>,
+    /// Output source: `raw`, `conditioned` (default), `drbg` or `fortuna`
+    #[serde(alias = "mode")]
+    pub source: Option<String>,
+}
+
+fn default_int_count() -> usize { 1 }
+
+#[derive(Debug, Deserialize)]
+pub struct Sp80022Query {
+    /// Sample size in megabytes
+    #[serde(default = "default_megabytes")]
+    pub megabytes: usize,
+    /// Serial of the device to test
+    pub device: Option<String>,
+}
+
+fn default_megabytes() -> usize { 1 }
+
+/// Largest sample the statistical test endpoint will draw
+const MAX_TEST_MEGABYTES: usize = 16;
+
+#[derive(Debug, Deserialize)]
+pub struct EstimateQuery {
+    /// Sample size in bytes
+    #[serde(default = "default_estimate_samples")]
+    pub samples: usize,
+    /// Serial of the device to assess
+    pub device: Option<String>,
+}
+
+fn default_estimate_samples() -> usize { estimators::RECOMMENDED_SAMPLES }
+
+#[derive(Debug, Serialize)]
+pub struct IntegersResponse {
+    pub integers: Vec<i64>,
+    pub min: i64,
+    pub max: i64,
+    pub count: usize,
+    pub source: OutputSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Earliest Unix timestamp, inclusive
+    pub from: Option<u64>,
+    /// Latest Unix timestamp, inclusive
+    pub to: Option<u64>,
+    pub category: Option<AuditCategory>,
+    /// Exact test name, e.g. `repetition_count`
+    pub test: Option<String>,
+    /// Newest matching records to return
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+fn default_audit_limit() -> usize { 100 }
+
+/// Largest page of audit records
+const MAX_AUDIT_RECORDS: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    pub count: usize,
+    /// Oldest first
+    pub records: Vec<AuditRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sp80022Response {
+    pub bits: usize,
+    pub passed: bool,
+    pub results: Vec<TestResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EstimateResponse {
+    #[serde(flatten)]
+    pub assessment: Assessment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceEntry {
+    pub index: usize,
+    pub serial: String,
+    pub product: String,
+    pub version: String,
+    pub state: DeviceState,
+    pub role: DeviceRole,
+    pub consecutive_errors: u32,
+}
+
+pub type AppState = Arc<AppStateInner>;
+
+pub struct AppStateInner {
+    pub devices: Arc<DevicePool>,
+    pub buffer: Arc<RingBuffer>,
+    pub health: Arc<HealthState>,
+    /// Pipeline applied when a request does not choose one
+    pub correction: Pipeline,
+    pub metrics: Metrics,
+    pub drbg: DrbgExpander,
+    /// Fortuna accumulator behind `source=fortuna`, when enabled
+    pub fortuna: Option<Arc<Fortuna>>,
+    pub credit: EntropyAccount,
+    /// Pre-conditioned output per correction pipeline
+    pub pools: Arc<PoolSet>,
+    /// Buffer slices and quotas of groups of API keys
+    pub tenants: Arc<Tenants>,
+    pub commitments: CommitmentStore,
+    pub vrf: DeviceKey<VrfKey>,
+    /// drand-compatible beacon, when enabled
+    pub beacon: Option<DeviceKey<Beacon>>,
+    /// Key ring signing attested outputs
+    pub signer: DeviceKey<Signer>,
+    /// Longest direct device read served when the buffer is starved
+    pub max_read_wait: Duration,
+    /// Concurrency limits of individual entropy routes
+    pub route_limits: RouteLimits,
+    /// Longest each route may take to answer
+    pub route_timeouts: RouteTimeouts,
+    /// Request sizes for callers without limits of their own
+    pub request_limits: RequestLimits,
+    /// Buffered bytes only interactive requests may draw on
+    pub interactive_reserve: usize,
+    /// Defers bulk requests while the devices fall behind
+    pub throttle: Throttle,
+    /// Whether the buffer is prefilled and API traffic is served
+    pub readiness: Arc<Readiness>,
+    /// Liveness and read history of the background reader
+    pub reader: Arc<ReaderStatus>,
+    /// Bearer token for `/admin` endpoints, which are disabled when unset
+    pub admin_token: Option<String>,
+    /// Validator for JWT bearer tokens, required on most routes when set
+    pub jwt: Option<Arc<JwtValidator>>,
+    /// API keys and their rate limits, required on most routes when any
+    pub api_keys: Arc<ApiKeys>,
+    /// Applies configuration changes for `/admin/config/reload`
+    pub reloader: Option<Arc<ConfigReloader>>,
+}
+
+/// Create API routes, without the admin endpoints
+pub fn routes(state: AppState) -> Router {
+    let limited = |path: &str, route| {
+        let route = limits::limit_route(route, state.route_limits.get(path));
+        limits::timeout_route(route, state.route_timeouts.get(path))
+    };
+    let timeout = state.route_timeouts.default_timeout();
+
+    Router::new()
+        .route("/random/bytes", limited("/random/bytes", get(random_bytes)))
+        .route("/random/int", limited("/random/int", get(random_integers)))
+        .route("/device/info", limited("/device/info", get(device_info)))
+        .route("/devices", limited("/devices", get(list_devices)))
+        .route("/tests/sp800-22", limited("/tests/sp800-22", get(sp800_22_suite)))
+        .route("/entropy/estimate", limited("/entropy/estimate", get(estimate_entropy)))
+        .route("/entropy/audit", limited("/entropy/audit", get(audit_trail)))
+        .route("/stats", limited("/stats", get(stats)))
+        .route("/metrics", limited("/metrics", get(metrics)))
+        .nest("/commitments", limits::timeout_router(commitments::routes(), timeout))
+        .merge(limits::timeout_router(vrf::routes(), timeout))
+        .route_layer(middleware::from_fn_with_state(state.clone(), readiness::require_ready))
+        // Answered while starting, so a deploy can be checked before it is ready
+        .route("/version", get(version))
+        .nest("/beacon", limits::timeout_router(beacon::routes(), timeout))
+        .route_layer(middleware::from_fn_with_state(state.clone(), jwt::require_jwt))
+        .route_layer(middleware::from_fn_with_state(state.api_keys.clone(), ratelimit::require_api_key))
+        // Open without a JWT or API key, and while starting: the index and health probes
+        .route("/", get(root))
+        .route("/health", get(health))
+        .with_state(state)
+}
+
+/// Root endpoint
+async fn root() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "service": "Quantis QRNG API",
+        "version": env!("CARGO_PKG_VERSION"),
+        "endpoints": [
+            "/api/v1/health",
+            "/api/v1/version",
+            "/api/v1/random/bytes",
+            "/api/v1/random/int",
+            "/api/v1/device/info",
+            "/api/v1/devices",
+            "/api/v1/tests/sp800-22",
+            "/api/v1/entropy/estimate",
+            "/api/v1/entropy/audit",
+            "/api/v1/commitments",
+            "/api/v1/vrf",
+            "/api/v1/pubkey",
+            "/api/v1/beacon/info",
+            "/api/v1/stats",
+            "/api/v1/metrics"
+        ]
+    }))
+}
+
+/// Cargo features compiled in
+const FEATURES: &[(&str, bool)] = &[
+    ("ringbuf-buffer", cfg!(feature = "ringbuf-buffer")),
+    ("zmq", cfg!(feature = "zmq")),
+];
+
+/// Build metadata and the active device backend
+///
+/// The commit and build time come from `build.rs`.
+async fn version(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let features: Vec<_> = FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect();
+    Json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": env!("QUANTIS_GIT_COMMIT"),
+        "build_timestamp": env!("QUANTIS_BUILD_TIMESTAMP").parse::<u64>().ok(),
+        "features": features,
+        "buffer_backend": if cfg!(feature = "ringbuf-buffer") { "ringbuf" } else { "builtin" },
+        "device_backend": state.devices.active().map(|slot| slot.backend()),
+    }))
+}
+
+/// Health check endpoint
+///
+/// Answers from state the server already keeps, without reading the
+/// devices, so probes never wait behind a device transfer.
+async fn health(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let failure = state.health.failure();
+    let device = state.devices.active().is_some_and(|slot| slot.state() == DeviceState::Healthy);
+    let reader = &state.reader;
+    let (status, reason) = if let Some(failure) = &failure {
+        ("unhealthy", Some(failure.to_string()))
+    } else if !reader.is_alive() {
+        ("unhealthy", Some("entropy reader stopped".to_string()))
+    } else if !device {
+        ("unhealthy", Some("no healthy device".to_string()))
+    } else if !state.readiness.is_ready() {
+        ("starting", None)
+    } else {
+        ("healthy", None)
+    };
+
+    let mut body = serde_json::json!({
+        "status": status,
+        "device": if device { "connected" } else { "unavailable" },
+        "reader": {
+            "alive": reader.is_alive(),
+            "consecutive_errors": reader.consecutive_errors(),
+            "last_read": reader.last_read(),
+        },
+        "health_tests": {
+            "status": if failure.is_some() { "failed" } else { "passing" },
+            "recovery": recovery_mode(&state),
+        },
+        "buffer_available": state.buffer.available(),
+        "buffer": buffer_watermark(&state),
+        "fips": fips_status(&state),
+    });
+    if let Some(reason) = reason {
+        body["reason"] = reason.into();
+    }
+    if let Some(failure) = failure {
+        body["health_test"] = serde_json::json!(failure);
+    }
+    if status == "starting" {
+        body["prefill"] = state.readiness.prefill().into();
+    }
+    let code = if status == "healthy" { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(body))
+}
+
+/// Fill level of the main buffer, for `/health`
+///
+/// `low` means only interactive requests are served from memory, `full`
+/// that the reader is at its highest fill target.
+fn buffer_watermark(state: &AppState) -> serde_json::Value {
+    let (available, capacity) = (state.buffer.available(), state.buffer.capacity());
+    let watermark = if available == 0 {
+        "empty"
+    } else if available < state.interactive_reserve {
+        "low"
+    } else if available as f64 >= capacity as f64 * demand::MAX_TARGET {
+        "full"
+    } else {
+        "normal"
+    };
+    serde_json::json!({
+        "available": available,
+        "capacity": capacity,
+        "watermark": watermark,
+    })
+}
+
+/// How a quarantined source resumes, for `/health`
+fn recovery_mode(state: &AppState) -> &'static str {
+    if state.health.auto_recovery() {
+        "automatic"
+    } else {
+        "admin_ack"
+    }
+}
+
+/// FIPS mode status for `/health`
+fn fips_status(state: &AppState) -> serde_json::Value {
+    match state.health.fips_status() {
+        Some(status) => serde_json::json!({
+            "enabled": true,
+            "continuous_rng_test": status,
+        }),
+        None => serde_json::json!({ "enabled": false }),
+    }
+}
+
+/// Refuse to serve entropy once a continuous health test has failed
+fn ensure_healthy(state: &AppState) -> Result<(), ApiError> {
+    match state.health.failure() {
+        Some(failure) => Err(ApiError::unavailable(format!(
+            "Entropy source failed continuous health test: {}",
+            failure
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Largest response served as an interactive request
+pub const INTERACTIVE_MAX: usize = 1024;
+
+/// Scheduling class of a request for buffered entropy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Small requests such as keys, UUIDs and passwords
+    Interactive,
+    /// Everything larger, which must leave the interactive reserve alone
+    Bulk,
+}
+
+impl Priority {
+    /// Class of a request for `count` output bytes
+    pub fn of(count: usize) -> Self {
+        if count <= INTERACTIVE_MAX {
+            Self::Interactive
+        } else {
+            Self::Bulk
+        }
+    }
+}
+
+/// Fetch raw entropy, from the buffer unless a device is pinned
+///
+/// Buffered bytes go back to the buffer unless the caller commits them.
+async fn fetch_entropy<'a>(
+    state: &'a AppState,
+    size: usize,
+    priority: Priority,
+    device: Option<&str>,
+) -> Result<Reservation<'a>, ApiError> {
+    // The buffer mixes output of whichever device was active, so pinned
+    // requests always read directly from the chosen unit
+    if let (None, Some(tenant)) = (device, caller_tenant(state)) {
+        // Tenants only draw on their own slice
+        if let Some(reservation) = tenant.reserve(size) {
+            return Ok(reservation);
+        }
+        check_backpressure(state, tenant.buffer(), size, 0)
+            .inspect_err(|_| state.metrics.record_starved())?;
+    } else if device.is_none() {
+        let floor = match priority {
+            Priority::Interactive => 0,
+            Priority::Bulk => state.interactive_reserve,
+        };
+        if let Some(reservation) = state.buffer.reserve_above(size, floor) {
+            // Buffered data was tested by the background reader
+            return Ok(reservation);
+        }
+        check_backpressure(state, &state.buffer, size, floor)
+            .inspect_err(|_| state.metrics.record_starved())?;
+    }
+
+    // Fall back to direct device read
+    let bytes = read_fresh(state, size, device).await?;
+    if device.is_none() {
+        state.metrics.record_direct_read(bytes.len());
+    }
+    Ok(Reservation::detached(secure::bytes(bytes)))
+}
+
+/// Tenant of the calling key, if it has one
+fn caller_tenant(state: &AppState) -> Option<&Tenant> {
+    ratelimit::tenant().and_then(|tenant| state.tenants.find(tenant.name()).map(|tenant| &**tenant))
+}
+
+/// Refuse direct reads that would hold the device for too long
+///
+/// The client is told when `buffer` should hold `size` bytes above `floor`
+/// instead. Requests larger than the buffer can only be read directly.
+fn check_backpressure(
+    state: &AppState,
+    buffer: &RingBuffer,
+    size: usize,
+    floor: usize,
+) -> Result<(), ApiError> {
+    let Some(bytes_per_sec) = state.devices.read_rate() else {
+        return Ok(());
+    };
+    if size > buffer.capacity() || rate::read_time(size, bytes_per_sec) <= state.max_read_wait {
+        return Ok(());
+    }
+
+    let available = buffer.available();
+    let refill = rate::read_time((size + floor).saturating_sub(available), bytes_per_sec);
+    Err(ApiError::unavailable(format!(
+        "Entropy buffer starved: {} bytes requested, {} buffered",
+        size, available
+    ))
+    .with_retry_after(refill.as_secs_f64().ceil().max(1.0) as u64))
+}
+
+/// Read raw entropy straight from the devices, bypassing the buffer
+async fn read_fresh(state: &AppState, size: usize, device: Option<&str>) -> Result<Vec<u8>, ApiError> {
+    let direct = match device {
+        Some(serial) => state.devices.read_from(serial, size).await,
+        None => state.devices.read(size).await,
+    };
+
+    let bytes = direct.map_err(|e| match e {
+        QuantisError::Busy => ApiError::unavailable("Device busy with another read").with_retry_after(1),
+        e => ApiError::failed(format!("Device error: {}", e)),
+    })?;
+    state.health.check(&bytes).map_err(|failure| {
+        ApiError::unavailable(format!("Entropy source failed continuous health test: {}", failure))
+    })?;
+    Ok(bytes)
+}
+
+/// Resolve a request's pipeline, falling back to the server default
+fn resolve_pipeline(state: &AppState, spec: Option<&str>, defaults: StageDefaults) -> Result<Pipeline, String> {
+    match spec {
+        Some(spec) => Pipeline::parse(spec, defaults),
+        None => Ok(state.correction.clone()),
+    }
+}
+
+/// Raw draws allowed when a pipeline yields less than expected
+const MAX_CONDITIONING_ROUNDS: usize = 4;
+
+/// Draw raw entropy through `pipeline` until `count` bytes are produced
+async fn conditioned_entropy(
+    state: &AppState,
+    count: usize,
+    pipeline: &Pipeline,
+    device: Option<&str>,
+) -> Result<Bytes, ApiError> {
+    // Without conditioning or FIPS filtering raw bytes pass through uncopied
+    if pipeline.is_empty() && state.health.fips_block_size().is_none() {
+        return Ok(fetch_entropy(state, count, Priority::of(count), device).await?.commit());
+    }
+
+    let mut output = Vec::with_capacity(count);
+    let mut drawn = Vec::new();
+    // FIPS mode only emits whole tested blocks
+    let block = state.health.fips_block_size().unwrap_or(1);
+
+    for _ in 0..MAX_CONDITIONING_ROUNDS {
+        let wanted = (count - output.len()).div_ceil(block) * block;
+        let raw = fetch_entropy(state, pipeline.input_len(wanted), Priority::of(count), device).await?;
+        output.extend(state.health.fips_filter(pipeline.apply(raw.bytes())));
+        drawn.push(raw);
+        if output.len() >= count {
+            for raw in drawn {
+                raw.commit();
+            }
+            output.truncate(count);
+            return Ok(secure::bytes(output));
+        }
+    }
+
+    // Dropping `drawn` returns the raw bytes to the buffer
+    Err(ApiError::failed(format!("Insufficient entropy after {} correction", pipeline)))
+}
+
+/// Full-entropy secret conditioned from raw device output, e.g. a DRBG seed
+/// or signing key
+pub async fn full_entropy<const N: usize>(state: &AppState) -> Result<Zeroizing<[u8; N]>, ApiError> {
+    let ratio = bias_correction::SHA3_DEFAULT_RATIO;
+    let size = bias_correction::sha3_input_len(N, ratio);
+    // A reseed holds up every DRBG request behind it
+    let raw = fetch_entropy(state, size, Priority::Interactive, None).await?.commit();
+    let conditioned = Zeroizing::new(bias_correction::sha3(&raw, ratio));
+
+    let mut secret = Zeroizing::new([0u8; N]);
+    secret.copy_from_slice(&conditioned[..N]);
+    Ok(secret)
+}
+
+/// Generate bytes from the CTR_DRBG, reseeding from the device when due
+async fn drbg_entropy(state: &AppState, count: usize) -> Result<Bytes, ApiError> {
+    for _ in 0..2 {
+        if state.drbg.needs_seed() {
+            let seed = full_entropy::<SEED_LEN>(state).await?;
+            state.drbg.seed(&seed);
+        }
+        if let Some(bytes) = state.drbg.generate(count) {
+            return Ok(secure::bytes(bytes));
+        }
+    }
+    Err(ApiError::failed("DRBG could not be reseeded"))
+}
+
+/// Generate bytes from the Fortuna accumulator
+fn fortuna_entropy(state: &AppState, count: usize) -> Result<Bytes, ApiError> {
+    let fortuna = state.fortuna.as_ref().ok_or_else(|| ApiError::failed("source=fortuna is not enabled"))?;
+    match fortuna.generate(count) {
+        Some(bytes) => Ok(secure::bytes(bytes)),
+        None => Err(ApiError::unavailable("Fortuna is not seeded from the device yet").with_retry_after(1)),
+    }
+}
+
+/// Produce `count` bytes from the requested source
+///
+/// The bytes are charged to the caller's limits and counted in the access
+/// and dispensing logs.
+async fn sourced_entropy(
+    state: &AppState,
+    source: OutputSource,
+    count: usize,
+    pipeline: &Pipeline,
+    device: Option<&str>,
+) -> Result<Bytes, ApiError> {
+    ensure_healthy(state)?;
+    defer_bulk(state, source, count, device)?;
+    ratelimit::charge_bytes(count)?;
+    let bytes = match draw_entropy(state, source, count, pipeline, device).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            ratelimit::refund_bytes(count);
+            return Err(e);
+        }
+    };
+    if let Some(tenant) = ratelimit::tenant() {
+        tenant.record_served(bytes.len());
+    }
+    access_log::record_delivery(delivery(state, source, bytes.len(), pipeline, device));
+    Ok(bytes)
+}
+
+/// Refuse bulk requests while demand outruns the devices
+///
+/// Only requests drawing on the main buffer are deferred: the generators,
+/// pinned devices and tenants' slices leave it alone.
+fn defer_bulk(
+    state: &AppState,
+    source: OutputSource,
+    count: usize,
+    device: Option<&str>,
+) -> Result<(), ApiError> {
+    let main_buffer = device.is_none() && caller_tenant(state).is_none();
+    if Priority::of(count) != Priority::Bulk || source.is_generated() || !main_buffer {
+        return Ok(());
+    }
+    let fill_rate = state.devices.read_rate();
+    let reserve = state.interactive_reserve;
+    match state.throttle.defer(&state.buffer, fill_rate, count, reserve, Instant::now()) {
+        None => Ok(()),
+        Some(wait) => {
+            state.metrics.record_deferred();
+            Err(ApiError::unavailable(format!(
+                "Demand exceeds the device rate, {} byte request deferred",
+                count
+            ))
+            .with_retry_after(wait))
+        }
+    }
+}
+
+/// Description of `bytes` drawn from `source` for the dispensing log
+fn delivery(
+    state: &AppState,
+    source: OutputSource,
+    bytes: usize,
+    pipeline: &Pipeline,
+    device: Option<&str>,
+) -> access_log::Delivery {
+    access_log::Delivery {
+        bytes,
+        correction: source_correction(source, pipeline),
+        source,
+        devices: match device {
+            Some(serial) => vec![serial.to_string()],
+            None => state.devices.serving_serials(),
+        },
+    }
+}
+
+async fn draw_entropy(
+    state: &AppState,
+    source: OutputSource,
+    count: usize,
+    pipeline: &Pipeline,
+    device: Option<&str>,
+) -> Result<Bytes, ApiError> {
+    let raw = Pipeline::default();
+    let credited = match source {
+        OutputSource::Raw => &raw,
+        OutputSource::Conditioned => pipeline,
+        OutputSource::Drbg => return drbg_entropy(state, count).await,
+        OutputSource::Fortuna => return fortuna_entropy(state, count),
+    };
+
+    // Pre-conditioned output was drawn from the buffer when it was pooled;
+    // the pools are shared, so tenants leave them alone
+    let tenant = caller_tenant(state);
+    if matches!(source, OutputSource::Conditioned) && device.is_none() && tenant.is_none() {
+        if let Some(bytes) = state.pools.read(pipeline, count) {
+            state.credit.debit(credited, count);
+            return Ok(bytes);
+        }
+    }
+
+    // Pinned requests read fresh device output rather than the buffer
+    if device.is_none() && state.credit.enforced() {
+        let buffered = tenant.map_or(&*state.buffer, Tenant::buffer).available();
+        if !state.credit.covers(buffered, credited, count) {
+            return Err(ApiError::unavailable(format!(
+                "Request exceeds credited entropy: {:.0} bits available through {}, {} required",
+                state.credit.available_bits(buffered, credited),
+                credited,
+                count * 8
+            )));
+        }
+    }
+
+    let bytes = match source {
+        OutputSource::Raw => fetch_entropy(state, count, Priority::of(count), device).await?.commit(),
+        _ => conditioned_entropy(state, count, pipeline, device).await?,
+    };
+    state.credit.debit(credited, count);
+    Ok(bytes)
+}
+
+/// Min-entropy per output byte credited to a source
+fn source_rate(state: &AppState, source: OutputSource, pipeline: &Pipeline) -> f64 {
+    match source {
+        OutputSource::Raw => state.health.min_entropy(),
+        OutputSource::Conditioned => pipeline.entropy_rate(state.health.min_entropy()),
+        OutputSource::Drbg | OutputSource::Fortuna => 8.0,
+    }
+}
+
+/// Pipeline name reported for a source
+fn source_correction(source: OutputSource, pipeline: &Pipeline) -> String {
+    match source {
+        OutputSource::Raw => "none".to_string(),
+        OutputSource::Conditioned => pipeline.to_string(),
+        OutputSource::Drbg => "ctr_drbg".to_string(),
+        OutputSource::Fortuna => "fortuna".to_string(),
+    }
+}
+
+/// Generate random bytes
+async fn random_bytes(
+    Query(params): Query<BytesQuery>,
+    State(state): State<AppState>,
+    granted: Option<Extension<Granted>>,
+) -> Result<Response, ApiError> {
+    // Validate parameters
+    let limits = ratelimit::request_limits().unwrap_or(state.request_limits);
+    let expand = match params.expand.as_deref() {
+        None => false,
+        Some("shake256") => true,
+        Some(other) => return Err(ApiError::failed(format!("Invalid expand: {}", other))),
+    };
+    if expand {
+        if params.count == 0 || params.count > MAX_EXPANDED_BYTES {
+            return Err(ApiError::failed(format!(
+                "Count must be between 1 and {} when expanding",
+                MAX_EXPANDED_BYTES
+            )));
+        }
+        if !(shake::MIN_STRENGTH..=shake::MAX_STRENGTH).contains(&params.strength) {
+            return Err(ApiError::failed(format!(
+                "strength must be between {} and {} bits",
+                shake::MIN_STRENGTH,
+                shake::MAX_STRENGTH
+            )));
+        }
+    }
+    let streamed = !expand && limits.streams(params.count)?;
+    if !matches!(params.format.as_str(), "hex" | "base64" | "binary") {
+        return Err(ApiError::failed("Invalid format"));
+    }
+    if params.sign {
+        // Signing uses the server's key
+        if granted.is_some_and(|Extension(Granted(role))| role < Role::Crypto) {
+            return Err(ApiError::forbidden("Signing requires the crypto role"));
+        }
+        if streamed {
+            return Err(ApiError::failed("Streamed responses cannot be signed"));
+        }
+    }
+    let signer = params.sign.then(|| state.signer.get()).transpose()?;
+
+    let pipeline = match resolve_pipeline(
+        &state,
+        params.correction.as_deref(),
+        StageDefaults {
+            ratio: params.ratio,
+            rate: params.rate,
+        },
+    ) {
+        Ok(pipeline) => pipeline,
+        Err(e) => return Err(ApiError::failed(e)),
+    };
+
+    let source = match OutputSource::parse(params.source.as_deref(), params.device.as_deref()) {
+        Ok(source) => source,
+        Err(e) => return Err(ApiError::failed(e)),
+    };
+
+    if streamed {
+        return stream_bytes(state, source, pipeline, params).await;
+    }
+
+    let (corrected_bytes, expanded) = if expand {
+        // Enough seed bytes to carry the requested strength from this source
+        let seed_bytes = shake::seed_len(params.strength, source_rate(&state, source, &pipeline));
+        let seed = sourced_entropy(&state, source, seed_bytes, &pipeline, params.device.as_deref()).await?;
+        let expansion = Expansion {
+            method: "shake256",
+            seed_bits: params.strength,
+            seed_bytes,
+        };
+        (secure::bytes(shake::expand(&seed, params.count)), Some(expansion))
+    } else {
+        let bytes = sourced_entropy(&state, source, params.count, &pipeline, params.device.as_deref()).await?;
+        (bytes, None)
+    };
+
+    let correction = source_correction(source, &pipeline);
+    let digest = Sha256::digest(&corrected_bytes);
+    let sha256 = hex::encode(digest);
+    let signature = signer.map(|signer| {
+        let signed_at = unix_time();
+        BytesSignature {
+            signature: signer.sign(&signing::bytes_message(signed_at, &digest)),
+            signed_at,
+        }
+    });
+
+    // Binary output is the buffered bytes themselves, with metadata in headers
+    if params.format == "binary" {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        headers.insert("x-correction", HeaderValue::from_str(&correction).expect("pipeline names are ASCII"));
+        headers.insert("x-sha256", HeaderValue::from_str(&sha256).expect("hex is ASCII"));
+        if let Some(BytesSignature { signature, signed_at }) = signature {
+            let hex_value = |hex: &str| HeaderValue::from_str(hex).expect("hex is ASCII");
+            headers.insert("x-signature", hex_value(&signature.signature));
+            headers.insert("x-signature-key-id", hex_value(&signature.key_id));
+            headers.insert("x-signed-at", HeaderValue::from(signed_at));
+        }
+        return Ok((headers, corrected_bytes).into_response());
+    }
+
+    // Format output
+    let formatted = match params.format.as_str() {
+        "hex" => hex::encode(&corrected_bytes),
+        _ => base64::engine::general_purpose::STANDARD.encode(&corrected_bytes),
+    };
+
+    Ok(Json(ApiResponse::success(BytesResponse {
+        bytes: formatted,
+        count: params.count,
+        format: params.format,
+        correction,
+        source,
+        sha256,
+        expanded,
+        device: params.device,
+        signature,
+    }))
+    .into_response())
+}
+
+/// Send a `/random/bytes` response too large for one piece as a stream
+///
+/// The whole count is charged up front and what was not sent is refunded
+/// once the stream ends. Health is checked before each piece, and a
+/// failure or the route's timeout ends the response early.
+async fn stream_bytes(
+    state: AppState,
+    source: OutputSource,
+    pipeline: Pipeline,
+    params: BytesQuery,
+) -> Result<Response, ApiError> {
+    let count = params.count;
+    ensure_healthy(&state)?;
+    // Once started, a stream is served to the end
+    defer_bulk(&state, source, count, params.device.as_deref())?;
+    ratelimit::charge_bytes(count)?;
+
+    let correction = source_correction(source, &pipeline);
+    let content_type = match params.format.as_str() {
+        "binary" => "application/octet-stream",
+        _ => "text/plain",
+    };
+    let format = params.format;
+    let (pieces, received) = mpsc::channel::<io::Result<Bytes>>(2);
+    let deadline = tokio::time::Instant::now() + state.route_timeouts.get("/random/bytes");
+    // The key stays attached so its tenant's slice is drawn on, and the
+    // request is logged with what was sent once the stream ends
+    tokio::spawn(access_log::with_tally(ratelimit::with_caller(async move {
+        let device = params.device.as_deref();
+        let mut sent = 0;
+        let streamed = tokio::time::timeout_at(deadline, async {
+            while sent < count {
+                let len = (count - sent).min(STREAM_CHUNK);
+                let drawn = match ensure_healthy(&state) {
+                    Ok(()) => draw_entropy(&state, source, len, &pipeline, device).await,
+                    Err(e) => Err(e),
+                };
+                let piece = match drawn {
+                    Ok(bytes) => Ok(match format.as_str() {
+                        "hex" => Bytes::from(hex::encode(&bytes)),
+                        "base64" => Bytes::from(base64::engine::general_purpose::STANDARD.encode(&bytes)),
+                        _ => bytes,
+                    }),
+                    Err(e) => Err(io::Error::other(e.message)),
+                };
+                let failed = piece.is_err();
+                if pieces.send(piece).await.is_err() || failed {
+                    break;
+                }
+                if let Some(tenant) = ratelimit::tenant() {
+                    tenant.record_served(len);
+                }
+                sent += len;
+            }
+        });
+        if streamed.await.is_err() {
+            let _ = pieces.try_send(Err(io::Error::other("Request timed out")));
+        }
+        ratelimit::refund_bytes(count - sent);
+        if sent > 0 {
+            access_log::record_delivery(delivery(&state, source, sent, &pipeline, device));
+        }
+    })));
+
+    let headers = [
+        (CONTENT_TYPE, content_type.to_string()),
+        (HeaderName::from_static("x-correction"), correction),
+    ];
+    Ok((headers, Body::from_stream(ReceiverStream::new(received))).into_response())
+}
+
+/// Generate random integers
+async fn random_integers(
+    Query(params): Query<IntegersQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<IntegersResponse>>, ApiError> {
+    // Validate parameters
+    if params.min >= params.max {
+        return Ok(Json(ApiResponse::error("min must be less than max")));
+    }
+    let max_integers = ratelimit::request_limits().unwrap_or(state.request_limits).max_integers;
+    if params.count == 0 || params.count > max_integers {
+        return Ok(Json(ApiResponse::error(format!("count must be between 1 and {}", max_integers))));
+    }
+
+    let range = (params.max - params.min + 1) as u64;
+    let bytes_per_int = ((range as f64).ln() / 256f64.ln()).ceil() as usize;
+    let total_bytes = bytes_per_int * params.count * 2; // Extra for rejection sampling
+
+    let pipeline =
+        match resolve_pipeline(&state, params.correction.as_deref(), StageDefaults::default()) {
+            Ok(pipeline) => pipeline,
+            Err(e) => return Ok(Json(ApiResponse::error(e))),
+        };
+
+    let source = match OutputSource::parse(params.source.as_deref(), params.device.as_deref()) {
+        Ok(source) => source,
+        Err(e) => return Ok(Json(ApiResponse::error(e))),
+    };
+
+    // Get random bytes
+    let raw_bytes =
+        sourced_entropy(&state, source, total_bytes, &pipeline, params.device.as_deref()).await?;
+
+    // Generate integers using rejection sampling
+    let mut integers = Vec::with_capacity(params.count);
+    let mut byte_offset = 0;
+
+    while integers.len() < params.count && byte_offset + bytes_per_int <= raw_bytes.len() {
+        let mut value = 0u64;
+        for i in 0..bytes_per_int {
+            value = (value << 8) | raw_bytes[byte_offset + i] as u64;
+        }
+
+        // Rejection sampling for uniform distribution
+        let max_valid = u64::MAX - (u64::MAX % range);
+        if value < max_valid {
+            integers.push(params.min + (value % range) as i64);
+        }
+
+        byte_offset += bytes_per_int;
+    }
+
+    if integers.len() < params.count {
+        return Ok(Json(ApiResponse::error("Insufficient entropy for requested integers")));
+    }
+
+    Ok(Json(ApiResponse::success(IntegersResponse {
+        integers: integers.into_iter().take(params.count).collect(),
+        min: params.min,
+        max: params.max,
+        count: params.count,
+        source,
+        device: params.device,
+    })))
+}
+
+/// Get device information
+async fn device_info(State(state): State<AppState>) -> Result<Json<ApiResponse<serde_json::Value>>, StatusCode> {
+    match state.devices.info().await {
+        Ok(info) => Ok(Json(ApiResponse::success(serde_json::json!({
+            "device": info,
+            "buffer_size": state.buffer.capacity(),
+            "buffer_available": state.buffer.available(),
+        })))),
+        Err(e) => Ok(Json(ApiResponse::error(format!("Failed to get device info: {}", e)))),
+    }
+}
+
+/// List every device the server knows about
+async fn list_devices(State(state): State<AppState>) -> Json<ApiResponse<Vec<DeviceEntry>>> {
+    let devices = state
+        .devices
+        .slots()
+        .iter()
+        .map(|slot| {
+            let info = slot.info();
+            DeviceEntry {
+                index: slot.index(),
+                serial: info.serial.clone(),
+                product: info.product.clone(),
+                version: info.version.clone(),
+                state: slot.state(),
+                role: state.devices.role(slot),
+                consecutive_errors: slot.consecutive_errors(),
+            }
+        })
+        .collect();
+
+    Json(ApiResponse::success(devices))
+}
+
+/// Run the SP 800-22 suite over a fresh raw sample
+async fn sp800_22_suite(
+    Query(params): Query<Sp80022Query>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Sp80022Response>>, ApiError> {
+    if params.megabytes == 0 || params.megabytes > MAX_TEST_MEGABYTES {
+        return Ok(Json(ApiResponse::error(format!(
+            "megabytes must be between 1 and {}",
+            MAX_TEST_MEGABYTES
+        ))));
+    }
+
+    ensure_healthy(&state)?;
+    let sample = read_fresh(&state, params.megabytes * 1024 * 1024, params.device.as_deref()).await?;
+    let bits = sample.len() * 8;
+
+    let results = tokio::task::spawn_blocking(move || sp800_22::run_all(&sample))
+        .await
+        .map_err(|e| ApiError::failed(format!("Test suite failed: {}", e)))?
+        .map_err(ApiError::failed)?;
+
+    for result in &results {
+        state.health.audit().record(
+            AuditCategory::SelfTest,
+            &format!("sp800_22:{}", result.test),
+            result.passed,
+            format!("p = {:.6} over {} bits", result.p_value, bits),
+        );
+    }
+
+    Ok(Json(ApiResponse::success(Sp80022Response {
+        bits,
+        passed: results.iter().all(|r| r.passed),
+        results,
+        device: params.device,
+    })))
+}
+
+/// Estimate min-entropy of a fresh raw sample
+async fn estimate_entropy(
+    Query(params): Query<EstimateQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<EstimateResponse>>, ApiError> {
+    if params.samples < 1024 || params.samples > MAX_TEST_MEGABYTES * 1024 * 1024 {
+        return Ok(Json(ApiResponse::error(format!(
+            "samples must be between 1024 and {}",
+            MAX_TEST_MEGABYTES * 1024 * 1024
+        ))));
+    }
+
+    ensure_healthy(&state)?;
+    let sample = read_fresh(&state, params.samples, params.device.as_deref()).await?;
+
+    let assessment = tokio::task::spawn_blocking(move || estimators::assess(&sample))
+        .await
+        .map_err(|e| ApiError::failed(format!("Estimation failed: {}", e)))?;
+    state.health.record_assessment(assessment.clone());
+
+    Ok(Json(ApiResponse::success(EstimateResponse {
+        assessment,
+        device: params.device,
+    })))
+}
+
+/// Query the health test audit trail
+async fn audit_trail(
+    Query(params): Query<AuditQuery>,
+    State(state): State<AppState>,
+) -> Json<ApiResponse<AuditResponse>> {
+    if params.limit == 0 || params.limit > MAX_AUDIT_RECORDS {
+        return Json(ApiResponse::error(format!(
+            "limit must be between 1 and {}",
+            MAX_AUDIT_RECORDS
+        )));
+    }
+
+    let filter = AuditFilter {
+        from: params.from,
+        to: params.to,
+        category: params.category,
+        test: params.test,
+    };
+    let records = state.health.audit().query(&filter, params.limit);
+
+    Json(ApiResponse::success(AuditResponse {
+        count: records.len(),
+        records,
+    }))
+}
+
+/// Buffer statistics
+async fn stats(State(state): State<AppState>) -> Json<ApiResponse<serde_json::Value>> {
+    Json(ApiResponse::success(serde_json::json!({
+        "buffer_size": state.buffer.capacity(),
+        "buffer_available": state.buffer.available(),
+        "interactive_reserve": state.interactive_reserve,
+        "buffer_traffic": state.buffer.traffic().snapshot(),
+        "rolling": state.health.monitor().stats(),
+        "alarm_thresholds": state.health.monitor().thresholds(),
+        "autocorrelation": state.health.autocorrelation().stats(),
+        "autocorrelation_threshold": state.health.autocorrelation().threshold(),
+        "assessment": state.health.assessment(),
+        "entropy_credit": state.credit.report(state.buffer.available(), &state.correction),
+        "pools": state.pools.status(),
+        "tenants": state.tenants.status(),
+    })))
+}
+
+/// Prometheus metrics
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    state.metrics.set_buffer(state.buffer.available(), state.buffer.capacity());
+    state.metrics.set_buffer_traffic("raw", &state.buffer.traffic().snapshot());
+    for pool in state.pools.pools() {
+        state
+            .metrics
+            .set_buffer_traffic(&pool.pipeline().to_string(), &pool.buffer().traffic().snapshot());
+    }
+    for tenant in state.tenants.tenants() {
+        let buffer = format!("tenant:{}", tenant.name());
+        state.metrics.set_buffer_traffic(&buffer, &tenant.buffer().traffic().snapshot());
+        state.metrics.set_tenant(&tenant.status());
+    }
+    state.metrics.set_rolling(&state.health.monitor().stats());
+    state.metrics.set_autocorrelation(&state.health.autocorrelation().stats());
+
+    (
+        [(axum::http::header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        state.metrics.render(),
+    )
+}
@@ -0,0 +1,87 @@
+//! Answering a panicking handler with a JSON error
+//!
+//! Without this a panic in a handler drops the connection and the client
+//! sees only a reset. The panic is caught, logged in the request's span and
+//! answered with a 500 in the usual error shape, carrying the request ID so
+//! the client's report can be matched to the log.
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    Router,
+};
+use futures_util::FutureExt;
+use std::{any::Any, panic::AssertUnwindSafe};
+use tower_http::request_id::RequestId;
+use tracing::error;
+
+/// Answer panics in the handlers of `router` with a 500
+///
+/// Belongs inside [`super::request_id::trace_requests`], which assigns the
+/// ID reported.
+pub fn catch_panics(router: Router) -> Router {
+    router.layer(middleware::from_fn(catch_panic))
+}
+
+async fn catch_panic(request: Request, next: Next) -> Response {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_string);
+    match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(panic) => {
+            error!("Handler panicked: {}", message(&*panic));
+            let body = serde_json::json!({
+                "success": false,
+                "data": null,
+                "error": "Internal server error",
+                "request_id": id,
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+    }
+}
+
+/// Text of a panic payload, which is a `&str` or `String` unless the
+/// panic was raised with a custom payload
+fn message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::request_id::{trace_requests, REQUEST_ID_HEADER};
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn panics_become_json_errors() {
+        let router = Router::new()
+            .route("/ok", get(|| async { "fine" }))
+            .route("/panic", get(|| async { panic!("boom") as &str }));
+        let router = trace_requests(catch_panics(router));
+
+        let request = Request::get("/panic").header(REQUEST_ID_HEADER, "req-7").body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "req-7");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["request_id"], "req-7");
+
+        let response = router.oneshot(Request::get("/ok").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
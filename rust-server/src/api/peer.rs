@@ -0,0 +1,47 @@
+//! The other end of a request's connection
+//!
+//! TCP connections carry the client's address as axum's `ConnectInfo`, and
+//! vsock connections the guest's context ID as a [`VsockPeer`] extension.
+//! Address filters and per-address limits work on either.
+
+use axum::{extract::ConnectInfo, http::Extensions};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Context ID of the guest VM a vsock request came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VsockPeer(pub u32);
+
+/// Client address or guest context ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Peer {
+    Ip(IpAddr),
+    Vsock(u32),
+}
+
+impl Peer {
+    /// Peer recorded by the server that accepted the request, if any
+    pub fn of(extensions: &Extensions) -> Option<Self> {
+        if let Some(ConnectInfo(addr)) = extensions.get::<ConnectInfo<SocketAddr>>() {
+            return Some(Self::Ip(addr.ip()));
+        }
+        extensions.get::<VsockPeer>().map(|peer| Self::Vsock(peer.0))
+    }
+}
+
+impl From<IpAddr> for Peer {
+    fn from(ip: IpAddr) -> Self {
+        Self::Ip(ip)
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{}", ip),
+            Self::Vsock(cid) => write!(f, "vsock:{}", cid),
+        }
+    }
+}
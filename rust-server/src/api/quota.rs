@@ -0,0 +1,271 @@
+//! Daily and monthly byte quotas per API key
+//!
+//! Periods follow UTC calendar days and months. Usage counters can be kept
+//! in a JSON file so a restart does not hand out a fresh budget; they are
+//! written every few seconds while they change.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+use super::{ratelimit::ApiKeys, ApiError};
+
+/// How often changed usage counters are written out
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Bytes served to one key in the current day and month
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// UTC day, counted from 1970-01-01
+    pub day: u64,
+    pub day_bytes: u64,
+    /// UTC month, counted from January 1970
+    pub month: u64,
+    pub month_bytes: u64,
+}
+
+impl Usage {
+    /// Start new periods once `day` has moved past the recorded ones
+    fn roll(&mut self, day: u64) {
+        if self.day != day {
+            self.day = day;
+            self.day_bytes = 0;
+        }
+        let month = month_of(day);
+        if self.month != month {
+            self.month = month;
+            self.month_bytes = 0;
+        }
+    }
+}
+
+/// Byte budgets of one key and what it has used of them
+#[derive(Debug)]
+pub struct Quota {
+    daily: Option<u64>,
+    monthly: Option<u64>,
+    usage: Mutex<Usage>,
+    changed: AtomicBool,
+}
+
+impl Quota {
+    pub fn new(daily: Option<u64>, monthly: Option<u64>) -> Self {
+        Self {
+            daily,
+            monthly,
+            usage: Mutex::default(),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Count `bytes` against the budgets, refusing them if either would run out
+    ///
+    /// The error says how long until the exhausted period resets.
+    pub fn charge(&self, label: &str, bytes: u64, now: SystemTime) -> Result<(), ApiError> {
+        let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let day = secs / SECS_PER_DAY;
+        let mut usage = self.usage.lock().unwrap();
+        usage.roll(day);
+
+        let exhausted = |period: &str, limit: u64, reset_day: u64| {
+            ApiError::rate_limited(
+                format!("{} has used its {} quota of {} bytes", label, period, limit),
+                limit,
+                reset_day * SECS_PER_DAY - secs,
+            )
+        };
+        if let Some(limit) = self.daily.filter(|limit| usage.day_bytes + bytes > *limit) {
+            return Err(exhausted("daily", limit, day + 1));
+        }
+        if let Some(limit) = self.monthly.filter(|limit| usage.month_bytes + bytes > *limit) {
+            return Err(exhausted("monthly", limit, first_day(usage.month + 1)));
+        }
+
+        usage.day_bytes += bytes;
+        usage.month_bytes += bytes;
+        self.changed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Give back `bytes` charged earlier in the current periods
+    pub fn refund(&self, bytes: u64, now: SystemTime) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.roll(now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECS_PER_DAY);
+        usage.day_bytes = usage.day_bytes.saturating_sub(bytes);
+        usage.month_bytes = usage.month_bytes.saturating_sub(bytes);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn daily(&self) -> Option<u64> {
+        self.daily
+    }
+
+    pub fn monthly(&self) -> Option<u64> {
+        self.monthly
+    }
+
+    /// Usage as of `now`, with periods that have ended reset
+    pub fn usage(&self, now: SystemTime) -> Usage {
+        let mut usage = *self.usage.lock().unwrap();
+        usage.roll(now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECS_PER_DAY);
+        usage
+    }
+
+    pub fn restore(&self, usage: Usage) {
+        *self.usage.lock().unwrap() = usage;
+    }
+
+    /// Whether usage changed since the last call
+    fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Read usage counters saved with [`save`], by key name
+pub fn load(path: &Path) -> io::Result<BTreeMap<String, Usage>> {
+    match fs::read(path) {
+        Ok(data) => {
+            serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write usage counters, replacing the file atomically
+pub fn save(path: &Path, usage: &BTreeMap<String, Usage>) -> io::Result<()> {
+    let partial = path.with_extension("tmp");
+    fs::write(&partial, serde_json::to_vec_pretty(usage)?)?;
+    fs::rename(&partial, path)
+}
+
+/// Write the quota usage of `keys` to `path` whenever it changes
+pub fn persist(keys: Arc<ApiKeys>, path: PathBuf) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SAVE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            // Check every quota so all flags are cleared
+            let quotas = keys.quotas();
+            let changed = quotas.iter().fold(false, |changed, (_, quota)| quota.take_changed() | changed);
+            if changed {
+                save_all(&keys, &path);
+            }
+        }
+    });
+}
+
+/// Write the quota usage of `keys` to `path`, logging failures
+pub fn save_all(keys: &ApiKeys, path: &Path) {
+    let now = SystemTime::now();
+    let usage = keys.quotas().into_iter().map(|(name, quota)| (name, quota.usage(now))).collect();
+    if let Err(e) = save(path, &usage) {
+        warn!("Failed to save quota usage to {}: {}", path.display(), e);
+    }
+}
+
+/// UTC month, counted from January 1970, that contains day `day`
+fn month_of(day: u64) -> u64 {
+    // Civil-from-days conversion (H. Hinnant), on a year starting in March
+    let days = day as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_month = (5 * day_of_year + 2) / 153;
+    let (year, month) = match march_month {
+        0..=9 => (year_of_era + era * 400, march_month + 2),
+        _ => (year_of_era + era * 400 + 1, march_month - 10),
+    };
+    ((year - 1970) * 12 + month) as u64
+}
+
+/// First day, counted from 1970-01-01, of the month counted from January 1970
+fn first_day(month: u64) -> u64 {
+    let (year, month) = (1970 + month as i64 / 12, month as i64 % 12);
+    // Days-from-civil on a year starting in March
+    let year = if month < 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let march_month = (month + 10) % 12;
+    let day_of_year = (153 * march_month + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era - 719_468) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16T12:00:00Z
+    const MID_OCTOBER: u64 = 1_792_152_000;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn calendar_months() {
+        assert_eq!(month_of(0), 0);
+        assert_eq!(first_day(0), 0);
+        // 2024-02-29 and 2024-03-01
+        assert_eq!(month_of(19_782), 649);
+        assert_eq!(month_of(19_783), 650);
+        assert_eq!(first_day(650), 19_783);
+        for month in 1..1200 {
+            assert_eq!(month_of(first_day(month)), month);
+            assert_eq!(month_of(first_day(month) - 1), month - 1);
+        }
+    }
+
+    #[test]
+    fn quotas_reset_with_their_periods() {
+        let quota = Quota::new(Some(100), Some(250));
+        let now = at(MID_OCTOBER);
+        assert!(quota.charge("key", 100, now).is_ok());
+
+        // Twelve hours until the next UTC day
+        let refused = quota.charge("key", 1, now).unwrap_err();
+        assert_eq!(refused.status, 429);
+        assert_eq!(refused.retry_after, Some(12 * 3600));
+
+        assert!(quota.charge("key", 100, at(MID_OCTOBER + SECS_PER_DAY)).is_ok());
+        let refused = quota.charge("key", 100, at(MID_OCTOBER + 2 * SECS_PER_DAY)).unwrap_err();
+        // 2026-11-01 is 13.5 days away
+        assert_eq!(refused.retry_after, Some(13 * SECS_PER_DAY + 12 * 3600));
+        assert_eq!(quota.usage(at(MID_OCTOBER + 16 * SECS_PER_DAY)), Usage {
+            day: (MID_OCTOBER + 16 * SECS_PER_DAY) / SECS_PER_DAY,
+            day_bytes: 0,
+            month: month_of(MID_OCTOBER / SECS_PER_DAY) + 1,
+            month_bytes: 0,
+        });
+    }
+
+    #[test]
+    fn usage_round_trips_through_the_file() {
+        let path = std::env::temp_dir().join(format!("quantis-quota-{}.json", std::process::id()));
+        assert!(load(&path).unwrap().is_empty());
+
+        let usage = Usage {
+            day: 20_742,
+            day_bytes: 10,
+            month: 681,
+            month_bytes: 99,
+        };
+        save(&path, &BTreeMap::from([("sensor".to_string(), usage)])).unwrap();
+        assert_eq!(load(&path).unwrap()["sensor"], usage);
+        fs::remove_file(&path).unwrap();
+    }
+}
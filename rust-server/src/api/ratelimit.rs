@@ -0,0 +1,691 @@
+//! Per-key and per-address rate limits
+//!
+//! Callers identify themselves with an `X-API-Key` header. Each key, and
+//! optionally each client address, has token buckets for requests and for
+//! bytes of entropy per second; a request that finds a bucket empty gets 429
+//! with `Retry-After` and `RateLimit-*` headers saying when to come back.
+//! Keys can also have daily and monthly byte [quotas](super::quota), and
+//! belong to a [tenant](super::tenants) with quotas of its own.
+//! Keys and address limits can be replaced while the server runs.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderName,
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    access_log,
+    limits::RequestLimits,
+    peer::Peer,
+    quota::{Quota, Usage},
+    roles::{self, Role},
+    tenants::Tenant,
+    ApiError,
+};
+
+/// Header carrying the caller's API key
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Tracked client addresses beyond which idle ones are forgotten
+const MAX_IDLE_CLIENTS: usize = 4096;
+
+tokio::task_local! {
+    /// Key of the request being handled, for charging entropy bytes
+    static CALLER: Arc<Caller>;
+    /// Address or vsock guest of the request being handled, for charging
+    /// entropy bytes
+    static CLIENT: Arc<Caller>;
+}
+
+/// Tokens refilled at a steady rate, holding at most one second's worth
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Full bucket refilled at `rate` tokens per second
+    pub fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: now,
+        }
+    }
+
+    /// Take `amount` tokens, or return how long until they can be taken
+    ///
+    /// Amounts beyond the bucket size are allowed from a full bucket and
+    /// leave it in debt, so large requests are spaced out, not refused.
+    pub fn take(&mut self, amount: u64, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+
+        let needed = (amount as f64).min(self.rate);
+        if self.tokens >= needed {
+            self.tokens -= amount as f64;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((needed - self.tokens) / self.rate))
+        }
+    }
+
+    /// Put back `amount` tokens taken earlier
+    pub fn refund(&mut self, amount: u64) {
+        self.tokens = (self.tokens + amount as f64).min(self.rate);
+    }
+
+    /// Whether the bucket will have refilled completely by `now`
+    pub fn is_full(&self, now: Instant) -> bool {
+        self.tokens + now.saturating_duration_since(self.updated).as_secs_f64() * self.rate >= self.rate
+    }
+}
+
+/// Limits granted to one API key
+#[derive(Debug, Clone, Default)]
+pub struct KeyLimits {
+    pub name: String,
+    pub requests_per_sec: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+    pub daily_bytes: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+    /// Request sizes in place of the deployment's
+    pub request_limits: Option<RequestLimits>,
+    pub role: Role,
+    pub tenant: Option<Arc<Tenant>>,
+}
+
+/// One per-second limit and its bucket
+struct Limit {
+    per_sec: u64,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl Limit {
+    fn new(per_sec: u64) -> Self {
+        Self {
+            per_sec,
+            bucket: Mutex::new(TokenBucket::new(per_sec, Instant::now())),
+        }
+    }
+}
+
+/// Buckets of one API key or client address
+pub struct Caller {
+    /// Who is limited, for error messages
+    label: String,
+    /// Key name or client address
+    name: String,
+    requests: Option<Limit>,
+    bytes: Option<Limit>,
+    /// Byte quota, for API keys that have one
+    quota: Option<Arc<Quota>>,
+    request_limits: Option<RequestLimits>,
+    role: Role,
+    tenant: Option<Arc<Tenant>>,
+}
+
+impl Caller {
+    fn new(label: String, name: String, requests_per_sec: Option<u64>, bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            label,
+            name,
+            requests: requests_per_sec.map(Limit::new),
+            bytes: bytes_per_sec.map(Limit::new),
+            quota: None,
+            request_limits: None,
+            role: Role::default(),
+            tenant: None,
+        }
+    }
+
+    /// Role granted to the caller's API key
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Whether forgetting the caller would not reset any of its limits
+    fn is_idle(&self, now: Instant) -> bool {
+        [&self.requests, &self.bytes]
+            .into_iter()
+            .flatten()
+            .all(|limit| limit.bucket.lock().unwrap().is_full(now))
+    }
+
+    /// Charge `bytes` to the byte limit, the tenant's quota and the key's
+    /// quota, undoing the earlier charges if a later one is refused
+    fn charge(&self, bytes: u64) -> Result<(), ApiError> {
+        let now = SystemTime::now();
+        self.take(&self.bytes, bytes, "bytes")?;
+        let tenant_quota = self.tenant.as_ref().and_then(|tenant| Some((tenant, tenant.quota()?)));
+        if let Some((tenant, quota)) = tenant_quota {
+            if let Err(refused) = quota.charge(&format!("Tenant {}", tenant.name()), bytes, now) {
+                self.refund_limit(bytes);
+                return Err(refused);
+            }
+        }
+        if let Some(quota) = &self.quota {
+            if let Err(refused) = quota.charge(&self.label, bytes, now) {
+                self.refund_limit(bytes);
+                if let Some((_, quota)) = tenant_quota {
+                    quota.refund(bytes, now);
+                }
+                return Err(refused);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undo a successful [`charge`](Self::charge)
+    fn refund(&self, bytes: u64) {
+        let now = SystemTime::now();
+        self.refund_limit(bytes);
+        if let Some(quota) = self.tenant.as_ref().and_then(|tenant| tenant.quota()) {
+            quota.refund(bytes, now);
+        }
+        if let Some(quota) = &self.quota {
+            quota.refund(bytes, now);
+        }
+    }
+
+    fn refund_limit(&self, bytes: u64) {
+        if let Some(limit) = &self.bytes {
+            limit.bucket.lock().unwrap().refund(bytes);
+        }
+    }
+
+    fn take(&self, limit: &Option<Limit>, amount: u64, unit: &str) -> Result<(), ApiError> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        let wait = limit.bucket.lock().unwrap().take(amount, Instant::now());
+        wait.map_err(|wait| {
+            ApiError::rate_limited(
+                format!("{} is limited to {} {} per second", self.label, limit.per_sec, unit),
+                limit.per_sec,
+                wait.as_secs_f64().ceil().max(1.0) as u64,
+            )
+        })
+    }
+}
+
+/// Known API keys, looked up by SHA-256 so key material is not kept
+#[derive(Default)]
+pub struct ApiKeys(RwLock<HashMap<[u8; 32], Arc<Caller>>>);
+
+impl ApiKeys {
+    pub fn new(keys: impl IntoIterator<Item = (String, KeyLimits)>) -> Self {
+        let keys = keys.into_iter().map(|(key, limits)| {
+            let label = format!("API key {}", limits.name);
+            let mut caller = Caller::new(label, limits.name, limits.requests_per_sec, limits.bytes_per_sec);
+            if limits.daily_bytes.is_some() || limits.monthly_bytes.is_some() {
+                caller.quota = Some(Arc::new(Quota::new(limits.daily_bytes, limits.monthly_bytes)));
+            }
+            caller.request_limits = limits.request_limits;
+            caller.role = limits.role;
+            caller.tenant = limits.tenant;
+            (Sha256::digest(key.as_bytes()).into(), Arc::new(caller))
+        });
+        Self(RwLock::new(keys.collect()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.read().unwrap().is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+
+    /// Whether any key is granted `role` or more
+    pub fn any_granted(&self, role: Role) -> bool {
+        self.0.read().unwrap().values().any(|caller| caller.role >= role)
+    }
+
+    pub fn find(&self, key: &str) -> Option<Arc<Caller>> {
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        self.0.read().unwrap().get(&digest).cloned()
+    }
+
+    /// Byte quotas by key name, and those of the keys' tenants by
+    /// `tenant:` and the tenant name
+    pub fn quotas(&self) -> Vec<(String, Arc<Quota>)> {
+        let keys = self.0.read().unwrap();
+        let tenants: BTreeMap<_, _> = keys
+            .values()
+            .filter_map(|caller| caller.tenant.as_ref())
+            .filter_map(|tenant| Some((format!("tenant:{}", tenant.name()), tenant.quota()?.clone())))
+            .collect();
+        keys.values()
+            .filter_map(|caller| Some((caller.name.clone(), caller.quota.clone()?)))
+            .chain(tenants)
+            .collect()
+    }
+
+    /// Carry on from usage saved earlier, ignoring keys no longer configured
+    pub fn restore(&self, usage: &BTreeMap<String, Usage>) {
+        for (name, quota) in self.quotas() {
+            if let Some(usage) = usage.get(&name) {
+                quota.restore(*usage);
+            }
+        }
+    }
+
+    /// Switch to the keys of `keys`
+    ///
+    /// Keys that keep their name keep their quota usage; rate limit buckets
+    /// start afresh.
+    pub fn replace(&self, keys: ApiKeys) {
+        let now = SystemTime::now();
+        let usage = self.quotas().into_iter().map(|(name, quota)| (name, quota.usage(now))).collect();
+        keys.restore(&usage);
+        *self.0.write().unwrap() = keys.0.into_inner().unwrap();
+    }
+}
+
+/// Require a known API key when any are configured and count the request
+///
+/// The key stays attached while the request is handled so the entropy it
+/// draws can be charged with [`charge_bytes`], and its role is granted to
+/// the request.
+pub async fn require_api_key(
+    State(keys): State<Arc<ApiKeys>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if keys.is_empty() {
+        return Ok(next.run(request).await);
+    }
+    let key = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| ApiError::unauthorized("Missing X-API-Key header"))?;
+    let caller = keys.find(key).ok_or_else(|| ApiError::unauthorized("Unknown API key"))?;
+    caller.take(&caller.requests, 1, "requests")?;
+    access_log::record_api_key(&caller.name);
+    roles::grant(&mut request, caller.role);
+    Ok(CALLER.scope(caller, next.run(request)).await)
+}
+
+/// Limits applied to every client address and vsock guest
+///
+/// IPv6 clients are grouped by /64, the smallest prefix usually assigned to
+/// one host. Behind a reverse proxy every request comes from the proxy's
+/// address, so limit there instead.
+pub struct ClientLimits {
+    /// Requests and bytes per second
+    rates: RwLock<(Option<u64>, Option<u64>)>,
+    clients: Mutex<HashMap<Peer, Arc<Caller>>>,
+}
+
+impl ClientLimits {
+    pub fn new(requests_per_sec: Option<u64>, bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            rates: RwLock::new((requests_per_sec, bytes_per_sec)),
+            clients: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let (requests, bytes) = *self.rates.read().unwrap();
+        requests.is_some() || bytes.is_some()
+    }
+
+    /// Switch to new rates, starting every client with a fresh bucket
+    pub fn set(&self, requests_per_sec: Option<u64>, bytes_per_sec: Option<u64>) {
+        *self.rates.write().unwrap() = (requests_per_sec, bytes_per_sec);
+        self.clients.lock().unwrap().clear();
+    }
+
+    /// Buckets of the client at `peer`, created on its first request
+    fn client(&self, peer: impl Into<Peer>) -> Arc<Caller> {
+        let peer = match peer.into() {
+            Peer::Ip(IpAddr::V6(ip)) => Peer::Ip(match ip.to_ipv4_mapped() {
+                Some(ip) => IpAddr::V4(ip),
+                None => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !(u64::MAX as u128))),
+            }),
+            peer => peer,
+        };
+
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_IDLE_CLIENTS && !clients.contains_key(&peer) {
+            let now = Instant::now();
+            clients.retain(|_, client| !client.is_idle(now));
+        }
+        let client = clients.entry(peer).or_insert_with(|| {
+            let (requests, bytes) = *self.rates.read().unwrap();
+            Arc::new(Caller::new(format!("Client {}", peer), peer.to_string(), requests, bytes))
+        });
+        client.clone()
+    }
+
+    pub fn tracked(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+}
+
+/// Apply `limits` to each client address and vsock guest of `router` while
+/// they are enabled
+///
+/// Needs the server's connect info or vsock peer; requests with neither are
+/// not limited.
+pub fn limit_clients(router: Router, limits: Arc<ClientLimits>) -> Router {
+    router.layer(middleware::from_fn_with_state(limits, limit_client))
+}
+
+async fn limit_client(
+    State(limits): State<Arc<ClientLimits>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(peer) = Peer::of(request.extensions()) else {
+        return Ok(next.run(request).await);
+    };
+    if !limits.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+    let client = limits.client(peer);
+    client.take(&client.requests, 1, "requests")?;
+    Ok(CLIENT.scope(client, next.run(request)).await)
+}
+
+/// Request sizes granted to the calling key, if it has its own
+pub fn request_limits() -> Option<RequestLimits> {
+    CALLER.try_with(|caller| caller.request_limits).ok().flatten()
+}
+
+/// Tenant of the calling key
+pub fn tenant() -> Option<Arc<Tenant>> {
+    CALLER.try_with(|caller| caller.tenant.clone()).ok().flatten()
+}
+
+/// Run `future` with the calling key and address still attached, e.g. in a
+/// spawned task
+pub fn with_caller<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let caller = CALLER.try_with(Arc::clone).ok();
+    let client = CLIENT.try_with(Arc::clone).ok();
+    let future = async move {
+        match caller {
+            Some(caller) => CALLER.scope(caller, future).await,
+            None => future.await,
+        }
+    };
+    async move {
+        match client {
+            Some(client) => CLIENT.scope(client, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Charge `bytes` of output to the calling key's and address's byte limits
+///
+/// Either every limit and quota is charged or, when one refuses, none is,
+/// so refused bytes do not use anything up.
+pub fn charge_bytes(bytes: usize) -> Result<(), ApiError> {
+    let client = CLIENT.try_with(Arc::clone).ok();
+    client.as_ref().map_or(Ok(()), |client| client.charge(bytes as u64))?;
+    match CALLER.try_with(|caller| caller.charge(bytes as u64)) {
+        Ok(Err(refused)) => {
+            if let Some(client) = client {
+                client.refund(bytes as u64);
+            }
+            Err(refused)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Give back `bytes` charged by [`charge_bytes`] that were not served,
+/// e.g. because the draw failed
+pub fn refund_bytes(bytes: usize) {
+    let _ = CLIENT.try_with(|client| client.refund(bytes as u64));
+    let _ = CALLER.try_with(|caller| caller.refund(bytes as u64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        peer::VsockPeer,
+        tenants::{TenantLimits, Tenants},
+    };
+    use axum::{body::Body, extract::ConnectInfo, routing::get};
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    #[test]
+    fn bucket_refills_at_its_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10, start);
+        assert!(bucket.take(10, start).is_ok());
+        assert_eq!(bucket.take(1, start), Err(Duration::from_millis(100)));
+        assert!(bucket.take(1, start + Duration::from_millis(100)).is_ok());
+
+        // Oversized takes empty a full bucket into debt
+        let later = start + Duration::from_secs(5);
+        assert!(bucket.take(30, later).is_ok());
+        assert_eq!(bucket.take(10, later), Err(Duration::from_secs(3)));
+    }
+
+    #[tokio::test]
+    async fn limits_requests_and_bytes_per_key() {
+        let keys = ApiKeys::new([(
+            "secret".to_string(),
+            KeyLimits {
+                name: "sensor".to_string(),
+                requests_per_sec: Some(2),
+                bytes_per_sec: Some(64),
+                ..Default::default()
+            },
+        )]);
+        let router = Router::new()
+            .route("/", get(|| async { charge_bytes(64) }))
+            .layer(middleware::from_fn_with_state(Arc::new(keys), require_api_key));
+        let request = |key: Option<&str>| {
+            let mut request = Request::get("/");
+            if let Some(key) = key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        assert_eq!(router.clone().oneshot(request(None)).await.unwrap().status(), 401);
+        assert_eq!(router.clone().oneshot(request(Some("guess"))).await.unwrap().status(), 401);
+        assert_eq!(router.clone().oneshot(request(Some("secret"))).await.unwrap().status(), 200);
+
+        // The second request is within the request limit but out of bytes
+        let limited = router.oneshot(request(Some("secret"))).await.unwrap();
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.headers()["ratelimit-limit"], "64");
+        assert_eq!(limited.headers()["retry-after"], "1");
+        assert!(charge_bytes(1 << 30).is_ok());
+    }
+
+    #[test]
+    fn quotas_are_charged_and_restored_by_name() {
+        let limits = |name: &str| KeyLimits {
+            name: name.to_string(),
+            daily_bytes: Some(100),
+            ..Default::default()
+        };
+        let keys = Arc::new(ApiKeys::new([
+            ("one".to_string(), limits("sensor")),
+            ("two".to_string(), KeyLimits::default()),
+        ]));
+        assert_eq!(keys.quotas().len(), 1);
+
+        let saved = Usage {
+            day_bytes: 90,
+            ..keys.quotas()[0].1.usage(SystemTime::now())
+        };
+        keys.restore(&BTreeMap::from([("sensor".to_string(), saved), ("gone".to_string(), saved)]));
+
+        let caller = keys.find("one").unwrap();
+        assert!(CALLER.sync_scope(caller.clone(), || charge_bytes(10)).is_ok());
+        let refused = CALLER.sync_scope(caller, || charge_bytes(1)).unwrap_err();
+        assert_eq!(refused.status, 429);
+        assert!(CALLER.sync_scope(keys.find("two").unwrap(), || charge_bytes(1000)).is_ok());
+
+        // Replacing the keys keeps usage by name, so "sensor" stays at its quota
+        keys.replace(ApiKeys::new([("three".to_string(), limits("sensor"))]));
+        assert!(keys.find("one").is_none());
+        let refused = CALLER.sync_scope(keys.find("three").unwrap(), || charge_bytes(1)).unwrap_err();
+        assert_eq!(refused.status, 429);
+    }
+
+    #[test]
+    fn tenant_quotas_are_shared_by_their_keys() {
+        let tenants = Tenants::new([(
+            "lab-a".to_string(),
+            TenantLimits {
+                buffer_bytes: 4096,
+                daily_bytes: Some(100),
+                monthly_bytes: None,
+            },
+        )]);
+        let member = |name: &str| KeyLimits {
+            name: name.to_string(),
+            tenant: tenants.find("lab-a").cloned(),
+            ..Default::default()
+        };
+        let keys = ApiKeys::new([("one".to_string(), member("sensor")), ("two".to_string(), member("seeder"))]);
+        let quotas = keys.quotas();
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].0, "tenant:lab-a");
+
+        assert!(CALLER.sync_scope(keys.find("one").unwrap(), || charge_bytes(60)).is_ok());
+        assert_eq!(CALLER.sync_scope(keys.find("two").unwrap(), tenant).unwrap().name(), "lab-a");
+        let refused = CALLER.sync_scope(keys.find("two").unwrap(), || charge_bytes(60)).unwrap_err();
+        assert_eq!(refused.status, 429);
+        assert!(refused.message.contains("Tenant lab-a"));
+    }
+
+    #[test]
+    fn refused_bytes_are_not_charged() {
+        let tenants = Tenants::new([(
+            "lab-a".to_string(),
+            TenantLimits {
+                buffer_bytes: 4096,
+                daily_bytes: Some(1000),
+                monthly_bytes: None,
+            },
+        )]);
+        let keys = ApiKeys::new([(
+            "one".to_string(),
+            KeyLimits {
+                name: "sensor".to_string(),
+                daily_bytes: Some(100),
+                tenant: tenants.find("lab-a").cloned(),
+                ..Default::default()
+            },
+        )]);
+        let clients = ClientLimits::new(None, Some(200));
+        let client = clients.client(IpAddr::from([10, 0, 0, 1]));
+        let key = keys.find("one").unwrap();
+        let charge = |bytes| {
+            CLIENT.sync_scope(client.clone(), || CALLER.sync_scope(key.clone(), || charge_bytes(bytes)))
+        };
+
+        // The key's quota refuses what the tenant's and the address's allow
+        assert!(charge(80).is_ok());
+        assert!(charge(80).unwrap_err().message.contains("API key sensor"));
+        let usage = |name: &str| {
+            let quotas = keys.quotas();
+            quotas.iter().find(|(quota, _)| quota == name).unwrap().1.usage(SystemTime::now()).day_bytes
+        };
+        assert_eq!(usage("tenant:lab-a"), 80);
+        assert_eq!(usage("sensor"), 80);
+        // The address was refunded too, keeping 120 of its 200 bytes
+        assert!(charge(20).is_ok());
+        assert!(CLIENT.sync_scope(client, || charge_bytes(100)).is_ok());
+    }
+
+    #[test]
+    fn unserved_bytes_are_refunded() {
+        let keys = ApiKeys::new([(
+            "one".to_string(),
+            KeyLimits {
+                name: "sensor".to_string(),
+                bytes_per_sec: Some(100),
+                daily_bytes: Some(100),
+                ..Default::default()
+            },
+        )]);
+        let clients = ClientLimits::new(None, Some(100));
+        let client = clients.client(IpAddr::from([10, 0, 0, 1]));
+        let key = keys.find("one").unwrap();
+        let charge = |bytes| {
+            CLIENT.sync_scope(client.clone(), || CALLER.sync_scope(key.clone(), || charge_bytes(bytes)))
+        };
+        let refund = |bytes| {
+            CLIENT.sync_scope(client.clone(), || CALLER.sync_scope(key.clone(), || refund_bytes(bytes)))
+        };
+
+        // A failed draw leaves every limit and quota as it was
+        assert!(charge(100).is_ok());
+        refund(100);
+        assert_eq!(keys.quotas()[0].1.usage(SystemTime::now()).day_bytes, 0);
+        assert!(charge(100).is_ok());
+    }
+
+    #[tokio::test]
+    async fn limits_each_client_address() {
+        let limits = Arc::new(ClientLimits::new(Some(1), None));
+        let router = limit_clients(Router::new().route("/", get(|| async {})), limits.clone());
+        let request = |ip: &str| {
+            let addr: SocketAddr = (ip.parse::<IpAddr>().unwrap(), 40000).into();
+            Request::get("/").extension(ConnectInfo(addr)).body(Body::empty()).unwrap()
+        };
+
+        assert_eq!(router.clone().oneshot(request("10.0.0.1")).await.unwrap().status(), 200);
+        assert_eq!(router.clone().oneshot(request("10.0.0.1")).await.unwrap().status(), 429);
+        assert_eq!(router.clone().oneshot(request("10.0.0.2")).await.unwrap().status(), 200);
+
+        // Addresses in one /64 share a limit
+        assert_eq!(router.clone().oneshot(request("2001:db8::1")).await.unwrap().status(), 200);
+        assert_eq!(router.clone().oneshot(request("2001:db8::2")).await.unwrap().status(), 429);
+
+        // Guests over vsock are limited by context ID
+        let guest = |cid| Request::get("/").extension(VsockPeer(cid)).body(Body::empty()).unwrap();
+        assert_eq!(router.clone().oneshot(guest(3)).await.unwrap().status(), 200);
+        assert_eq!(router.clone().oneshot(guest(3)).await.unwrap().status(), 429);
+        assert_eq!(router.clone().oneshot(guest(4)).await.unwrap().status(), 200);
+
+        // New rates apply at once, and turning them off lets everyone through
+        limits.set(Some(2), None);
+        assert_eq!(router.clone().oneshot(request("10.0.0.1")).await.unwrap().status(), 200);
+        assert_eq!(router.clone().oneshot(request("10.0.0.1")).await.unwrap().status(), 200);
+        assert_eq!(router.clone().oneshot(request("10.0.0.1")).await.unwrap().status(), 429);
+        limits.set(None, None);
+        assert_eq!(router.oneshot(request("10.0.0.1")).await.unwrap().status(), 200);
+    }
+
+    #[test]
+    fn forgets_idle_clients() {
+        let limits = ClientLimits::new(Some(1000), None);
+        for host in 0..MAX_IDLE_CLIENTS as u32 {
+            limits.client(IpAddr::from(host.to_be_bytes()));
+        }
+        assert_eq!(limits.tracked(), MAX_IDLE_CLIENTS);
+
+        // Untouched buckets are full, so every earlier client is dropped
+        limits.client("192.0.2.1".parse::<IpAddr>().unwrap());
+        assert_eq!(limits.tracked(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! Holding API traffic until the server has warmed up
+//!
+//! Right after boot the buffer is empty and every request would be served
+//! by a slow direct device read. Until the buffer holds its prefill and the
+//! startup health tests have passed, entropy and data routes answer 503
+//! with a `Retry-After`, `/health` reports `starting` and systemd is not
+//! told the service is ready. Once ready, the server stays ready; later
+//! shortfalls are left to backpressure and the health tests.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::info;
+
+use super::{ApiError, AppState};
+use crate::health::HealthState;
+use crate::utils::{systemd, RingBuffer};
+
+/// How often the buffer is checked while warming up
+const PREFILL_POLL: Duration = Duration::from_millis(50);
+
+/// Default buffer fill percentage to reach before serving
+pub const DEFAULT_READY_PERCENT: u8 = 5;
+
+/// Whether the server has warmed up
+#[derive(Debug)]
+pub struct Readiness {
+    ready: AtomicBool,
+    /// Buffered bytes needed to become ready
+    prefill: usize,
+}
+
+impl Readiness {
+    /// Not ready until `prefill` bytes are buffered
+    pub fn new(prefill: usize) -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            prefill,
+        }
+    }
+
+    /// Ready from the start, for servers that need no warm-up
+    pub fn ready() -> Self {
+        Self {
+            ready: AtomicBool::new(true),
+            prefill: 0,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn prefill(&self) -> usize {
+        self.prefill
+    }
+
+    /// Mark ready once `buffer` holds the prefill and `health` has no
+    /// failure, telling systemd too
+    pub async fn wait(&self, buffer: &RingBuffer, health: &HealthState) {
+        // A resized buffer may no longer fit the prefill
+        while buffer.available() < self.prefill.min(buffer.capacity()) || health.failure().is_some() {
+            tokio::time::sleep(PREFILL_POLL).await;
+        }
+        self.ready.store(true, Ordering::Relaxed);
+        let status = format!("Serving with {} bytes buffered", buffer.available());
+        info!("{}", status);
+        if systemd::notify_ready(&status) {
+            info!("Reported readiness to systemd");
+        }
+    }
+}
+
+/// Watch for the server to become ready in the background
+pub fn start_watch(readiness: Arc<Readiness>, buffer: Arc<RingBuffer>, health: Arc<HealthState>) {
+    tokio::spawn(async move { readiness.wait(&buffer, &health).await });
+}
+
+/// Refuse requests until the server is ready
+pub async fn require_ready(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if state.readiness.is_ready() {
+        return Ok(next.run(request).await);
+    }
+    Err(ApiError::unavailable(format!(
+        "Starting up: {} of {} bytes buffered",
+        state.buffer.available(),
+        state.readiness.prefill()
+    ))
+    .with_retry_after(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::DEFAULT_MIN_ENTROPY;
+
+    #[tokio::test]
+    async fn ready_once_prefilled_and_healthy() {
+        let buffer = Arc::new(RingBuffer::new(1024));
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let readiness = Arc::new(Readiness::new(512));
+        start_watch(readiness.clone(), buffer.clone(), health);
+        tokio::time::sleep(PREFILL_POLL * 2).await;
+        assert!(!readiness.is_ready());
+
+        buffer.write(&[0; 512]);
+        tokio::time::sleep(PREFILL_POLL * 2).await;
+        assert!(readiness.is_ready());
+        assert!(Readiness::ready().is_ready());
+        assert!(systemd::activated_listener(systemd::PUBLIC_SOCKET).unwrap().is_none());
+    }
+}
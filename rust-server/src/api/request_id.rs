@@ -0,0 +1,56 @@
+//! Request IDs for log correlation
+//!
+//! Every request gets an `X-Request-Id`, kept from the client when it sends
+//! one and generated otherwise. The ID is recorded on the request's tracing
+//! span, so each log line written while handling the request carries it, and
+//! is echoed in the response.
+
+use axum::{
+    http::{HeaderName, Request},
+    Router,
+};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::Span;
+
+/// Header carrying the request ID both ways
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Assign request IDs and trace each request of `router` in a span holding one
+pub fn trace_requests(router: Router) -> Router {
+    router
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+}
+
+fn request_span<B>(request: &Request<B>) -> Span {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!("request", id, method = %request.method(), path = request.uri().path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn responses_carry_the_request_id() {
+        let router = trace_requests(Router::new().route("/", get(|| async {})));
+
+        let generated = router.clone().oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+        let id = generated.headers()[&REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok());
+
+        let request = Request::get("/").header(REQUEST_ID_HEADER, "upstream-42").body(Body::empty()).unwrap();
+        let kept = router.oneshot(request).await.unwrap();
+        assert_eq!(kept.headers()[&REQUEST_ID_HEADER], "upstream-42");
+    }
+}
@@ -0,0 +1,122 @@
+//! Roles granted to API keys and JWT bearer tokens
+//!
+//! Each role includes the ones before it: `reader` may draw entropy and read
+//! device, statistics and verification endpoints; `crypto` may also use the
+//! server's keys, e.g. to prove VRF outputs and open commitments; `admin`
+//! may also use the admin listener. Routes needing more than `reader` check
+//! the caller's role with [`require_role`]. Without API keys or JWT
+//! authentication there is no caller to check, and every route is open.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::fmt;
+
+use super::ApiError;
+
+/// What a caller may do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Granted to keys and tokens that do not name a role
+    #[default]
+    Reader,
+    Crypto,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Reader, Role::Crypto, Role::Admin];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Crypto => "crypto",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.name() == name)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Role of the authenticated caller, attached to the request
+///
+/// With both an API key and a token the lower of their roles applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Granted(pub Role);
+
+/// Attach `role` to `request`, keeping a lower one already granted
+pub(super) fn grant(request: &mut Request, role: Role) {
+    let granted = match request.extensions().get::<Granted>() {
+        Some(Granted(existing)) => role.min(*existing),
+        None => role,
+    };
+    request.extensions_mut().insert(Granted(granted));
+}
+
+/// Refuse callers granted less than `required`
+///
+/// Use as a route layer inside the authentication layers, which grant the
+/// role, e.g. `middleware::from_fn_with_state(Role::Crypto, require_role)`.
+pub async fn require_role(
+    State(required): State<Role>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    match request.extensions().get::<Granted>() {
+        Some(Granted(role)) if *role < required => {
+            Err(ApiError::forbidden(format!("Requires the {} role", required)))
+        }
+        _ => Ok(next.run(request).await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn status(granted: &[Role]) -> StatusCode {
+        let granted = granted.to_vec();
+        let router = Router::new()
+            .route("/vrf", get(|| async {}))
+            .route_layer(middleware::from_fn_with_state(Role::Crypto, require_role))
+            .layer(middleware::from_fn(move |mut request: Request, next: Next| {
+                for role in &granted {
+                    grant(&mut request, *role);
+                }
+                next.run(request)
+            }));
+        let response = router.oneshot(Request::get("/vrf").body(Body::empty()).unwrap()).await.unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn higher_roles_include_lower_ones() {
+        assert_eq!(status(&[]).await, StatusCode::OK);
+        assert_eq!(status(&[Role::Crypto]).await, StatusCode::OK);
+        assert_eq!(status(&[Role::Admin]).await, StatusCode::OK);
+        assert_eq!(status(&[Role::Reader]).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(&[Role::Admin, Role::Reader]).await, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn names() {
+        for role in Role::ALL {
+            assert_eq!(Role::from_name(role.name()), Some(role));
+        }
+        assert_eq!(Role::from_name("root"), None);
+    }
+}
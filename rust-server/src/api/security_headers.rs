@@ -0,0 +1,85 @@
+//! Security headers on every response
+//!
+//! Random bytes must never be served twice, so responses are marked
+//! `Cache-Control: no-store` for browsers, proxies and CDNs alike. Browsers
+//! are also told not to sniff content types, frame or run the responses,
+//! and, over HTTPS, to keep using HTTPS. Headers a handler set itself are
+//! left alone.
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{
+            CACHE_CONTROL, CONTENT_SECURITY_POLICY, PRAGMA, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+            X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+        HeaderName, HeaderValue,
+    },
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use std::sync::Arc;
+
+/// Headers added to every response
+const HEADERS: [(HeaderName, &str); 6] = [
+    (CACHE_CONTROL, "no-store"),
+    // For HTTP/1.0 caches, which ignore Cache-Control
+    (PRAGMA, "no-cache"),
+    (X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    (X_FRAME_OPTIONS, "DENY"),
+    (CONTENT_SECURITY_POLICY, "default-src 'none'; frame-ancestors 'none'"),
+    (REFERRER_POLICY, "no-referrer"),
+];
+
+/// Add the security headers to every response of `router`, with
+/// `Strict-Transport-Security` for `hsts_max_age` seconds when it is served
+/// over HTTPS
+pub fn secure_responses(router: Router, hsts_max_age: Option<u64>) -> Router {
+    let mut headers: Vec<_> =
+        HEADERS.into_iter().map(|(name, value)| (name, HeaderValue::from_static(value))).collect();
+    if let Some(max_age) = hsts_max_age {
+        let value = format!("max-age={}; includeSubDomains", max_age);
+        headers.push((STRICT_TRANSPORT_SECURITY, HeaderValue::from_str(&value).expect("digits are ASCII")));
+    }
+    router.layer(middleware::from_fn_with_state(Arc::new(headers), add_headers))
+}
+
+async fn add_headers(
+    State(headers): State<Arc<Vec<(HeaderName, HeaderValue)>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    for (name, value) in headers.iter() {
+        response.headers_mut().entry(name).or_insert_with(|| value.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn marks_responses_uncacheable() {
+        let router = Router::new()
+            .route("/random/bytes", get(|| async { "00" }))
+            .route("/cached", get(|| async { ([(CACHE_CONTROL, "max-age=60")], "") }));
+
+        let get = |path| Request::get(path).body(Body::empty()).unwrap();
+        let response = secure_responses(router.clone(), None).oneshot(get("/random/bytes")).await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+        assert_eq!(response.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+        let https = secure_responses(router, Some(63_072_000));
+        let response = https.clone().oneshot(get("/missing")).await.unwrap();
+        assert_eq!(response.headers()[STRICT_TRANSPORT_SECURITY], "max-age=63072000; includeSubDomains");
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+        let response = https.oneshot(get("/cached")).await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "max-age=60");
+    }
+}
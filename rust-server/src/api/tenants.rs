@@ -0,0 +1,226 @@
+//! Tenants sharing the hardware
+//!
+//! A tenant is a group of API keys with a buffer slice of its own, filled
+//! in the background from tested raw data in the main buffer, plus optional
+//! byte quotas shared by its keys. Its requests draw only from its slice,
+//! never from the main buffer or the conditioned pools, so one tenant's
+//! bulk usage cannot starve other tenants or callers without one. Refills
+//! take turns between tenants and leave the main buffer's interactive
+//! reserve alone.
+
+use serde::Serialize;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Notify;
+
+use super::quota::Quota;
+use crate::health::HealthState;
+use crate::utils::{pools::REFILL_CHUNK, RingBuffer, Reservation};
+
+/// One tenant's buffer slice, quota and counters
+pub struct Tenant {
+    name: String,
+    buffer: RingBuffer,
+    quota: Option<Arc<Quota>>,
+    /// Output bytes delivered to the tenant's keys
+    served: AtomicU64,
+    drained: Arc<Notify>,
+}
+
+impl Tenant {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reserved raw entropy
+    pub fn buffer(&self) -> &RingBuffer {
+        &self.buffer
+    }
+
+    /// Byte quota shared by the tenant's keys
+    pub fn quota(&self) -> Option<&Arc<Quota>> {
+        self.quota.as_ref()
+    }
+
+    /// Take `size` bytes of the tenant's slice
+    pub fn reserve(&self, size: usize) -> Option<Reservation<'_>> {
+        // Misses wake the filler too, as they show demand for the slice
+        self.drained.notify_one();
+        self.buffer.reserve(size)
+    }
+
+    /// Count `bytes` of output delivered to the tenant
+    pub fn record_served(&self, bytes: usize) {
+        self.served.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn served(&self) -> u64 {
+        self.served.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> TenantStatus {
+        TenantStatus {
+            name: self.name.clone(),
+            capacity: self.buffer.capacity(),
+            available: self.buffer.available(),
+            served_bytes: self.served(),
+        }
+    }
+}
+
+impl fmt::Debug for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tenant").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+/// Fill level and usage of a tenant, for `/stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantStatus {
+    pub name: String,
+    pub capacity: usize,
+    pub available: usize,
+    pub served_bytes: u64,
+}
+
+/// Slice size and quotas of one tenant
+#[derive(Debug, Clone, Default)]
+pub struct TenantLimits {
+    /// Capacity of the buffer slice in bytes
+    pub buffer_bytes: usize,
+    pub daily_bytes: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+/// All configured tenants
+#[derive(Default)]
+pub struct Tenants {
+    tenants: Vec<Arc<Tenant>>,
+    drained: Arc<Notify>,
+}
+
+impl Tenants {
+    pub fn new(tenants: impl IntoIterator<Item = (String, TenantLimits)>) -> Self {
+        let drained = Arc::new(Notify::new());
+        let tenants = tenants
+            .into_iter()
+            .map(|(name, limits)| {
+                let quota = (limits.daily_bytes.is_some() || limits.monthly_bytes.is_some())
+                    .then(|| Arc::new(Quota::new(limits.daily_bytes, limits.monthly_bytes)));
+                Arc::new(Tenant {
+                    name,
+                    buffer: RingBuffer::new(limits.buffer_bytes),
+                    quota,
+                    served: AtomicU64::new(0),
+                    drained: drained.clone(),
+                })
+            })
+            .collect();
+        Self { tenants, drained }
+    }
+
+    pub fn tenants(&self) -> &[Arc<Tenant>] {
+        &self.tenants
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Arc<Tenant>> {
+        self.tenants.iter().find(|tenant| tenant.name == name)
+    }
+
+    pub fn status(&self) -> Vec<TenantStatus> {
+        self.tenants.iter().map(|tenant| tenant.status()).collect()
+    }
+}
+
+/// Raw bytes to move into `tenant`'s slice in the next refill step
+fn wanted(tenant: &Tenant) -> usize {
+    let buffer = &tenant.buffer;
+    buffer.capacity().saturating_sub(buffer.available()).min(REFILL_CHUNK)
+}
+
+/// Keep the tenants' slices topped up from the raw buffer
+///
+/// Each pass moves at most one chunk into every slice, so a tenant that
+/// drains its slice quickly cannot take the refills of the others. Like
+/// bulk requests, refills leave `reserve` bytes buffered for interactive
+/// requests.
+pub fn start_filler(
+    buffer: Arc<RingBuffer>,
+    tenants: Arc<Tenants>,
+    health: Arc<HealthState>,
+    reserve: usize,
+) {
+    if tenants.tenants.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            let (mut filled, mut starved) = (false, false);
+            for tenant in &tenants.tenants {
+                let wanted = wanted(tenant);
+                if wanted == 0 || health.failure().is_some() {
+                    continue;
+                }
+                // Raw data in the buffer has already passed the continuous tests
+                let Some(raw) = buffer.read_above(wanted, reserve) else {
+                    starved = true;
+                    continue;
+                };
+                tenant.buffer.write(&raw);
+                filled = true;
+            }
+
+            if filled {
+                tokio::task::yield_now().await;
+            } else if starved {
+                // The reader is refilling the raw buffer
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            } else {
+                // Recovery from quarantine is picked up on the timeout
+                let drained = tenants.drained.notified();
+                let _ = tokio::time::timeout(tokio::time::Duration::from_secs(1), drained).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_are_separate_and_refilled_in_chunks() {
+        let limits = |buffer_bytes, daily_bytes| TenantLimits {
+            buffer_bytes,
+            daily_bytes,
+            monthly_bytes: None,
+        };
+        let tenants = Tenants::new([
+            ("lab-a".to_string(), limits(4 * REFILL_CHUNK, Some(100))),
+            ("lab-b".to_string(), limits(1024, None)),
+        ]);
+        let [a, b] = tenants.tenants() else { panic!("two tenants") };
+        assert_eq!(wanted(a), REFILL_CHUNK);
+        assert_eq!(wanted(b), 1024);
+        assert!(a.quota().is_some() && b.quota().is_none());
+
+        b.buffer().write(&[7; 1024]);
+        assert_eq!(wanted(b), 0);
+        assert!(a.reserve(16).is_none());
+        assert_eq!(b.reserve(16).unwrap().commit()[..], [7; 16]);
+        assert_eq!(wanted(b), 16);
+
+        // Uncommitted reservations go back to the slice
+        drop(b.reserve(64).unwrap());
+        assert_eq!(b.buffer().available(), 1008);
+
+        b.record_served(16);
+        assert_eq!(tenants.find("lab-b").unwrap().status().served_bytes, 16);
+        assert!(tenants.find("lab-c").is_none());
+    }
+}
@@ -0,0 +1,135 @@
+//! Deferring bulk requests while the devices fall behind
+//!
+//! Consumption from the buffer is measured from its traffic counters and
+//! compared with the devices' measured read rate. While consumption runs
+//! ahead and the buffer has drained below a set level, bulk requests are
+//! refused with 503 and a `Retry-After` for when the devices should have
+//! refilled what they asked for, so interactive requests keep being served
+//! from memory instead of everything slowing down together.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::device::rate;
+use crate::utils::RingBuffer;
+
+/// Weight of the newest sample in the moving average
+const SMOOTHING: f64 = 0.3;
+
+/// Shortest window measured as one consumption sample
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default buffer fill percentage below which bulk requests may be deferred
+pub const DEFAULT_THROTTLE_BELOW_PERCENT: u8 = 25;
+
+/// Consumption seen at the last sample
+#[derive(Debug)]
+struct Sample {
+    at: Instant,
+    bytes_out: u64,
+    bytes_per_sec: Option<f64>,
+}
+
+/// Decides which bulk requests to defer
+#[derive(Debug)]
+pub struct Throttle {
+    /// Fill percentage below which deferring starts, 0 when off
+    below_percent: u8,
+    sample: Mutex<Option<Sample>>,
+}
+
+impl Throttle {
+    pub fn new(below_percent: u8) -> Self {
+        Self {
+            below_percent,
+            sample: Mutex::new(None),
+        }
+    }
+
+    /// Smoothed consumption from `buffer` as of `now`
+    fn demand(&self, buffer: &RingBuffer, now: Instant) -> Option<f64> {
+        let bytes_out = buffer.traffic().snapshot().bytes_out;
+        let mut sample = self.sample.lock().unwrap();
+        let Some(last) = sample.as_mut() else {
+            *sample = Some(Sample {
+                at: now,
+                bytes_out,
+                bytes_per_sec: None,
+            });
+            return None;
+        };
+
+        let elapsed = now.saturating_duration_since(last.at);
+        if elapsed >= SAMPLE_INTERVAL {
+            let rate = bytes_out.saturating_sub(last.bytes_out) as f64 / elapsed.as_secs_f64();
+            last.bytes_per_sec = Some(match last.bytes_per_sec {
+                Some(average) => average + SMOOTHING * (rate - average),
+                None => rate,
+            });
+            last.at = now;
+            last.bytes_out = bytes_out;
+        }
+        last.bytes_per_sec
+    }
+
+    /// Seconds a bulk request for `size` bytes above `floor` should wait,
+    /// or None to serve it now
+    ///
+    /// `fill_rate` is the devices' read rate in bytes per second.
+    pub fn defer(
+        &self,
+        buffer: &RingBuffer,
+        fill_rate: Option<f64>,
+        size: usize,
+        floor: usize,
+        now: Instant,
+    ) -> Option<u64> {
+        if self.below_percent == 0 {
+            return None;
+        }
+        let demand = self.demand(buffer, now)?;
+        let fill_rate = fill_rate?;
+        let available = buffer.available();
+        let level = buffer.capacity() as f64 * f64::from(self.below_percent) / 100.0;
+        if demand <= fill_rate || available as f64 >= level {
+            return None;
+        }
+
+        // Time for the devices alone to make up the shortfall
+        let refill = rate::read_time((size + floor).saturating_sub(available), fill_rate);
+        Some(refill.as_secs_f64().ceil().max(1.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defers_bulk_requests_while_demand_outruns_the_devices() {
+        let buffer = RingBuffer::new(1 << 20);
+        let throttle = Throttle::new(25);
+        let start = Instant::now();
+        let fill_rate = Some(100_000.0);
+
+        // Nothing is known about demand at first
+        assert_eq!(throttle.defer(&buffer, fill_rate, 64 * 1024, 0, start), None);
+
+        // 200 kB/s drawn from a buffer the devices refill at 100 kB/s
+        buffer.write(&vec![0; 1 << 20]);
+        buffer.read(200_000).unwrap();
+        let later = start + Duration::from_secs(1);
+        assert_eq!(throttle.defer(&buffer, fill_rate, 64 * 1024, 0, later), None);
+        buffer.read(700_000).unwrap();
+        let later = later + Duration::from_secs(1);
+        assert_eq!(throttle.defer(&buffer, fill_rate, 400_000, 0, later), Some(3));
+        assert_eq!(throttle.defer(&buffer, None, 400_000, 0, later), None);
+
+        // Once the buffer is back above the level requests are served again
+        buffer.write(&vec![0; 500_000]);
+        assert_eq!(throttle.defer(&buffer, fill_rate, 400_000, 0, later), None);
+        assert_eq!(Throttle::new(0).defer(&buffer, fill_rate, 400_000, 0, later), None);
+    }
+}
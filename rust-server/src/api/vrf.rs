@@ -0,0 +1,147 @@
+//! Verifiable random function endpoints
+//!
+//! `/pubkey` also publishes the keys that sign attested outputs.
+
+use axum::{
+    extract::{Query, State},
+    middleware,
+    response::Json,
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ensure_healthy,
+    roles::{require_role, Role},
+    ApiError, ApiResponse, AppState,
+};
+use crate::signing::PublicKeyInfo;
+use crate::vrf::{self, PROOF_LEN, SUITE};
+
+/// Create VRF routes
+///
+/// Proving uses the server's key and needs the crypto role; the public key
+/// and verification are open to readers.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/vrf", get(prove))
+        .route_layer(middleware::from_fn_with_state(Role::Crypto, require_role))
+        .route("/pubkey", get(public_key))
+        .route("/vrf/verify", get(verify))
+}
+
+/// Largest alpha string accepted, in bytes
+const MAX_ALPHA_LEN: usize = 1024;
+
+#[derive(Debug, Serialize)]
+pub struct PublicKeyResponse {
+    pub suite: &'static str,
+    /// Hex VRF public key
+    pub public_key: String,
+    /// Current and retired keys of signed outputs, newest first
+    pub signing_keys: Vec<PublicKeyInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProveQuery {
+    /// Alpha as UTF-8 text
+    pub alpha: Option<String>,
+    /// Alpha as hex, for binary input
+    pub alpha_hex: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProveResponse {
+    pub suite: &'static str,
+    pub public_key: String,
+    /// Hex alpha string the proof covers
+    pub alpha: String,
+    pub proof: String,
+    /// Hex VRF output (beta)
+    pub output: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyQuery {
+    pub public_key: String,
+    pub alpha: Option<String>,
+    pub alpha_hex: Option<String>,
+    pub proof: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyResponse {
+    pub valid: bool,
+    /// Hex VRF output when the proof is valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+/// Alpha from either query form
+fn alpha(text: Option<String>, hex_text: Option<String>) -> Result<Vec<u8>, String> {
+    let alpha = match (text, hex_text) {
+        (Some(text), None) => text.into_bytes(),
+        (None, Some(hex_text)) => hex::decode(hex_text).map_err(|_| "alpha_hex must be hex".to_string())?,
+        (None, None) => Vec::new(),
+        (Some(_), Some(_)) => return Err("Give either alpha or alpha_hex".to_string()),
+    };
+    if alpha.len() > MAX_ALPHA_LEN {
+        return Err(format!("alpha must be at most {} bytes", MAX_ALPHA_LEN));
+    }
+    Ok(alpha)
+}
+
+/// Server VRF public key and signing keys
+async fn public_key(State(state): State<AppState>) -> Result<Json<ApiResponse<PublicKeyResponse>>, ApiError> {
+    Ok(Json(ApiResponse::success(PublicKeyResponse {
+        suite: SUITE,
+        public_key: hex::encode(state.vrf.get()?.public_key()),
+        signing_keys: state.signer.get()?.public_keys(),
+    })))
+}
+
+/// Prove an alpha string with the server key
+async fn prove(
+    Query(params): Query<ProveQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<ProveResponse>>, ApiError> {
+    let alpha = match alpha(params.alpha, params.alpha_hex) {
+        Ok(alpha) => alpha,
+        Err(e) => return Ok(Json(ApiResponse::error(e))),
+    };
+    // The key came from the device, so don't vouch for it while quarantined
+    ensure_healthy(&state)?;
+    let vrf = state.vrf.get()?;
+
+    let (proof, output) = vrf.prove(&alpha);
+    Ok(Json(ApiResponse::success(ProveResponse {
+        suite: SUITE,
+        public_key: hex::encode(vrf.public_key()),
+        alpha: hex::encode(alpha),
+        proof: hex::encode(proof),
+        output: hex::encode(output),
+    })))
+}
+
+/// Check a proof against any public key
+async fn verify(Query(params): Query<VerifyQuery>) -> Json<ApiResponse<VerifyResponse>> {
+    let alpha = match alpha(params.alpha, params.alpha_hex) {
+        Ok(alpha) => alpha,
+        Err(e) => return Json(ApiResponse::error(e)),
+    };
+    let public_key = hex::decode(&params.public_key).ok().and_then(|key| <[u8; 32]>::try_from(key).ok());
+    let proof = hex::decode(&params.proof).ok().and_then(|proof| <[u8; PROOF_LEN]>::try_from(proof).ok());
+    let (Some(public_key), Some(proof)) = (public_key, proof) else {
+        return Json(ApiResponse::error(format!(
+            "public_key must be 32 and proof {} hex-encoded bytes",
+            PROOF_LEN
+        )));
+    };
+
+    let output = vrf::verify(&public_key, &alpha, &proof);
+    Json(ApiResponse::success(VerifyResponse {
+        valid: output.is_some(),
+        output: output.map(hex::encode),
+    }))
+}
@@ -0,0 +1,283 @@
+//! drand-compatible randomness beacon
+//!
+//! A single-node chain in drand's `bls-unchained-g1-rfc9380` scheme, the
+//! one its quicknet uses: round `n` is a BLS12-381 signature on
+//! SHA-256 of `n` as a big-endian u64, with signatures on G1 and the public
+//! key on G2, and the round's randomness is the SHA-256 of its signature.
+//! The secret key is derived from device entropy, so rounds cannot be
+//! predicted without it, and anyone holding the public key can check them.
+
+use blst::{
+    min_sig::{PublicKey, SecretKey, Signature},
+    BLST_ERROR,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use crate::signing::{self, SigningError};
+
+/// drand scheme identifier
+pub const SCHEME: &str = "bls-unchained-g1-rfc9380";
+
+/// Beacon ID reported in the chain metadata and hashed into the chain hash
+pub const BEACON_ID: &str = "quantis";
+
+/// Hash-to-curve domain separation tag of the scheme
+const DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+/// Secret key length in bytes
+pub const SECRET_LEN: usize = 32;
+
+/// Compressed G2 public key length
+pub const PUBLIC_KEY_LEN: usize = 96;
+
+/// Compressed G1 signature length
+pub const SIGNATURE_LEN: usize = 48;
+
+/// Associated data of a sealed beacon key
+const KEY_CONTEXT: &[u8] = b"quantis/beacon-key/v1";
+
+/// Beacon signing key
+pub struct BeaconKey {
+    secret: [u8; SECRET_LEN],
+    key: SecretKey,
+    public: [u8; PUBLIC_KEY_LEN],
+}
+
+impl BeaconKey {
+    /// Derive the key pair from 32 bytes of key material (IETF KeyGen)
+    pub fn from_secret(secret: [u8; SECRET_LEN]) -> Self {
+        let key = SecretKey::key_gen(&secret, &[]).expect("32 bytes of key material");
+        let public = key.sk_to_pk().compress();
+        Self { secret, key, public }
+    }
+
+    /// Compressed public key
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.public
+    }
+
+    /// Load a key saved with `save` under the same passphrase
+    pub fn load(path: &Path, passphrase: &str) -> Result<Self, SigningError> {
+        let plaintext = signing::unseal(path, passphrase, KEY_CONTEXT)?;
+        let secret: Zeroizing<[u8; SECRET_LEN]> =
+            Zeroizing::new(plaintext.as_slice().try_into().map_err(|_| SigningError::Invalid {
+                path: path.to_path_buf(),
+                reason: "secret is not 32 bytes".to_string(),
+            })?);
+        Ok(Self::from_secret(*secret))
+    }
+
+    /// Save the key material sealed with `passphrase`, as the signing key
+    /// ring is, since it is enough to compute every future round
+    pub fn save(&self, path: &Path, passphrase: &str) -> Result<(), SigningError> {
+        signing::seal(path, passphrase, KEY_CONTEXT, &self.secret)
+    }
+
+    /// Compressed signature of `round`
+    pub fn sign(&self, round: u64) -> [u8; SIGNATURE_LEN] {
+        self.key.sign(&round_digest(round), DST, &[]).compress()
+    }
+}
+
+/// Message signed for `round`
+fn round_digest(round: u64) -> [u8; 32] {
+    Sha256::digest(round.to_be_bytes()).into()
+}
+
+/// Whether `signature` is the signature of `round` under `public_key`
+pub fn verify(public_key: &[u8], round: u64, signature: &[u8]) -> bool {
+    let public_key = PublicKey::uncompress(public_key);
+    let signature = Signature::uncompress(signature);
+    let (Ok(public_key), Ok(signature)) = (public_key, signature) else {
+        return false;
+    };
+    signature.verify(true, &round_digest(round), DST, &[], &public_key, true) == BLST_ERROR::BLST_SUCCESS
+}
+
+/// drand's chain hash for a chain with a non-default beacon ID
+///
+/// The scheme is not part of it, as quicknet's hash shows.
+fn chain_hash(
+    period: u64,
+    genesis_time: u64,
+    public_key: &[u8],
+    group_hash: &[u8],
+    beacon_id: &str,
+) -> [u8; 32] {
+    let mut hash = Sha256::new();
+    hash.update((period as u32).to_be_bytes());
+    hash.update((genesis_time as i64).to_be_bytes());
+    hash.update(public_key);
+    hash.update(group_hash);
+    hash.update(beacon_id);
+    hash.finalize().into()
+}
+
+/// Chain parameters in drand's `/info` format
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainInfo {
+    pub public_key: String,
+    /// Seconds between rounds
+    pub period: u64,
+    /// Unix time of round 1
+    pub genesis_time: u64,
+    /// Chain hash clients pin the chain by
+    pub hash: String,
+    #[serde(rename = "groupHash")]
+    pub group_hash: String,
+    #[serde(rename = "schemeID")]
+    pub scheme_id: &'static str,
+    pub metadata: ChainMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainMetadata {
+    #[serde(rename = "beaconID")]
+    pub beacon_id: &'static str,
+}
+
+/// One round in drand's `/public/{round}` format
+#[derive(Debug, Clone, Serialize)]
+pub struct Round {
+    pub round: u64,
+    /// Hex SHA-256 of the signature
+    pub randomness: String,
+    pub signature: String,
+}
+
+/// The chain: a key, a genesis time and a period
+pub struct Beacon {
+    key: BeaconKey,
+    genesis_time: u64,
+    period: u64,
+}
+
+impl Beacon {
+    pub fn new(key: BeaconKey, genesis_time: u64, period: u64) -> Self {
+        assert!(period > 0, "beacon period must be at least a second");
+        Self { key, genesis_time, period }
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// drand group hash; the single-node group is its public key
+    fn group_hash(&self) -> [u8; 32] {
+        Sha256::digest(self.key.public_key()).into()
+    }
+
+    /// Hash clients pin the chain by
+    pub fn chain_hash(&self) -> [u8; 32] {
+        chain_hash(
+            self.period,
+            self.genesis_time,
+            &self.key.public_key(),
+            &self.group_hash(),
+            BEACON_ID,
+        )
+    }
+
+    pub fn info(&self) -> ChainInfo {
+        ChainInfo {
+            public_key: hex::encode(self.key.public_key()),
+            period: self.period,
+            genesis_time: self.genesis_time,
+            hash: hex::encode(self.chain_hash()),
+            group_hash: hex::encode(self.group_hash()),
+            scheme_id: SCHEME,
+            metadata: ChainMetadata { beacon_id: BEACON_ID },
+        }
+    }
+
+    /// Latest round published at Unix time `now`, 0 before genesis
+    pub fn latest_round(&self, now: u64) -> u64 {
+        match now.checked_sub(self.genesis_time) {
+            Some(elapsed) => elapsed / self.period + 1,
+            None => 0,
+        }
+    }
+
+    /// Unix time at which `round` is published, saturating for rounds
+    /// beyond the end of time
+    pub fn round_time(&self, round: u64) -> u64 {
+        self.genesis_time.saturating_add(round.saturating_sub(1).saturating_mul(self.period))
+    }
+
+    /// Signature and randomness of `round`
+    pub fn round(&self, round: u64) -> Round {
+        let signature = self.key.sign(round);
+        Round {
+            round,
+            randomness: hex::encode(Sha256::digest(signature)),
+            signature: hex::encode(signature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beacon() -> Beacon {
+        Beacon::new(BeaconKey::from_secret([3; SECRET_LEN]), 1_700_000_000, 3)
+    }
+
+    #[test]
+    fn rounds_verify_under_the_public_key() {
+        let beacon = beacon();
+        let public_key = beacon.key.public_key();
+        let round = beacon.round(42);
+        let signature = hex::decode(&round.signature).unwrap();
+        assert_eq!(signature.len(), SIGNATURE_LEN);
+        assert!(verify(&public_key, 42, &signature));
+        assert!(!verify(&public_key, 43, &signature));
+        assert_eq!(round.randomness, hex::encode(Sha256::digest(&signature)));
+
+        // Deterministic, as drand clients expect of a round
+        assert_eq!(beacon.round(42).signature, round.signature);
+    }
+
+    #[test]
+    fn matches_the_quicknet_chain_hash() {
+        let public_key = hex::decode(
+            "83cf0f2896adee7eb8b5f01fcad3912212c437e0073e911fb90022d3e760183c\
+             8c4b450b6a0a6c3ac6a5776a2d1064510d1fec758c921cc22b0e17e63aaf4bcb\
+             5ed66304de9cf809bd274ca73bab4af5a6e9c76a4bc09e76eae8991ef5ece45a",
+        )
+        .unwrap();
+        let group_hash =
+            hex::decode("f477d5c89f21a17c863a7f937c6a6d15859414d2be09cd448d4279af331c5d3e").unwrap();
+        assert_eq!(
+            hex::encode(chain_hash(3, 1_692_803_367, &public_key, &group_hash, "quicknet")),
+            "52db9ba70e0cc0f6eaf7803dd07447a1f5477735fd3f661792ba94600c84e971"
+        );
+    }
+
+    #[test]
+    fn counts_rounds_from_genesis() {
+        let beacon = beacon();
+        assert_eq!(beacon.latest_round(1_699_999_999), 0);
+        assert_eq!(beacon.latest_round(1_700_000_000), 1);
+        assert_eq!(beacon.latest_round(1_700_000_005), 2);
+        assert_eq!(beacon.latest_round(1_700_000_006), 3);
+        assert_eq!(beacon.round_time(3), 1_700_000_006);
+        assert_eq!(beacon.round_time(u64::MAX), u64::MAX);
+        assert_eq!(beacon.round_time(u64::MAX / 3 + 1), u64::MAX);
+    }
+
+    #[test]
+    fn saved_key_is_encrypted() {
+        let path = std::env::temp_dir().join(format!("quantis-beacon-{}.key", std::process::id()));
+        let key = BeaconKey::from_secret([5; SECRET_LEN]);
+        key.save(&path, "correct horse").unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains(&hex::encode([5u8; SECRET_LEN])));
+        assert!(matches!(BeaconKey::load(&path, "wrong"), Err(SigningError::Decrypt { .. })));
+        assert_eq!(BeaconKey::load(&path, "correct horse").unwrap().public_key(), key.public_key());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,301 @@
+//! Commit-reveal draws
+//!
+//! A draw is published first as `SHA-256(nonce || value)` and revealed on
+//! a later call, so a third party can check the value was fixed before
+//! the outcome mattered. Records stay retrievable after the reveal.
+//!
+//! Draws are kept in memory and, given a file, also appended to it as they
+//! are committed and revealed, so they survive a restart. The file holds
+//! unrevealed values and is only readable by the server's user.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+use crate::health::unix_time;
+
+/// Nonce length in bytes
+pub const NONCE_LEN: usize = 32;
+
+/// Largest committed value in bytes
+pub const MAX_VALUE_LEN: usize = 1024;
+
+/// Records kept in memory before the oldest are dropped
+pub const MAX_COMMITMENTS: usize = 100_000;
+
+/// Public view of a draw; value and nonce appear once revealed
+#[derive(Debug, Clone, Serialize)]
+pub struct Commitment {
+    pub id: Uuid,
+    /// Hex SHA-256 over the nonce followed by the value
+    pub commitment: String,
+    /// Value length in bytes
+    pub count: usize,
+    /// Unix timestamp of the commitment
+    pub committed_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revealed_at: Option<u64>,
+    /// Hex value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Hex nonce
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+struct Draw {
+    value: Vec<u8>,
+    nonce: [u8; NONCE_LEN],
+    committed_at: u64,
+    revealed_at: Option<u64>,
+}
+
+impl Draw {
+    fn view(&self, id: Uuid) -> Commitment {
+        let revealed = self.revealed_at.is_some();
+        Commitment {
+            id,
+            commitment: hex::encode(digest(&self.nonce, &self.value)),
+            count: self.value.len(),
+            committed_at: self.committed_at,
+            revealed_at: self.revealed_at,
+            value: revealed.then(|| hex::encode(&self.value)),
+            nonce: revealed.then(|| hex::encode(self.nonce)),
+        }
+    }
+}
+
+/// One line of the store's file
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Entry {
+    Commit {
+        id: Uuid,
+        /// Hex value
+        value: String,
+        /// Hex nonce
+        nonce: String,
+        committed_at: u64,
+    },
+    Reveal {
+        id: Uuid,
+        revealed_at: u64,
+    },
+}
+
+#[derive(Default)]
+struct Draws {
+    by_id: HashMap<Uuid, Draw>,
+    order: VecDeque<Uuid>,
+    /// File every draw and reveal is appended to, if any
+    file: Option<File>,
+}
+
+impl Draws {
+    /// Add `draw`, dropping the oldest once full
+    fn insert(&mut self, id: Uuid, draw: Draw) {
+        if self.order.len() == MAX_COMMITMENTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_id.remove(&oldest);
+            }
+        }
+        self.order.push_back(id);
+        self.by_id.insert(id, draw);
+    }
+
+    /// Apply an entry read back from the file
+    fn replay(&mut self, entry: Entry) {
+        match entry {
+            Entry::Commit {
+                id,
+                value,
+                nonce,
+                committed_at,
+            } => {
+                let nonce = hex::decode(nonce).ok().and_then(|nonce| nonce.try_into().ok());
+                if let (Ok(value), Some(nonce)) = (hex::decode(value), nonce) {
+                    let draw = Draw {
+                        value,
+                        nonce,
+                        committed_at,
+                        revealed_at: None,
+                    };
+                    self.insert(id, draw);
+                }
+            }
+            Entry::Reveal { id, revealed_at } => {
+                if let Some(draw) = self.by_id.get_mut(&id) {
+                    draw.revealed_at.get_or_insert(revealed_at);
+                }
+            }
+        }
+    }
+}
+
+/// Append `entry` to `file`, when there is one
+fn append(file: &mut Option<File>, entry: &Entry) -> io::Result<()> {
+    let Some(file) = file else {
+        return Ok(());
+    };
+    let mut line = serde_json::to_vec(entry).expect("commitment entry serializes");
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+/// Bounded store of draws, optionally backed by an append-only file
+#[derive(Default)]
+pub struct CommitmentStore {
+    draws: Mutex<Draws>,
+}
+
+impl CommitmentStore {
+    /// Store kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store appending to `path`, starting with the draws recorded there
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut draws = Draws::default();
+        let mut torn = false;
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? > 0 {
+                // A last line cut short by a crash belongs to a draw that
+                // was never returned, so it is skipped
+                torn = !line.ends_with(b"\n");
+                if let (false, Ok(entry)) = (torn, serde_json::from_slice(&line)) {
+                    draws.replay(entry);
+                }
+                line.clear();
+            }
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        if torn {
+            file.write_all(b"\n")?;
+        }
+        draws.file = Some(file);
+        Ok(Self {
+            draws: Mutex::new(draws),
+        })
+    }
+
+    /// Commit to `value`, returning the public record once it is stored
+    pub fn commit(&self, value: Vec<u8>, nonce: [u8; NONCE_LEN]) -> io::Result<Commitment> {
+        let id = Uuid::new_v4();
+        let draw = Draw {
+            value,
+            nonce,
+            committed_at: unix_time(),
+            revealed_at: None,
+        };
+        let view = draw.view(id);
+        let entry = Entry::Commit {
+            id,
+            value: hex::encode(&draw.value),
+            nonce: hex::encode(draw.nonce),
+            committed_at: draw.committed_at,
+        };
+
+        let mut draws = self.draws.lock().unwrap();
+        append(&mut draws.file, &entry)?;
+        draws.insert(id, draw);
+        Ok(view)
+    }
+
+    /// Reveal a draw; revealing again returns the same record
+    pub fn reveal(&self, id: Uuid) -> io::Result<Option<Commitment>> {
+        let mut draws = self.draws.lock().unwrap();
+        let Draws { by_id, file, .. } = &mut *draws;
+        let Some(draw) = by_id.get_mut(&id) else {
+            return Ok(None);
+        };
+        if draw.revealed_at.is_none() {
+            let revealed_at = unix_time();
+            append(file, &Entry::Reveal { id, revealed_at })?;
+            draw.revealed_at = Some(revealed_at);
+        }
+        Ok(Some(draw.view(id)))
+    }
+
+    /// Look up a draw
+    pub fn get(&self, id: Uuid) -> Option<Commitment> {
+        self.draws.lock().unwrap().by_id.get(&id).map(|draw| draw.view(id))
+    }
+}
+
+/// Commitment digest over `nonce || value`
+pub fn digest(nonce: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(nonce);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_is_hidden_until_revealed() {
+        let store = CommitmentStore::new();
+        let committed = store.commit(vec![7; 16], [9; NONCE_LEN]).unwrap();
+        assert!(committed.value.is_none() && committed.nonce.is_none());
+        assert!(store.get(committed.id).unwrap().value.is_none());
+
+        let revealed = store.reveal(committed.id).unwrap().unwrap();
+        let value = hex::decode(revealed.value.unwrap()).unwrap();
+        let nonce = hex::decode(revealed.nonce.unwrap()).unwrap();
+        assert_eq!(hex::encode(digest(&nonce, &value)), committed.commitment);
+
+        // Later lookups keep the reveal
+        let again = store.get(committed.id).unwrap();
+        assert_eq!(again.revealed_at, revealed.revealed_at);
+        assert!(store.reveal(Uuid::new_v4()).unwrap().is_none());
+    }
+
+    #[test]
+    fn draws_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("quantis-commitments-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = CommitmentStore::open(&path).unwrap();
+        let revealed = store.commit(vec![1; 8], [2; NONCE_LEN]).unwrap();
+        let hidden = store.commit(vec![3; 8], [4; NONCE_LEN]).unwrap();
+        let revealed = store.reveal(revealed.id).unwrap().unwrap();
+        drop(store);
+
+        // A crash part way through a line leaves the draws before it intact
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"event":"commit","id":"#).unwrap();
+        drop(file);
+
+        let store = CommitmentStore::open(&path).unwrap();
+        let again = store.get(revealed.id).unwrap();
+        assert_eq!(again.revealed_at, revealed.revealed_at);
+        assert_eq!(again.value, revealed.value);
+        assert!(store.get(hidden.id).unwrap().value.is_none());
+        let opened = store.reveal(hidden.id).unwrap().unwrap();
+        assert_eq!(opened.commitment, hidden.commitment);
+        drop(store);
+
+        let store = CommitmentStore::open(&path).unwrap();
+        assert_eq!(store.get(hidden.id).unwrap().revealed_at, opened.revealed_at);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
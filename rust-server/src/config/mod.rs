@@ -0,0 +1,1367 @@
+//! Server configuration
+//!
+//! Settings start from built-in defaults and are overridden in turn by an
+//! optional TOML file, `QUANTIS_<SECTION>_<KEY>` environment variables and
+//! command-line flags.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+
+use crate::api::cors::CorsOrigins;
+use crate::api::ipfilter::IpFilter;
+use crate::api::jwt::JwtValidator;
+use crate::api::limits::{self, RequestLimits, RouteLimits, RouteTimeouts};
+use crate::api::ratelimit::{ApiKeys, ClientLimits, KeyLimits};
+use crate::api::roles::Role;
+use crate::api::tenants::{TenantLimits, Tenants};
+use crate::api::{readiness, throttle};
+use crate::device::{mix::MixMode, pool};
+use crate::drbg::fortuna;
+use crate::signing;
+use crate::utils::{self, egd, pools};
+
+pub mod reload;
+
+/// Prefix of environment variables that override file settings
+pub const ENV_PREFIX: &str = "QUANTIS_";
+
+/// Older variable names still honoured, and the settings they map to
+const ENV_ALIASES: [(&str, &str); 4] = [
+    ("BIND_ADDRESS", "server.listen"),
+    ("RUST_LOG", "server.log_level"),
+    ("BUFFER_SIZE", "buffer.size_mib"),
+    ("QUANTIS_ADMIN_TOKEN", "auth.admin_token"),
+];
+
+/// Complaint about more than one JWT key source
+const JWT_SOURCES: &str = "set only one of secret, public_key and jwks_url";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid config file {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    #[error("Invalid {key}: {reason}")]
+    Invalid { key: String, reason: String },
+}
+
+impl ConfigError {
+    fn invalid(key: &str, reason: impl ToString) -> Self {
+        Self::Invalid {
+            key: key.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// All file-configurable settings
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub buffer: BufferConfig,
+    pub devices: DeviceConfig,
+    pub limits: LimitsConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub access_log: AccessLogConfig,
+    pub dispense_log: DispenseLogConfig,
+    pub commitments: CommitmentsConfig,
+    pub ip_filter: IpFilterConfig,
+    pub http: HttpConfig,
+    pub admin: AdminConfig,
+    pub signing: SigningConfig,
+    pub beacon: BeaconConfig,
+    pub rngd: RngdConfig,
+    pub egd: EgdConfig,
+    pub virtio: VirtioConfig,
+    pub fifo: FifoConfig,
+    pub vsock: VsockConfig,
+    pub zmq: ZmqConfig,
+    pub capture: CaptureConfig,
+    pub fortuna: FortunaConfig,
+    /// Groups of API keys with buffer slices and quotas of their own
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the HTTP server binds to
+    pub listen: SocketAddr,
+    /// Origins allowed by CORS, `*` for any
+    pub cors_origins: Vec<String>,
+    /// Log level or `tracing` filter directives, e.g. `quantis_server=debug`
+    pub log_level: String,
+    pub log_format: LogFormat,
+    /// Seconds in-flight requests get to finish on shutdown
+    pub shutdown_timeout_secs: u64,
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            cors_origins: vec!["*".to_string()],
+            log_level: "info".to_string(),
+            log_format: LogFormat::default(),
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BufferConfig {
+    /// Entropy buffer size in MiB
+    pub size_mib: usize,
+    /// Buffered bytes held back for interactive requests
+    pub interactive_reserve: usize,
+    /// Longest direct device read served when the buffer is starved
+    pub max_read_wait_ms: u64,
+    /// Comma-separated pipelines kept pre-conditioned
+    pub pools: String,
+    /// Capacity in bytes of each pre-conditioned pool
+    pub pool_size: usize,
+    /// Fill percentage below which bulk requests are deferred while demand
+    /// outruns the devices, 0 to never defer them
+    pub throttle_below_percent: u8,
+    /// Fill percentage reached before API traffic is served, at least the
+    /// interactive reserve
+    pub ready_percent: u8,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            size_mib: utils::DEFAULT_BUFFER_MIB,
+            interactive_reserve: utils::DEFAULT_INTERACTIVE_RESERVE,
+            max_read_wait_ms: 250,
+            pools: pools::DEFAULT_POOLS.to_string(),
+            pool_size: pools::DEFAULT_POOL_SIZE,
+            throttle_below_percent: throttle::DEFAULT_THROTTLE_BELOW_PERCENT,
+            ready_percent: readiness::DEFAULT_READY_PERCENT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// How output of several devices is combined
+    pub mix: MixMode,
+    /// Serials of the devices to use, all attached devices if empty
+    pub serials: Vec<String>,
+    /// USB enumeration index of the only device to open at startup
+    pub index: Option<usize>,
+    /// Longest wait in milliseconds for a device another read is holding
+    pub lock_timeout_ms: u64,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            mix: MixMode::default(),
+            serials: Vec::new(),
+            index: None,
+            lock_timeout_ms: pool::DEFAULT_LOCK_TIMEOUT.as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Requests in flight across all routes
+    pub max_concurrency: usize,
+    /// Requests in flight for individual entropy routes
+    pub routes: BTreeMap<String, usize>,
+    /// Seconds a request may take before it is answered with 503
+    pub timeout_secs: u64,
+    /// Seconds for individual entropy routes, overriding `timeout_secs`
+    pub timeouts: BTreeMap<String, u64>,
+    /// Requests per second from one client address
+    pub ip_requests_per_sec: Option<u64>,
+    /// Output bytes per second to one client address
+    pub ip_bytes_per_sec: Option<u64>,
+    /// Largest `/random/bytes` count answered in one piece
+    pub max_bytes: usize,
+    /// Larger counts up to this are streamed in chunks, refused when unset
+    pub max_stream_bytes: Option<usize>,
+    /// Most integers from one `/random/int` request
+    pub max_integers: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: limits::DEFAULT_MAX_CONCURRENCY,
+            routes: BTreeMap::new(),
+            timeout_secs: limits::DEFAULT_REQUEST_TIMEOUT.as_secs(),
+            timeouts: BTreeMap::new(),
+            ip_requests_per_sec: None,
+            ip_bytes_per_sec: None,
+            max_bytes: limits::DEFAULT_MAX_BYTES,
+            max_stream_bytes: None,
+            max_integers: limits::DEFAULT_MAX_INTEGERS,
+        }
+    }
+}
+
+/// HTTP connection tuning, for HTTP/1.1 and HTTP/2 (h2c or over TLS)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Keep HTTP/1.1 connections open between requests
+    pub keep_alive: bool,
+    /// Seconds a client gets to send HTTP/1.1 request headers
+    pub header_read_timeout_secs: u64,
+    /// Seconds between HTTP/2 pings on idle connections, off when unset
+    pub keep_alive_interval_secs: Option<u64>,
+    /// Seconds to wait for a ping reply before closing the connection
+    pub keep_alive_timeout_secs: u64,
+    /// Requests in flight on one HTTP/2 connection
+    pub max_concurrent_streams: u32,
+    /// HTTP/2 flow control windows in KiB, sized adaptively when unset
+    pub stream_window_kib: Option<u32>,
+    pub connection_window_kib: Option<u32>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive: true,
+            header_read_timeout_secs: 30,
+            keep_alive_interval_secs: None,
+            keep_alive_timeout_secs: 20,
+            max_concurrent_streams: 200,
+            stream_window_kib: None,
+            connection_window_kib: None,
+        }
+    }
+}
+
+/// Listener for the admin endpoints, kept off the public one
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Address the admin API binds to, e.g. loopback or a management interface
+    pub listen: SocketAddr,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8081)),
+        }
+    }
+}
+
+/// Key signing attested outputs, ephemeral without a key file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    /// Encrypted key ring, created on first start
+    pub key_file: Option<PathBuf>,
+    /// Passphrase the key ring is encrypted with
+    pub passphrase: Option<String>,
+    /// Days after which the key is replaced automatically
+    pub rotate_days: Option<u64>,
+    /// Retired public keys still published for verification
+    pub keep_previous: usize,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            key_file: None,
+            passphrase: None,
+            rotate_days: None,
+            keep_previous: signing::DEFAULT_KEEP_PREVIOUS,
+        }
+    }
+}
+
+/// drand-compatible beacon under `/beacon`, off by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BeaconConfig {
+    pub enabled: bool,
+    /// Encrypted BLS secret key, created from device entropy if missing;
+    /// without it the chain changes on every start
+    pub key_file: Option<PathBuf>,
+    /// Passphrase the key file is encrypted with
+    pub passphrase: Option<String>,
+    /// Seconds between rounds
+    pub period_secs: u64,
+    /// Unix time of round 1, the start time when unset
+    pub genesis_time: Option<u64>,
+}
+
+impl Default for BeaconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: None,
+            passphrase: None,
+            period_secs: 3,
+            genesis_time: None,
+        }
+    }
+}
+
+/// Feeding the host kernel's entropy pool in place of rngd, off by default
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RngdConfig {
+    pub enabled: bool,
+    /// Random device the entropy is added through
+    pub device: PathBuf,
+    /// Conditioned bytes fed per second
+    pub bytes_per_sec: usize,
+    /// Entropy credited to the kernel per byte fed, 0 to 8 bits
+    pub credit_bits_per_byte: u8,
+}
+
+impl Default for RngdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: PathBuf::from("/dev/random"),
+            bytes_per_sec: 1024,
+            credit_bits_per_byte: 8,
+        }
+    }
+}
+
+/// Entropy Gathering Daemon socket for legacy clients, off without a path
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EgdConfig {
+    pub socket: Option<PathBuf>,
+    /// Permissions of the socket file, e.g. `0o660`
+    pub mode: u32,
+}
+
+impl Default for EgdConfig {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            mode: egd::DEFAULT_MODE,
+        }
+    }
+}
+
+/// vhost-user virtio-rng socket for guest VMs, off without a path
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtioConfig {
+    pub socket: Option<PathBuf>,
+    /// Permissions of the socket file, e.g. `0o660`
+    pub mode: u32,
+}
+
+impl Default for VirtioConfig {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            mode: 0o660,
+        }
+    }
+}
+
+/// Continuous output to a named pipe, off without a path
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FifoConfig {
+    /// FIFO written to, created if missing, or any other writable file
+    pub path: Option<PathBuf>,
+    /// Conditioned bytes written per second at most
+    pub bytes_per_sec: usize,
+    /// Permissions of a created FIFO, e.g. `0o640`
+    pub mode: u32,
+}
+
+impl Default for FifoConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            bytes_per_sec: 64 * 1024,
+            mode: 0o640,
+        }
+    }
+}
+
+/// The API over AF_VSOCK for guests on this host, off without a port
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VsockConfig {
+    pub port: Option<u32>,
+}
+
+/// ZeroMQ PUB socket for entropy and device status, off without an endpoint
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZmqConfig {
+    /// Endpoint bound, e.g. `tcp://0.0.0.0:5556` or `ipc:///run/quantis.zmq`
+    pub endpoint: Option<String>,
+    /// Conditioned bytes published per second at most
+    pub bytes_per_sec: usize,
+    /// Seconds between health summaries on the status topic
+    pub status_interval_secs: u64,
+}
+
+impl Default for ZmqConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            bytes_per_sec: 64 * 1024,
+            status_interval_secs: 10,
+        }
+    }
+}
+
+/// Recording of raw device output, and replay of a recording instead of
+/// the devices
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Capture file every read of the entropy reader is appended to
+    pub record: Option<PathBuf>,
+    /// Size in MiB at which recording stops
+    pub max_size_mib: u64,
+    /// Capture served in place of the attached devices, for testing only
+    pub replay: Option<PathBuf>,
+    /// Start the replay over at the end of the capture instead of failing
+    pub replay_loop: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            record: None,
+            max_size_mib: 1024,
+            replay: None,
+            replay_loop: false,
+        }
+    }
+}
+
+/// Fortuna accumulator served as `source=fortuna`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FortunaConfig {
+    pub enabled: bool,
+    /// Also feed CPU timing jitter
+    pub jitter: bool,
+    /// Milliseconds between feeding rounds
+    pub feed_interval_ms: u64,
+}
+
+impl Default for FortunaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            jitter: false,
+            feed_interval_ms: fortuna::DEFAULT_FEED_INTERVAL.as_millis() as u64,
+        }
+    }
+}
+
+/// Per-request record of entropy consumption, off without a path
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub path: Option<PathBuf>,
+    /// Size in MiB at which the file is rotated
+    pub max_size_mib: u64,
+    /// Rotated files kept
+    pub keep: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_size_mib: 100,
+            keep: 5,
+        }
+    }
+}
+
+/// One tenant's buffer slice and quotas
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    /// Name keys refer to, also the metrics label
+    pub name: String,
+    /// Raw entropy kept for the tenant alone
+    pub buffer_kib: usize,
+    /// Output bytes per UTC day across the tenant's keys
+    pub daily_bytes: Option<u64>,
+    /// Output bytes per UTC calendar month across the tenant's keys
+    pub monthly_bytes: Option<u64>,
+}
+
+/// Client networks served, as CIDR blocks or addresses
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpFilterConfig {
+    /// Only these are served, unless empty
+    pub allow: Vec<String>,
+    /// Never served, even if allowed
+    pub deny: Vec<String>,
+}
+
+/// Append-only record of every entropy delivery, off without a path
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DispenseLogConfig {
+    pub path: Option<PathBuf>,
+    /// Link each record to the one before it by SHA-256
+    pub hash_chain: bool,
+}
+
+impl Default for DispenseLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            hash_chain: true,
+        }
+    }
+}
+
+/// Commit-reveal draws
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommitmentsConfig {
+    /// Append-only file keeping draws across restarts, memory only when unset
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Bearer token for the admin endpoints, which are disabled when unset
+    pub admin_token: Option<String>,
+    pub jwt: JwtConfig,
+    /// Keys callers send as `X-API-Key`, required on entropy routes when any
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// Role of API keys and tokens that do not name one
+    pub default_role: Role,
+    /// JSON file keeping quota usage across restarts
+    pub quota_file: Option<PathBuf>,
+}
+
+/// One API key with its rate limits and quotas
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Name used in logs and errors
+    pub name: String,
+    pub key: String,
+    pub requests_per_sec: Option<u64>,
+    /// Output bytes per second across all entropy endpoints
+    pub bytes_per_sec: Option<u64>,
+    /// Output bytes per UTC day
+    pub daily_bytes: Option<u64>,
+    /// Output bytes per UTC calendar month
+    pub monthly_bytes: Option<u64>,
+    /// Request sizes in place of the `[limits]` ones
+    pub max_bytes: Option<usize>,
+    pub max_stream_bytes: Option<usize>,
+    pub max_integers: Option<usize>,
+    /// `reader`, `crypto` or `admin`, `auth.default_role` if unset
+    pub role: Option<Role>,
+    /// Name of the tenant the key belongs to
+    pub tenant: Option<String>,
+}
+
+/// JWT bearer authentication, on when a key source is set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// HS256 shared secret
+    pub secret: Option<String>,
+    /// PEM RSA public key for RS256 tokens
+    pub public_key: Option<PathBuf>,
+    /// JWKS document with RS256 keys, e.g. an OIDC provider's `jwks_uri`
+    pub jwks_url: Option<String>,
+    /// Required `iss` claim
+    pub issuer: Option<String>,
+    /// Required `aud` claim
+    pub audience: Option<String>,
+    /// Scope granting the admin endpoints, `quantis:admin` if unset
+    pub admin_scope: Option<String>,
+}
+
+impl JwtConfig {
+    fn sources(&self) -> usize {
+        [self.secret.is_some(), self.public_key.is_some(), self.jwks_url.is_some()]
+            .into_iter()
+            .filter(|set| *set)
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: Option<PathBuf>,
+    /// PEM private key
+    pub key: Option<PathBuf>,
+    /// PEM CA bundle; when set, clients must present a certificate it issued
+    pub client_ca: Option<PathBuf>,
+    /// Paths below `/api/v1` each client certificate common name may call;
+    /// any verified client may call anything when empty
+    pub clients: BTreeMap<String, Vec<String>>,
+    /// Seconds browsers keep to HTTPS after a response, 0 to send no HSTS
+    pub hsts_max_age_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: None,
+            key: None,
+            client_ca: None,
+            clients: BTreeMap::new(),
+            hsts_max_age_secs: 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Config {
+    /// Load `path` if given, then apply the process environment
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env(std::env::vars())?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    /// Override settings from `QUANTIS_<SECTION>_<KEY>` variables
+    ///
+    /// `QUANTIS_BUFFER_SIZE_MIB=64` sets `buffer.size_mib`. List values are
+    /// comma-separated and route limits are given as `path=limit`. Other
+    /// `QUANTIS_` variables are ignored.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        for (name, value) in vars {
+            let key = match ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+                Some((_, key)) => key.to_string(),
+                None => match name.strip_prefix(ENV_PREFIX).and_then(|key| key.split_once('_')) {
+                    Some((section, field)) => format!("{}.{}", section, field).to_lowercase(),
+                    None => continue,
+                },
+            };
+            self.set(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Set one setting from its string form, ignoring unknown keys
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value.trim().parse().map_err(|e| ConfigError::invalid(key, e))
+        }
+        fn list(value: &str) -> Vec<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        }
+        /// Octal permissions, with or without a `0o` prefix
+        fn parse_mode(key: &str, value: &str) -> Result<u32, ConfigError> {
+            let octal = value.trim().trim_start_matches("0o");
+            u32::from_str_radix(octal, 8).map_err(|e| ConfigError::invalid(key, e))
+        }
+        /// `path=value` entries, e.g. `/random/bytes=64`
+        fn path_values<T>(key: &str, value: &str) -> Result<BTreeMap<String, T>, ConfigError>
+        where
+            T: std::str::FromStr,
+            T::Err: std::fmt::Display,
+        {
+            list(value)
+                .iter()
+                .map(|spec| {
+                    let (path, value) = spec.split_once('=').ok_or_else(|| {
+                        ConfigError::invalid(key, format!("expected path=value, got {}", spec))
+                    })?;
+                    Ok((path.to_string(), parse(key, value)?))
+                })
+                .collect()
+        }
+
+        match key {
+            "server.listen" => self.server.listen = parse(key, value)?,
+            "server.cors_origins" => self.server.cors_origins = list(value),
+            "server.log_level" => self.server.log_level = value.to_string(),
+            "admin.listen" => self.admin.listen = parse(key, value)?,
+            "server.shutdown_timeout_secs" => self.server.shutdown_timeout_secs = parse(key, value)?,
+            "server.log_format" => {
+                self.server.log_format =
+                    LogFormat::from_str(value, true).map_err(|e| ConfigError::invalid(key, e))?
+            }
+            "buffer.size_mib" => self.buffer.size_mib = parse(key, value)?,
+            "buffer.interactive_reserve" => self.buffer.interactive_reserve = parse(key, value)?,
+            "buffer.max_read_wait_ms" => self.buffer.max_read_wait_ms = parse(key, value)?,
+            "buffer.pools" => self.buffer.pools = value.to_string(),
+            "buffer.pool_size" => self.buffer.pool_size = parse(key, value)?,
+            "buffer.throttle_below_percent" => self.buffer.throttle_below_percent = parse(key, value)?,
+            "buffer.ready_percent" => self.buffer.ready_percent = parse(key, value)?,
+            "devices.mix" => {
+                self.devices.mix =
+                    MixMode::from_str(value, true).map_err(|e| ConfigError::invalid(key, e))?
+            }
+            "devices.serials" => self.devices.serials = list(value),
+            "devices.index" => self.devices.index = Some(parse(key, value)?),
+            "devices.lock_timeout_ms" => self.devices.lock_timeout_ms = parse(key, value)?,
+            "limits.max_concurrency" => self.limits.max_concurrency = parse(key, value)?,
+            "limits.routes" => self.limits.routes = path_values(key, value)?,
+            "limits.timeout_secs" => self.limits.timeout_secs = parse(key, value)?,
+            "limits.timeouts" => self.limits.timeouts = path_values(key, value)?,
+            "limits.ip_requests_per_sec" => self.limits.ip_requests_per_sec = Some(parse(key, value)?),
+            "limits.ip_bytes_per_sec" => self.limits.ip_bytes_per_sec = Some(parse(key, value)?),
+            "limits.max_bytes" => self.limits.max_bytes = parse(key, value)?,
+            "limits.max_stream_bytes" => self.limits.max_stream_bytes = Some(parse(key, value)?),
+            "limits.max_integers" => self.limits.max_integers = parse(key, value)?,
+            "auth.admin_token" => {
+                self.auth.admin_token = Some(value.to_string()).filter(|token| !token.is_empty())
+            }
+            "auth.quota_file" => self.auth.quota_file = Some(PathBuf::from(value)),
+            "auth.default_role" => {
+                self.auth.default_role =
+                    Role::from_name(value).ok_or_else(|| ConfigError::invalid(key, "unknown role"))?
+            }
+            "auth.jwt_secret" => self.auth.jwt.secret = Some(value.to_string()),
+            "auth.jwt_public_key" => self.auth.jwt.public_key = Some(PathBuf::from(value)),
+            "auth.jwt_jwks_url" => self.auth.jwt.jwks_url = Some(value.to_string()),
+            "auth.jwt_issuer" => self.auth.jwt.issuer = Some(value.to_string()),
+            "auth.jwt_audience" => self.auth.jwt.audience = Some(value.to_string()),
+            "auth.jwt_admin_scope" => self.auth.jwt.admin_scope = Some(value.to_string()),
+            "http.keep_alive" => self.http.keep_alive = parse(key, value)?,
+            "http.header_read_timeout_secs" => self.http.header_read_timeout_secs = parse(key, value)?,
+            "http.keep_alive_interval_secs" => self.http.keep_alive_interval_secs = Some(parse(key, value)?),
+            "http.keep_alive_timeout_secs" => self.http.keep_alive_timeout_secs = parse(key, value)?,
+            "http.max_concurrent_streams" => self.http.max_concurrent_streams = parse(key, value)?,
+            "http.stream_window_kib" => self.http.stream_window_kib = Some(parse(key, value)?),
+            "http.connection_window_kib" => self.http.connection_window_kib = Some(parse(key, value)?),
+            "access_log.path" => self.access_log.path = Some(PathBuf::from(value)),
+            "access_log.max_size_mib" => self.access_log.max_size_mib = parse(key, value)?,
+            "access_log.keep" => self.access_log.keep = parse(key, value)?,
+            "ip_filter.allow" => self.ip_filter.allow = list(value),
+            "ip_filter.deny" => self.ip_filter.deny = list(value),
+            "dispense_log.path" => self.dispense_log.path = Some(PathBuf::from(value)),
+            "dispense_log.hash_chain" => self.dispense_log.hash_chain = parse(key, value)?,
+            "commitments.path" => self.commitments.path = Some(PathBuf::from(value)),
+            "signing.key_file" => self.signing.key_file = Some(PathBuf::from(value)),
+            "signing.passphrase" => {
+                self.signing.passphrase = Some(value.to_string()).filter(|passphrase| !passphrase.is_empty())
+            }
+            "signing.rotate_days" => self.signing.rotate_days = Some(parse(key, value)?),
+            "signing.keep_previous" => self.signing.keep_previous = parse(key, value)?,
+            "beacon.enabled" => self.beacon.enabled = parse(key, value)?,
+            "beacon.key_file" => self.beacon.key_file = Some(PathBuf::from(value)),
+            "beacon.passphrase" => {
+                self.beacon.passphrase = Some(value.to_string()).filter(|passphrase| !passphrase.is_empty())
+            }
+            "beacon.period_secs" => self.beacon.period_secs = parse(key, value)?,
+            "beacon.genesis_time" => self.beacon.genesis_time = Some(parse(key, value)?),
+            "rngd.enabled" => self.rngd.enabled = parse(key, value)?,
+            "rngd.device" => self.rngd.device = PathBuf::from(value),
+            "rngd.bytes_per_sec" => self.rngd.bytes_per_sec = parse(key, value)?,
+            "rngd.credit_bits_per_byte" => self.rngd.credit_bits_per_byte = parse(key, value)?,
+            "egd.socket" => self.egd.socket = Some(PathBuf::from(value)),
+            "egd.mode" => self.egd.mode = parse_mode(key, value)?,
+            "virtio.socket" => self.virtio.socket = Some(PathBuf::from(value)),
+            "virtio.mode" => self.virtio.mode = parse_mode(key, value)?,
+            "vsock.port" => self.vsock.port = Some(parse(key, value)?),
+            "fifo.path" => self.fifo.path = Some(PathBuf::from(value)),
+            "fifo.bytes_per_sec" => self.fifo.bytes_per_sec = parse(key, value)?,
+            "fifo.mode" => self.fifo.mode = parse_mode(key, value)?,
+            "zmq.endpoint" => self.zmq.endpoint = Some(value.to_string()),
+            "zmq.bytes_per_sec" => self.zmq.bytes_per_sec = parse(key, value)?,
+            "zmq.status_interval_secs" => self.zmq.status_interval_secs = parse(key, value)?,
+            "capture.record" => self.capture.record = Some(PathBuf::from(value)),
+            "capture.max_size_mib" => self.capture.max_size_mib = parse(key, value)?,
+            "capture.replay" => self.capture.replay = Some(PathBuf::from(value)),
+            "capture.replay_loop" => self.capture.replay_loop = parse(key, value)?,
+            "fortuna.enabled" => self.fortuna.enabled = parse(key, value)?,
+            "fortuna.jitter" => self.fortuna.jitter = parse(key, value)?,
+            "fortuna.feed_interval_ms" => self.fortuna.feed_interval_ms = parse(key, value)?,
+            "tls.cert" => self.tls.cert = Some(PathBuf::from(value)),
+            "tls.key" => self.tls.key = Some(PathBuf::from(value)),
+            "tls.client_ca" => self.tls.client_ca = Some(PathBuf::from(value)),
+            "tls.hsts_max_age_secs" => self.tls.hsts_max_age_secs = parse(key, value)?,
+            "tls.clients" => {
+                let mut clients = BTreeMap::<String, Vec<String>>::new();
+                for spec in list(value) {
+                    let (name, path) = spec.split_once('=').ok_or_else(|| {
+                        ConfigError::invalid(key, format!("expected name=path, got {}", spec))
+                    })?;
+                    clients.entry(name.to_string()).or_default().push(path.to_string());
+                }
+                self.tls.clients = clients;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Buffer size in bytes
+    pub fn buffer_bytes(&self) -> usize {
+        self.buffer.size_mib.saturating_mul(1024 * 1024)
+    }
+
+    /// Buffered bytes needed before API traffic is served
+    pub fn prefill_bytes(&self) -> usize {
+        let percent = self.buffer_bytes() / 100 * usize::from(self.buffer.ready_percent);
+        percent.max(self.buffer.interactive_reserve).min(self.buffer_bytes())
+    }
+
+    /// Per-route concurrency limits
+    pub fn route_limits(&self) -> Result<RouteLimits, ConfigError> {
+        RouteLimits::from_limits(self.limits.routes.clone())
+            .map_err(|e| ConfigError::invalid("limits.routes", e))
+    }
+
+    /// Per-route request timeouts
+    pub fn route_timeouts(&self) -> Result<RouteTimeouts, ConfigError> {
+        let routes =
+            self.limits.timeouts.iter().map(|(path, secs)| (path.clone(), Duration::from_secs(*secs)));
+        RouteTimeouts::new(Duration::from_secs(self.limits.timeout_secs), routes)
+            .map_err(|e| ConfigError::invalid("limits.timeouts", e))
+    }
+
+    /// Request sizes for callers without their own
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits {
+            max_bytes: self.limits.max_bytes,
+            max_stream_bytes: self.limits.max_stream_bytes,
+            max_integers: self.limits.max_integers,
+        }
+    }
+
+    /// Request sizes of an API key, None when it sets none of its own
+    fn key_request_limits(&self, key: &ApiKeyConfig) -> Option<RequestLimits> {
+        if key.max_bytes.is_none() && key.max_stream_bytes.is_none() && key.max_integers.is_none() {
+            return None;
+        }
+        let deployment = self.request_limits();
+        Some(RequestLimits {
+            max_bytes: key.max_bytes.unwrap_or(deployment.max_bytes),
+            max_stream_bytes: key.max_stream_bytes.or(deployment.max_stream_bytes),
+            max_integers: key.max_integers.unwrap_or(deployment.max_integers),
+        })
+    }
+
+    /// Validator for JWT bearer tokens, None unless a key source is set
+    pub fn jwt_validator(&self) -> Result<Option<JwtValidator>, ConfigError> {
+        let jwt = &self.auth.jwt;
+        let mut validator = match (&jwt.secret, &jwt.public_key, &jwt.jwks_url) {
+            (Some(secret), None, None) => JwtValidator::hs256(secret.as_bytes()),
+            (None, Some(path), None) => {
+                JwtValidator::rs256_pem(path).map_err(|e| ConfigError::invalid("auth.jwt.public_key", e))?
+            }
+            (None, None, Some(url)) => JwtValidator::jwks(url.clone()),
+            (None, None, None) => return Ok(None),
+            _ => return Err(ConfigError::invalid("auth.jwt", JWT_SOURCES)),
+        };
+        if let Some(issuer) = &jwt.issuer {
+            validator = validator.with_issuer(issuer);
+        }
+        if let Some(audience) = &jwt.audience {
+            validator = validator.with_audience(audience);
+        }
+        if let Some(scope) = &jwt.admin_scope {
+            validator = validator.with_admin_scope(scope.clone());
+        }
+        Ok(Some(validator.with_default_role(self.auth.default_role)))
+    }
+
+    /// Rate limits for each client address
+    pub fn client_limits(&self) -> ClientLimits {
+        ClientLimits::new(self.limits.ip_requests_per_sec, self.limits.ip_bytes_per_sec)
+    }
+
+    /// Tenants with their buffer slices and quotas
+    pub fn tenants(&self) -> Tenants {
+        Tenants::new(self.tenants.iter().map(|tenant| {
+            let limits = TenantLimits {
+                buffer_bytes: tenant.buffer_kib.saturating_mul(1024),
+                daily_bytes: tenant.daily_bytes,
+                monthly_bytes: tenant.monthly_bytes,
+            };
+            (tenant.name.clone(), limits)
+        }))
+    }
+
+    /// API keys with their rate limits and quotas, in `tenants`
+    pub fn api_keys(&self, tenants: &Tenants) -> ApiKeys {
+        ApiKeys::new(self.auth.api_keys.iter().map(|key| {
+            let limits = KeyLimits {
+                name: key.name.clone(),
+                requests_per_sec: key.requests_per_sec,
+                bytes_per_sec: key.bytes_per_sec,
+                daily_bytes: key.daily_bytes,
+                monthly_bytes: key.monthly_bytes,
+                request_limits: self.key_request_limits(key),
+                role: key.role.unwrap_or(self.auth.default_role),
+                tenant: key.tenant.as_deref().and_then(|name| tenants.find(name)).cloned(),
+            };
+            (key.key.clone(), limits)
+        }))
+    }
+
+    /// Check settings that deserialize fine but cannot be served
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        EnvFilter::try_new(&self.server.log_level)
+            .map_err(|e| ConfigError::invalid("server.log_level", e))?;
+        CorsOrigins::new(&self.server.cors_origins)
+            .map_err(|e| ConfigError::invalid("server.cors_origins", e))?;
+        IpFilter::new(&self.ip_filter.allow, &self.ip_filter.deny)
+            .map_err(|e| ConfigError::invalid("ip_filter", e))?;
+        let buffer_size = self.buffer_bytes();
+        if !(utils::MIN_BUFFER_SIZE..=utils::MAX_BUFFER_SIZE).contains(&buffer_size) {
+            return Err(ConfigError::invalid(
+                "buffer.size_mib",
+                format!("must be between 1 and {} MiB", utils::MAX_BUFFER_SIZE >> 20),
+            ));
+        }
+        if self.buffer.interactive_reserve >= buffer_size {
+            return Err(ConfigError::invalid(
+                "buffer.interactive_reserve",
+                "must be smaller than the buffer",
+            ));
+        }
+        if !(pools::MIN_POOL_SIZE..=utils::MAX_BUFFER_SIZE).contains(&self.buffer.pool_size) {
+            return Err(ConfigError::invalid(
+                "buffer.pool_size",
+                format!("must be between {} and {} bytes", pools::MIN_POOL_SIZE, utils::MAX_BUFFER_SIZE),
+            ));
+        }
+        if self.buffer.throttle_below_percent > 100 {
+            return Err(ConfigError::invalid("buffer.throttle_below_percent", "must be at most 100"));
+        }
+        if self.buffer.ready_percent > 100 {
+            return Err(ConfigError::invalid("buffer.ready_percent", "must be at most 100"));
+        }
+        if self.limits.max_concurrency == 0 {
+            return Err(ConfigError::invalid("limits.max_concurrency", "must be at least 1"));
+        }
+        if self.devices.index.is_some() && !self.devices.serials.is_empty() {
+            return Err(ConfigError::invalid("devices.index", "cannot be combined with devices.serials"));
+        }
+        self.route_limits()?;
+        self.route_timeouts()?;
+        if self.devices.lock_timeout_ms == 0 {
+            return Err(ConfigError::invalid("devices.lock_timeout_ms", "must be at least 1"));
+        }
+        if self.limits.ip_requests_per_sec == Some(0) || self.limits.ip_bytes_per_sec == Some(0) {
+            return Err(ConfigError::invalid("limits", "per-address limits must be at least 1"));
+        }
+        self.request_limits().check().map_err(|e| ConfigError::invalid("limits", e))?;
+        if self.http.max_concurrent_streams == 0 {
+            return Err(ConfigError::invalid("http.max_concurrent_streams", "must be at least 1"));
+        }
+        if self.http.header_read_timeout_secs == 0 || self.http.keep_alive_timeout_secs == 0 {
+            return Err(ConfigError::invalid("http", "timeouts must be at least 1 second"));
+        }
+        // HTTP/2 windows are at most 2^31 - 1 bytes
+        for window in [self.http.stream_window_kib, self.http.connection_window_kib].into_iter().flatten() {
+            if !(1..=2_097_151).contains(&window) {
+                return Err(ConfigError::invalid("http", "windows must be between 1 and 2097151 KiB"));
+            }
+        }
+        let (public, admin) = (self.server.listen, self.admin.listen);
+        let shared = public.ip() == admin.ip() || public.ip().is_unspecified() || admin.ip().is_unspecified();
+        if shared && public.port() == admin.port() {
+            return Err(ConfigError::invalid("admin.listen", "must differ from server.listen"));
+        }
+        if self.rngd.enabled && !cfg!(target_os = "linux") {
+            return Err(ConfigError::invalid("rngd.enabled", "the kernel entropy feed is Linux-only"));
+        }
+        if self.rngd.bytes_per_sec == 0 {
+            return Err(ConfigError::invalid("rngd.bytes_per_sec", "must be at least 1"));
+        }
+        if self.rngd.credit_bits_per_byte > 8 {
+            return Err(ConfigError::invalid("rngd.credit_bits_per_byte", "must be at most 8"));
+        }
+        let modes = [
+            ("egd.mode", self.egd.mode),
+            ("virtio.mode", self.virtio.mode),
+            ("fifo.mode", self.fifo.mode),
+        ];
+        for (key, mode) in modes {
+            if mode > 0o777 {
+                return Err(ConfigError::invalid(key, "must be a permission mode of at most 0o777"));
+            }
+        }
+        if self.fifo.bytes_per_sec == 0 {
+            return Err(ConfigError::invalid("fifo.bytes_per_sec", "must be at least 1"));
+        }
+        if self.egd.socket.is_some() && !cfg!(unix) {
+            return Err(ConfigError::invalid("egd.socket", "EGD sockets are unix-only"));
+        }
+        if self.fifo.path.is_some() && !cfg!(unix) {
+            return Err(ConfigError::invalid("fifo.path", "named pipes are unix-only"));
+        }
+        if self.virtio.socket.is_some() && !cfg!(target_os = "linux") {
+            return Err(ConfigError::invalid("virtio.socket", "vhost-user is Linux-only"));
+        }
+        if self.vsock.port.is_some() && !cfg!(target_os = "linux") {
+            return Err(ConfigError::invalid("vsock.port", "vsock is Linux-only"));
+        }
+        if self.zmq.endpoint.is_some() && !cfg!(feature = "zmq") {
+            return Err(ConfigError::invalid("zmq.endpoint", "built without the `zmq` feature"));
+        }
+        if self.zmq.bytes_per_sec == 0 {
+            return Err(ConfigError::invalid("zmq.bytes_per_sec", "must be at least 1"));
+        }
+        if self.zmq.status_interval_secs == 0 {
+            return Err(ConfigError::invalid("zmq.status_interval_secs", "must be at least 1"));
+        }
+        if self.capture.max_size_mib == 0 {
+            return Err(ConfigError::invalid("capture.max_size_mib", "must be at least 1"));
+        }
+        if self.capture.replay.is_some() && self.capture.replay == self.capture.record {
+            return Err(ConfigError::invalid("capture.record", "must differ from capture.replay"));
+        }
+        let selected = self.devices.index.is_some() || !self.devices.serials.is_empty();
+        if self.capture.replay.is_some() && selected {
+            return Err(ConfigError::invalid("capture.replay", "cannot be combined with a device selection"));
+        }
+        if self.fortuna.feed_interval_ms == 0 {
+            return Err(ConfigError::invalid("fortuna.feed_interval_ms", "must be at least 1"));
+        }
+        if self.access_log.max_size_mib == 0 {
+            return Err(ConfigError::invalid("access_log.max_size_mib", "must be at least 1"));
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            return Err(ConfigError::invalid("tls", "cert and key must be set together"));
+        }
+        if self.tls.client_ca.is_some() && self.tls.cert.is_none() {
+            return Err(ConfigError::invalid("tls.client_ca", "requires tls.cert and tls.key"));
+        }
+        let passphrase = self.signing.passphrase.as_deref().unwrap_or_default();
+        if self.signing.key_file.is_some() && passphrase.is_empty() {
+            return Err(ConfigError::invalid("signing.passphrase", "is required with signing.key_file"));
+        }
+        if self.signing.rotate_days == Some(0) {
+            return Err(ConfigError::invalid("signing.rotate_days", "must be at least 1"));
+        }
+        let passphrase = self.beacon.passphrase.as_deref().unwrap_or_default();
+        if self.beacon.key_file.is_some() && passphrase.is_empty() {
+            return Err(ConfigError::invalid("beacon.passphrase", "is required with beacon.key_file"));
+        }
+        if !(1..=u64::from(u32::MAX)).contains(&self.beacon.period_secs) {
+            return Err(ConfigError::invalid("beacon.period_secs", "must be at least 1"));
+        }
+        let mut tenants = std::collections::HashSet::new();
+        for tenant in &self.tenants {
+            if !tenants.insert(&tenant.name) {
+                return Err(ConfigError::invalid("tenants", format!("{} is listed twice", tenant.name)));
+            }
+            if !(pools::MIN_POOL_SIZE..=utils::MAX_BUFFER_SIZE).contains(&(tenant.buffer_kib.saturating_mul(1024))) {
+                return Err(ConfigError::invalid(
+                    "tenants",
+                    format!(
+                        "{} needs a buffer_kib between {} and {}",
+                        tenant.name,
+                        pools::MIN_POOL_SIZE / 1024,
+                        utils::MAX_BUFFER_SIZE / 1024
+                    ),
+                ));
+            }
+            if [tenant.daily_bytes, tenant.monthly_bytes].contains(&Some(0)) {
+                return Err(ConfigError::invalid("tenants", format!("{} has a zero quota", tenant.name)));
+            }
+        }
+        let mut names = std::collections::HashSet::new();
+        let mut keys = std::collections::HashSet::new();
+        for key in &self.auth.api_keys {
+            if let Some(tenant) = key.tenant.as_ref().filter(|tenant| !tenants.contains(tenant)) {
+                return Err(ConfigError::invalid(
+                    "auth.api_keys",
+                    format!("{} belongs to unknown tenant {}", key.name, tenant),
+                ));
+            }
+            if key.key.is_empty() || !keys.insert(&key.key) {
+                return Err(ConfigError::invalid("auth.api_keys", format!("{} needs a unique key", key.name)));
+            }
+            if !names.insert(&key.name) {
+                return Err(ConfigError::invalid("auth.api_keys", format!("{} is listed twice", key.name)));
+            }
+            let limits = [key.requests_per_sec, key.bytes_per_sec, key.daily_bytes, key.monthly_bytes];
+            if limits.contains(&Some(0)) {
+                return Err(ConfigError::invalid("auth.api_keys", format!("{} has a zero limit", key.name)));
+            }
+            if let Some(limits) = self.key_request_limits(key) {
+                limits
+                    .check()
+                    .map_err(|e| ConfigError::invalid("auth.api_keys", format!("{}: {}", key.name, e)))?;
+            }
+        }
+        if self.auth.default_role == Role::Admin {
+            return Err(ConfigError::invalid("auth.default_role", "admin must be granted key by key"));
+        }
+        if self.auth.jwt.sources() > 1 {
+            return Err(ConfigError::invalid("auth.jwt", JWT_SOURCES));
+        }
+        if !self.tls.clients.is_empty() && self.tls.client_ca.is_none() {
+            return Err(ConfigError::invalid("tls.clients", "requires tls.client_ca"));
+        }
+        if let Some(path) = self.tls.clients.values().flatten().find(|path| !path.starts_with('/')) {
+            return Err(ConfigError::invalid("tls.clients", format!("{} must start with /", path)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+        [server]
+        listen = "127.0.0.1:9000"
+        cors_origins = ["https://lab.example"]
+
+        [buffer]
+        size_mib = 64
+
+        [devices]
+        mix = "xor"
+        serials = ["0912A", "0912B"]
+
+        [limits.routes]
+        "/random/bytes" = 32
+    "#;
+
+    #[test]
+    fn example_file_is_valid() {
+        let example: Config = toml::from_str(include_str!("../../config.example.toml")).unwrap();
+        assert_eq!(example, Config::default());
+    }
+
+    #[test]
+    fn file_settings_override_defaults() {
+        let config: Config = toml::from_str(EXAMPLE).unwrap();
+        assert_eq!(config.server.listen, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(config.buffer.size_mib, 64);
+        assert_eq!(config.buffer.pool_size, pools::DEFAULT_POOL_SIZE);
+        assert_eq!(config.devices.mix, MixMode::Xor);
+        assert_eq!(config.route_limits().unwrap().get("/random/bytes"), Some(32));
+        assert!(config.validate().is_ok());
+
+        assert!(toml::from_str::<Config>("[buffer]\nsize = 64").is_err());
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let mut config: Config = toml::from_str(EXAMPLE).unwrap();
+        let vars = [
+            ("QUANTIS_BUFFER_SIZE_MIB", "128"),
+            ("QUANTIS_DEVICES_MIX", "hash"),
+            ("QUANTIS_LIMITS_ROUTES", "/random/int=4, /random/bytes=8"),
+            ("QUANTIS_LIMITS_TIMEOUTS", "/tests/sp800-22=120"),
+            ("QUANTIS_ADMIN_TOKEN", "secret"),
+            ("QUANTIS_TLS_CLIENTS", "sensor=/random, sensor=/stats, ops=/"),
+            ("QUANTIS_EGD_MODE", "0600"),
+            ("QUANTIS_UNRELATED", "ignored"),
+            ("PATH", "/usr/bin"),
+        ];
+        config
+            .apply_env(vars.map(|(name, value)| (name.to_string(), value.to_string())))
+            .unwrap();
+
+        assert_eq!(config.buffer.size_mib, 128);
+        assert_eq!(config.devices.mix, MixMode::Hash);
+        assert_eq!(config.limits.routes.get("/random/int"), Some(&4));
+        assert_eq!(config.route_timeouts().unwrap().get("/tests/sp800-22"), Duration::from_secs(120));
+        assert_eq!(config.auth.admin_token.as_deref(), Some("secret"));
+        assert_eq!(config.server.listen.port(), 9000);
+        assert_eq!(config.tls.clients["sensor"], ["/random", "/stats"]);
+        assert_eq!(config.egd.mode, 0o600);
+
+        let invalid = config.apply_env([("QUANTIS_BUFFER_POOL_SIZE".to_string(), "big".to_string())]);
+        assert!(matches!(invalid, Err(ConfigError::Invalid { key, .. }) if key == "buffer.pool_size"));
+    }
+
+    #[test]
+    fn rejects_unservable_settings() {
+        let mut config = Config::default();
+        config.buffer.interactive_reserve = config.buffer_bytes();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.limits.routes.insert("/stats".to_string(), 4);
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.tls.cert = Some(PathBuf::from("cert.pem"));
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.tls.clients.insert("sensor".to_string(), vec!["/random".to_string()]);
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.auth.jwt.secret = Some("shared".to_string());
+        config.auth.jwt.jwks_url = Some("https://idp.example/jwks".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.auth.default_role = Role::Admin;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.rngd.credit_bits_per_byte = 9;
+        assert!(config.validate().is_err());
+
+        let key = ApiKeyConfig {
+            name: "sensor".to_string(),
+            key: "secret".to_string(),
+            requests_per_sec: Some(10),
+            bytes_per_sec: None,
+            daily_bytes: None,
+            monthly_bytes: None,
+            max_bytes: None,
+            max_stream_bytes: None,
+            max_integers: None,
+            role: None,
+            tenant: None,
+        };
+        let mut config = Config::default();
+        config.auth.api_keys = vec![key.clone(), ApiKeyConfig { name: "other".to_string(), ..key.clone() }];
+        assert!(config.validate().is_err());
+        config.auth.api_keys = vec![ApiKeyConfig { daily_bytes: Some(0), ..key.clone() }];
+        assert!(config.validate().is_err());
+        // A key's stream ceiling must lie above its single-piece limit
+        config.limits.max_stream_bytes = Some(1 << 20);
+        config.auth.api_keys = vec![ApiKeyConfig { max_bytes: Some(1 << 20), ..key.clone() }];
+        assert!(config.validate().is_err());
+        config.auth.api_keys = vec![ApiKeyConfig { max_stream_bytes: Some(1 << 24), ..key.clone() }];
+        assert!(config.validate().is_ok());
+        assert_eq!(config.key_request_limits(&config.auth.api_keys[0]), Some(RequestLimits {
+            max_bytes: limits::DEFAULT_MAX_BYTES,
+            max_stream_bytes: Some(1 << 24),
+            max_integers: limits::DEFAULT_MAX_INTEGERS,
+        }));
+
+        // Keys may only join configured tenants, whose slices are bounded
+        let mut config = Config::default();
+        config.auth.api_keys = vec![ApiKeyConfig { tenant: Some("lab-a".to_string()), ..key }];
+        assert!(config.validate().is_err());
+        let tenant = TenantConfig {
+            name: "lab-a".to_string(),
+            buffer_kib: 1,
+            daily_bytes: None,
+            monthly_bytes: None,
+        };
+        config.tenants = vec![tenant.clone()];
+        assert!(config.validate().is_err());
+        config.tenants = vec![TenantConfig { buffer_kib: 4096, ..tenant }];
+        assert!(config.validate().is_ok());
+        let tenants = config.tenants();
+        assert_eq!(tenants.find("lab-a").unwrap().buffer().capacity(), 4 << 20);
+        assert_eq!(config.api_keys(&tenants).quotas().len(), 0);
+
+        let mut config = Config::default();
+        config.limits.max_integers = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.server.log_level = "quantis_server=loud".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.http.stream_window_kib = Some(4_194_304);
+        assert!(config.validate().is_err());
+        config.http.stream_window_kib = Some(1024);
+        config.http.max_concurrent_streams = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.admin.listen = SocketAddr::from(([127, 0, 0, 1], 8080));
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.signing.key_file = Some(PathBuf::from("signing.json"));
+        assert!(config.validate().is_err());
+        config.signing.passphrase = Some("correct horse".to_string());
+        assert!(config.validate().is_ok());
+        config.beacon.key_file = Some(PathBuf::from("beacon.key"));
+        assert!(config.validate().is_err());
+        config.beacon.passphrase = Some("battery staple".to_string());
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.devices.index = Some(0);
+        config.devices.serials = vec!["QRNG-1".to_string()];
+        assert!(config.validate().is_err());
+        config.devices.index = None;
+        config.capture.replay = Some(PathBuf::from("capture.jsonl"));
+        assert!(config.validate().is_err());
+        config.devices.serials.clear();
+        assert!(config.validate().is_ok());
+        config.capture.record = config.capture.replay.clone();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.fortuna.feed_interval_ms = 0;
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Applying a changed configuration without a restart
+//!
+//! API keys, per-address rate limits, network allow and deny lists, CORS
+//! origins and the log level are swapped in place, so the buffered entropy and open connections survive.
+//! Other changed sections are reported as needing a restart.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+use super::Config;
+use crate::api::{
+    cors::CorsOrigins,
+    ipfilter::IpFilter,
+    ratelimit::{ApiKeys, ClientLimits},
+    tenants::Tenants,
+};
+
+/// Reads the configuration again, the same way as at startup
+pub type LoadConfig = Box<dyn Fn() -> Result<Config> + Send + Sync>;
+
+/// Installs a new filter in the log subscriber
+pub type SetLogFilter = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+/// What a reload changed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReloadOutcome {
+    /// Sections that changed but only take effect on restart
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Settings the server reads while serving, shared with the reloader
+pub struct LiveSettings {
+    pub api_keys: Arc<ApiKeys>,
+    pub client_limits: Arc<ClientLimits>,
+    pub cors: Arc<CorsOrigins>,
+    pub ip_filter: Arc<IpFilter>,
+    /// Tenants keys are assigned to, fixed until restart
+    pub tenants: Arc<Tenants>,
+}
+
+/// Handles on the settings that can change while serving
+pub struct ConfigReloader {
+    load: LoadConfig,
+    set_log_filter: SetLogFilter,
+    live: LiveSettings,
+    /// Configuration as last applied
+    current: Mutex<Config>,
+}
+
+impl ConfigReloader {
+    pub fn new(config: Config, load: LoadConfig, set_log_filter: SetLogFilter, live: LiveSettings) -> Self {
+        Self {
+            load,
+            set_log_filter,
+            live,
+            current: Mutex::new(config),
+        }
+    }
+
+    /// Load the configuration and apply what can be applied live
+    ///
+    /// Nothing changes if the new configuration is invalid.
+    pub fn reload(&self) -> Result<ReloadOutcome> {
+        let config = (self.load)()?;
+        let mut current = self.current.lock().unwrap();
+
+        (self.set_log_filter)(EnvFilter::try_new(&config.server.log_level)?)?;
+        let live = &self.live;
+        live.cors.set(&config.server.cors_origins).map_err(anyhow::Error::msg)?;
+        live.ip_filter.set(&config.ip_filter.allow, &config.ip_filter.deny).map_err(anyhow::Error::msg)?;
+        live.client_limits.set(config.limits.ip_requests_per_sec, config.limits.ip_bytes_per_sec);
+        live.api_keys.replace(config.api_keys(&live.tenants));
+        info!(
+            "Configuration reloaded: {} API keys, log level {}",
+            config.auth.api_keys.len(),
+            config.server.log_level
+        );
+
+        let outcome = ReloadOutcome {
+            restart_required: restart_required(&current, &config),
+        };
+        if !outcome.restart_required.is_empty() {
+            warn!("Changes to {} take effect on restart", outcome.restart_required.join(", "));
+        }
+        *current = config;
+        Ok(outcome)
+    }
+}
+
+/// Sections of `new` that differ from `old` in settings not applied live
+fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut new = new.clone();
+    new.server.log_level.clone_from(&old.server.log_level);
+    new.server.cors_origins.clone_from(&old.server.cors_origins);
+    new.limits.ip_requests_per_sec = old.limits.ip_requests_per_sec;
+    new.limits.ip_bytes_per_sec = old.limits.ip_bytes_per_sec;
+    new.auth.api_keys.clone_from(&old.auth.api_keys);
+    new.ip_filter.clone_from(&old.ip_filter);
+
+    [
+        ("server", old.server != new.server),
+        ("buffer", old.buffer != new.buffer),
+        ("devices", old.devices != new.devices),
+        ("limits", old.limits != new.limits),
+        ("auth", old.auth != new.auth),
+        ("tls", old.tls != new.tls),
+        ("access_log", old.access_log != new.access_log),
+        ("dispense_log", old.dispense_log != new.dispense_log),
+        ("commitments", old.commitments != new.commitments),
+        ("http", old.http != new.http),
+        ("admin", old.admin != new.admin),
+        ("signing", old.signing != new.signing),
+        ("tenants", old.tenants != new.tenants),
+    ]
+    .into_iter()
+    .filter_map(|(section, changed)| changed.then_some(section))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+
+    #[test]
+    fn applies_live_settings_and_reports_the_rest() {
+        let next = Arc::new(Mutex::new(Config::default()));
+        let loaded = next.clone();
+        let reloader = ConfigReloader::new(
+            Config::default(),
+            Box::new(move || Ok(loaded.lock().unwrap().clone())),
+            Box::new(|_| Ok(())),
+            LiveSettings {
+                api_keys: Arc::new(ApiKeys::default()),
+                client_limits: Arc::new(ClientLimits::new(None, None)),
+                cors: Arc::new(CorsOrigins::new(&["*".to_string()]).unwrap()),
+                ip_filter: Arc::new(IpFilter::default()),
+                tenants: Arc::new(Tenants::default()),
+            },
+        );
+
+        let mut config = Config::default();
+        config.server.log_level = "debug".to_string();
+        config.limits.ip_requests_per_sec = Some(10);
+        config.ip_filter.deny = vec!["192.0.2.0/24".to_string()];
+        config.auth.api_keys = vec![ApiKeyConfig {
+            name: "sensor".to_string(),
+            key: "secret".to_string(),
+            requests_per_sec: None,
+            bytes_per_sec: None,
+            daily_bytes: None,
+            monthly_bytes: None,
+            max_bytes: None,
+            max_stream_bytes: None,
+            max_integers: None,
+            role: None,
+            tenant: None,
+        }];
+        *next.lock().unwrap() = config.clone();
+        assert_eq!(reloader.reload().unwrap(), ReloadOutcome::default());
+        assert!(reloader.live.api_keys.find("secret").is_some());
+        assert!(reloader.live.client_limits.is_enabled());
+        assert!(!reloader.live.ip_filter.allows("192.0.2.1".parse().unwrap()));
+
+        config.buffer.size_mib += 1;
+        config.tls.cert = Some("cert.pem".into());
+        *next.lock().unwrap() = config;
+        assert_eq!(reloader.reload().unwrap().restart_required, ["buffer", "tls"]);
+    }
+}
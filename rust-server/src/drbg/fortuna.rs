@@ -0,0 +1,268 @@
+//! Fortuna accumulator mixing the device with the OS RNG and CPU jitter
+//!
+//! Ferguson and Schneier's design: events from every source are spread
+//! round-robin over 32 SHA-256 pools, and the AES-256 counter-mode generator
+//! reseeds from pool 0 every time, pool 1 every second time and pool `i`
+//! every `2^i`-th time. Output stays unpredictable while any one source is
+//! sound, even if the others are broken or controlled by an attacker.
+//!
+//! Nothing is generated until device entropy has reached the generator.
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes256,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use zeroize::Zeroizing;
+
+use super::jitter;
+use crate::utils::conditioned::ConditionedSource;
+
+/// Entropy pools
+pub const POOLS: usize = 32;
+
+/// Bytes pool 0 must collect before a reseed
+pub const MIN_POOL_SIZE: usize = 64;
+
+/// Largest event, in bytes
+pub const MAX_EVENT: usize = 32;
+
+/// Shortest time between reseeds
+const RESEED_SPACING: Duration = Duration::from_millis(100);
+
+/// Output between generator rekeys, in bytes
+const MAX_REQUEST: usize = 1 << 20;
+
+/// Default pause between feeder rounds
+pub const DEFAULT_FEED_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where an event came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventSource {
+    Device,
+    Os,
+    Jitter,
+}
+
+impl EventSource {
+    const ALL: usize = 3;
+
+    fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+/// AES-256 in counter mode, rekeyed after every request
+struct Generator {
+    key: [u8; 32],
+    counter: u128,
+}
+
+impl Generator {
+    fn reseed(&mut self, seed: &[u8]) {
+        let inner = Sha256::new().chain_update(self.key).chain_update(seed).finalize();
+        self.key = Sha256::digest(inner).into();
+        self.counter = self.counter.wrapping_add(1);
+    }
+
+    fn blocks(&mut self, output: &mut [u8]) {
+        let cipher = Aes256::new(GenericArray::from_slice(&self.key));
+        for chunk in output.chunks_mut(16) {
+            let mut block = GenericArray::from(self.counter.to_le_bytes());
+            cipher.encrypt_block(&mut block);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            self.counter = self.counter.wrapping_add(1);
+        }
+    }
+
+    fn generate(&mut self, output: &mut [u8]) {
+        for chunk in output.chunks_mut(MAX_REQUEST) {
+            self.blocks(chunk);
+            // A fresh key keeps earlier output safe if the state leaks
+            let mut key = [0u8; 32];
+            self.blocks(&mut key);
+            self.key = key;
+        }
+    }
+}
+
+/// Pools drained by reseed number `reseed` (counting from 1)
+fn pools_for(reseed: u64) -> usize {
+    (reseed.trailing_zeros() as usize + 1).min(POOLS)
+}
+
+struct Accumulator {
+    generator: Generator,
+    pools: Vec<Sha256>,
+    /// Bytes added to pool 0 since the last reseed
+    pool0_len: usize,
+    /// Whether pool 0 holds a device event, needed before the first reseed
+    pool0_device: bool,
+    next_pool: [usize; EventSource::ALL],
+    reseeds: u64,
+    last_reseed: Option<Instant>,
+}
+
+impl Accumulator {
+    fn reseed_due(&self, now: Instant) -> bool {
+        self.pool0_len >= MIN_POOL_SIZE
+            && (self.reseeds > 0 || self.pool0_device)
+            && self.last_reseed.is_none_or(|at| now.duration_since(at) >= RESEED_SPACING)
+    }
+
+    fn reseed(&mut self, now: Instant) {
+        self.reseeds += 1;
+        let mut seed = Zeroizing::new(Vec::with_capacity(POOLS * 32));
+        for pool in &mut self.pools[..pools_for(self.reseeds)] {
+            seed.extend_from_slice(&Sha256::digest(pool.finalize_reset()));
+        }
+        self.generator.reseed(&seed);
+        self.pool0_len = 0;
+        self.pool0_device = false;
+        self.last_reseed = Some(now);
+    }
+}
+
+/// Shared Fortuna accumulator and generator
+pub struct Fortuna {
+    state: Mutex<Accumulator>,
+}
+
+impl Default for Fortuna {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fortuna {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(Accumulator {
+                generator: Generator { key: [0; 32], counter: 0 },
+                pools: vec![Sha256::new(); POOLS],
+                pool0_len: 0,
+                pool0_device: false,
+                next_pool: [0; EventSource::ALL],
+                reseeds: 0,
+                last_reseed: None,
+            }),
+        }
+    }
+
+    /// Add an event of 1 to `MAX_EVENT` bytes to the source's next pool
+    pub fn add_event(&self, source: EventSource, data: &[u8]) {
+        assert!((1..=MAX_EVENT).contains(&data.len()), "Fortuna events are 1 to 32 bytes");
+        let mut state = self.state.lock().unwrap();
+        let pool = state.next_pool[source as usize];
+        state.pools[pool].update([source.id(), data.len() as u8]);
+        state.pools[pool].update(data);
+        if pool == 0 {
+            state.pool0_len += 2 + data.len();
+            state.pool0_device |= source == EventSource::Device;
+        }
+        state.next_pool[source as usize] = (pool + 1) % POOLS;
+    }
+
+    /// Generator reseeds so far
+    pub fn reseeds(&self) -> u64 {
+        self.state.lock().unwrap().reseeds
+    }
+
+    /// `count` bytes, reseeding first if pool 0 is full enough, or None
+    /// before the first reseed
+    pub fn generate(&self, count: usize) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if state.reseed_due(now) {
+            state.reseed(now);
+        }
+        if state.reseeds == 0 {
+            return None;
+        }
+        let mut output = vec![0u8; count];
+        state.generator.generate(&mut output);
+        Some(output)
+    }
+}
+
+/// Feed `fortuna` every `interval` with conditioned device output, the OS
+/// RNG and, if `jitter`, CPU timing jitter
+pub fn start_feeder(fortuna: Arc<Fortuna>, device: ConditionedSource, jitter: bool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            // Empty while the buffer is drained or a health test failed
+            let conditioned = device.read(MAX_EVENT);
+            if !conditioned.is_empty() {
+                fortuna.add_event(EventSource::Device, &conditioned);
+            }
+            let mut os = Zeroizing::new([0u8; MAX_EVENT]);
+            if getrandom::getrandom(&mut *os).is_ok() {
+                fortuna.add_event(EventSource::Os, &*os);
+            }
+            if jitter {
+                fortuna.add_event(EventSource::Jitter, &jitter::sample());
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Give every source's next `rounds` pools an event
+    fn feed(fortuna: &Fortuna, rounds: usize) {
+        for round in 0..rounds {
+            fortuna.add_event(EventSource::Device, &[round as u8; MAX_EVENT]);
+            fortuna.add_event(EventSource::Os, &[!(round as u8); MAX_EVENT]);
+        }
+    }
+
+    #[test]
+    fn waits_for_device_entropy() {
+        let fortuna = Fortuna::new();
+        for _ in 0..4 {
+            fortuna.add_event(EventSource::Os, &[1; MAX_EVENT]);
+            fortuna.add_event(EventSource::Jitter, &jitter::sample());
+        }
+        assert!(fortuna.generate(32).is_none());
+
+        feed(&fortuna, POOLS);
+        let first = fortuna.generate(64).unwrap();
+        assert_eq!(fortuna.reseeds(), 1);
+        // Rekeyed after each request, so the stream never repeats
+        assert_ne!(fortuna.generate(64).unwrap(), first);
+        assert_eq!(fortuna.reseeds(), 1);
+    }
+
+    #[test]
+    fn deeper_pools_reseed_less_often() {
+        assert_eq!(pools_for(1), 1);
+        assert_eq!(pools_for(2), 2);
+        assert_eq!(pools_for(6), 2);
+        assert_eq!(pools_for(8), 4);
+        assert_eq!(pools_for(1 << 40), POOLS);
+    }
+
+    #[test]
+    fn generator_output_depends_on_the_seed() {
+        let (mut a, mut b) = (Generator { key: [0; 32], counter: 0 }, Generator { key: [0; 32], counter: 0 });
+        a.reseed(b"device");
+        b.reseed(b"device");
+        let (mut out_a, mut out_b) = ([0u8; 48], [0u8; 48]);
+        a.generate(&mut out_a);
+        b.generate(&mut out_b);
+        assert_eq!(out_a, out_b);
+
+        b.reseed(b"more");
+        a.generate(&mut out_a);
+        b.generate(&mut out_b);
+        assert_ne!(out_a, out_b);
+    }
+}
@@ -0,0 +1,32 @@
+//! CPU timing jitter as an auxiliary entropy source
+//!
+//! Times a short memory walk many times over and hashes the low bits of the
+//! durations. The entropy comes from cache, pipeline and scheduler noise, is
+//! not credited anywhere, and only ever reaches output mixed with other
+//! sources.
+
+use sha2::{Digest, Sha256};
+use std::{hint::black_box, time::Instant};
+
+/// Timings hashed into each sample
+const MEASUREMENTS: usize = 256;
+
+/// Bytes touched by each timed walk
+const WALK_LEN: usize = 4096;
+
+/// 32 bytes hashed from fresh timing measurements
+pub fn sample() -> [u8; 32] {
+    let mut memory = [0u8; WALK_LEN];
+    let mut hash = Sha256::new();
+    let mut position = 0usize;
+    for i in 0..MEASUREMENTS {
+        let start = Instant::now();
+        for _ in 0..64 {
+            position = (position + 67 + memory[position] as usize) % WALK_LEN;
+            memory[position] = memory[position].wrapping_add(i as u8);
+        }
+        black_box(&memory);
+        hash.update(start.elapsed().subsec_nanos().to_le_bytes());
+    }
+    hash.finalize().into()
+}
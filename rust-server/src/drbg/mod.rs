@@ -0,0 +1,221 @@
+//! CTR_DRBG expansion of device entropy
+//!
+//! SP 800-90A CTR_DRBG with AES-256 and no derivation function, seeded
+//! and periodically reseeded from the QRNG. It lets bulk requests be
+//! served faster than the device's native rate while every output block
+//! stays bound to hardware entropy.
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes256,
+};
+use std::sync::Mutex;
+
+pub mod fortuna;
+pub mod jitter;
+pub mod shake;
+
+/// AES block length in bytes
+const BLOCK_LEN: usize = 16;
+
+/// AES-256 key length in bytes
+const KEY_LEN: usize = 32;
+
+/// Seed length (key plus counter block) in bytes
+pub const SEED_LEN: usize = KEY_LEN + BLOCK_LEN;
+
+/// Largest single generate call (2^19 bits)
+pub const MAX_REQUEST: usize = 1 << 16;
+
+/// Generate calls allowed between reseeds by SP 800-90A for AES
+pub const RESEED_LIMIT: u64 = 1 << 48;
+
+/// Default output between reseeds from the device, in bytes
+pub const DEFAULT_RESEED_INTERVAL: u64 = 1024 * 1024;
+
+/// AES-256 CTR_DRBG without derivation function (SP 800-90A 10.2.1)
+pub struct CtrDrbg {
+    key: [u8; KEY_LEN],
+    v: [u8; BLOCK_LEN],
+    reseed_counter: u64,
+}
+
+impl CtrDrbg {
+    /// Instantiate from full-entropy input and a personalization string
+    ///
+    /// The personalization string is truncated to `SEED_LEN` bytes.
+    pub fn new(entropy: &[u8; SEED_LEN], personalization: &[u8]) -> Self {
+        let mut drbg = Self {
+            key: [0; KEY_LEN],
+            v: [0; BLOCK_LEN],
+            reseed_counter: 1,
+        };
+        drbg.update(&xor_seed(entropy, personalization));
+        drbg
+    }
+
+    /// Mix fresh entropy into the state
+    pub fn reseed(&mut self, entropy: &[u8; SEED_LEN], additional: &[u8]) {
+        self.update(&xor_seed(entropy, additional));
+        self.reseed_counter = 1;
+    }
+
+    /// Whether the reseed limit has been reached
+    pub fn needs_reseed(&self) -> bool {
+        self.reseed_counter > RESEED_LIMIT
+    }
+
+    /// Fill `output` (at most `MAX_REQUEST` bytes)
+    ///
+    /// Returns false without producing output if a reseed is required.
+    pub fn generate(&mut self, output: &mut [u8]) -> bool {
+        assert!(output.len() <= MAX_REQUEST, "CTR_DRBG request too large");
+        if self.needs_reseed() {
+            return false;
+        }
+
+        let cipher = Aes256::new(GenericArray::from_slice(&self.key));
+        for chunk in output.chunks_mut(BLOCK_LEN) {
+            increment(&mut self.v);
+            let mut block = GenericArray::from(self.v);
+            cipher.encrypt_block(&mut block);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        self.update(&[0; SEED_LEN]);
+        self.reseed_counter += 1;
+        true
+    }
+
+    /// CTR_DRBG_Update
+    fn update(&mut self, provided: &[u8; SEED_LEN]) {
+        let cipher = Aes256::new(GenericArray::from_slice(&self.key));
+        let mut temp = [0u8; SEED_LEN];
+        for chunk in temp.chunks_mut(BLOCK_LEN) {
+            increment(&mut self.v);
+            let mut block = GenericArray::from(self.v);
+            cipher.encrypt_block(&mut block);
+            chunk.copy_from_slice(&block);
+        }
+
+        for (t, p) in temp.iter_mut().zip(provided) {
+            *t ^= p;
+        }
+        self.key.copy_from_slice(&temp[..KEY_LEN]);
+        self.v.copy_from_slice(&temp[KEY_LEN..]);
+    }
+}
+
+/// Big-endian increment of the counter block
+fn increment(v: &mut [u8; BLOCK_LEN]) {
+    *v = u128::from_be_bytes(*v).wrapping_add(1).to_be_bytes();
+}
+
+fn xor_seed(entropy: &[u8; SEED_LEN], extra: &[u8]) -> [u8; SEED_LEN] {
+    let mut seed = *entropy;
+    for (s, e) in seed.iter_mut().zip(extra) {
+        *s ^= e;
+    }
+    seed
+}
+
+struct ExpanderState {
+    drbg: Option<CtrDrbg>,
+    since_reseed: u64,
+}
+
+/// Shared DRBG reseeded from the device every `interval` output bytes
+pub struct DrbgExpander {
+    interval: u64,
+    state: Mutex<ExpanderState>,
+}
+
+impl DrbgExpander {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            state: Mutex::new(ExpanderState {
+                drbg: None,
+                since_reseed: 0,
+            }),
+        }
+    }
+
+    /// Output bytes between reseeds
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Whether fresh device entropy is needed before generating
+    pub fn needs_seed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        match &state.drbg {
+            Some(drbg) => state.since_reseed >= self.interval || drbg.needs_reseed(),
+            None => true,
+        }
+    }
+
+    /// Instantiate or reseed from device entropy
+    pub fn seed(&self, entropy: &[u8; SEED_LEN]) {
+        let mut state = self.state.lock().unwrap();
+        match state.drbg.as_mut() {
+            Some(drbg) => drbg.reseed(entropy, b""),
+            None => state.drbg = Some(CtrDrbg::new(entropy, b"quantis-server ctr_drbg")),
+        }
+        state.since_reseed = 0;
+    }
+
+    /// Generate `count` bytes, or None if the DRBG must be (re)seeded first
+    pub fn generate(&self, count: usize) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let drbg = state.drbg.as_mut()?;
+
+        let mut output = vec![0u8; count];
+        for chunk in output.chunks_mut(MAX_REQUEST) {
+            if !drbg.generate(chunk) {
+                return None;
+            }
+        }
+        state.since_reseed += count as u64;
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_output() {
+        // Cross-checked against an independent implementation over AES-256
+        let entropy: [u8; SEED_LEN] = std::array::from_fn(|i| i as u8);
+        let mut drbg = CtrDrbg::new(&entropy, b"");
+        let mut output = [0u8; 32];
+        assert!(drbg.generate(&mut output));
+        assert!(drbg.generate(&mut output));
+        assert_eq!(hex::encode(output), "1a9fbcbc8da36dff2abe203296170fdb97c3297f67fcb679ac719c9fd00253b0");
+    }
+
+    #[test]
+    fn reseed_changes_the_stream() {
+        let entropy = [7u8; SEED_LEN];
+        let (mut a, mut b) = (CtrDrbg::new(&entropy, b""), CtrDrbg::new(&entropy, b""));
+        b.reseed(&[9u8; SEED_LEN], b"");
+
+        let (mut out_a, mut out_b) = ([0u8; 64], [0u8; 64]);
+        assert!(a.generate(&mut out_a));
+        assert!(b.generate(&mut out_b));
+        assert_ne!(out_a, out_b);
+    }
+
+    #[test]
+    fn expander_requests_reseed_after_interval() {
+        let expander = DrbgExpander::new(100);
+        assert!(expander.needs_seed());
+        assert!(expander.generate(16).is_none());
+
+        expander.seed(&[1u8; SEED_LEN]);
+        assert_eq!(expander.generate(MAX_REQUEST + 10).unwrap().len(), MAX_REQUEST + 10);
+        assert!(expander.needs_seed());
+    }
+}
@@ -0,0 +1,18 @@
+//! Quantis QRNG Server library
+//!
+//! The REST API and its integrations on top of `quantis-core`, shared by
+//! the `quantis-server` binary, the benchmarks and the integration tests.
+
+pub mod api;
+pub mod beacon;
+pub mod commitment;
+pub mod config;
+pub mod drbg;
+pub mod metrics;
+pub mod signing;
+pub mod tls;
+pub mod utils;
+pub mod vrf;
+
+// Device access and health tests live in the core crate
+pub use quantis_core::{device, health};
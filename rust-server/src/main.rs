@@ -1,91 +1,1154 @@
-//! Quantis QRNG Server
-//!
-//! High-performance REST API server for quantum random number generation
-//! using ID Quantique Quantis hardware.
-
-use anyhow::Result;
-use axum::{Router, Server};
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::Mutex;
-use tower_http::{
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
-};
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
-
-mod api;
-mod device;
-mod utils;
-
-use crate::device::QuantisDevice;
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_thread_names(false)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    info!("Starting Quantis QRNG Server v1.0.0");
-
-    // Open Quantis device
-    let device = match QuantisDevice::open(0) {
-        Ok(dev) => {
-            info!("Successfully opened Quantis device");
-            Arc::new(Mutex::new(dev))
-        }
-        Err(e) => {
-            eprintln!("Failed to open Quantis device: {}", e);
-            eprintln!("Make sure the device is connected and you have permissions");
-            eprintln!("You may need to run: sudo usermod -a -G plugdev $USER");
-            std::process::exit(1);
-        }
-    };
-
-    // Get device info
-    {
-        let mut dev = device.lock().await;
-        match dev.info() {
-            Ok(info) => {
-                info!("Device: {}", info.product);
-                info!("Serial: {}", info.serial);
-                info!("Version: {}", info.version);
-            }
-            Err(e) => {
-                eprintln!("Failed to get device info: {}", e);
-            }
-        }
-    }
-
-    // Create entropy buffer
-    let buffer = Arc::new(utils::RingBuffer::new(16 * 1024 * 1024)); // 16MB buffer
-    
-    // Start background entropy reader
-    utils::start_entropy_reader(device.clone(), buffer.clone()).await?;
-
-    // Build router
-    let app = Router::new()
-        .nest("/api/v1", api::routes(device.clone(), buffer.clone()))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        )
-        .layer(TraceLayer::new_for_http());
-
-    // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    info!("Listening on {}", addr);
-    
-    Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await?;
-
-    Ok(())
+//! Quantis QRNG Server
+//!
+//! High-performance REST API server for quantum random number generation
+//! using ID Quantique Quantis hardware.
+
+use anyhow::Result;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use hyper_util::{
+    rt::{TokioExecutor, TokioTimer},
+    server::conn::auto,
+};
+use clap::{Parser, Subcommand};
+use std::{io::Write, net::{IpAddr, SocketAddr}, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    net::TcpListener,
+    sync::broadcast,
+};
+use tracing::{error, info, warn};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+
+use quantis_server::{
+    api::{
+        self,
+        access_log::{self, AccessLog},
+        admin,
+        clients::{self, ClientAccess},
+        cors::{self, CorsOrigins},
+        dispensing::{self, DispenseLog},
+        ipfilter::{self, IpFilter},
+        keys::{self, DeviceKey},
+        limits, panics, quota, ratelimit,
+        readiness::{self, Readiness},
+        request_id, security_headers, tenants,
+        throttle::Throttle,
+        AppState, AppStateInner,
+    },
+    beacon::{self, Beacon, BeaconKey},
+    commitment::CommitmentStore,
+    config::{
+        reload::{ConfigReloader, LiveSettings, SetLogFilter},
+        BeaconConfig, Config, HttpConfig, LogFormat, ServerConfig, SigningConfig,
+    },
+    device::{
+        self,
+        capture::{CaptureWriter, ReplaySource},
+        hotplug,
+        mix::MixMode,
+        pipeline::{Pipeline, StageDefaults},
+        pool::DevicePool,
+        QuantisError,
+    },
+    drbg::{
+        self,
+        fortuna::{self, Fortuna},
+        DrbgExpander,
+    },
+    health::{
+        audit::AuditLog, autocorrelation, credit::EntropyAccount, estimators, fips,
+        monitor::AlarmThresholds, unix_time, HealthState, DEFAULT_MIN_ENTROPY, RECOVERY_BYTES,
+        STARTUP_SAMPLES,
+    },
+    metrics::Metrics,
+    signing::{self, Signer},
+    tls::{IdentityAcceptor, Reloader, TlsFiles},
+    utils::{
+        self,
+        conditioned::ConditionedSource,
+        egd,
+        pools::{ConditionedPool, PoolSet},
+        secure, systemd, webhook,
+    },
+    vrf::{self, VrfKey},
+};
+
+#[derive(Parser)]
+#[command(name = "quantis-server", version, about)]
+struct Cli {
+    /// TOML configuration file; flags override its settings
+    #[arg(long, env = "QUANTIS_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address to listen on [config: server.listen, default 0.0.0.0]
+    #[arg(long)]
+    bind: Option<IpAddr>,
+
+    /// Port to listen on [config: server.listen, default 8080]
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Log level or filter directives, e.g. `debug` or
+    /// `quantis_server=debug` [config: server.log_level, default info]
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// Log line format [config: server.log_format, default text]
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Open only the device at this USB enumeration index
+    /// [config: devices.index]
+    #[arg(long)]
+    device_index: Option<usize>,
+
+    /// Combine output of all healthy devices before buffering
+    /// [config: devices.mix]
+    #[arg(long, value_enum)]
+    mix: Option<MixMode>,
+
+    /// Default post-processing pipeline, e.g. `von_neumann|sha3:4`
+    #[arg(long, default_value = "none")]
+    correction: String,
+
+    /// Entropy buffer size in MiB, resizable at runtime via the admin API
+    /// [config: buffer.size_mib, default 16]
+    #[arg(long)]
+    buffer_size: Option<usize>,
+
+    /// Longest direct device read (ms) served when the buffer is starved;
+    /// longer reads get 503 with Retry-After [config: buffer.max_read_wait_ms,
+    /// default 250]
+    #[arg(long)]
+    max_read_wait: Option<u64>,
+
+    /// Buffered bytes held back for interactive requests (up to 1 KiB),
+    /// which bulk requests and pool refills may not draw on
+    /// [config: buffer.interactive_reserve, default 262144]
+    #[arg(long)]
+    interactive_reserve: Option<usize>,
+
+    /// Requests in flight across all routes before new ones get 503
+    /// [config: limits.max_concurrency, default 1024]
+    #[arg(long)]
+    max_concurrency: Option<usize>,
+
+    /// Limit on requests in flight for one entropy route, as `path=limit`,
+    /// e.g. `/random/bytes=64` (repeatable) [config: limits.routes]
+    #[arg(long = "route-concurrency", value_delimiter = ',')]
+    route_concurrency: Vec<String>,
+
+    /// Seconds a request may take before it is abandoned with 503
+    /// [config: limits.timeout_secs, default 30]
+    #[arg(long)]
+    request_timeout: Option<u64>,
+
+    /// Comma-separated pipelines kept pre-conditioned in the background,
+    /// empty to disable [config: buffer.pools, default von_neumann,sha3]
+    #[arg(long)]
+    pools: Option<String>,
+
+    /// Capacity in bytes of each pre-conditioned pool
+    /// [config: buffer.pool_size, default 1048576]
+    #[arg(long)]
+    pool_size: Option<usize>,
+
+    /// Lock all process memory into RAM, not only the entropy buffers
+    #[arg(long)]
+    mlock_all: bool,
+
+    /// Assessed min-entropy of the raw stream (bits per byte) for health tests
+    #[arg(long, default_value_t = DEFAULT_MIN_ENTROPY)]
+    min_entropy: f64,
+
+    /// Run the FIPS 140-2 continuous RNG test on conditioned output
+    #[arg(long)]
+    fips: bool,
+
+    /// Comparison block size in bytes for the FIPS continuous RNG test
+    #[arg(long, default_value_t = fips::DEFAULT_BLOCK_SIZE)]
+    fips_block: usize,
+
+    /// Rolling byte chi-square that raises an alarm
+    #[arg(long)]
+    alarm_chi_square: Option<f64>,
+
+    /// Rolling mean bit bias (deviation from 0.5) that raises an alarm
+    #[arg(long)]
+    alarm_bias: Option<f64>,
+
+    /// Rolling lag-1 serial correlation that raises an alarm
+    #[arg(long)]
+    alarm_correlation: Option<f64>,
+
+    /// Raw stream bit autocorrelation (any lag 1-8) that raises an alarm
+    #[arg(long, default_value_t = autocorrelation::DEFAULT_THRESHOLD)]
+    alarm_autocorrelation: f64,
+
+    /// URL that receives every raised alarm as a JSON POST
+    #[arg(long)]
+    alarm_webhook: Option<String>,
+
+    /// Refuse requests that exceed the entropy credited to the buffer
+    #[arg(long)]
+    enforce_credit: bool,
+
+    /// Resume after a health test failure once fresh output passes again,
+    /// instead of waiting for an admin acknowledgement
+    #[arg(long)]
+    auto_recover: bool,
+
+    /// Bearer token for the `/api/v1/admin` endpoints, disabled if unset
+    /// [config: auth.admin_token]
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// Address the admin endpoints listen on, apart from the public API
+    /// [config: admin.listen, default 127.0.0.1:8081]
+    #[arg(long)]
+    admin_listen: Option<SocketAddr>,
+
+    /// Run the SP 800-90B estimators on a fresh capture every N seconds
+    #[arg(long)]
+    estimate_interval: Option<u64>,
+
+    /// File holding the VRF secret key; created from device entropy if
+    /// missing (a new key is generated on every start if unset)
+    #[arg(long)]
+    vrf_key: Option<PathBuf>,
+
+    /// Append health test results to this JSON lines file
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Bytes served by `mode=drbg` between reseeds from the device
+    #[arg(long, default_value_t = drbg::DEFAULT_RESEED_INTERVAL)]
+    drbg_reseed_interval: u64,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    /// Let flags given on the command line override `config`
+    fn override_config(&self, config: &mut Config) -> Result<()> {
+        let listen = config.server.listen;
+        config.server.listen = SocketAddr::new(
+            self.bind.unwrap_or(listen.ip()),
+            self.port.unwrap_or(listen.port()),
+        );
+        if let Some(level) = &self.log_level {
+            config.server.log_level = level.clone();
+        }
+        if let Some(format) = self.log_format {
+            config.server.log_format = format;
+        }
+        if let Some(index) = self.device_index {
+            config.devices.index = Some(index);
+        }
+        if let Some(mix) = self.mix {
+            config.devices.mix = mix;
+        }
+        if let Some(size) = self.buffer_size {
+            config.buffer.size_mib = size;
+        }
+        if let Some(wait) = self.max_read_wait {
+            config.buffer.max_read_wait_ms = wait;
+        }
+        if let Some(reserve) = self.interactive_reserve {
+            config.buffer.interactive_reserve = reserve;
+        }
+        if let Some(max) = self.max_concurrency {
+            config.limits.max_concurrency = max;
+        }
+        if !self.route_concurrency.is_empty() {
+            let limits = limits::RouteLimits::parse(&self.route_concurrency)
+                .map_err(|e| anyhow::anyhow!("Invalid --route-concurrency: {}", e))?;
+            config.limits.routes = limits::LIMITED_ROUTES
+                .iter()
+                .filter_map(|path| limits.get(path).map(|limit| (path.to_string(), limit)))
+                .collect();
+        }
+        if let Some(timeout) = self.request_timeout {
+            config.limits.timeout_secs = timeout;
+        }
+        if let Some(pools) = &self.pools {
+            config.buffer.pools = pools.clone();
+        }
+        if let Some(size) = self.pool_size {
+            config.buffer.pool_size = size;
+        }
+        if let Some(token) = &self.admin_token {
+            config.auth.admin_token = Some(token.clone());
+        }
+        if let Some(listen) = self.admin_listen {
+            config.admin.listen = listen;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the udev rule for Quantis devices (install it with --write)
+    #[cfg(target_os = "linux")]
+    SetupUdev {
+        /// Install the rule and reload udev (requires root)
+        #[arg(long)]
+        write: bool,
+        /// Rule file to write
+        #[arg(long, default_value = device::udev::DEFAULT_RULES_PATH)]
+        path: PathBuf,
+        /// Group granted access to the device
+        #[arg(long, default_value = "plugdev")]
+        group: String,
+        /// Device node permissions
+        #[arg(long, default_value = "0660")]
+        mode: String,
+    },
+    /// Write a continuous raw binary stream to stdout for external test
+    /// suites, e.g. `quantis-server export | dieharder -a -g 200`
+    Export {
+        /// Stop after this many bytes (runs until the reader exits if unset)
+        #[arg(long)]
+        bytes: Option<u64>,
+        /// Post-processing pipeline applied before output
+        #[arg(long, default_value = "none")]
+        correction: String,
+        /// Serial of the device to read from
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Check the sequence and hash chain of a dispensing log
+    VerifyDispenseLog {
+        /// Log file to check
+        path: PathBuf,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        #[cfg(target_os = "linux")]
+        Some(Command::SetupUdev {
+            write,
+            path,
+            group,
+            mode,
+        }) => setup_udev(write, &path, &group, &mode),
+        Some(Command::Export {
+            bytes,
+            correction,
+            device,
+        }) => export(bytes, &correction, device.as_deref(), cli.device_index, cli.min_entropy).await,
+        Some(Command::VerifyDispenseLog { path }) => {
+            let records =
+                dispensing::verify(&path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            println!("{}: {} records verified", path.display(), records);
+            Ok(())
+        }
+        None => serve(cli).await,
+    }
+}
+
+/// Emit or install the udev rule
+#[cfg(target_os = "linux")]
+fn setup_udev(write: bool, path: &std::path::Path, group: &str, mode: &str) -> Result<()> {
+    let rule = device::udev::rule(group, mode);
+
+    if !write {
+        print!("{}", rule);
+        return Ok(());
+    }
+
+    let failed = device::udev::install(path, &rule)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    println!("Installed udev rule to {}", path.display());
+    for command in failed {
+        eprintln!("Warning: `{}` failed, run it manually", command);
+    }
+    println!(
+        "Members of the '{}' group can now access the device (replug it if already attached)",
+        group
+    );
+
+    Ok(())
+}
+
+/// Reject a `--min-entropy` the health test cutoffs cannot be derived from
+fn check_min_entropy(min_entropy: f64) -> Result<()> {
+    if !(1.0..=8.0).contains(&min_entropy) {
+        anyhow::bail!("--min-entropy must be between 1 and 8 bits per byte");
+    }
+    Ok(())
+}
+
+/// Chunk size for exported reads
+const EXPORT_CHUNK: usize = 64 * 1024;
+
+/// Stream device output to stdout until `limit` bytes or a closed pipe
+async fn export(
+    limit: Option<u64>,
+    correction: &str,
+    device: Option<&str>,
+    device_index: Option<usize>,
+    min_entropy: f64,
+) -> Result<()> {
+    check_min_entropy(min_entropy)?;
+    let correction = Pipeline::parse(correction, StageDefaults::default())
+        .map_err(|e| anyhow::anyhow!("Invalid --correction: {}", e))?;
+
+    let (device_events, _) = broadcast::channel(16);
+    let devices = match device_index {
+        Some(index) => DevicePool::open_index(device_events, index),
+        None => DevicePool::open_all(device_events, &[]),
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to open Quantis device: {}", e))?;
+    let health = HealthState::new(min_entropy);
+
+    let mut stdout = std::io::stdout().lock();
+    let mut written = 0u64;
+
+    while limit.is_none_or(|limit| written < limit) {
+        let raw = match device {
+            Some(serial) => devices.read_from(serial, correction.input_len(EXPORT_CHUNK)).await?,
+            None => devices.read(correction.input_len(EXPORT_CHUNK)).await?,
+        };
+        health
+            .check(&raw)
+            .map_err(|failure| anyhow::anyhow!("Continuous health test failed: {}", failure))?;
+
+        let mut data = correction.apply(&raw);
+        if let Some(limit) = limit {
+            data.truncate((limit - written).min(data.len() as u64) as usize);
+        }
+
+        // A closed pipe means the consumer has read all it needs
+        match stdout.write_all(&data) {
+            Ok(()) => written += data.len() as u64,
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    match stdout.flush() {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Load the VRF key, or leave it to be derived from device entropy
+fn vrf_key(path: Option<&std::path::Path>) -> Result<DeviceKey<VrfKey>> {
+    let Some(path) = path.filter(|path| path.exists()) else {
+        return Ok(DeviceKey::pending("VRF key"));
+    };
+    let key = VrfKey::load(path).map_err(|e| anyhow::anyhow!("Failed to load VRF key {}: {}", path.display(), e))?;
+    info!("VRF public key {}", hex::encode(key.public_key()));
+    Ok(DeviceKey::ready("VRF key", key))
+}
+
+/// Derive a new VRF key from device entropy, saving it to `path` if set
+async fn create_vrf_key(state: &AppState, path: Option<&std::path::Path>) -> Result<()> {
+    let key = VrfKey::from_secret(*keys::device_secret::<{ vrf::SECRET_LEN }>(state, "VRF key").await);
+    if let Some(path) = path {
+        key.save(path)
+            .map_err(|e| anyhow::anyhow!("Failed to save VRF key {}: {}", path.display(), e))?;
+        info!("Saved new VRF key to {}", path.display());
+    }
+    info!("VRF public key {}", hex::encode(key.public_key()));
+    state.vrf.set(key);
+    Ok(())
+}
+
+/// Set up the drand-compatible beacon when enabled, loading its key if the
+/// file exists
+fn beacon(config: &BeaconConfig) -> Result<Option<DeviceKey<Beacon>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    if config.genesis_time.is_none() {
+        warn!("No beacon.genesis_time set, the beacon chain starts now and changes on restart");
+    }
+    let Some(path) = config.key_file.as_deref().filter(|path| path.exists()) else {
+        return Ok(Some(DeviceKey::pending("beacon key")));
+    };
+    let passphrase = config.passphrase.as_deref().unwrap_or_default();
+    let key = BeaconKey::load(path, passphrase).map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(Some(DeviceKey::ready("beacon key", beacon_chain(config, key))))
+}
+
+/// Create the beacon key from device entropy, saving it to the key file if set
+async fn create_beacon_key(state: &AppState, config: &BeaconConfig) -> Result<()> {
+    let Some(pending) = &state.beacon else {
+        return Ok(());
+    };
+    let key = BeaconKey::from_secret(*keys::device_secret::<{ beacon::SECRET_LEN }>(state, "beacon key").await);
+    match config.key_file.as_deref() {
+        Some(path) => {
+            key.save(path, config.passphrase.as_deref().unwrap_or_default())
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            info!("Saved new beacon key to {}", path.display());
+        }
+        None => warn!("No beacon.key_file set, the beacon chain changes on restart"),
+    }
+    pending.set(beacon_chain(config, key));
+    Ok(())
+}
+
+/// Beacon chain signed by `key`
+fn beacon_chain(config: &BeaconConfig, key: BeaconKey) -> Beacon {
+    let beacon = Beacon::new(key, config.genesis_time.unwrap_or_else(unix_time), config.period_secs);
+    info!("Beacon chain {}, a round every {}s", hex::encode(beacon.chain_hash()), beacon.period());
+    beacon
+}
+
+/// Open the signing key ring, or leave it to be created from device entropy
+fn signing_key(config: &SigningConfig) -> Result<DeviceKey<Signer>> {
+    let Some(path) = config.key_file.as_deref().filter(|path| path.exists()) else {
+        return Ok(DeviceKey::pending("signing key"));
+    };
+    let passphrase = config.passphrase.as_deref().unwrap_or_default();
+    let signer = Signer::load(path, passphrase, config.keep_previous).map_err(|e| anyhow::anyhow!("{}", e))?;
+    info!("Signing key {}", signer.current().key_id);
+    Ok(DeviceKey::ready("signing key", signer))
+}
+
+/// Create the signing key ring from device entropy
+///
+/// Without a key file the key lasts until the server stops.
+async fn create_signing_key(state: &AppState, config: &SigningConfig) -> Result<()> {
+    let secret = keys::device_secret::<{ signing::SECRET_LEN }>(state, "signing key").await;
+    let signer = Signer::new(*secret, config.keep_previous);
+    let signer = match &config.key_file {
+        Some(path) => {
+            let passphrase = config.passphrase.as_deref().unwrap_or_default();
+            let signer = signer.with_store(path.clone(), passphrase).map_err(|e| anyhow::anyhow!("{}", e))?;
+            info!("Saved new signing key to {}", path.display());
+            signer
+        }
+        None => {
+            warn!("No signing.key_file set, signatures use a key that is lost on restart");
+            signer
+        }
+    };
+    info!("Signing key {}", signer.current().key_id);
+    state.signer.set(signer);
+    Ok(())
+}
+
+/// Create the keys that had no file to load from, once the entropy source
+/// has passed its health tests
+async fn create_keys(state: AppState, vrf_path: Option<PathBuf>, signing: SigningConfig, beacon: BeaconConfig) {
+    let created = async {
+        if state.vrf.is_pending() {
+            create_vrf_key(&state, vrf_path.as_deref()).await?;
+        }
+        if state.signer.is_pending() {
+            create_signing_key(&state, &signing).await?;
+        }
+        if state.beacon.as_ref().is_some_and(DeviceKey::is_pending) {
+            create_beacon_key(&state, &beacon).await?;
+        }
+        anyhow::Ok(())
+    };
+    if let Err(e) = created.await {
+        error!("Failed to create device keys, their routes stay unavailable: {:#}", e);
+    }
+}
+
+/// Install the global log subscriber, returning how to change its filter
+fn init_logging(server: &ServerConfig) -> Result<SetLogFilter> {
+    let filter = EnvFilter::new(&server.log_level);
+    let builder = FmtSubscriber::builder().with_target(false).with_thread_ids(false).with_thread_names(false);
+    match server.log_format {
+        LogFormat::Text => {
+            let builder = builder.with_env_filter(filter).with_filter_reloading();
+            let handle = builder.reload_handle();
+            tracing::subscriber::set_global_default(builder.finish())?;
+            Ok(Box::new(move |filter| Ok(handle.reload(filter)?)))
+        }
+        LogFormat::Json => {
+            // Request spans carry the request ID, so keep the current one on each line
+            let builder = builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_env_filter(filter)
+                .with_filter_reloading();
+            let handle = builder.reload_handle();
+            tracing::subscriber::set_global_default(builder.finish())?;
+            Ok(Box::new(move |filter| Ok(handle.reload(filter)?)))
+        }
+    }
+}
+
+/// Run the HTTP server
+async fn serve(cli: Cli) -> Result<()> {
+    // Settings are loaded first so they can set the log level
+    let cli = Arc::new(cli);
+    let config = load_config(&cli)?;
+    let set_log_filter = init_logging(&config.server)?;
+
+    info!("Starting Quantis QRNG Server v1.0.0");
+
+    if cli.mlock_all {
+        secure::lock_all().map_err(|e| anyhow::anyhow!("Failed to lock process memory: {}", e))?;
+        info!("Process memory locked into RAM");
+    }
+    if let Some(path) = &cli.config {
+        info!("Loaded configuration from {}", path.display());
+    }
+
+    // Load the certificate before touching devices so a bad one fails fast
+    let tls = match (&config.tls.cert, &config.tls.key) {
+        (Some(cert), Some(key)) => {
+            let files = TlsFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: config.tls.client_ca.clone(),
+            };
+            let tls = RustlsConfig::from_config(files.load()?);
+            Reloader::new(files, tls.clone()).spawn();
+            info!("Serving HTTPS with the certificate in {}", cert.display());
+            if let Some(ca) = &config.tls.client_ca {
+                info!("Requiring client certificates issued by {}", ca.display());
+            }
+            Some(tls)
+        }
+        _ => None,
+    };
+
+    let correction = Pipeline::parse(&cli.correction, StageDefaults::default())
+        .map_err(|e| anyhow::anyhow!("Invalid --correction: {}", e))?;
+    info!("Default correction pipeline: {}", correction);
+
+    let route_limits = config.route_limits()?;
+    let route_timeouts = config.route_timeouts()?;
+    let jwt = config.jwt_validator()?;
+    if jwt.is_some() {
+        info!("Requiring JWT bearer tokens");
+    }
+    if !config.auth.api_keys.is_empty() {
+        info!("Requiring one of {} API keys", config.auth.api_keys.len());
+    }
+    let tenants = Arc::new(config.tenants());
+    for tenant in tenants.tenants() {
+        info!("Reserving a {} byte buffer slice for tenant {}", tenant.buffer().capacity(), tenant.name());
+    }
+    let api_keys = Arc::new(config.api_keys(&tenants));
+    match &config.auth.quota_file {
+        Some(path) => {
+            let usage =
+                quota::load(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            api_keys.restore(&usage);
+            quota::persist(api_keys.clone(), path.clone());
+        }
+        None if !api_keys.quotas().is_empty() => {
+            warn!("API key quotas start afresh on restart; set auth.quota_file to keep them")
+        }
+        None => {}
+    }
+    let client_limits = Arc::new(config.client_limits());
+    let cors = Arc::new(CorsOrigins::new(&config.server.cors_origins).map_err(anyhow::Error::msg)?);
+    let ip_filter =
+        Arc::new(IpFilter::new(&config.ip_filter.allow, &config.ip_filter.deny).map_err(anyhow::Error::msg)?);
+    let reloader = Arc::new(ConfigReloader::new(
+        config.clone(),
+        Box::new({
+            let cli = cli.clone();
+            move || load_config(&cli)
+        }),
+        set_log_filter,
+        LiveSettings {
+            api_keys: api_keys.clone(),
+            client_limits: client_limits.clone(),
+            cors: cors.clone(),
+            ip_filter: ip_filter.clone(),
+            tenants: tenants.clone(),
+        },
+    ));
+    #[cfg(unix)]
+    reload_on_hangup(reloader.clone());
+    let buffer_size = config.buffer_bytes();
+    let pool_size = config.buffer.pool_size;
+    let pools = config
+        .buffer
+        .pools
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(|spec| {
+            Pipeline::parse(spec, StageDefaults::default())
+                .map(|pipeline| ConditionedPool::new(pipeline, pool_size))
+                .map_err(|e| anyhow::anyhow!("Invalid pools entry {}: {}", spec, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let pools = Arc::new(PoolSet::new(pools));
+    for pool in pools.pools() {
+        info!("Pre-conditioning {} into a {} byte pool", pool.pipeline(), pool_size);
+    }
+
+    let min_entropy = cli.min_entropy;
+    check_min_entropy(min_entropy)?;
+    let drbg_reseed_interval = cli.drbg_reseed_interval;
+    if drbg_reseed_interval == 0 {
+        anyhow::bail!("--drbg-reseed-interval must be at least 1 byte");
+    }
+    if cli.estimate_interval == Some(0) {
+        anyhow::bail!("--estimate-interval must be at least 1 second");
+    }
+    let fips_block = cli.fips.then_some(cli.fips_block);
+    if fips_block.is_some_and(|block| !(2..=1024).contains(&block)) {
+        anyhow::bail!("--fips-block must be between 2 and 1024 bytes");
+    }
+    let defaults = AlarmThresholds::default();
+    let thresholds = AlarmThresholds {
+        chi_square: cli.alarm_chi_square.unwrap_or(defaults.chi_square),
+        bias: cli.alarm_bias.unwrap_or(defaults.bias),
+        correlation: cli.alarm_correlation.unwrap_or(defaults.correlation),
+    };
+
+    // Watch for device arrival/removal and failover
+    let (device_events, _) = broadcast::channel(16);
+    tokio::spawn(hotplug::log_device_events(device_events.subscribe()));
+
+    // Open a replayed capture, the selected device, or all attached Quantis devices
+    let replay = config.capture.replay.as_deref();
+    let devices = if let Some(path) = replay {
+        warn!("Serving the capture {} instead of the devices, output is NOT random", path.display());
+        let pool = DevicePool::new(device_events.clone());
+        ReplaySource::open(path, config.capture.replay_loop)
+            .map_err(QuantisError::from)
+            .and_then(|source| pool.add(Box::new(source)))
+            .map_err(|e| anyhow::anyhow!("Failed to replay {}: {}", path.display(), e))?;
+        Arc::new(pool)
+    } else {
+        let opened = match config.devices.index {
+            Some(index) => DevicePool::open_index(device_events.clone(), index),
+            None => DevicePool::open_all(device_events.clone(), &config.devices.serials),
+        };
+        match opened {
+            Ok(pool) => {
+                info!("Successfully opened {} Quantis device(s)", pool.len());
+                Arc::new(pool)
+            }
+            Err(e) => {
+                eprintln!("Failed to open Quantis device: {}", e);
+                eprintln!("Make sure the device is connected and you have permissions");
+                eprintln!("{}", device::SETUP_HINT);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    devices.set_lock_timeout(Duration::from_millis(config.devices.lock_timeout_ms));
+    let mix = config.devices.mix;
+    devices.set_mix_mode(mix);
+    if mix != MixMode::None {
+        info!("Mixing device output with {:?}", mix);
+        if devices.len() < 2 {
+            warn!("Mixing needs at least two devices, using a single device until another is attached");
+        }
+    }
+
+    // Log device info
+    for slot in devices.slots() {
+        let info = slot.info();
+        info!(
+            "Device {}: {} (serial {}, version {}, {:?})",
+            slot.index(),
+            info.product,
+            info.serial,
+            info.version,
+            devices.role(&slot)
+        );
+    }
+
+    // A replay stays the only source, whatever is plugged in
+    if replay.is_none() {
+        match hotplug::start_hotplug_monitor(device_events.clone()) {
+            Ok(true) => info!("USB hotplug monitoring enabled"),
+            Ok(false) => info!(
+                "libusb has no hotplug support on this platform, polling for devices every {:?}",
+                hotplug::POLL_INTERVAL
+            ),
+            Err(e) => warn!("Failed to start USB hotplug monitoring: {}", e),
+        }
+        tokio::spawn(devices.clone().watch(device_events.subscribe()));
+    }
+
+    // Create entropy buffer
+    let buffer = Arc::new(utils::RingBuffer::new(buffer_size));
+    
+    // Continuous health tests over all raw device output
+    let mut health = HealthState::new(min_entropy)
+        .with_alarm_thresholds(thresholds)
+        .with_autocorrelation_threshold(cli.alarm_autocorrelation);
+    info!("Continuous health tests assume {} bits of min-entropy per byte", min_entropy);
+    if let Some(block) = fips_block {
+        health = health.with_fips(block);
+        info!("FIPS mode enabled, continuous RNG test on {}-byte blocks", block);
+    }
+    if let Some(path) = &cli.audit_log {
+        let audit = AuditLog::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open audit log {}: {}", path.display(), e))?;
+        health = health.with_audit_log(audit);
+        info!("Recording health test results to {}", path.display());
+    }
+    if cli.auto_recover {
+        health = health.with_auto_recovery();
+        info!("Health test failures recover automatically after {} passing bytes", RECOVERY_BYTES);
+    } else if config.auth.admin_token.is_none() {
+        warn!("No admin token set, a health test failure will need a restart to clear");
+    }
+    let health = Arc::new(health);
+    if let Some(url) = cli.alarm_webhook.clone() {
+        tokio::spawn(webhook::notify_webhook(health.subscribe_alarms(), url));
+    }
+
+    let mut credit = EntropyAccount::new(min_entropy);
+    if cli.enforce_credit {
+        credit = credit.enforcing();
+        info!("Refusing requests beyond the credited entropy");
+    }
+
+    // Startup tests on the first raw output
+    let sample = devices
+        .read(STARTUP_SAMPLES)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read startup test samples: {}", e))?;
+    match health.startup(&sample) {
+        Ok(()) => info!("Startup health tests passed on {} samples", sample.len()),
+        Err(failure) => warn!("Startup health tests failed, entropy source quarantined: {}", failure),
+    }
+
+    // Keys without a file are created once the source passes its tests
+    let vrf = vrf_key(cli.vrf_key.as_deref())?;
+    let signer = signing_key(&config.signing)?;
+    let beacon = beacon(&config.beacon)?;
+
+    // Start background entropy reader
+    let capture = match &config.capture.record {
+        Some(path) => {
+            let max_size = config.capture.max_size_mib.saturating_mul(1024 * 1024);
+            let capture = CaptureWriter::open(path, max_size)
+                .map_err(|e| anyhow::anyhow!("Failed to open capture {}: {}", path.display(), e))?;
+            info!("Recording raw device output to {}", path.display());
+            Some(capture)
+        }
+        None => None,
+    };
+    let reader = utils::start_entropy_reader(devices.clone(), buffer.clone(), health.clone(), capture).await;
+    let reserve = config.buffer.interactive_reserve;
+    utils::start_pool_filler(buffer.clone(), pools.clone(), health.clone(), reserve);
+    tenants::start_filler(buffer.clone(), tenants.clone(), health.clone(), reserve);
+    // Local consumers outside the HTTP API
+    let local = ConditionedSource::new(buffer.clone(), health.clone(), reserve);
+    #[cfg(target_os = "linux")]
+    if config.rngd.enabled {
+        use utils::kernel_feed::{self, KernelFeed};
+        let rngd = &config.rngd;
+        let feed = KernelFeed::open(&rngd.device, rngd.credit_bits_per_byte)
+            .map_err(|e| anyhow::anyhow!("Failed to feed {}: {}", rngd.device.display(), e))?;
+        kernel_feed::start_kernel_feed(local.clone(), feed, rngd.bytes_per_sec);
+    }
+    if let Some(path) = &config.egd.socket {
+        egd::start_egd_server(path, config.egd.mode, local.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", path.display(), e))?;
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(path) = &config.virtio.socket {
+        utils::vhost_user::start_vhost_user_server(path, config.virtio.mode, local.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", path.display(), e))?;
+    }
+    #[cfg(unix)]
+    if let Some(path) = &config.fifo.path {
+        let fifo = &config.fifo;
+        utils::fifo::start_fifo_writer(path.clone(), fifo.mode, local.clone(), fifo.bytes_per_sec)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+    }
+    let fortuna = config.fortuna.enabled.then(|| {
+        let fortuna = Arc::new(Fortuna::new());
+        let interval = Duration::from_millis(config.fortuna.feed_interval_ms);
+        fortuna::start_feeder(fortuna.clone(), local.clone(), config.fortuna.jitter, interval);
+        info!(
+            "Serving source=fortuna from the device and the OS RNG{}",
+            if config.fortuna.jitter { " and CPU jitter" } else { "" }
+        );
+        fortuna
+    });
+    #[cfg(feature = "zmq")]
+    if let Some(endpoint) = &config.zmq.endpoint {
+        let publication = utils::zmq::Publication {
+            source: local.clone(),
+            bytes_per_sec: config.zmq.bytes_per_sec,
+            health: health.clone(),
+            devices: devices.clone(),
+            events: device_events.subscribe(),
+            status_interval: Duration::from_secs(config.zmq.status_interval_secs),
+        };
+        utils::zmq::start_zmq_publisher(endpoint, publication)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to bind ZeroMQ endpoint {}: {}", endpoint, e))?;
+    }
+    if let Some(seconds) = cli.estimate_interval {
+        utils::start_entropy_assessment(
+            devices.clone(),
+            health.clone(),
+            Duration::from_secs(seconds),
+            estimators::RECOMMENDED_SAMPLES,
+        );
+    }
+
+    // Entropy routes wait for the prefill and the startup tests
+    let readiness = Arc::new(Readiness::new(config.prefill_bytes()));
+    info!("Serving API traffic once {} bytes are buffered", readiness.prefill());
+    readiness::start_watch(readiness.clone(), buffer.clone(), health.clone());
+
+    let commitments = match &config.commitments.path {
+        Some(path) => {
+            let store = CommitmentStore::open(path)
+                .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+            info!("Keeping commitments in {}", path.display());
+            store
+        }
+        None => CommitmentStore::new(),
+    };
+
+    // Build routers
+    let state = Arc::new(AppStateInner {
+        devices: devices.clone(),
+        buffer: buffer.clone(),
+        health,
+        correction,
+        metrics: Metrics::new(),
+        drbg: DrbgExpander::new(drbg_reseed_interval),
+        fortuna,
+        credit,
+        pools,
+        tenants,
+        commitments,
+        vrf,
+        beacon,
+        signer,
+        max_read_wait: Duration::from_millis(config.buffer.max_read_wait_ms),
+        route_limits,
+        route_timeouts,
+        request_limits: config.request_limits(),
+        interactive_reserve: config.buffer.interactive_reserve,
+        throttle: Throttle::new(config.buffer.throttle_below_percent),
+        readiness: readiness.clone(),
+        reader: reader.status(),
+        admin_token: config.auth.admin_token.clone(),
+        jwt: jwt.map(Arc::new),
+        api_keys: api_keys.clone(),
+        reloader: Some(reloader),
+    });
+    tokio::spawn(create_keys(
+        state.clone(),
+        cli.vrf_key.clone(),
+        config.signing.clone(),
+        config.beacon.clone(),
+    ));
+    if let Some(days) = config.signing.rotate_days {
+        admin::start_key_rotation(state.clone(), Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+        info!("Rotating the signing key every {} days", days);
+    }
+    let admin = admin::enabled(&state).then(|| {
+        let admin = panics::catch_panics(admin::router(state.clone()));
+        let admin = security_headers::secure_responses(admin, None);
+        request_id::trace_requests(admin)
+    });
+    let api = panics::catch_panics(Router::new().nest("/api/v1", api::routes(state)));
+    let api = limits::limit_router(api, config.limits.max_concurrency);
+    let api = ratelimit::limit_clients(api, client_limits);
+    let app = clients::authorize_clients(api, ClientAccess::new(config.tls.clients.clone()));
+    let app = ipfilter::filter_addresses(app, ip_filter).layer(cors::layer(cors));
+    let app = match &config.access_log.path {
+        Some(path) => {
+            let max_bytes = config.access_log.max_size_mib.saturating_mul(1024 * 1024);
+            let log = AccessLog::open(path, max_bytes, config.access_log.keep)
+                .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+            info!("Writing access log to {}", path.display());
+            access_log::log_access(app, log)
+        }
+        None => app,
+    };
+    let app = match &config.dispense_log.path {
+        Some(path) => {
+            let log = DispenseLog::open(path, config.dispense_log.hash_chain)
+                .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+            info!("Recording entropy deliveries in {}", path.display());
+            dispensing::log_dispensing(app, log)
+        }
+        None => app,
+    };
+    // HSTS only means something over HTTPS
+    let hsts_max_age = Some(config.tls.hsts_max_age_secs).filter(|max_age| tls.is_some() && *max_age > 0);
+    let app = security_headers::secure_responses(app, hsts_max_age);
+    let app = request_id::trace_requests(app);
+
+    let drain = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let (stopping, _) = tokio::sync::watch::channel(false);
+
+    // Guests on this host reach the same API over vsock
+    #[cfg(target_os = "linux")]
+    let vsock_server = match config.vsock.port {
+        Some(port) => {
+            let listener = utils::vsock::VsockListener::bind(port)
+                .map_err(|e| anyhow::anyhow!("Failed to bind vsock port {}: {}", port, e))?;
+            let mut http = auto::Builder::new(TokioExecutor::new());
+            tune_http(&mut http, &config.http);
+            info!("Serving the API over vsock port {}", port);
+            Some(utils::vsock::start_vsock_server(listener, app.clone(), http, stopping.subscribe(), drain))
+        }
+        None => None,
+    };
+
+    // Start server, draining requests in flight on shutdown
+    let listener = match systemd::activated_listener(systemd::PUBLIC_SOCKET)? {
+        Some(listener) => {
+            info!("Using the socket passed by systemd");
+            listener
+        }
+        None => TcpListener::bind(config.server.listen).await?.into_std()?,
+    };
+    let addr = listener.local_addr()?;
+    let handle = axum_server::Handle::new();
+    let admin_handle = axum_server::Handle::new();
+    let signalled = (handle.clone(), admin_handle.clone());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        signalled.0.graceful_shutdown(Some(drain));
+        signalled.1.graceful_shutdown(Some(drain));
+        stopping.send_replace(true);
+    });
+
+    // The admin API is plain HTTP on its own listener, never the public one
+    let admin_server = match admin {
+        Some(admin) => {
+            let listener = match systemd::activated_listener(systemd::ADMIN_SOCKET)? {
+                Some(listener) => listener,
+                None => {
+                    let addr = config.admin.listen;
+                    TcpListener::bind(addr)
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Failed to bind admin.listen {}: {}", addr, e))?
+                        .into_std()?
+                }
+            };
+            let admin_addr = listener.local_addr()?;
+            if admin_addr.ip().is_loopback() {
+                info!("Admin API listening on {}", admin_addr);
+            } else {
+                warn!("Admin API listening on {}, which is not a loopback address", admin_addr);
+            }
+            let service = admin.into_make_service_with_connect_info::<SocketAddr>();
+            Some(tokio::spawn(axum_server::from_tcp(listener).handle(admin_handle).serve(service)))
+        }
+        None => {
+            info!("Admin API disabled: no admin token or JWT authentication configured");
+            None
+        }
+    };
+
+    // HTTP/1.1 and HTTP/2 are both served, h2c included
+    let mut server = axum_server::from_tcp(listener).handle(handle);
+    tune_http(server.http_builder(), &config.http);
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(tls) => {
+            info!("Listening on {} (HTTPS)", addr);
+            server.acceptor(IdentityAcceptor::new(tls)).serve(service).await?;
+        }
+        None => {
+            info!("Listening on {}", addr);
+            server.serve(service).await?;
+        }
+    }
+
+    if let Some(admin_server) = admin_server {
+        admin_server.await??;
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(vsock_server) = vsock_server {
+        vsock_server.await?;
+    }
+
+    // Nothing reads entropy any more, so the devices can be let go
+    reader.stop().await;
+    devices.close().await;
+    if let Some(path) = &config.auth.quota_file {
+        quota::save_all(&api_keys, path);
+    }
+    info!("Shutdown complete");
+    Ok(())
+}
+
+/// Apply connection settings to both HTTP versions
+fn tune_http(builder: &mut auto::Builder<TokioExecutor>, http: &HttpConfig) {
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(http.keep_alive)
+        .header_read_timeout(Duration::from_secs(http.header_read_timeout_secs));
+    let kib = |window: Option<u32>| window.map(|kib| kib * 1024);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(http.max_concurrent_streams)
+        .keep_alive_interval(http.keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(http.keep_alive_timeout_secs))
+        .adaptive_window(http.stream_window_kib.is_none() && http.connection_window_kib.is_none())
+        .initial_stream_window_size(kib(http.stream_window_kib))
+        .initial_connection_window_size(kib(http.connection_window_kib));
+}
+
+/// Read the configuration file, environment and flags, and check the result
+fn load_config(cli: &Cli) -> Result<Config> {
+    let mut config = Config::load(cli.config.as_deref())?;
+    cli.override_config(&mut config)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Reload the configuration on every SIGHUP
+#[cfg(unix)]
+fn reload_on_hangup(reloader: Arc<ConfigReloader>) {
+    let mut hangups = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => return warn!("Cannot watch for SIGHUP: {}", e),
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            if let Err(e) = reloader.reload() {
+                warn!("Configuration not reloaded: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Resolve on Ctrl-C or SIGTERM
+async fn shutdown_signal() {
+    let terminate = async {
+        #[cfg(unix)]
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+                return;
+            }
+            Err(e) => warn!("Cannot watch for SIGTERM: {}", e),
+        }
+        std::future::pending::<()>().await
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+    info!("Shutting down, finishing requests in flight");
+    systemd::notify_stopping();
 }
\ No newline at end of file
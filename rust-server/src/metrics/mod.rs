@@ -0,0 +1,231 @@
+//! Prometheus metrics
+
+use prometheus::{
+    Encoder, Gauge, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::Mutex;
+
+use crate::api::tenants::TenantStatus;
+use crate::health::{autocorrelation::AutocorrelationStats, monitor::RollingStats};
+use crate::utils::telemetry::BufferTraffic;
+
+/// Statistics tracked by the rolling buffer monitor
+const ROLLING_ALARMS: [&str; 3] = ["chi_square", "bias", "serial_correlation"];
+
+/// Metrics exported at `/metrics`
+///
+/// Values are gauges refreshed from server state when scraped, except for
+/// the request counters, which handlers increment as they go. Buffer
+/// traffic and tenant totals are caught up to their own counters on scrape.
+pub struct Metrics {
+    registry: Registry,
+    buffer_available: IntGauge,
+    buffer_capacity: IntGauge,
+    chi_square: Gauge,
+    bit_bias: Gauge,
+    serial_correlation: Gauge,
+    rolling_alarm: IntGaugeVec,
+    autocorrelation: GaugeVec,
+    autocorrelation_alarm: IntGaugeVec,
+    buffer_traffic: [IntCounterVec; 5],
+    traffic_sync: Mutex<()>,
+    buffer_misses: IntCounterVec,
+    direct_read_bytes: IntCounter,
+    tenant_available: IntGaugeVec,
+    tenant_capacity: IntGaugeVec,
+    tenant_served: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("quantis".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let buffer_available = IntGauge::new("buffer_available_bytes", "Bytes available in the entropy buffer")
+            .expect("valid metric");
+        let buffer_capacity = IntGauge::new("buffer_capacity_bytes", "Entropy buffer capacity")
+            .expect("valid metric");
+        let chi_square = Gauge::new("rolling_chi_square", "Byte chi-square over the rolling window")
+            .expect("valid metric");
+        let bit_bias = Gauge::new("rolling_bit_bias", "Mean bit value minus 0.5 over the rolling window")
+            .expect("valid metric");
+        let serial_correlation = Gauge::new(
+            "rolling_serial_correlation",
+            "Lag-1 byte serial correlation over the rolling window",
+        )
+        .expect("valid metric");
+        let rolling_alarm = IntGaugeVec::new(
+            Opts::new("rolling_alarm", "Whether a rolling statistic is past its alarm threshold"),
+            &["statistic"],
+        )
+        .expect("valid metric");
+
+        let autocorrelation = GaugeVec::new(
+            Opts::new("autocorrelation", "Raw stream bit autocorrelation by lag"),
+            &["lag"],
+        )
+        .expect("valid metric");
+        let autocorrelation_alarm = IntGaugeVec::new(
+            Opts::new("autocorrelation_alarm", "Whether a lag is past the autocorrelation threshold"),
+            &["lag"],
+        )
+        .expect("valid metric");
+
+        let traffic_counter = |name: &str, help: &str| {
+            IntCounterVec::new(Opts::new(name, help), &["buffer"]).expect("valid metric")
+        };
+        let buffer_traffic = [
+            traffic_counter("buffer_bytes_in_total", "Bytes written to a buffer"),
+            traffic_counter("buffer_bytes_out_total", "Bytes read from a buffer"),
+            traffic_counter("buffer_bytes_restored_total", "Bytes returned to a buffer by failed requests"),
+            traffic_counter("buffer_overflows_total", "Writes to a buffer that did not fit in full"),
+            traffic_counter("buffer_overflowed_bytes_total", "Bytes discarded by buffer overflows"),
+        ];
+        let buffer_misses = IntCounterVec::new(
+            Opts::new("buffer_misses_total", "Requests the entropy buffer could not serve, by outcome"),
+            &["outcome"],
+        )
+        .expect("valid metric");
+        let direct_read_bytes = IntCounter::new(
+            "direct_read_bytes_total",
+            "Bytes read straight from the devices because the buffer was short",
+        )
+        .expect("valid metric");
+        let tenant_gauge = |name: &str, help: &str| {
+            IntGaugeVec::new(Opts::new(name, help), &["tenant"]).expect("valid metric")
+        };
+        let tenant_available = tenant_gauge("tenant_buffer_available_bytes", "Bytes in a tenant's buffer slice");
+        let tenant_capacity = tenant_gauge("tenant_buffer_capacity_bytes", "Capacity of a tenant's buffer slice");
+        let tenant_served = IntCounterVec::new(
+            Opts::new("tenant_served_bytes_total", "Output bytes delivered to a tenant's API keys"),
+            &["tenant"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(buffer_available.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(buffer_capacity.clone()),
+            Box::new(chi_square.clone()),
+            Box::new(bit_bias.clone()),
+            Box::new(serial_correlation.clone()),
+            Box::new(rolling_alarm.clone()),
+            Box::new(autocorrelation.clone()),
+            Box::new(autocorrelation_alarm.clone()),
+            Box::new(buffer_misses.clone()),
+            Box::new(direct_read_bytes.clone()),
+            Box::new(tenant_available.clone()),
+            Box::new(tenant_capacity.clone()),
+            Box::new(tenant_served.clone()),
+        ]
+        .into_iter()
+        .chain(buffer_traffic.iter().map(|counter| Box::new(counter.clone()) as _))
+        {
+            registry.register(collector).expect("unique metric");
+        }
+
+        Self {
+            registry,
+            buffer_available,
+            buffer_capacity,
+            chi_square,
+            bit_bias,
+            serial_correlation,
+            rolling_alarm,
+            autocorrelation,
+            autocorrelation_alarm,
+            buffer_traffic,
+            traffic_sync: Mutex::new(()),
+            buffer_misses,
+            direct_read_bytes,
+            tenant_available,
+            tenant_capacity,
+            tenant_served,
+        }
+    }
+
+    /// Record the buffer fill level
+    pub fn set_buffer(&self, available: usize, capacity: usize) {
+        self.buffer_available.set(available as i64);
+        self.buffer_capacity.set(capacity as i64);
+    }
+
+    /// Record the rolling buffer statistics
+    pub fn set_rolling(&self, stats: &RollingStats) {
+        self.chi_square.set(stats.chi_square);
+        self.bit_bias.set(stats.bias);
+        self.serial_correlation.set(stats.serial_correlation);
+        for statistic in ROLLING_ALARMS {
+            self.rolling_alarm
+                .with_label_values(&[statistic])
+                .set(stats.alarms.contains(&statistic) as i64);
+        }
+    }
+
+    /// Record the raw stream autocorrelation
+    pub fn set_autocorrelation(&self, stats: &AutocorrelationStats) {
+        for (i, value) in stats.lags.iter().enumerate() {
+            let lag = (i + 1).to_string();
+            self.autocorrelation.with_label_values(&[&lag]).set(*value);
+            self.autocorrelation_alarm
+                .with_label_values(&[&lag])
+                .set(stats.alarms.contains(&(i + 1)) as i64);
+        }
+    }
+
+    /// Catch the traffic counters of `buffer` up to its totals
+    pub fn set_buffer_traffic(&self, buffer: &str, traffic: &BufferTraffic) {
+        // Concurrent scrapes must not both add the same difference
+        let _sync = self.traffic_sync.lock().unwrap();
+        let totals = [
+            traffic.bytes_in,
+            traffic.bytes_out,
+            traffic.bytes_restored,
+            traffic.overflows,
+            traffic.overflowed_bytes,
+        ];
+        for (counter, total) in self.buffer_traffic.iter().zip(totals) {
+            let counter = counter.with_label_values(&[buffer]);
+            counter.inc_by(total.saturating_sub(counter.get()));
+        }
+    }
+
+    /// Record a tenant's slice fill level and catch up its delivered bytes
+    pub fn set_tenant(&self, status: &TenantStatus) {
+        let tenant = [status.name.as_str()];
+        self.tenant_available.with_label_values(&tenant).set(status.available as i64);
+        self.tenant_capacity.with_label_values(&tenant).set(status.capacity as i64);
+        let _sync = self.traffic_sync.lock().unwrap();
+        let served = self.tenant_served.with_label_values(&tenant);
+        served.inc_by(status.served_bytes.saturating_sub(served.get()));
+    }
+
+    /// Count a request served by a direct device read of `bytes`
+    pub fn record_direct_read(&self, bytes: usize) {
+        self.buffer_misses.with_label_values(&["direct_read"]).inc();
+        self.direct_read_bytes.inc_by(bytes as u64);
+    }
+
+    /// Count a request refused with 503 because the buffer was starved
+    pub fn record_starved(&self) {
+        self.buffer_misses.with_label_values(&["starved"]).inc();
+    }
+
+    /// Count a bulk request deferred because demand outran the devices
+    pub fn record_deferred(&self) {
+        self.buffer_misses.with_label_values(&["deferred"]).inc();
+    }
+
+    /// Render all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut output = Vec::new();
+        // Encoding into a Vec only fails on malformed metric families
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut output);
+        String::from_utf8(output).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,396 @@
+//! Server signing keys
+//!
+//! Ed25519 keys sign the outputs the server attests to. The key ring holds
+//! the current key and the public halves of the keys it replaced, so
+//! signatures made before a rotation still verify. Saved key rings, and
+//! the beacon's key, are sealed: encrypted with AES-256-GCM under a key
+//! derived from a passphrase with Argon2id.
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, Key, KeyInit, Nonce,
+};
+use argon2::Argon2;
+use ed25519_dalek::{Signer as _, SigningKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::health::unix_time;
+
+/// Signature scheme reported to clients
+pub const ALGORITHM: &str = "ed25519";
+
+/// Secret key length in bytes
+pub const SECRET_LEN: usize = 32;
+
+/// Retired public keys kept unless configured otherwise
+pub const DEFAULT_KEEP_PREVIOUS: usize = 3;
+
+/// Context signed ahead of `/random/bytes` output
+const BYTES_CONTEXT: &[u8] = b"quantis/random-bytes/v1";
+
+/// Associated data of an encrypted key ring
+const RING_CONTEXT: &[u8] = b"quantis/signing-keys/v1";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum SigningError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to write {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to decrypt {}: wrong passphrase or damaged file", path.display())]
+    Decrypt { path: PathBuf },
+
+    #[error("Invalid key file {}: {reason}", path.display())]
+    Invalid { path: PathBuf, reason: String },
+}
+
+/// Public half of a signing key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeyInfo {
+    /// Hex of the first 8 bytes of the public key's SHA-256
+    pub key_id: String,
+    pub algorithm: String,
+    /// Hex encoded public key
+    pub public_key: String,
+    /// Unix timestamp of the key's creation
+    pub created_at: u64,
+    /// Unix timestamp of the rotation that replaced the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<u64>,
+}
+
+impl PublicKeyInfo {
+    fn new(key: &SigningKey, created_at: u64) -> Self {
+        let public = key.verifying_key().to_bytes();
+        Self {
+            key_id: hex::encode(&Sha256::digest(public)[..8]),
+            algorithm: ALGORITHM.to_string(),
+            public_key: hex::encode(public),
+            created_at,
+            retired_at: None,
+        }
+    }
+}
+
+/// Signature made with a key of the ring
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Signature {
+    pub key_id: String,
+    pub algorithm: &'static str,
+    /// Hex encoded signature
+    pub signature: String,
+}
+
+/// Message signed for `/random/bytes` output with SHA-256 `digest`, signed
+/// at Unix time `signed_at`
+pub fn bytes_message(signed_at: u64, digest: &[u8]) -> Vec<u8> {
+    let mut message = BYTES_CONTEXT.to_vec();
+    message.extend_from_slice(&signed_at.to_be_bytes());
+    message.extend_from_slice(digest);
+    message
+}
+
+/// Current key and the public halves of its predecessors, newest first
+struct KeyRing {
+    key: SigningKey,
+    info: PublicKeyInfo,
+    previous: Vec<PublicKeyInfo>,
+}
+
+/// Key ring as saved, before encryption
+#[derive(Serialize, Deserialize)]
+struct StoredRing {
+    secret: String,
+    created_at: u64,
+    previous: Vec<PublicKeyInfo>,
+}
+
+/// Sealed key file
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Where and how the key ring is saved
+struct Store {
+    path: PathBuf,
+    passphrase: Zeroizing<String>,
+}
+
+/// The server's signing key ring
+pub struct Signer {
+    ring: RwLock<KeyRing>,
+    keep_previous: usize,
+    store: Option<Store>,
+}
+
+impl Signer {
+    /// Ring holding a new key from `secret`, keeping `keep_previous`
+    /// retired keys across rotations
+    pub fn new(secret: [u8; SECRET_LEN], keep_previous: usize) -> Self {
+        let key = SigningKey::from_bytes(&secret);
+        Self {
+            ring: RwLock::new(KeyRing {
+                info: PublicKeyInfo::new(&key, unix_time()),
+                key,
+                previous: Vec::new(),
+            }),
+            keep_previous,
+            store: None,
+        }
+    }
+
+    /// Save the ring to `path`, encrypted with `passphrase`, now and after
+    /// every rotation
+    pub fn with_store(mut self, path: PathBuf, passphrase: &str) -> Result<Self, SigningError> {
+        self.store = Some(Store {
+            path,
+            passphrase: Zeroizing::new(passphrase.to_string()),
+        });
+        self.save(&self.ring.read().unwrap())?;
+        Ok(self)
+    }
+
+    /// Open a ring saved with [`with_store`](Self::with_store)
+    pub fn load(path: &Path, passphrase: &str, keep_previous: usize) -> Result<Self, SigningError> {
+        let invalid = |reason: &str| SigningError::Invalid {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        };
+        let plaintext = unseal(path, passphrase, RING_CONTEXT)?;
+        let mut stored: StoredRing = serde_json::from_slice(&plaintext).map_err(|e| invalid(&e.to_string()))?;
+        let bytes = Zeroizing::new(hex::decode(&stored.secret).unwrap_or_default());
+        stored.secret.zeroize();
+        let secret: Zeroizing<[u8; SECRET_LEN]> = Zeroizing::new(
+            bytes.as_slice().try_into().map_err(|_| invalid("secret is not 32 hex-encoded bytes"))?,
+        );
+
+        let key = SigningKey::from_bytes(&secret);
+        Ok(Self {
+            ring: RwLock::new(KeyRing {
+                info: PublicKeyInfo::new(&key, stored.created_at),
+                key,
+                previous: stored.previous,
+            }),
+            keep_previous,
+            store: Some(Store {
+                path: path.to_path_buf(),
+                passphrase: Zeroizing::new(passphrase.to_string()),
+            }),
+        })
+    }
+
+    /// Sign `message` with the current key
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        let ring = self.ring.read().unwrap();
+        Signature {
+            key_id: ring.info.key_id.clone(),
+            algorithm: ALGORITHM,
+            signature: hex::encode(ring.key.sign(message).to_bytes()),
+        }
+    }
+
+    /// Public half of the current key
+    pub fn current(&self) -> PublicKeyInfo {
+        self.ring.read().unwrap().info.clone()
+    }
+
+    /// Current and retired public keys, newest first
+    pub fn public_keys(&self) -> Vec<PublicKeyInfo> {
+        let ring = self.ring.read().unwrap();
+        std::iter::once(ring.info.clone()).chain(ring.previous.iter().cloned()).collect()
+    }
+
+    /// Replace the current key with one from `secret`, retiring the old one
+    ///
+    /// With a store the new ring is saved first, and nothing changes if
+    /// that fails.
+    pub fn rotate(&self, secret: [u8; SECRET_LEN]) -> Result<PublicKeyInfo, SigningError> {
+        let mut ring = self.ring.write().unwrap();
+        let now = unix_time();
+        let key = SigningKey::from_bytes(&secret);
+        let mut retired = ring.info.clone();
+        retired.retired_at = Some(now);
+        let mut previous = vec![retired];
+        previous.extend(ring.previous.iter().cloned());
+        previous.truncate(self.keep_previous);
+
+        let rotated = KeyRing {
+            info: PublicKeyInfo::new(&key, now),
+            key,
+            previous,
+        };
+        self.save(&rotated)?;
+        *ring = rotated;
+        Ok(ring.info.clone())
+    }
+
+    /// Encrypt and write `ring` to the store, if there is one
+    fn save(&self, ring: &KeyRing) -> Result<(), SigningError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let mut stored = StoredRing {
+            secret: hex::encode(ring.key.to_bytes()),
+            created_at: ring.info.created_at,
+            previous: ring.previous.clone(),
+        };
+        let plaintext = Zeroizing::new(serde_json::to_vec(&stored).expect("key ring serializes"));
+        stored.secret.zeroize();
+        seal(&store.path, &store.passphrase, RING_CONTEXT, &plaintext)
+    }
+}
+
+/// Encrypt `plaintext` with `passphrase` and write it to `path`, bound to
+/// `context` so one kind of key file cannot pass for another
+pub fn seal(path: &Path, passphrase: &str, context: &[u8], plaintext: &[u8]) -> Result<(), SigningError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    let write_error = |source| SigningError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    getrandom::getrandom(&mut salt).map_err(|e| write_error(io::Error::other(e)))?;
+    getrandom::getrandom(&mut nonce).map_err(|e| write_error(io::Error::other(e)))?;
+    let cipher = cipher(passphrase, &salt).expect("salt is long enough");
+    let payload = Payload {
+        msg: plaintext,
+        aad: context,
+    };
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), payload).expect("key file encrypts");
+    let envelope = Envelope {
+        kdf: "argon2id".to_string(),
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    };
+
+    // Replace the file atomically so a crash cannot lose the key
+    let partial = path.with_extension("tmp");
+    fs::write(&partial, serde_json::to_vec_pretty(&envelope).expect("envelope serializes"))
+        .map_err(write_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&partial, fs::Permissions::from_mode(0o600)).map_err(write_error)?;
+    }
+    fs::rename(&partial, path).map_err(write_error)
+}
+
+/// Read and decrypt a file written by [`seal`] with the same `context`
+pub fn unseal(path: &Path, passphrase: &str, context: &[u8]) -> Result<Zeroizing<Vec<u8>>, SigningError> {
+    let invalid = |reason: &str| SigningError::Invalid {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    let data = fs::read(path).map_err(|source| SigningError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let envelope: Envelope = serde_json::from_slice(&data).map_err(|e| invalid(&e.to_string()))?;
+    if envelope.kdf != "argon2id" {
+        return Err(invalid("unknown key derivation"));
+    }
+    let salt = hex::decode(&envelope.salt).map_err(|_| invalid("salt is not hex"))?;
+    let nonce = hex::decode(&envelope.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == NONCE_LEN)
+        .ok_or_else(|| invalid("nonce is not 12 hex-encoded bytes"))?;
+    let ciphertext = hex::decode(&envelope.ciphertext).map_err(|_| invalid("ciphertext is not hex"))?;
+
+    let cipher = cipher(passphrase, &salt).map_err(|_| invalid("salt is too short"))?;
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: context,
+    };
+    Ok(Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| SigningError::Decrypt { path: path.to_path_buf() })?,
+    ))
+}
+
+/// AES-256-GCM keyed from `passphrase` and `salt` with Argon2id
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, argon2::Error> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default().hash_password_into(passphrase.as_bytes(), salt, key.as_mut())?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_ref())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Verifier, VerifyingKey};
+
+    fn verifies(info: &PublicKeyInfo, message: &[u8], signature: &Signature) -> bool {
+        let public: [u8; 32] = hex::decode(&info.public_key).unwrap().try_into().unwrap();
+        let signature: [u8; 64] = hex::decode(&signature.signature).unwrap().try_into().unwrap();
+        let signature = ed25519_dalek::Signature::from_bytes(&signature);
+        VerifyingKey::from_bytes(&public).unwrap().verify(message, &signature).is_ok()
+    }
+
+    #[test]
+    fn rotation_keeps_recent_public_keys() {
+        let signer = Signer::new([1; SECRET_LEN], 2);
+        let message = bytes_message(1_700_000_000, &[0; 32]);
+        let first = signer.current();
+        let signature = signer.sign(&message);
+        assert_eq!(signature.key_id, first.key_id);
+        assert!(verifies(&first, &message, &signature));
+
+        let second = signer.rotate([2; SECRET_LEN]).unwrap();
+        assert_ne!(second.key_id, first.key_id);
+        assert!(!verifies(&second, &message, &signature));
+        signer.rotate([3; SECRET_LEN]).unwrap();
+        signer.rotate([4; SECRET_LEN]).unwrap();
+
+        // Only the two most recently retired keys are kept
+        let keys = signer.public_keys();
+        assert_eq!(keys.len(), 3);
+        assert!(keys[0].retired_at.is_none());
+        assert!(keys[1..].iter().all(|key| key.retired_at.is_some()));
+        assert!(!keys.iter().any(|key| key.key_id == first.key_id));
+    }
+
+    #[test]
+    fn stored_ring_is_encrypted() {
+        let path = std::env::temp_dir().join(format!("quantis-signing-{}.json", std::process::id()));
+        let signer = Signer::new([5; SECRET_LEN], 3).with_store(path.clone(), "correct horse").unwrap();
+        let retired = signer.current();
+        signer.rotate([6; SECRET_LEN]).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains(&hex::encode([6u8; SECRET_LEN])));
+        assert!(matches!(Signer::load(&path, "wrong", 3), Err(SigningError::Decrypt { .. })));
+
+        let loaded = Signer::load(&path, "correct horse", 3).unwrap();
+        assert_eq!(loaded.public_keys(), signer.public_keys());
+        assert_eq!(loaded.public_keys()[1].key_id, retired.key_id);
+        assert_eq!(loaded.sign(b"beacon"), signer.sign(b"beacon"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! HTTPS termination
+//!
+//! Serves the API over TLS with rustls when a certificate and key are
+//! configured. The files are polled for changes so a renewed certificate is
+//! picked up without a restart; a renewal that fails to load keeps the
+//! previous certificate in service.
+//!
+//! With a client CA bundle, every connection must present a certificate
+//! issued by it, and requests carry the certificate's common name as a
+//! [`ClientIdentity`] for per-client authorization.
+
+use axum::{middleware::AddExtension, Extension};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{VerifierBuilderError, WebPkiClientVerifier},
+    RootCertStore, ServerConfig,
+};
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use tracing::{info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// How often the certificate and key files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: rustls::pki_types::pem::Error,
+    },
+
+    #[error("No certificate found in {}", .0.display())]
+    NoCertificate(PathBuf),
+
+    #[error("Invalid TLS settings: {0}")]
+    Rustls(#[from] rustls::Error),
+
+    #[error("Invalid client CA bundle: {0}")]
+    ClientCa(#[from] VerifierBuilderError),
+}
+
+/// PEM files served over HTTPS
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    /// Certificate chain, leaf first
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA bundle client certificates must chain to, if they are required
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    /// Build a server config from the current contents of the files
+    pub fn load(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        let certs = read_certs(&self.cert)?;
+        let key = PrivateKeyDer::from_pem_file(&self.key).map_err(|source| TlsError::Read {
+            path: self.key.clone(),
+            source,
+        })?;
+
+        let provider = Arc::new(ring::default_provider());
+        let builder =
+            ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(path)? {
+                    roots.add(cert)?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// Modification times of all files, None while any is missing
+    fn modified(&self) -> Option<Vec<SystemTime>> {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        [&self.cert, &self.key].into_iter().chain(&self.client_ca).map(modified).collect()
+    }
+}
+
+/// Read a non-empty PEM certificate list
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|source| TlsError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+/// Verified client certificate of the connection a request arrived on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// Subject common name, None without a client certificate or CN
+    pub common_name: Option<String>,
+}
+
+impl ClientIdentity {
+    /// Identity from the peer certificates of a completed handshake
+    pub fn of(certs: Option<&[CertificateDer<'_>]>) -> Self {
+        let common_name = certs.and_then(|certs| certs.first()).and_then(|leaf| {
+            let (_, cert) = X509Certificate::from_der(leaf).ok()?;
+            let name = cert.subject().iter_common_name().next()?.as_str().ok()?.to_string();
+            Some(name)
+        });
+        Self { common_name }
+    }
+}
+
+/// TLS acceptor that tags each connection's requests with its [`ClientIdentity`]
+#[derive(Clone)]
+pub struct IdentityAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl IdentityAcceptor {
+    pub fn new(config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+        }
+    }
+}
+
+impl<S: Send + 'static> Accept<TcpStream, S> for IdentityAcceptor {
+    type Stream = TlsStream<TcpStream>;
+    type Service = AddExtension<S, ClientIdentity>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let handshake = Accept::<TcpStream, S>::accept(&self.inner, stream, service);
+        Box::pin(async move {
+            let (stream, service) = handshake.await?;
+            let identity = ClientIdentity::of(stream.get_ref().1.peer_certificates());
+            Ok((stream, Extension(identity).layer(service)))
+        })
+    }
+}
+
+/// Swaps a served config for a new one when its files change
+pub struct Reloader {
+    files: TlsFiles,
+    config: RustlsConfig,
+    seen: Option<Vec<SystemTime>>,
+}
+
+impl Reloader {
+    /// Watch `files`, which `config` was loaded from
+    pub fn new(files: TlsFiles, config: RustlsConfig) -> Self {
+        let seen = files.modified();
+        Self { files, config, seen }
+    }
+
+    /// Reload if either file changed since the last check
+    ///
+    /// Returns whether a new config is now served. A change that fails to
+    /// load is reported once and the current config kept.
+    pub fn check(&mut self) -> Result<bool, TlsError> {
+        let modified = self.files.modified();
+        if modified.is_none() || modified == self.seen {
+            return Ok(false);
+        }
+        self.seen = modified;
+        self.config.reload_from_config(self.files.load()?);
+        Ok(true)
+    }
+
+    /// Check for changes every [`RELOAD_INTERVAL`] in the background
+    pub fn spawn(mut self) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(RELOAD_INTERVAL);
+            loop {
+                ticker.tick().await;
+                match self.check() {
+                    Ok(true) => info!("Reloaded TLS certificate from {}", self.files.cert.display()),
+                    Ok(false) => {}
+                    Err(e) => warn!("Keeping the current TLS certificate: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+
+    /// Write a fresh self-signed certificate and key into `dir`
+    fn write_pair(dir: &Path) -> TlsFiles {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let files = TlsFiles {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            client_ca: None,
+        };
+        fs::write(&files.cert, cert.cert.pem()).unwrap();
+        fs::write(&files.key, cert.key_pair.serialize_pem()).unwrap();
+        files
+    }
+
+    /// Move both files' modification times forward so a change is seen
+    fn touch(files: &TlsFiles, seconds: u64) {
+        let later = SystemTime::now() + Duration::from_secs(seconds);
+        for path in [&files.cert, &files.key] {
+            File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+        }
+    }
+
+    #[test]
+    fn reloads_changed_files_and_keeps_config_on_bad_ones() {
+        let dir = std::env::temp_dir().join(format!("quantis-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = write_pair(&dir);
+        let config = RustlsConfig::from_config(files.load().unwrap());
+        let mut reloader = Reloader::new(files.clone(), config.clone());
+        assert!(!reloader.check().unwrap());
+
+        let first = config.get_inner();
+        write_pair(&dir);
+        touch(&files, 60);
+        assert!(reloader.check().unwrap());
+        let renewed = config.get_inner();
+        assert!(!Arc::ptr_eq(&first, &renewed));
+
+        fs::write(&files.key, "not a key").unwrap();
+        touch(&files, 120);
+        assert!(reloader.check().is_err());
+        assert!(Arc::ptr_eq(&renewed, &config.get_inner()));
+        assert!(!reloader.check().unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn requires_clients_from_the_ca_bundle() {
+        let dir = std::env::temp_dir().join(format!("quantis-tls-ca-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut files = write_pair(&dir);
+        files.client_ca = Some(files.cert.clone());
+        assert!(files.load().is_ok());
+
+        files.client_ca = Some(dir.join("missing.pem"));
+        assert!(matches!(files.load(), Err(TlsError::Read { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn identity_is_the_leaf_common_name() {
+        let mut params = rcgen::CertificateParams::new(vec!["sensor.lab".to_string()]).unwrap();
+        params.distinguished_name.push(rcgen::DnType::CommonName, "sensor-7");
+        let cert = params.self_signed(&rcgen::KeyPair::generate().unwrap()).unwrap();
+
+        let identity = ClientIdentity::of(Some(std::slice::from_ref(cert.der())));
+        assert_eq!(identity.common_name.as_deref(), Some("sensor-7"));
+        assert_eq!(ClientIdentity::of(None), ClientIdentity::default());
+    }
+
+    #[test]
+    fn rejects_missing_certificate() {
+        let dir = std::env::temp_dir().join(format!("quantis-tls-empty-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = write_pair(&dir);
+        fs::write(&files.cert, "").unwrap();
+        assert!(matches!(files.load(), Err(TlsError::NoCertificate(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+//! Conditioned entropy for local consumers
+//!
+//! The kernel feed, EGD socket, virtio-rng backend and FIFO output hand
+//! out bytes from the raw buffer outside the HTTP request path. They share one SHA-3
+//! conditioning step, leave the interactive reserve alone and serve
+//! nothing while a health test failure is outstanding.
+
+use std::{sync::Arc, time::Duration};
+use zeroize::Zeroizing;
+
+use super::RingBuffer;
+use crate::device::pipeline::{Pipeline, StageDefaults};
+use crate::health::HealthState;
+
+/// Conditioning applied to raw bytes before they are served
+const PIPELINE: &str = "sha3";
+
+/// SHA-3 conditioned bytes drawn from the raw buffer
+#[derive(Clone)]
+pub struct ConditionedSource {
+    buffer: Arc<RingBuffer>,
+    health: Arc<HealthState>,
+    pipeline: Arc<Pipeline>,
+    reserve: usize,
+}
+
+impl ConditionedSource {
+    /// Draw from `buffer`, leaving `reserve` bytes for interactive requests
+    pub fn new(buffer: Arc<RingBuffer>, health: Arc<HealthState>, reserve: usize) -> Self {
+        let pipeline = Pipeline::parse(PIPELINE, StageDefaults::default()).expect("valid pipeline");
+        Self {
+            buffer,
+            health,
+            pipeline: Arc::new(pipeline),
+            reserve,
+        }
+    }
+
+    /// Conditioned bytes that could be served now
+    pub fn available(&self) -> usize {
+        if self.health.failure().is_some() {
+            return 0;
+        }
+        let raw = self.buffer.available().saturating_sub(self.reserve);
+        let ratio = self.pipeline.input_len(1024).div_ceil(1024).max(1);
+        raw / ratio
+    }
+
+    /// Up to `count` conditioned bytes, fewer if the buffer runs short
+    pub fn read(&self, count: usize) -> Zeroizing<Vec<u8>> {
+        let count = count.min(self.available());
+        if count == 0 {
+            return Zeroizing::new(Vec::new());
+        }
+        // Raw data in the buffer has already passed the continuous tests
+        let Some(raw) = self.buffer.read_above(self.pipeline.input_len(count), self.reserve) else {
+            return Zeroizing::new(Vec::new());
+        };
+        let mut output = Zeroizing::new(self.health.fips_filter(self.pipeline.apply(&raw)));
+        output.truncate(count);
+        output
+    }
+}
+
+/// Bytes per write, at most `max_chunk`, and the pause between writes
+/// that deliver `bytes_per_sec`
+pub fn pace(bytes_per_sec: usize, max_chunk: usize) -> (usize, Duration) {
+    let chunk = bytes_per_sec.clamp(1, max_chunk);
+    (chunk, Duration::from_secs_f64(chunk as f64 / bytes_per_sec.max(1) as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::DEFAULT_MIN_ENTROPY;
+
+    #[test]
+    fn keeps_the_reserve() {
+        let buffer = Arc::new(RingBuffer::new(64 * 1024));
+        buffer.write(&[0x5a; 4096]);
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let source = ConditionedSource::new(buffer.clone(), health, 2048);
+
+        let available = source.available();
+        assert!(available > 0 && available < 2048);
+        assert_eq!(source.read(available * 4).len(), available);
+        assert!(buffer.available() >= 2048);
+        assert!(source.read(64).is_empty());
+    }
+
+    #[test]
+    fn paces_writes() {
+        assert_eq!(pace(1024, 512), (512, Duration::from_millis(500)));
+        assert_eq!(pace(100, 512), (100, Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,170 @@
+//! Entropy Gathering Daemon protocol on a Unix socket
+//!
+//! Serves SHA-3 conditioned bytes from the raw buffer to software that
+//! predates `/dev/urandom` support, such as older OpenSSL (`RAND_egd`) and
+//! GnuPG builds. Each request is a command byte and its arguments:
+//!
+//! | Command | Request | Reply |
+//! |---|---|---|
+//! | `0x00` | | bits of entropy available, 4 bytes big-endian |
+//! | `0x01` | count | length byte, then up to count bytes without waiting |
+//! | `0x02` | count | exactly count bytes, waiting for them |
+//! | `0x03` | bits (4 bytes), count, data | none; the data is discarded |
+//! | `0x04` | | length byte, then the process ID in ASCII |
+
+use std::{io, path::Path, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(unix)]
+use tracing::{debug, info, warn};
+use zeroize::Zeroizing;
+
+use super::conditioned::ConditionedSource;
+
+/// Default permissions of the socket file
+pub const DEFAULT_MODE: u32 = 0o660;
+
+/// Pause before retrying a blocking read on a drained buffer
+const RETRY: Duration = Duration::from_millis(10);
+
+/// Exactly `count` conditioned bytes, waiting for the buffer to refill
+async fn read_exact(source: &ConditionedSource, count: usize) -> Zeroizing<Vec<u8>> {
+    let mut output = Zeroizing::new(Vec::with_capacity(count));
+    while output.len() < count {
+        let chunk = source.read(count - output.len());
+        if chunk.is_empty() {
+            tokio::time::sleep(RETRY).await;
+        }
+        output.extend_from_slice(&chunk);
+    }
+    output
+}
+
+/// Answer EGD requests on `stream` until the peer hangs up
+///
+/// Unknown commands close the connection, as the peer's framing is lost.
+pub async fn serve_connection<S>(mut stream: S, source: &ConditionedSource) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let command = match stream.read_u8().await {
+            Ok(command) => command,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match command {
+            0x00 => {
+                let bits = source.available().saturating_mul(8).min(u32::MAX as usize) as u32;
+                stream.write_u32(bits).await?;
+            }
+            0x01 => {
+                let count = stream.read_u8().await?;
+                let bytes = source.read(count.into());
+                stream.write_u8(bytes.len() as u8).await?;
+                stream.write_all(&bytes).await?;
+            }
+            0x02 => {
+                let count = stream.read_u8().await?;
+                stream.write_all(&read_exact(source, count.into()).await).await?;
+            }
+            0x03 => {
+                let _bits = stream.read_u32().await?;
+                let count = stream.read_u8().await?;
+                let mut discarded = Zeroizing::new(vec![0; count.into()]);
+                stream.read_exact(&mut discarded).await?;
+            }
+            0x04 => {
+                let pid = std::process::id().to_string();
+                stream.write_u8(pid.len() as u8).await?;
+                stream.write_all(pid.as_bytes()).await?;
+            }
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown EGD command {:#04x}", other),
+                ))
+            }
+        }
+        stream.flush().await?;
+    }
+}
+
+/// Listen for EGD clients on `path`, replacing a stale socket file there
+#[cfg(unix)]
+pub fn start_egd_server(path: &Path, mode: u32, source: ConditionedSource) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    info!("Serving EGD clients on {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept EGD connection: {}", e);
+                    tokio::time::sleep(RETRY).await;
+                    continue;
+                }
+            };
+            let source = source.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(stream, &source).await {
+                    debug!("EGD connection closed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn start_egd_server(_: &Path, _: u32, _: ConditionedSource) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "EGD sockets are unix-only"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{HealthState, DEFAULT_MIN_ENTROPY};
+    use crate::utils::RingBuffer;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn answers_egd_commands() {
+        let buffer = Arc::new(RingBuffer::new(64 * 1024));
+        buffer.write(&(0..16 * 1024).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>());
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let source = ConditionedSource::new(buffer, health, 1024);
+        let (mut client, server) = tokio::io::duplex(4096);
+        let served = tokio::spawn(async move { serve_connection(server, &source).await });
+
+        client.write_u8(0x00).await.unwrap();
+        let bits = client.read_u32().await.unwrap();
+        assert!(bits > 0 && bits < 16 * 1024 * 8);
+
+        client.write_all(&[0x01, 32]).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), 32);
+        let mut bytes = [0; 32];
+        client.read_exact(&mut bytes).await.unwrap();
+
+        client.write_all(&[0x03, 0, 0, 0, 16, 2, 0xaa, 0xbb, 0x02, 16]).await.unwrap();
+        let mut blocking = [0; 16];
+        client.read_exact(&mut blocking).await.unwrap();
+        assert_ne!(bytes[..16], blocking);
+
+        client.write_u8(0x04).await.unwrap();
+        let len = client.read_u8().await.unwrap();
+        let mut pid = vec![0; len.into()];
+        client.read_exact(&mut pid).await.unwrap();
+        assert_eq!(String::from_utf8(pid).unwrap(), std::process::id().to_string());
+
+        client.write_u8(0x7f).await.unwrap();
+        assert!(served.await.unwrap().is_err());
+    }
+}
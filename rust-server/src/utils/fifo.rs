@@ -0,0 +1,124 @@
+//! Continuous entropy output to a named pipe
+//!
+//! Conditioned bytes are written to a FIFO, created if missing, at a capped
+//! rate for local processes that just read a file, e.g.
+//! `dd if=/run/quantis.fifo bs=32 count=1`. Any writable path works,
+//! including `/dev/fd/N` for a descriptor inherited from the parent.
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+use super::conditioned::{self, ConditionedSource};
+
+/// Most bytes per write
+const MAX_CHUNK: usize = 4096;
+
+/// Pause before retrying on a drained buffer or a failed open
+const RETRY: Duration = Duration::from_millis(100);
+
+/// Create a FIFO at `path` unless something is there already
+fn create(path: &Path, mode: u32) -> io::Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_fifo() => {
+            debug!("{} is not a FIFO, writing to it as it is", path.display());
+            return Ok(());
+        }
+        Ok(_) => return Ok(()),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => {}
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    // SAFETY: `c_path` is a valid NUL-terminated path
+    if unsafe { libc::mkfifo(c_path.as_ptr(), mode as libc::mode_t) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // mkfifo honours the umask; the configured mode is meant as given
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Write `bytes_per_sec` conditioned bytes from `source` to `path`
+///
+/// Opening a FIFO waits for a reader; when the reader goes away the pipe
+/// is reopened for the next one. Readers that fall behind hold up the
+/// writes rather than losing bytes.
+pub fn start_fifo_writer(
+    path: PathBuf,
+    mode: u32,
+    source: ConditionedSource,
+    bytes_per_sec: usize,
+) -> io::Result<()> {
+    create(&path, mode)?;
+    let (chunk, pause) = conditioned::pace(bytes_per_sec, MAX_CHUNK);
+    info!("Writing {} bytes/s of entropy to {}", bytes_per_sec, path.display());
+
+    std::thread::Builder::new().name("fifo".to_string()).spawn(move || loop {
+        let mut file = match File::options().write(true).open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Failed to open {}: {}", path.display(), e);
+                std::thread::sleep(RETRY);
+                continue;
+            }
+        };
+        debug!("Reader attached to {}", path.display());
+        if let Err(e) = write_paced(&mut file, &source, chunk, pause) {
+            debug!("Stopped writing to {}: {}", path.display(), e);
+        }
+    })?;
+    Ok(())
+}
+
+/// Write `chunk` bytes every `pause` until the write fails
+fn write_paced(
+    file: &mut impl Write,
+    source: &ConditionedSource,
+    chunk: usize,
+    pause: Duration,
+) -> io::Result<()> {
+    let mut next = Instant::now();
+    loop {
+        let bytes = source.read(chunk);
+        if bytes.is_empty() {
+            std::thread::sleep(RETRY);
+            continue;
+        }
+        file.write_all(&bytes)?;
+
+        // Catch up after slow readers without bursting past the rate
+        next = (next + pause).max(Instant::now());
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{HealthState, DEFAULT_MIN_ENTROPY};
+    use crate::utils::RingBuffer;
+    use std::{io::Read, sync::Arc};
+
+    #[test]
+    fn streams_to_a_fifo() {
+        let buffer = Arc::new(RingBuffer::new(64 * 1024));
+        buffer.write(&(0..32 * 1024).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>());
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let source = ConditionedSource::new(buffer, health, 0);
+
+        let path = std::env::temp_dir().join(format!("quantis-fifo-{}", std::process::id()));
+        start_fifo_writer(path.clone(), 0o600, source, 1 << 20).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        let mut bytes = [0u8; 256];
+        File::open(&path).unwrap().read_exact(&mut bytes).unwrap();
+        assert!(bytes.iter().any(|&b| b != 0));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! Feeding the host kernel's entropy pool, like rngd
+//!
+//! Conditioned output is handed to the kernel with the `RNDADDENTROPY`
+//! ioctl on `/dev/random`, which mixes it into the input pool and credits
+//! the given amount of entropy. Crediting needs `CAP_SYS_ADMIN`.
+
+use std::{fs::File, io, os::fd::AsRawFd, path::Path};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use super::conditioned::{self, ConditionedSource};
+
+/// Most bytes handed to the kernel per ioctl, as rngd does
+pub const MAX_CHUNK: usize = 512;
+
+/// `_IOW('R', 0x03, int[2])` from `linux/random.h`
+const RNDADDENTROPY: libc::c_ulong = 0x4008_5203;
+
+/// Handle on the kernel's random device
+pub struct KernelFeed {
+    file: File,
+    credit_bits_per_byte: u8,
+}
+
+impl KernelFeed {
+    /// Open `device`, checking the ioctl is permitted before any entropy
+    /// is spent on it
+    pub fn open(device: &Path, credit_bits_per_byte: u8) -> io::Result<Self> {
+        let file = File::options().write(true).open(device)?;
+        let feed = Self {
+            file,
+            credit_bits_per_byte,
+        };
+        feed.add(&[])?;
+        Ok(feed)
+    }
+
+    /// Mix `bytes` into the kernel pool, crediting their entropy
+    pub fn add(&self, bytes: &[u8]) -> io::Result<()> {
+        let request = pool_info(bytes, self.credit_bits_per_byte);
+        // SAFETY: `request` is a complete `rand_pool_info` the kernel only reads
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), RNDADDENTROPY as _, request.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// `struct rand_pool_info`: entropy bits credited, buffer length in bytes,
+/// then the buffer padded to whole words
+fn pool_info(bytes: &[u8], credit_bits_per_byte: u8) -> Zeroizing<Vec<u32>> {
+    let mut words = Zeroizing::new(Vec::with_capacity(2 + bytes.len().div_ceil(4)));
+    let credit = bytes.len() * usize::from(credit_bits_per_byte);
+    words.push(credit as i32 as u32);
+    words.push(bytes.len() as i32 as u32);
+    for chunk in bytes.chunks(4) {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        words.push(u32::from_ne_bytes(word));
+    }
+    words
+}
+
+/// Feed `bytes_per_sec` conditioned bytes from `source` to the kernel
+pub fn start_kernel_feed(source: ConditionedSource, feed: KernelFeed, bytes_per_sec: usize) {
+    let (chunk, pause) = conditioned::pace(bytes_per_sec, MAX_CHUNK);
+    tokio::spawn(async move {
+        info!(
+            "Feeding {} bytes/s to the kernel, credited at {} bits per byte",
+            bytes_per_sec, feed.credit_bits_per_byte
+        );
+        let mut ticker = tokio::time::interval(pause);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let output = source.read(chunk);
+            if output.is_empty() {
+                continue;
+            }
+            if let Err(e) = feed.add(&output) {
+                warn!("Failed to feed the kernel entropy pool: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_pool_info() {
+        let info = pool_info(&[1, 2, 3, 4, 5], 6);
+        assert_eq!(info[0], 30);
+        assert_eq!(info[1], 5);
+        assert_eq!(info[2], u32::from_ne_bytes([1, 2, 3, 4]));
+        assert_eq!(info[3], u32::from_ne_bytes([5, 0, 0, 0]));
+        assert_eq!(*pool_info(&[], 8), vec![0, 0]);
+    }
+
+    #[test]
+    fn refuses_files_without_the_ioctl() {
+        let path = std::env::temp_dir().join(format!("quantis-kernel-feed-{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        assert!(KernelFeed::open(&path, 8).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Pre-conditioned entropy pools
+//!
+//! Each pool holds the output of one correction pipeline, filled in the
+//! background from tested raw data in the main buffer, so requests for
+//! that pipeline skip conditioning on the request path.
+
+use bytes::Bytes;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+use super::RingBuffer;
+use crate::device::pipeline::Pipeline;
+
+/// Pipelines pooled by default
+pub const DEFAULT_POOLS: &str = "von_neumann,sha3";
+
+/// Default capacity of each pool in bytes
+pub const DEFAULT_POOL_SIZE: usize = 1024 * 1024;
+
+/// Smallest pool capacity
+pub const MIN_POOL_SIZE: usize = 4096;
+
+/// Default refill watermark as a fraction of pool capacity
+pub const REFILL_BELOW: f64 = 0.5;
+
+/// Most output bytes produced per refill step
+pub const REFILL_CHUNK: usize = 16 * 1024;
+
+/// Conditioned output of a single pipeline
+pub struct ConditionedPool {
+    pipeline: Pipeline,
+    buffer: RingBuffer,
+    /// Pool is refilled while holding fewer bytes than this
+    refill_below: AtomicUsize,
+}
+
+impl ConditionedPool {
+    pub fn new(pipeline: Pipeline, capacity: usize) -> Self {
+        Self {
+            pipeline,
+            buffer: RingBuffer::new(capacity),
+            refill_below: AtomicUsize::new(default_watermark(capacity)),
+        }
+    }
+
+    /// Pipeline whose output the pool holds
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Buffered conditioned output
+    pub fn buffer(&self) -> &RingBuffer {
+        &self.buffer
+    }
+
+    /// Fill level below which the pool is refilled
+    pub fn refill_below(&self) -> usize {
+        self.refill_below.load(Ordering::Relaxed)
+    }
+
+    /// Change the capacity and refill watermark, keeping pooled output
+    ///
+    /// Without a watermark the default fraction of the new capacity is
+    /// used. Returns the number of bytes dropped when shrinking.
+    pub fn resize(&self, capacity: usize, refill_below: Option<usize>) -> usize {
+        let dropped = if capacity == self.buffer.capacity() {
+            0
+        } else {
+            self.buffer.resize(capacity)
+        };
+        let refill_below = refill_below.unwrap_or_else(|| default_watermark(capacity));
+        self.refill_below.store(refill_below.min(capacity), Ordering::Relaxed);
+        dropped
+    }
+
+    /// Current fill level
+    pub fn status(&self) -> PoolStatus {
+        PoolStatus {
+            pipeline: self.pipeline.to_string(),
+            capacity: self.buffer.capacity(),
+            available: self.buffer.available(),
+            refill_below: self.refill_below(),
+        }
+    }
+
+    /// Output bytes to produce in the next refill step, 0 if full enough
+    pub fn wanted(&self) -> usize {
+        let available = self.buffer.available();
+        if available < self.refill_below() {
+            self.buffer.capacity().saturating_sub(available).min(REFILL_CHUNK)
+        } else {
+            0
+        }
+    }
+}
+
+fn default_watermark(capacity: usize) -> usize {
+    (capacity as f64 * REFILL_BELOW) as usize
+}
+
+/// Fill level of a pool
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    pub pipeline: String,
+    pub capacity: usize,
+    pub available: usize,
+    pub refill_below: usize,
+}
+
+/// All configured pools
+#[derive(Default)]
+pub struct PoolSet {
+    pools: Vec<ConditionedPool>,
+    drained: Notify,
+}
+
+impl PoolSet {
+    pub fn new(pools: Vec<ConditionedPool>) -> Self {
+        Self {
+            pools,
+            drained: Notify::new(),
+        }
+    }
+
+    pub fn pools(&self) -> &[ConditionedPool] {
+        &self.pools
+    }
+
+    /// Pool holding `pipeline` output
+    pub fn find(&self, pipeline: &Pipeline) -> Option<&ConditionedPool> {
+        let spec = pipeline.to_string();
+        self.pools.iter().find(|pool| pool.pipeline.to_string() == spec)
+    }
+
+    /// Read `count` bytes of `pipeline` output if its pool holds enough
+    pub fn read(&self, pipeline: &Pipeline, count: usize) -> Option<Bytes> {
+        let pool = self.find(pipeline)?;
+        // Misses wake the filler too, as they show demand for the pool
+        self.drained.notify_one();
+        pool.buffer.read(count)
+    }
+
+    /// Wait until a pool has been read from
+    pub async fn drained(&self) {
+        self.drained.notified().await
+    }
+
+    /// Fill levels for `/stats`
+    pub fn status(&self) -> Vec<PoolStatus> {
+        self.pools.iter().map(ConditionedPool::status).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::pipeline::StageDefaults;
+
+    #[test]
+    fn serves_matching_pipeline_and_refills_below_watermark() {
+        let sha3 = Pipeline::parse("sha3", StageDefaults::default()).unwrap();
+        let pools = PoolSet::new(vec![ConditionedPool::new(sha3, 64)]);
+        let pool = &pools.pools()[0];
+        assert_eq!(pool.wanted(), 64);
+
+        pool.buffer().write(&[1; 40]);
+        assert_eq!(pool.wanted(), 0);
+
+        // Specs are matched in their normalized form
+        let explicit = Pipeline::parse("sha3:2", StageDefaults::default()).unwrap();
+        assert_eq!(pools.read(&explicit, 16).unwrap()[..], [1; 16]);
+        assert!(pools.read(&Pipeline::default(), 8).is_none());
+        assert!(pools.read(&explicit, 32).is_none());
+        assert_eq!(pools.status()[0].available, 24);
+        assert_eq!(pool.wanted(), 40);
+
+        // Watermarks follow the capacity unless set explicitly
+        assert_eq!(pool.resize(128, None), 0);
+        assert_eq!(pool.refill_below(), 64);
+        assert_eq!(pool.resize(16, Some(8)), 8);
+        assert_eq!(pool.refill_below(), 8);
+        assert_eq!(pool.wanted(), 0);
+    }
+}
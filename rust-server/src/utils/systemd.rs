@@ -0,0 +1,63 @@
+//! systemd service integration
+//!
+//! Under a `Type=notify` unit the server reports readiness once it has
+//! warmed up (see [`crate::api::readiness`]), and reports when it starts
+//! stopping. Listening sockets passed by socket activation are served
+//! instead of binding `server.listen` and `admin.listen`. Outside systemd
+//! both are no-ops, as is notification on platforms without systemd.
+
+use listenfd::ListenFd;
+#[cfg(unix)]
+use sd_notify::NotifyState;
+use std::{io, net::TcpListener};
+#[cfg(unix)]
+use tracing::warn;
+
+/// Position of the public API socket among those passed by socket activation
+pub const PUBLIC_SOCKET: usize = 0;
+
+/// Position of the admin API socket, which is optional
+pub const ADMIN_SOCKET: usize = 1;
+
+/// The TCP socket passed by socket activation at `index`, ready for async use
+pub fn activated_listener(index: usize) -> io::Result<Option<TcpListener>> {
+    let listener = ListenFd::from_env().take_tcp_listener(index)?;
+    if let Some(listener) = &listener {
+        listener.set_nonblocking(true)?;
+    }
+    Ok(listener)
+}
+
+/// Report readiness with `status`, returning whether systemd is listening
+pub fn notify_ready(status: &str) -> bool {
+    #[cfg(unix)]
+    {
+        notify(&[NotifyState::Ready, NotifyState::Status(status)])
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+/// Report that the server is shutting down
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    notify(&[NotifyState::Stopping]);
+}
+
+/// Send `states` to the service manager, returning whether one is listening
+#[cfg(unix)]
+fn notify(states: &[NotifyState]) -> bool {
+    if std::env::var_os("NOTIFY_SOCKET").is_none() {
+        return false;
+    }
+    match sd_notify::notify(false, states) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to notify systemd: {}", e);
+            false
+        }
+    }
+}
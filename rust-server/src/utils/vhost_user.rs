@@ -0,0 +1,642 @@
+//! virtio-rng device for guest VMs over vhost-user
+//!
+//! Hypervisors such as QEMU hand the device's virtqueue to this process
+//! through a vhost-user socket (`-device vhost-user-rng-pci`), so guests
+//! read conditioned entropy through their ordinary virtio-rng driver
+//! without USB passthrough. Guest memory must be shared with the backend,
+//! e.g. with `-object memory-backend-memfd,share=on`.
+//!
+//! Each connection is served on a thread of its own: vhost-user messages
+//! carry file descriptors, and guest memory is accessed through mappings.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::atomic::{fence, Ordering},
+};
+use tracing::{debug, info, warn};
+
+use super::conditioned::ConditionedSource;
+
+/// Pause in milliseconds before refilling requests left on a drained buffer
+const RETRY_MS: i32 = 10;
+
+/// Largest virtqueue accepted
+const MAX_QUEUE_SIZE: u32 = 32768;
+
+/// Most memory regions in one `SET_MEM_TABLE`
+const MAX_REGIONS: usize = 8;
+
+/// Header flags
+const VERSION: u32 = 0x1;
+const REPLY: u32 = 1 << 2;
+const NEED_REPLY: u32 = 1 << 3;
+
+/// Device features: little-endian virtio 1.0 rings
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+/// Protocol extensions are negotiated separately
+const PROTOCOL_FEATURES: u64 = 1 << 30;
+/// Requests may ask for a success or failure reply
+const PROTOCOL_F_REPLY_ACK: u64 = 1 << 3;
+
+/// Descriptor continues in `next`
+const DESC_F_NEXT: u16 = 1;
+/// Descriptor is writable by the device
+const DESC_F_WRITE: u16 = 2;
+
+/// Front-end requests handled here, from the vhost-user specification
+mod request {
+    pub const GET_FEATURES: u32 = 1;
+    pub const SET_FEATURES: u32 = 2;
+    pub const SET_OWNER: u32 = 3;
+    pub const RESET_OWNER: u32 = 4;
+    pub const SET_MEM_TABLE: u32 = 5;
+    pub const SET_VRING_NUM: u32 = 8;
+    pub const SET_VRING_ADDR: u32 = 9;
+    pub const SET_VRING_BASE: u32 = 10;
+    pub const GET_VRING_BASE: u32 = 11;
+    pub const SET_VRING_KICK: u32 = 12;
+    pub const SET_VRING_CALL: u32 = 13;
+    pub const SET_VRING_ERR: u32 = 14;
+    pub const GET_PROTOCOL_FEATURES: u32 = 15;
+    pub const SET_PROTOCOL_FEATURES: u32 = 16;
+    pub const GET_QUEUE_NUM: u32 = 17;
+    pub const SET_VRING_ENABLE: u32 = 18;
+}
+
+fn invalid(reason: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// One guest memory region mapped into this process
+struct Region {
+    guest_addr: u64,
+    user_addr: u64,
+    size: u64,
+    base: *mut u8,
+    /// Mapping to release, absent for borrowed memory in tests
+    mapping: Option<(*mut libc::c_void, usize)>,
+}
+
+impl Region {
+    fn map(fd: &OwnedFd, guest_addr: u64, size: u64, user_addr: u64, offset: u64) -> io::Result<Self> {
+        let len = usize::try_from(size.checked_add(offset).ok_or_else(|| invalid("region overflows"))?)
+            .map_err(invalid)?;
+        // SAFETY: a fresh shared mapping of the region's file, released on drop
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            guest_addr,
+            user_addr,
+            size,
+            // SAFETY: `offset` lies within the `len` bytes just mapped
+            base: unsafe { map.cast::<u8>().add(offset as usize) },
+            mapping: Some((map, len)),
+        })
+    }
+
+    /// `len` bytes at `addr` in a space starting at `start`, if inside
+    fn slice(&self, start: u64, addr: u64, len: u64) -> Option<*mut u8> {
+        let offset = addr.checked_sub(start)?;
+        (offset.checked_add(len)? <= self.size).then(|| {
+            // SAFETY: the range was checked against the region's size
+            unsafe { self.base.add(offset as usize) }
+        })
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        if let Some((map, len)) = self.mapping {
+            // SAFETY: the mapping was made in `map` and is not used again
+            unsafe { libc::munmap(map, len) };
+        }
+    }
+}
+
+/// Guest memory as the front-end described it
+#[derive(Default)]
+struct Memory {
+    regions: Vec<Region>,
+}
+
+impl Memory {
+    /// Guest physical range, as descriptors give it
+    fn guest(&self, addr: u64, len: u64) -> Option<*mut u8> {
+        self.regions.iter().find_map(|r| r.slice(r.guest_addr, addr, len))
+    }
+
+    /// Front-end virtual range, as ring addresses are given
+    fn user(&self, addr: u64, len: u64) -> Option<*mut u8> {
+        self.regions.iter().find_map(|r| r.slice(r.user_addr, addr, len))
+    }
+}
+
+/// Little-endian ring fields at an address already checked to be mapped
+///
+/// Guest memory changes underneath us, so every access is volatile.
+fn read_u16(ptr: *mut u8, offset: usize) -> u16 {
+    // SAFETY: callers check `offset + 2` lies inside the mapping
+    u16::from_le(unsafe { std::ptr::read_volatile(ptr.add(offset).cast::<u16>()) })
+}
+
+fn read_u32(ptr: *mut u8, offset: usize) -> u32 {
+    // SAFETY: as `read_u16`
+    u32::from_le(unsafe { std::ptr::read_volatile(ptr.add(offset).cast::<u32>()) })
+}
+
+fn read_u64(ptr: *mut u8, offset: usize) -> u64 {
+    // SAFETY: as `read_u16`
+    u64::from_le(unsafe { std::ptr::read_volatile(ptr.add(offset).cast::<u64>()) })
+}
+
+fn write_u16(ptr: *mut u8, offset: usize, value: u16) {
+    // SAFETY: as `read_u16`
+    unsafe { std::ptr::write_volatile(ptr.add(offset).cast::<u16>(), value.to_le()) }
+}
+
+fn write_u32(ptr: *mut u8, offset: usize, value: u32) {
+    // SAFETY: as `read_u16`
+    unsafe { std::ptr::write_volatile(ptr.add(offset).cast::<u32>(), value.to_le()) }
+}
+
+/// The device's single request queue
+#[derive(Default)]
+struct Queue {
+    size: u16,
+    /// Front-end addresses of the descriptor table, available and used rings
+    desc: u64,
+    avail: u64,
+    used: u64,
+    next_avail: u16,
+    kick: Option<File>,
+    call: Option<File>,
+    enabled: bool,
+}
+
+impl Queue {
+    fn ready(&self) -> bool {
+        self.enabled && self.kick.is_some() && self.size > 0
+    }
+
+    /// Fill the guest's queued buffers from `source`
+    ///
+    /// Returns how many buffers were used and whether the source ran dry
+    /// with requests still queued.
+    fn fill(&mut self, memory: &Memory, source: &ConditionedSource) -> io::Result<(usize, bool)> {
+        let size = u64::from(self.size);
+        let unmapped = || invalid("ring outside guest memory");
+        let desc = memory.user(self.desc, 16 * size).ok_or_else(unmapped)?;
+        let avail = memory.user(self.avail, 6 + 2 * size).ok_or_else(unmapped)?;
+        let used = memory.user(self.used, 6 + 8 * size).ok_or_else(unmapped)?;
+
+        let mut filled = 0;
+        let available = read_u16(avail, 2);
+        // Ring entries are read only after the index announcing them
+        fence(Ordering::Acquire);
+        while self.next_avail != available {
+            let head = read_u16(avail, 4 + 2 * usize::from(self.next_avail % self.size));
+            let mut written = 0u32;
+            let (mut index, mut hops) = (head, 0);
+            loop {
+                if index >= self.size || hops >= self.size {
+                    return Err(invalid("descriptor chain out of range"));
+                }
+                let entry = 16 * usize::from(index);
+                let (addr, len) = (read_u64(desc, entry), read_u32(desc, entry + 8));
+                let (flags, next) = (read_u16(desc, entry + 12), read_u16(desc, entry + 14));
+                if flags & DESC_F_WRITE != 0 && len > 0 {
+                    let target = memory.guest(addr, len.into()).ok_or_else(unmapped)?;
+                    let bytes = source.read(len as usize);
+                    if bytes.is_empty() && written == 0 {
+                        // Leave the request queued until the buffer refills
+                        return Ok((filled, true));
+                    }
+                    // SAFETY: `target` has room for `len` bytes and `bytes` is no longer
+                    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), target, bytes.len()) };
+                    written += bytes.len() as u32;
+                    if bytes.len() < len as usize {
+                        break;
+                    }
+                }
+                if flags & DESC_F_NEXT == 0 {
+                    break;
+                }
+                (index, hops) = (next, hops + 1);
+            }
+
+            let used_index = read_u16(used, 2);
+            let element = 4 + 8 * usize::from(used_index % self.size);
+            write_u32(used, element, head.into());
+            write_u32(used, element + 4, written);
+            // The element must be visible before the index publishing it
+            fence(Ordering::Release);
+            write_u16(used, 2, used_index.wrapping_add(1));
+            self.next_avail = self.next_avail.wrapping_add(1);
+            filled += 1;
+        }
+        Ok((filled, false))
+    }
+
+    /// Tell the guest buffers were used
+    fn notify(&self) -> io::Result<()> {
+        match self.call.as_ref() {
+            Some(mut call) => call.write_all(&1u64.to_ne_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Message header and any file descriptors sent with it
+fn receive_header(stream: &UnixStream) -> io::Result<Option<([u8; 12], Vec<OwnedFd>)>> {
+    let mut header = [0u8; 12];
+    let mut iov = libc::iovec {
+        iov_base: header.as_mut_ptr().cast(),
+        iov_len: header.len(),
+    };
+    // u64 words keep the control buffer aligned for `cmsghdr`
+    let mut control = [0u64; 16];
+    // SAFETY: an all-zero msghdr is valid; the pointers set below outlive the call
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    // SAFETY: `message` describes buffers valid for the call
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if received == 0 {
+        return Ok(None);
+    }
+
+    let mut fds = Vec::new();
+    // SAFETY: the control messages were filled in by recvmsg
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&message);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg).cast::<libc::c_int>();
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / 4;
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&message, cmsg);
+        }
+    }
+    if message.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(invalid("too many file descriptors"));
+    }
+    let mut stream = stream;
+    stream.read_exact(&mut header[received as usize..])?;
+    Ok(Some((header, fds)))
+}
+
+/// Backend state for one front-end connection
+#[derive(Default)]
+struct Backend {
+    memory: Memory,
+    queue: Queue,
+    /// `PROTOCOL_FEATURES` was acknowledged, so rings wait for `SET_VRING_ENABLE`
+    protocol: bool,
+    reply_ack: bool,
+}
+
+impl Backend {
+    /// Handle one message, returning the reply payload if the request has one
+    fn handle(&mut self, request: u32, body: &[u8], fds: Vec<OwnedFd>) -> io::Result<Option<Vec<u8>>> {
+        let u32_at = |offset: usize| -> io::Result<u32> {
+            let bytes = body.get(offset..offset + 4).ok_or_else(|| invalid("short message"))?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let u64_at = |offset: usize| -> io::Result<u64> {
+            let bytes = body.get(offset..offset + 8).ok_or_else(|| invalid("short message"))?;
+            Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        // Ring requests start with the queue index; this device has one queue
+        let queue_index = |index: u64| match index & 0xff {
+            0 => Ok(()),
+            other => Err(invalid(format!("no queue {}", other))),
+        };
+        let eventfd = |fds: Vec<OwnedFd>, value: u64| -> io::Result<Option<File>> {
+            queue_index(value)?;
+            // Bit 8 marks a request without a descriptor
+            Ok(match value & 0x100 {
+                0 => Some(File::from(fds.into_iter().next().ok_or_else(|| invalid("missing eventfd"))?)),
+                _ => None,
+            })
+        };
+
+        match request {
+            request::GET_FEATURES => {
+                return Ok(Some((VIRTIO_F_VERSION_1 | PROTOCOL_FEATURES).to_le_bytes().to_vec()))
+            }
+            request::SET_FEATURES => self.protocol = u64_at(0)? & PROTOCOL_FEATURES != 0,
+            request::GET_PROTOCOL_FEATURES => return Ok(Some(PROTOCOL_F_REPLY_ACK.to_le_bytes().to_vec())),
+            request::SET_PROTOCOL_FEATURES => self.reply_ack = u64_at(0)? & PROTOCOL_F_REPLY_ACK != 0,
+            request::GET_QUEUE_NUM => return Ok(Some(1u64.to_le_bytes().to_vec())),
+            request::SET_OWNER => {}
+            request::RESET_OWNER => self.queue = Queue::default(),
+            request::SET_MEM_TABLE => {
+                let count = u32_at(0)? as usize;
+                if count > MAX_REGIONS || fds.len() != count {
+                    return Err(invalid("memory regions and descriptors do not match"));
+                }
+                let mut regions = Vec::with_capacity(count);
+                for (i, fd) in fds.iter().enumerate() {
+                    let entry = 8 + 32 * i;
+                    let (guest, size) = (u64_at(entry)?, u64_at(entry + 8)?);
+                    let (user, offset) = (u64_at(entry + 16)?, u64_at(entry + 24)?);
+                    regions.push(Region::map(fd, guest, size, user, offset)?);
+                }
+                self.memory = Memory { regions };
+            }
+            request::SET_VRING_NUM => {
+                queue_index(u32_at(0)?.into())?;
+                let size = u32_at(4)?;
+                if size == 0 || size > MAX_QUEUE_SIZE || !size.is_power_of_two() {
+                    return Err(invalid(format!("unsupported queue size {}", size)));
+                }
+                self.queue.size = size as u16;
+            }
+            request::SET_VRING_ADDR => {
+                queue_index(u32_at(0)?.into())?;
+                (self.queue.desc, self.queue.used, self.queue.avail) = (u64_at(8)?, u64_at(16)?, u64_at(24)?);
+            }
+            request::SET_VRING_BASE => {
+                queue_index(u32_at(0)?.into())?;
+                self.queue.next_avail = u32_at(4)? as u16;
+            }
+            request::GET_VRING_BASE => {
+                queue_index(u32_at(0)?.into())?;
+                // Stops the ring until it is kicked again
+                self.queue.enabled = false;
+                self.queue.kick = None;
+                let mut reply = 0u32.to_le_bytes().to_vec();
+                reply.extend(u32::from(self.queue.next_avail).to_le_bytes());
+                return Ok(Some(reply));
+            }
+            request::SET_VRING_KICK => {
+                self.queue.kick = eventfd(fds, u64_at(0)?)?;
+                // Without protocol features a kick starts the ring
+                self.queue.enabled |= !self.protocol;
+            }
+            request::SET_VRING_CALL => self.queue.call = eventfd(fds, u64_at(0)?)?,
+            request::SET_VRING_ERR => {}
+            request::SET_VRING_ENABLE => {
+                queue_index(u32_at(0)?.into())?;
+                self.queue.enabled = u32_at(4)? != 0;
+            }
+            other => return Err(invalid(format!("unsupported request {}", other))),
+        }
+        Ok(None)
+    }
+}
+
+fn send(stream: &mut UnixStream, request: u32, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(12 + payload.len());
+    message.extend(request.to_le_bytes());
+    message.extend((VERSION | REPLY).to_le_bytes());
+    message.extend((payload.len() as u32).to_le_bytes());
+    message.extend(payload);
+    stream.write_all(&message)
+}
+
+/// Read and answer one front-end message, returning false once it hangs up
+fn answer(stream: &mut UnixStream, backend: &mut Backend) -> io::Result<bool> {
+    let Some((header, fds)) = receive_header(stream)? else {
+        return Ok(false);
+    };
+    let field = |i: usize| u32::from_le_bytes(header[4 * i..4 * i + 4].try_into().unwrap());
+    let (request, flags, size) = (field(0), field(1), field(2));
+    if size > 4096 {
+        return Err(invalid("oversized message"));
+    }
+    let mut body = vec![0; size as usize];
+    stream.read_exact(&mut body)?;
+
+    let result = backend.handle(request, &body, fds);
+    let ack = flags & NEED_REPLY != 0 && backend.reply_ack;
+    match result {
+        Ok(Some(reply)) => send(stream, request, &reply)?,
+        Ok(None) if ack => send(stream, request, &0u64.to_le_bytes())?,
+        Ok(None) => {}
+        Err(e) if ack => {
+            debug!("Refused vhost-user request {}: {}", request, e);
+            send(stream, request, &1u64.to_le_bytes())?;
+        }
+        Err(e) => return Err(e),
+    }
+    Ok(true)
+}
+
+/// Serve one front-end until it disconnects
+fn serve_connection(mut stream: UnixStream, source: &ConditionedSource) -> io::Result<()> {
+    let mut backend = Backend::default();
+    let mut starved = false;
+    loop {
+        let mut polled = [libc::pollfd {
+            fd: stream.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }; 2];
+        let mut count = 1;
+        if let (true, Some(kick)) = (backend.queue.ready(), &backend.queue.kick) {
+            polled[1].fd = kick.as_raw_fd();
+            count = 2;
+        }
+        let timeout = if starved { RETRY_MS } else { -1 };
+        // SAFETY: `polled` holds `count` initialised entries
+        if unsafe { libc::poll(polled.as_mut_ptr(), count as libc::nfds_t, timeout) } < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        if polled[0].revents != 0 && !answer(&mut stream, &mut backend)? {
+            return Ok(());
+        }
+        let kicked = count == 2 && polled[1].revents != 0;
+        if kicked {
+            let mut counter = [0u8; 8];
+            if let Some(mut kick) = backend.queue.kick.as_ref() {
+                kick.read_exact(&mut counter)?;
+            }
+        }
+        if backend.queue.ready() && (kicked || starved) {
+            let filled;
+            (filled, starved) = backend.queue.fill(&backend.memory, source)?;
+            if filled > 0 {
+                backend.queue.notify()?;
+            }
+        } else if !backend.queue.ready() {
+            starved = false;
+        }
+    }
+}
+
+/// Listen for vhost-user front-ends on `path`, replacing a stale socket
+/// file there
+pub fn start_vhost_user_server(path: &Path, mode: u32, source: ConditionedSource) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    info!("Serving virtio-rng to guests on {}", path.display());
+
+    std::thread::Builder::new().name("vhost-user".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept vhost-user connection: {}", e);
+                    continue;
+                }
+            };
+            let source = source.clone();
+            let spawned = std::thread::Builder::new().name("vhost-user-rng".to_string()).spawn(move || {
+                info!("Guest virtio-rng device connected");
+                match serve_connection(stream, &source) {
+                    Ok(()) => info!("Guest virtio-rng device disconnected"),
+                    Err(e) => warn!("Guest virtio-rng device dropped: {}", e),
+                }
+            });
+            if let Err(e) = spawned {
+                warn!("Failed to start vhost-user connection thread: {}", e);
+            }
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{HealthState, DEFAULT_MIN_ENTROPY};
+    use crate::utils::RingBuffer;
+    use std::sync::Arc;
+
+    const QUEUE_SIZE: u16 = 4;
+
+    /// Guest memory at address 0, laid out as
+    /// descriptors | available ring | used ring | buffers
+    fn guest_memory(memory: &mut [u8]) -> Memory {
+        let region = Region {
+            guest_addr: 0,
+            user_addr: 0,
+            size: memory.len() as u64,
+            base: memory.as_mut_ptr(),
+            mapping: None,
+        };
+        Memory { regions: vec![region] }
+    }
+
+    #[test]
+    fn fills_queued_buffers() {
+        let buffer = Arc::new(RingBuffer::new(64 * 1024));
+        buffer.write(&(0..8192).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>());
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let source = ConditionedSource::new(buffer, health, 0);
+
+        let mut bytes = vec![0u8; 4096];
+        let (desc, avail, used, data) = (0, 64, 128, 1024u64);
+        // Two requests: 16 bytes, then a chain of 8 read-only and 32 writable bytes
+        let descriptors: [(u64, u32, u16, u16); 3] = [
+            (data, 16, DESC_F_WRITE, 0),
+            (data + 64, 8, DESC_F_NEXT, 2),
+            (data + 128, 32, DESC_F_WRITE, 0),
+        ];
+        for (i, (addr, len, flags, next)) in descriptors.into_iter().enumerate() {
+            let entry = desc + 16 * i;
+            bytes[entry..entry + 8].copy_from_slice(&addr.to_le_bytes());
+            bytes[entry + 8..entry + 12].copy_from_slice(&len.to_le_bytes());
+            bytes[entry + 12..entry + 14].copy_from_slice(&flags.to_le_bytes());
+            bytes[entry + 14..entry + 16].copy_from_slice(&next.to_le_bytes());
+        }
+        bytes[avail + 2..avail + 4].copy_from_slice(&2u16.to_le_bytes());
+        bytes[avail + 4..avail + 8].copy_from_slice(&[0, 0, 1, 0]);
+        let memory = guest_memory(&mut bytes);
+
+        let mut queue = Queue {
+            size: QUEUE_SIZE,
+            desc: desc as u64,
+            avail: avail as u64,
+            used: used as u64,
+            enabled: true,
+            ..Queue::default()
+        };
+        assert_eq!(queue.fill(&memory, &source).unwrap(), (2, false));
+        assert_eq!(queue.next_avail, 2);
+        drop(memory);
+
+        let field = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(u16::from_le_bytes([bytes[used + 2], bytes[used + 3]]), 2);
+        assert_eq!((field(used + 4), field(used + 8)), (0, 16));
+        assert_eq!((field(used + 12), field(used + 16)), (1, 32));
+        let data = data as usize;
+        assert!(bytes[data..data + 16].iter().any(|&b| b != 0));
+        assert!(bytes[data + 64..data + 72].iter().all(|&b| b == 0));
+        assert!(bytes[data + 128..data + 160].iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn negotiates_features() {
+        let mut backend = Backend::default();
+        let features = backend.handle(request::GET_FEATURES, &[], Vec::new()).unwrap().unwrap();
+        let features = u64::from_le_bytes(features.try_into().unwrap());
+        assert_eq!(features & VIRTIO_F_VERSION_1, VIRTIO_F_VERSION_1);
+
+        backend.handle(request::SET_FEATURES, &features.to_le_bytes(), Vec::new()).unwrap();
+        backend.handle(request::SET_VRING_NUM, &[0, 0, 0, 0, 0, 1, 0, 0], Vec::new()).unwrap();
+        assert_eq!(backend.queue.size, 256);
+        assert!(backend.handle(request::SET_VRING_NUM, &[1, 0, 0, 0, 0, 1, 0, 0], Vec::new()).is_err());
+        // Kicks without a descriptor leave the ring stopped
+        backend.handle(request::SET_VRING_KICK, &0x100u64.to_le_bytes(), Vec::new()).unwrap();
+        backend.handle(request::SET_VRING_ENABLE, &[0, 0, 0, 0, 1, 0, 0, 0], Vec::new()).unwrap();
+        assert!(!backend.queue.ready());
+        assert!(backend.handle(request::SET_VRING_CALL, &0u64.to_le_bytes(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn answers_over_the_socket() {
+        let (mut front, mut back) = UnixStream::pair().unwrap();
+        let mut backend = Backend::default();
+        let mut message = request::GET_PROTOCOL_FEATURES.to_le_bytes().to_vec();
+        message.extend(VERSION.to_le_bytes());
+        message.extend(0u32.to_le_bytes());
+        front.write_all(&message).unwrap();
+        assert!(answer(&mut back, &mut backend).unwrap());
+
+        let mut reply = [0u8; 20];
+        front.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[..4], request::GET_PROTOCOL_FEATURES.to_le_bytes());
+        assert_eq!(reply[4..8], (VERSION | REPLY).to_le_bytes());
+        assert_eq!(reply[12..], PROTOCOL_F_REPLY_ACK.to_le_bytes());
+
+        drop(front);
+        assert!(!answer(&mut back, &mut backend).unwrap());
+    }
+}
@@ -0,0 +1,307 @@
+//! The HTTP API over AF_VSOCK for guest VMs
+//!
+//! Guests on the same host reach the server at the host's context ID (2)
+//! and `vsock.port`, with no network configured between them. Requests
+//! carry the guest's context ID in place of an IP address, which address
+//! filters and per-address limits match like one.
+
+use axum::{Extension, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{
+        conn::auto,
+        graceful::{GracefulShutdown, Watcher},
+    },
+    service::TowerToHyperService,
+};
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf},
+    sync::watch,
+    task::{JoinHandle, JoinSet},
+};
+use tower::Layer;
+use tracing::{debug, warn};
+
+use crate::api::peer::VsockPeer;
+
+/// Pending connections queued by the kernel
+const BACKLOG: libc::c_int = 128;
+
+/// Listening vsock socket
+pub struct VsockListener {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl VsockListener {
+    /// Listen on `port` for any context ID
+    pub fn bind(port: u32) -> io::Result<Self> {
+        let flags = libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+        // SAFETY: socket has no memory arguments
+        let raw = unsafe { libc::socket(libc::AF_VSOCK, flags, 0) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `raw` is a fresh descriptor owned by nothing else
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        // SAFETY: an all-zero sockaddr_vm is valid
+        let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+        addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        addr.svm_port = port;
+        addr.svm_cid = libc::VMADDR_CID_ANY;
+        let len = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        // SAFETY: `addr` is a complete sockaddr_vm of `len` bytes
+        if unsafe { libc::bind(fd.as_raw_fd(), (&addr as *const libc::sockaddr_vm).cast(), len) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: listen has no memory arguments
+        if unsafe { libc::listen(fd.as_raw_fd(), BACKLOG) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd: AsyncFd::new(fd)? })
+    }
+
+    /// Next connection and the context ID of the guest that made it
+    pub async fn accept(&self) -> io::Result<(VsockStream, u32)> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let accepted = guard.try_io(|fd| {
+                // SAFETY: as in `bind`
+                let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+                let mut len = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+                let flags = libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+                let addr_ptr = (&mut addr as *mut libc::sockaddr_vm).cast();
+                // SAFETY: `addr` and `len` describe a writable sockaddr_vm
+                let raw = unsafe { libc::accept4(fd.as_raw_fd(), addr_ptr, &mut len, flags) };
+                if raw < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SAFETY: accept4 returned a fresh descriptor
+                Ok((unsafe { OwnedFd::from_raw_fd(raw) }, addr.svm_cid))
+            });
+            if let Ok(result) = accepted {
+                let (fd, cid) = result?;
+                return Ok((VsockStream::new(fd)?, cid));
+            }
+        }
+    }
+}
+
+/// Connected vsock socket, or any other non-blocking stream socket
+pub struct VsockStream {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl VsockStream {
+    /// Wrap a connected socket already in non-blocking mode
+    pub fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self { fd: AsyncFd::new(fd)? })
+    }
+}
+
+impl AsyncRead for VsockStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            let read = guard.try_io(|fd| {
+                // SAFETY: `unfilled` is writable for its whole length
+                let read =
+                    unsafe { libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr().cast(), unfilled.len()) };
+                if read < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(read as usize)
+            });
+            if let Ok(result) = read {
+                buf.advance(result?);
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for VsockStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            let written = guard.try_io(|fd| {
+                // SAFETY: `buf` is readable for its whole length
+                let sent =
+                    unsafe { libc::send(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len(), libc::MSG_NOSIGNAL) };
+                if sent < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(sent as usize)
+            });
+            if let Ok(result) = written {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // SAFETY: shutdown has no memory arguments
+        if unsafe { libc::shutdown(self.fd.as_raw_fd(), libc::SHUT_WR) } < 0 {
+            return Poll::Ready(Err(io::Error::last_os_error()));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Serve `app` on one connection from guest `cid` until it closes, or
+/// until `watcher` signals shutdown and the request in flight is answered
+async fn serve_connection(
+    stream: VsockStream,
+    app: Router,
+    cid: u32,
+    http: &auto::Builder<TokioExecutor>,
+    watcher: Watcher,
+) {
+    let service = TowerToHyperService::new(Extension(VsockPeer(cid)).layer(app));
+    if let Err(e) = watcher.watch(http.serve_connection(TokioIo::new(stream), service)).await {
+        debug!("vsock connection closed: {}", e);
+    }
+}
+
+/// Serve `app` to every guest connecting on `listener` until `stopping`
+/// turns true
+///
+/// Connections then finish the requests in flight, and are closed once
+/// `drain` has passed. The returned task ends when they all have.
+pub fn start_vsock_server(
+    listener: VsockListener,
+    app: Router,
+    http: auto::Builder<TokioExecutor>,
+    mut stopping: watch::Receiver<bool>,
+    drain: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let graceful = GracefulShutdown::new();
+        let mut connections = JoinSet::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                Some(_) = connections.join_next() => continue,
+                _ = stopping.wait_for(|stopping| *stopping) => break,
+            };
+            let (stream, cid) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept vsock connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            debug!("vsock connection from context {}", cid);
+            let (app, http, watcher) = (app.clone(), http.clone(), graceful.watcher());
+            connections.spawn(async move { serve_connection(stream, app, cid, &http, watcher).await });
+        }
+
+        drop(listener);
+        if tokio::time::timeout(drain, graceful.shutdown()).await.is_err() {
+            warn!("Closing {} vsock connections still open after {:?}", connections.len(), drain);
+        }
+        connections.shutdown().await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ipfilter::{self, IpFilter};
+    use axum::routing::get;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Response to a GET of `/health` from guest `cid`, over a Unix socket
+    /// pair standing in for a guest connection
+    async fn get_health(app: Router, cid: u32) -> String {
+        let (guest, host) = std::os::unix::net::UnixStream::pair().unwrap();
+        host.set_nonblocking(true).unwrap();
+        let host = VsockStream::new(OwnedFd::from(host)).unwrap();
+        let http = auto::Builder::new(TokioExecutor::new());
+        let graceful = GracefulShutdown::new();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move { serve_connection(host, app, cid, &http, watcher).await });
+
+        guest.set_nonblocking(true).unwrap();
+        let mut guest = tokio::net::UnixStream::from_std(guest).unwrap();
+        guest.write_all(b"GET /health HTTP/1.1\r\nHost: host\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        guest.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn shutdown_answers_the_request_in_flight_then_closes() {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let released = release.clone();
+        let app = Router::new().route(
+            "/slow",
+            get(move || async move {
+                released.notified().await;
+                "done"
+            }),
+        );
+        let (guest, host) = std::os::unix::net::UnixStream::pair().unwrap();
+        host.set_nonblocking(true).unwrap();
+        let host = VsockStream::new(OwnedFd::from(host)).unwrap();
+        let http = auto::Builder::new(TokioExecutor::new());
+        let graceful = GracefulShutdown::new();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move { serve_connection(host, app, 3, &http, watcher).await });
+
+        guest.set_nonblocking(true).unwrap();
+        let mut guest = tokio::net::UnixStream::from_std(guest).unwrap();
+        guest.write_all(b"GET /slow HTTP/1.1\r\nHost: host\r\n\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The keep-alive connection closes once its request is answered
+        let drained = tokio::spawn(graceful.shutdown());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drained.is_finished());
+        release.notify_one();
+        let mut response = String::new();
+        guest.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("done"));
+        tokio::time::timeout(Duration::from_secs(1), drained).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_the_router_over_a_stream_socket() {
+        let app = Router::new().route("/health", get(|| async { "ok" }));
+        let response = get_health(app, 3).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("ok"));
+    }
+
+    #[tokio::test]
+    async fn filters_guests_by_context_id() {
+        let rules = |rules: &[&str]| rules.iter().map(|rule| rule.to_string()).collect::<Vec<_>>();
+        let filter = Arc::new(IpFilter::new(&rules(&["10.0.0.0/8"]), &[]).unwrap());
+        let app = Router::new().route("/health", get(|| async { "ok" }));
+        let app = ipfilter::filter_addresses(app, filter.clone());
+
+        // Guests are refused by an allow list that does not name them
+        let response = get_health(app.clone(), 3).await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+        assert!(response.contains("vsock:3"));
+
+        filter.set(&rules(&["10.0.0.0/8", "vsock:3"]), &[]).unwrap();
+        assert!(get_health(app.clone(), 3).await.starts_with("HTTP/1.1 200"));
+        assert!(get_health(app, 4).await.starts_with("HTTP/1.1 403"));
+    }
+}
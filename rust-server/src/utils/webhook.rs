@@ -0,0 +1,34 @@
+//! Delivery of health alarms to an HTTP endpoint
+
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::health::alarm::Alarm;
+
+/// POST every alarm as JSON to `url` until the channel closes
+pub async fn notify_webhook(mut alarms: broadcast::Receiver<Alarm>, url: String) {
+    let client = reqwest::Client::new();
+    info!("Sending alarms to webhook {}", url);
+
+    loop {
+        let alarm = match alarms.recv().await {
+            Ok(alarm) => alarm,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Alarm webhook fell behind, {} alarm(s) not delivered", missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let sent = client
+            .post(&url)
+            .json(&alarm)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = sent {
+            warn!("Failed to deliver {} alarm to webhook: {}", alarm.kind, e);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! ZeroMQ PUB socket publishing entropy and device status
+//!
+//! Every message has two frames, a topic and its payload, so subscribers
+//! filter on the topic:
+//!
+//! - `entropy`: SHA-3 conditioned bytes, at most `zmq.bytes_per_sec`
+//! - `status`: JSON, each device event as it happens and a health summary
+//!   every `zmq.status_interval_secs`
+//!
+//! PUB sockets drop messages for subscribers whose queue is full, so slow
+//! subscribers lose frames rather than holding up the others.
+
+use bytes::Bytes;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use zeromq::{Endpoint, PubSocket, Socket, SocketSend, ZmqError, ZmqMessage};
+
+use super::conditioned::{self, ConditionedSource};
+use crate::device::{pool::DevicePool, DeviceEvent};
+use crate::health::HealthState;
+
+/// Topic of entropy frames
+pub const ENTROPY_TOPIC: &[u8] = b"entropy";
+
+/// Topic of device events and health summaries
+pub const STATUS_TOPIC: &[u8] = b"status";
+
+/// Most entropy bytes per frame
+const MAX_FRAME: usize = 4096;
+
+/// Pause before retrying on a drained buffer
+const RETRY: Duration = Duration::from_millis(100);
+
+/// What the publisher sends, and how often
+pub struct Publication {
+    pub source: ConditionedSource,
+    pub bytes_per_sec: usize,
+    pub health: Arc<HealthState>,
+    pub devices: Arc<DevicePool>,
+    pub events: broadcast::Receiver<DeviceEvent>,
+    pub status_interval: Duration,
+}
+
+fn message(topic: &'static [u8], payload: Vec<u8>) -> ZmqMessage {
+    ZmqMessage::try_from(vec![Bytes::from_static(topic), Bytes::from(payload)]).expect("frames present")
+}
+
+/// Health and serving devices, as published every status interval
+fn summary(health: &HealthState, devices: &DevicePool, available: usize) -> serde_json::Value {
+    let failure = health.failure();
+    serde_json::json!({
+        "event": "status",
+        "healthy": failure.is_none(),
+        "failure": failure,
+        "devices": devices.serving_serials(),
+        "available": available,
+    })
+}
+
+/// Bind a PUB socket to `endpoint`, e.g. `tcp://0.0.0.0:5556`, and publish
+///
+/// Returns the bound endpoint, with the port filled in if `endpoint` asked
+/// for any free one.
+pub async fn start_zmq_publisher(endpoint: &str, publication: Publication) -> Result<Endpoint, ZmqError> {
+    let mut socket = PubSocket::new();
+    let bound = socket.bind(endpoint).await?;
+    info!(
+        "Publishing {} bytes/s of entropy over ZeroMQ at {}",
+        publication.bytes_per_sec, bound
+    );
+    tokio::spawn(publish(socket, publication));
+    Ok(bound)
+}
+
+async fn publish(mut socket: PubSocket, publication: Publication) {
+    let Publication {
+        source,
+        bytes_per_sec,
+        health,
+        devices,
+        mut events,
+        status_interval,
+    } = publication;
+    let (chunk, pause) = conditioned::pace(bytes_per_sec, MAX_FRAME);
+    let mut frames = tokio::time::interval(pause);
+    frames.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut statuses = tokio::time::interval(status_interval);
+
+    loop {
+        let sent = tokio::select! {
+            _ = frames.tick() => {
+                let bytes = source.read(chunk);
+                if bytes.is_empty() {
+                    tokio::time::sleep(RETRY).await;
+                    continue;
+                }
+                socket.send(message(ENTROPY_TOPIC, bytes.to_vec())).await
+            }
+            _ = statuses.tick() => {
+                let status = summary(&health, &devices, source.available());
+                socket.send(message(STATUS_TOPIC, status.to_string().into_bytes())).await
+            }
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let payload = serde_json::to_vec(&event).expect("device events serialize");
+                    socket.send(message(STATUS_TOPIC, payload)).await
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    debug!("ZeroMQ publisher missed {} device event(s)", missed);
+                    continue;
+                }
+                // Device events stop at shutdown; entropy and summaries go on
+                Err(broadcast::error::RecvError::Closed) => {
+                    events = broadcast::channel(1).1;
+                    continue;
+                }
+            },
+        };
+        if let Err(e) = sent {
+            warn!("Failed to publish over ZeroMQ: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::DEFAULT_MIN_ENTROPY;
+    use crate::utils::RingBuffer;
+    use zeromq::{SocketRecv, SubSocket};
+
+    #[tokio::test]
+    async fn publishes_entropy_and_status() {
+        let buffer = Arc::new(RingBuffer::new(64 * 1024));
+        buffer.write(&(0..32 * 1024).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>());
+        let health = Arc::new(HealthState::new(DEFAULT_MIN_ENTROPY));
+        let (device_events, _) = broadcast::channel(16);
+        let publication = Publication {
+            source: ConditionedSource::new(buffer, health.clone(), 0),
+            bytes_per_sec: 1 << 20,
+            health,
+            devices: Arc::new(DevicePool::new(device_events.clone())),
+            events: device_events.subscribe(),
+            status_interval: Duration::from_millis(50),
+        };
+        let endpoint = start_zmq_publisher("tcp://127.0.0.1:0", publication).await.unwrap();
+
+        let mut subscriber = SubSocket::new();
+        subscriber.connect(&endpoint.to_string()).await.unwrap();
+        subscriber.subscribe("").await.unwrap();
+        let (mut entropy, mut status) = (None, None);
+        let received = tokio::time::timeout(Duration::from_secs(10), async {
+            while entropy.is_none() || status.is_none() {
+                let message = subscriber.recv().await.unwrap();
+                let frames = message.into_vec();
+                assert_eq!(frames.len(), 2);
+                match &frames[0][..] {
+                    ENTROPY_TOPIC => entropy = Some(frames[1].clone()),
+                    STATUS_TOPIC => status = Some(frames[1].clone()),
+                    topic => panic!("unexpected topic {:?}", topic),
+                }
+            }
+        });
+        received.await.expect("both topics published");
+
+        assert_eq!(entropy.unwrap().len(), MAX_FRAME);
+        let status: serde_json::Value = serde_json::from_slice(&status.unwrap()).unwrap();
+        assert_eq!(status["event"], "status");
+        assert_eq!(status["healthy"], true);
+    }
+}
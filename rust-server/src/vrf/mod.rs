@@ -0,0 +1,216 @@
+//! ECVRF-EDWARDS25519-SHA512-TAI (RFC 9381)
+//!
+//! The server key is derived from device entropy. A proof binds the
+//! output to the caller's alpha string and the public key, so anyone can
+//! verify it without trusting the server.
+
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_POINT,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::{clamp_integer, Scalar},
+};
+use sha2::{Digest, Sha512};
+use std::{fs, io, path::Path};
+
+/// Suite name reported to clients
+pub const SUITE: &str = "ECVRF-EDWARDS25519-SHA512-TAI";
+
+/// Suite identifier from RFC 9381
+const SUITE_STRING: u8 = 0x03;
+
+/// Secret key length in bytes
+pub const SECRET_LEN: usize = 32;
+
+/// Proof length: Gamma, 16-byte challenge, 32-byte response
+pub const PROOF_LEN: usize = 80;
+
+/// Challenge length in bytes
+const CHALLENGE_LEN: usize = 16;
+
+/// VRF key pair
+pub struct VrfKey {
+    secret: [u8; SECRET_LEN],
+    scalar: Scalar,
+    public: [u8; 32],
+}
+
+impl VrfKey {
+    /// Derive the key pair from a 32-byte secret, as for Ed25519
+    pub fn from_secret(secret: [u8; SECRET_LEN]) -> Self {
+        let hashed: [u8; 64] = Sha512::digest(secret).into();
+        let mut low = [0u8; 32];
+        low.copy_from_slice(&hashed[..32]);
+        let scalar = Scalar::from_bytes_mod_order(clamp_integer(low));
+        let public = (scalar * ED25519_BASEPOINT_POINT).compress().to_bytes();
+        Self { secret, scalar, public }
+    }
+
+    /// Encoded public key
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public
+    }
+
+    /// Load a key saved with `save`
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let secret = hex::decode(fs::read_to_string(path)?.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected 32 hex-encoded bytes"))?;
+        Ok(Self::from_secret(secret))
+    }
+
+    /// Save the secret as hex, readable only by the owner
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, hex::encode(self.secret))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// Prove `alpha`, returning the proof and the VRF output
+    pub fn prove(&self, alpha: &[u8]) -> ([u8; PROOF_LEN], [u8; 64]) {
+        let h = encode_to_curve(&self.public, alpha).expect("hash to curve succeeds for valid keys");
+        let h_string = h.compress().to_bytes();
+        let gamma = self.scalar * h;
+
+        // Deterministic nonce (RFC 9381 5.4.2.2)
+        let hashed: [u8; 64] = Sha512::digest(self.secret).into();
+        let mut nonce_hash = Sha512::new();
+        nonce_hash.update(&hashed[32..]);
+        nonce_hash.update(h_string);
+        let k = Scalar::from_bytes_mod_order_wide(&nonce_hash.finalize().into());
+
+        let c = challenge(
+            &self.public,
+            &h,
+            &gamma,
+            &(k * ED25519_BASEPOINT_POINT),
+            &(k * h),
+        );
+        let s = k + challenge_scalar(&c) * self.scalar;
+
+        let mut proof = [0u8; PROOF_LEN];
+        proof[..32].copy_from_slice(&gamma.compress().to_bytes());
+        proof[32..48].copy_from_slice(&c);
+        proof[48..].copy_from_slice(&s.to_bytes());
+        (proof, proof_to_hash(&gamma))
+    }
+}
+
+/// Verify `proof` for `alpha` under `public_key`, returning the output
+pub fn verify(public_key: &[u8; 32], alpha: &[u8], proof: &[u8; PROOF_LEN]) -> Option<[u8; 64]> {
+    let y = CompressedEdwardsY(*public_key).decompress()?;
+    if y.is_small_order() {
+        return None;
+    }
+    let gamma = CompressedEdwardsY(proof[..32].try_into().ok()?).decompress()?;
+    let c: [u8; CHALLENGE_LEN] = proof[32..48].try_into().ok()?;
+    let s = Option::<Scalar>::from(Scalar::from_canonical_bytes(proof[48..].try_into().ok()?))?;
+
+    let h = encode_to_curve(public_key, alpha)?;
+    let c_scalar = challenge_scalar(&c);
+    let u = s * ED25519_BASEPOINT_POINT - c_scalar * y;
+    let v = s * h - c_scalar * gamma;
+
+    (challenge(public_key, &h, &gamma, &u, &v) == c).then(|| proof_to_hash(&gamma))
+}
+
+/// Try-and-increment hash to the prime-order subgroup
+fn encode_to_curve(public_key: &[u8; 32], alpha: &[u8]) -> Option<EdwardsPoint> {
+    (0..=255u8).find_map(|counter| {
+        let hash = Sha512::new()
+            .chain_update([SUITE_STRING, 0x01])
+            .chain_update(public_key)
+            .chain_update(alpha)
+            .chain_update([counter, 0x00])
+            .finalize();
+        CompressedEdwardsY(hash[..32].try_into().unwrap())
+            .decompress()
+            .map(|point| point.mul_by_cofactor())
+    })
+}
+
+fn challenge(
+    public_key: &[u8; 32],
+    h: &EdwardsPoint,
+    gamma: &EdwardsPoint,
+    u: &EdwardsPoint,
+    v: &EdwardsPoint,
+) -> [u8; CHALLENGE_LEN] {
+    let mut hash = Sha512::new().chain_update([SUITE_STRING, 0x02]).chain_update(public_key);
+    for point in [h, gamma, u, v] {
+        hash.update(point.compress().as_bytes());
+    }
+    hash.update([0x00]);
+    hash.finalize()[..CHALLENGE_LEN].try_into().unwrap()
+}
+
+fn challenge_scalar(c: &[u8; CHALLENGE_LEN]) -> Scalar {
+    let mut bytes = [0u8; 32];
+    bytes[..CHALLENGE_LEN].copy_from_slice(c);
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+fn proof_to_hash(gamma: &EdwardsPoint) -> [u8; 64] {
+    Sha512::new()
+        .chain_update([SUITE_STRING, 0x03])
+        .chain_update(gamma.mul_by_cofactor().compress().as_bytes())
+        .chain_update([0x00])
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> VrfKey {
+        let secret = hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60").unwrap();
+        VrfKey::from_secret(secret.try_into().unwrap())
+    }
+
+    #[test]
+    fn matches_rfc_9381_example() {
+        let key = key();
+        assert_eq!(
+            hex::encode(key.public_key()),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+
+        let (proof, beta) = key.prove(b"");
+        assert_eq!(
+            hex::encode(proof),
+            "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f\
+             26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab12\
+             68a1b0db10836d9826a528ca76567805"
+        );
+        assert_eq!(
+            hex::encode(beta),
+            "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff\
+             66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae"
+        );
+    }
+
+    #[test]
+    fn saved_key_round_trips() {
+        let path = std::env::temp_dir().join(format!("quantis-vrf-{}.key", std::process::id()));
+        key().save(&path).unwrap();
+        assert_eq!(VrfKey::load(&path).unwrap().public_key(), key().public_key());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn verification_rejects_other_inputs() {
+        let key = key();
+        let (proof, beta) = key.prove(b"draw 42");
+        assert_eq!(verify(&key.public_key(), b"draw 42", &proof), Some(beta));
+        assert_eq!(verify(&key.public_key(), b"draw 43", &proof), None);
+
+        let mut tampered = proof;
+        tampered[40] ^= 1;
+        assert_eq!(verify(&key.public_key(), b"draw 42", &tampered), None);
+    }
+}